@@ -0,0 +1,456 @@
+use std::{sync::atomic::AtomicU64, time::Duration};
+
+use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt}, net::{TcpStream, UnixStream}};
+
+use crate::{config, snmp, stats};
+
+// An AgentX (RFC 2741) subagent exposing the collector's own statistics
+// under `subtree` through the host's snmpd:
+//   .1.0 uptime (TimeTicks)        .2.0 targets (Gauge32)
+//   .3.0 profiles (Gauge32)        .4.0 collections (Counter64)
+//   .5.0 collection failures       .6.0 sink writes
+//   .7.0 sink failures
+// The session is reopened whenever the master agent goes away.
+pub fn spawn(config: &config::Config, profiles: usize) {
+  let Some(master) = config.agentx.master.clone() else {
+    return;
+  };
+  let subtree = config.agentx.subtree.arcs().to_vec();
+  let targets = config.targets.len() as u32;
+  tokio::spawn(async move {
+    loop {
+      let result = match master.starts_with('/') {
+        true => match UnixStream::connect(&master).await {
+          Ok(stream) => session(stream, &subtree, targets, profiles as u32).await,
+          Err(error) => Err(Error::Io(error)),
+        },
+        false => match TcpStream::connect(&master).await {
+          Ok(stream) => session(stream, &subtree, targets, profiles as u32).await,
+          Err(error) => Err(Error::Io(error)),
+        },
+      };
+      if let Err(error) = result {
+        eprintln!("AgentX session with {} ended: {}", master, error);
+      }
+      tokio::time::sleep(RECONNECT).await;
+    }
+  });
+}
+
+const RECONNECT: Duration = Duration::from_secs(10);
+// Seconds the master waits for our answers.
+const TIMEOUT: u8 = 5;
+
+const OPEN: u8 = 1;
+const CLOSE: u8 = 2;
+const REGISTER: u8 = 3;
+const GET: u8 = 5;
+const GET_NEXT: u8 = 6;
+const GET_BULK: u8 = 7;
+const TEST_SET: u8 = 8;
+const COMMIT_SET: u8 = 9;
+const UNDO_SET: u8 = 10;
+const CLEANUP_SET: u8 = 11;
+const RESPONSE: u8 = 18;
+
+const NON_DEFAULT_CONTEXT: u8 = 0x08;
+const NETWORK_BYTE_ORDER: u8 = 0x10;
+
+const NOT_WRITABLE: u16 = 17;
+
+#[derive(Debug)]
+enum Error {
+  Io(std::io::Error),
+  Protocol(String),
+}
+
+impl std::fmt::Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(error) => write!(f, "{}", error),
+      Error::Protocol(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+// An OID with its `include` flag, as in search ranges.
+type SearchOid = (Vec<u32>, bool);
+
+struct Header {
+  kind: u8,
+  flags: u8,
+  session_id: u32,
+  transaction_id: u32,
+  packet_id: u32,
+}
+
+async fn session<S: AsyncRead + AsyncWrite + Unpin>(
+  mut stream: S,
+  subtree: &[u32],
+  targets: u32,
+  profiles: u32,
+) -> Result<(), Error> {
+  let mut open = vec![TIMEOUT, 0, 0, 0];
+  encode_oid(&mut open, &[], false);
+  encode_octets(&mut open, b"snmp-collector");
+  let header = Header { kind: OPEN, flags: 0, session_id: 0, transaction_id: 0, packet_id: 1 };
+  send(&mut stream, &header, &open).await?;
+  let session_id = expect_response(&mut stream).await?;
+  let mut register = vec![TIMEOUT, 127, 0, 0];
+  encode_oid(&mut register, subtree, false);
+  let header = Header { kind: REGISTER, flags: 0, session_id, transaction_id: 0, packet_id: 2 };
+  send(&mut stream, &header, &register).await?;
+  expect_response(&mut stream).await?;
+  loop {
+    let (header, payload) = receive(&mut stream).await?;
+    let mut reader = Reader { data: &payload, position: 0, big_endian: header.flags & NETWORK_BYTE_ORDER != 0 };
+    if header.flags & NON_DEFAULT_CONTEXT != 0 {
+      reader.octets()?;
+    }
+    let mut response = Vec::new();
+    let mut error = 0;
+    let objects = objects(subtree, targets, profiles);
+    match header.kind {
+      GET => while !reader.done() {
+        let (start, _end) = reader.range()?;
+        let value = objects.iter()
+          .find(|(oid, _)| *oid == start.0)
+          .map_or(snmp::ObjectValue::NoSuchObject, |(_, value)| value.clone());
+        encode_binding(&mut response, &start.0, &value);
+      },
+      GET_NEXT => while !reader.done() {
+        let (start, end) = reader.range()?;
+        next(&mut response, &objects, &start, &end);
+      },
+      GET_BULK => {
+        let non_repeaters = reader.u16()? as usize;
+        let max_repetitions = reader.u16()? as usize;
+        let mut ranges = Vec::new();
+        while !reader.done() {
+          ranges.push(reader.range()?);
+        }
+        for (start, end) in ranges.iter().take(non_repeaters) {
+          next(&mut response, &objects, start, end);
+        }
+        // Rows of one binding per repeater (RFC 3416 4.2.3); a repeater
+        // past the end gives endOfMibView in each row after, and the rows
+        // stop once all of them are.
+        let mut repeaters = ranges.into_iter().skip(non_repeaters).collect::<Vec<_>>();
+        for _ in 0..max_repetitions {
+          let mut ended = true;
+          for (start, end) in repeaters.iter_mut() {
+            if let Some(oid) = next(&mut response, &objects, start, end) {
+              *start = (oid, false);
+              ended = false;
+            }
+          }
+          if ended {
+            break;
+          }
+        }
+      },
+      TEST_SET => error = NOT_WRITABLE,
+      COMMIT_SET | UNDO_SET => {},
+      CLEANUP_SET => continue,
+      CLOSE => return Err(Error::Protocol("closed by the master agent".to_string())),
+      kind => return Err(Error::Protocol(format!("unexpected PDU type {}", kind))),
+    }
+    let mut payload = stats::uptime().to_be_bytes().to_vec();
+    payload.extend(error.to_be_bytes());
+    payload.extend(0u16.to_be_bytes());
+    payload.extend(response);
+    let header = Header { kind: RESPONSE, flags: 0, ..header };
+    send(&mut stream, &header, &payload).await?;
+  }
+}
+
+// The collector's objects in lexicographic order.
+fn objects(subtree: &[u32], targets: u32, profiles: u32) -> Vec<(Vec<u32>, snmp::ObjectValue)> {
+  let oid = |arc: u32| subtree.iter().copied().chain([arc, 0]).collect::<Vec<_>>();
+  let counter = |counter: &AtomicU64| snmp::ObjectValue::Counter64(stats::read(counter));
+  vec![
+    (oid(1), snmp::ObjectValue::TimeTicks(stats::uptime())),
+    (oid(2), snmp::ObjectValue::Unsigned32(targets)),
+    (oid(3), snmp::ObjectValue::Unsigned32(profiles)),
+    (oid(4), counter(&stats::STATS.collections)),
+    (oid(5), counter(&stats::STATS.collection_failures)),
+    (oid(6), counter(&stats::STATS.sink_writes)),
+    (oid(7), counter(&stats::STATS.sink_failures)),
+  ]
+}
+
+// Encodes the first object after `start` (or at it, when included) and
+// before a non-empty `end`, or endOfMibView; returns its OID if found.
+fn next(
+  response: &mut Vec<u8>,
+  objects: &[(Vec<u32>, snmp::ObjectValue)],
+  start: &SearchOid,
+  end: &SearchOid,
+) -> Option<Vec<u32>> {
+  let (start, include) = start;
+  let found = objects.iter()
+    .find(|(oid, _)| oid > start || (*include && oid == start))
+    .filter(|(oid, _)| end.0.is_empty() || *oid < end.0);
+  match found {
+    Some((oid, value)) => {
+      encode_binding(response, oid, value);
+      Some(oid.clone())
+    },
+    None => {
+      encode_binding(response, start, &snmp::ObjectValue::EndOfMibView);
+      None
+    },
+  }
+}
+
+async fn send<S: AsyncWrite + Unpin>(stream: &mut S, header: &Header, payload: &[u8]) -> Result<(), Error> {
+  let mut packet = vec![1, header.kind, header.flags | NETWORK_BYTE_ORDER, 0];
+  packet.extend(header.session_id.to_be_bytes());
+  packet.extend(header.transaction_id.to_be_bytes());
+  packet.extend(header.packet_id.to_be_bytes());
+  packet.extend((payload.len() as u32).to_be_bytes());
+  packet.extend(payload);
+  stream.write_all(&packet).await.map_err(Error::Io)
+}
+
+async fn receive<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(Header, Vec<u8>), Error> {
+  let mut bytes = [0; 20];
+  stream.read_exact(&mut bytes).await.map_err(Error::Io)?;
+  let big_endian = bytes[2] & NETWORK_BYTE_ORDER != 0;
+  let word = |at: usize| {
+    let word = [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+    match big_endian {
+      true => u32::from_be_bytes(word),
+      false => u32::from_le_bytes(word),
+    }
+  };
+  let header = Header {
+    kind: bytes[1],
+    flags: bytes[2],
+    session_id: word(4),
+    transaction_id: word(8),
+    packet_id: word(12),
+  };
+  let mut payload = vec![0; word(16) as usize];
+  stream.read_exact(&mut payload).await.map_err(Error::Io)?;
+  Ok((header, payload))
+}
+
+// Returns the session ID of a successful Response-PDU.
+async fn expect_response<S: AsyncRead + Unpin>(stream: &mut S) -> Result<u32, Error> {
+  let (header, payload) = receive(stream).await?;
+  let mut reader = Reader { data: &payload, position: 0, big_endian: header.flags & NETWORK_BYTE_ORDER != 0 };
+  reader.u32()?;
+  let error = reader.u16()?;
+  if header.kind != RESPONSE || error != 0 {
+    return Err(Error::Protocol(format!("master agent refused the session (error {})", error)));
+  }
+  Ok(header.session_id)
+}
+
+struct Reader<'a> {
+  data: &'a [u8],
+  position: usize,
+  big_endian: bool,
+}
+
+impl Reader<'_> {
+
+  fn done(&self) -> bool {
+    self.position >= self.data.len()
+  }
+
+  fn take(&mut self, count: usize) -> Result<&[u8], Error> {
+    let bytes = self.data.get(self.position..self.position + count)
+      .ok_or_else(|| Error::Protocol("truncated PDU".to_string()))?;
+    self.position += count;
+    Ok(bytes)
+  }
+
+  fn u16(&mut self) -> Result<u16, Error> {
+    let big_endian = self.big_endian;
+    let bytes = <[u8; 2]>::try_from(self.take(2)?).expect("two bytes were taken");
+    Ok(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+  }
+
+  fn u32(&mut self) -> Result<u32, Error> {
+    let big_endian = self.big_endian;
+    let bytes = <[u8; 4]>::try_from(self.take(4)?).expect("four bytes were taken");
+    Ok(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+  }
+
+  fn oid(&mut self) -> Result<SearchOid, Error> {
+    let &[count, prefix, include, _] = self.take(4)? else {
+      unreachable!("four bytes were taken");
+    };
+    let mut arcs = match prefix {
+      0 => Vec::new(),
+      prefix => vec![1, 3, 6, 1, u32::from(prefix)],
+    };
+    for _ in 0..count {
+      arcs.push(self.u32()?);
+    }
+    Ok((arcs, include != 0))
+  }
+
+  fn range(&mut self) -> Result<(SearchOid, SearchOid), Error> {
+    Ok((self.oid()?, self.oid()?))
+  }
+
+  fn octets(&mut self) -> Result<Vec<u8>, Error> {
+    let length = self.u32()? as usize;
+    let octets = self.take(length)?.to_vec();
+    self.take((4 - length % 4) % 4)?;
+    Ok(octets)
+  }
+}
+
+fn encode_oid(buffer: &mut Vec<u8>, arcs: &[u32], include: bool) {
+  let (prefix, arcs) = match arcs {
+    [1, 3, 6, 1, prefix, rest @ ..] if *prefix <= 255 && !rest.is_empty() => (*prefix as u8, rest),
+    arcs => (0, arcs),
+  };
+  buffer.extend([arcs.len() as u8, prefix, include as u8, 0]);
+  for arc in arcs {
+    buffer.extend(arc.to_be_bytes());
+  }
+}
+
+fn encode_octets(buffer: &mut Vec<u8>, octets: &[u8]) {
+  buffer.extend((octets.len() as u32).to_be_bytes());
+  buffer.extend(octets);
+  buffer.extend(std::iter::repeat_n(0, (4 - octets.len() % 4) % 4));
+}
+
+fn encode_binding(buffer: &mut Vec<u8>, oid: &[u32], value: &snmp::ObjectValue) {
+  let kind: u16 = match value {
+    snmp::ObjectValue::Unsigned32(_) => 66,
+    snmp::ObjectValue::TimeTicks(_) => 67,
+    snmp::ObjectValue::Counter64(_) => 70,
+    snmp::ObjectValue::NoSuchObject => 128,
+    snmp::ObjectValue::EndOfMibView => 130,
+    _ => unreachable!("the collector only exposes gauges, counters and time ticks"),
+  };
+  buffer.extend(kind.to_be_bytes());
+  buffer.extend([0, 0]);
+  encode_oid(buffer, oid, false);
+  match value {
+    snmp::ObjectValue::Unsigned32(value) | snmp::ObjectValue::TimeTicks(value) => buffer.extend(value.to_be_bytes()),
+    snmp::ObjectValue::Counter64(value) => buffer.extend(value.to_be_bytes()),
+    _ => {},
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SUBTREE: [u32; 8] = [1, 3, 6, 1, 4, 1, 99999, 1];
+
+  fn reader(data: &[u8]) -> Reader<'_> {
+    Reader { data, position: 0, big_endian: true }
+  }
+
+  // The type and OID of each binding of a Response-PDU.
+  fn bindings(payload: &[u8]) -> Vec<(u16, Vec<u32>)> {
+    let mut reader = reader(payload);
+    reader.u32().unwrap();
+    assert_eq!(reader.u16().unwrap(), 0);
+    reader.u16().unwrap();
+    let mut bindings = Vec::new();
+    while !reader.done() {
+      let kind = reader.u16().unwrap();
+      reader.u16().unwrap();
+      let (oid, _) = reader.oid().unwrap();
+      match kind {
+        66 | 67 => reader.position += 4,
+        70 => reader.position += 8,
+        _ => {},
+      }
+      bindings.push((kind, oid));
+    }
+    bindings
+  }
+
+  async fn respond<S: AsyncWrite + Unpin>(master: &mut S, to: &Header) {
+    let header = Header { kind: RESPONSE, flags: 0, session_id: 42, transaction_id: to.transaction_id, packet_id: to.packet_id };
+    send(master, &header, &[0; 8]).await.unwrap();
+  }
+
+  #[test]
+  fn encodes_oids_with_prefixes() {
+    let mut buffer = Vec::new();
+    encode_oid(&mut buffer, &SUBTREE, true);
+    assert_eq!(buffer[..4], [3, 4, 1, 0]);
+    encode_oid(&mut buffer, &[1, 3, 6, 1, 2], false);
+    encode_oid(&mut buffer, &[], false);
+    let mut reader = reader(&buffer);
+    assert_eq!(reader.oid().unwrap(), (SUBTREE.to_vec(), true));
+    assert_eq!(reader.oid().unwrap(), (vec![1, 3, 6, 1, 2], false));
+    assert_eq!(reader.oid().unwrap(), (vec![], false));
+    assert!(reader.done());
+  }
+
+  #[test]
+  fn pads_octets() {
+    let mut buffer = Vec::new();
+    encode_octets(&mut buffer, b"snmp-collector");
+    encode_octets(&mut buffer, b"");
+    assert_eq!(buffer.len(), 4 + 16 + 4);
+    let mut reader = reader(&buffer);
+    assert_eq!(reader.octets().unwrap(), b"snmp-collector");
+    assert_eq!(reader.octets().unwrap(), b"");
+    assert!(reader.done());
+  }
+
+  #[test]
+  fn reads_either_byte_order() {
+    let mut little = Reader { data: &[1, 0, 0, 0, 2, 0], position: 0, big_endian: false };
+    assert_eq!(little.u32().unwrap(), 1);
+    assert_eq!(little.u16().unwrap(), 2);
+    assert!(matches!(little.u16(), Err(Error::Protocol(_))));
+  }
+
+  #[tokio::test]
+  async fn opens_a_session_and_answers_get_bulk() {
+    let (mut master, subagent) = tokio::io::duplex(4096);
+    let session = tokio::spawn(async move { session(subagent, &SUBTREE, 3, 5).await });
+    let (open, payload) = receive(&mut master).await.unwrap();
+    assert_eq!(open.kind, OPEN);
+    let mut fields = reader(&payload);
+    assert_eq!(fields.u32().unwrap().to_be_bytes(), [TIMEOUT, 0, 0, 0]);
+    assert_eq!(fields.oid().unwrap(), (vec![], false));
+    assert_eq!(fields.octets().unwrap(), b"snmp-collector");
+    respond(&mut master, &open).await;
+    let (register, payload) = receive(&mut master).await.unwrap();
+    assert_eq!((register.kind, register.session_id), (REGISTER, 42));
+    let mut fields = reader(&payload);
+    fields.u32().unwrap();
+    assert_eq!(fields.oid().unwrap(), (SUBTREE.to_vec(), false));
+    respond(&mut master, &register).await;
+    // Two repeaters, one of them a binding from the end of the subtree.
+    let mut bulk = Vec::new();
+    bulk.extend(0u16.to_be_bytes());
+    bulk.extend(3u16.to_be_bytes());
+    for arc in [6, 1] {
+      encode_oid(&mut bulk, &[&SUBTREE[..], &[arc]].concat(), false);
+      encode_oid(&mut bulk, &[], false);
+    }
+    let header = Header { kind: GET_BULK, flags: 0, session_id: 42, transaction_id: 7, packet_id: 3 };
+    send(&mut master, &header, &bulk).await.unwrap();
+    let (response, payload) = receive(&mut master).await.unwrap();
+    assert_eq!((response.kind, response.transaction_id, response.packet_id), (RESPONSE, 7, 3));
+    let object = |arc: u32| [&SUBTREE[..], &[arc, 0]].concat();
+    assert_eq!(bindings(&payload), [
+      (70, object(6)),
+      (67, object(1)),
+      (70, object(7)),
+      (66, object(2)),
+      (130, object(7)),
+      (66, object(3)),
+    ]);
+    drop(master);
+    assert!(matches!(session.await.unwrap(), Err(Error::Io(_))));
+  }
+}
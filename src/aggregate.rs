@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, profile};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+  #[default]
+  Sum,
+  Avg,
+  Max,
+}
+
+// One row of an aggregation: the devices sharing a value of the grouping
+// tag (all selected devices when there is no grouping) and the aggregate of
+// the metric over their samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct Group {
+  pub group: Option<String>,
+  pub devices: usize,
+  pub failed: usize,
+  pub samples: usize,
+  pub value: Option<f64>,
+}
+
+impl Operation {
+
+  pub fn apply(&self, values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+      return None;
+    }
+    let sum = values.iter().sum::<f64>();
+    Some(match self {
+      Operation::Sum => sum,
+      Operation::Avg => sum / values.len() as f64,
+      Operation::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+  }
+}
+
+// Targets carrying every one of the requested tags with the given value.
+pub fn select<'a>(
+  config: &'a config::Config,
+  tags: &'a BTreeMap<String, String>,
+) -> impl Iterator<Item = &'a config::TargetConfig> + 'a {
+  config.targets.iter()
+    .filter(|target| tags.iter().all(|(tag, value)| target.tags.get(tag) == Some(value)))
+}
+
+#[derive(Default)]
+pub struct Aggregation {
+  groups: BTreeMap<Option<String>, (usize, usize, Vec<f64>)>,
+}
+
+impl Aggregation {
+
+  // Adds one device's samples, or `None` when it could not be collected.
+  pub fn add(&mut self, group: Option<String>, metric: &str, samples: Option<Vec<profile::Sample>>) {
+    let (devices, failed, values) = self.groups.entry(group).or_default();
+    *devices += 1;
+    match samples {
+      Some(samples) => values.extend(samples.into_iter()
+        .filter(|sample| sample.name == metric)
+        .map(|sample| sample.value)),
+      None => *failed += 1,
+    }
+  }
+
+  pub fn finish(self, operation: Operation) -> Vec<Group> {
+    self.groups.into_iter()
+      .map(|(group, (devices, failed, values))| Group {
+        group,
+        devices,
+        failed,
+        samples: values.len(),
+        value: operation.apply(&values),
+      })
+      .collect()
+  }
+}
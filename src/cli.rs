@@ -0,0 +1,105 @@
+use std::net::Ipv4Addr;
+
+use clap::{Parser, Subcommand};
+
+use crate::{config, snmp, snmpwalk};
+
+#[derive(Parser)]
+#[command(version, about = "Collects SNMP data and serves it over HTTP")]
+pub struct Cli {
+  #[command(subcommand)]
+  pub command: Option<Command>,
+}
+
+// Besides `serve`, the default, requests to a single agent for debugging
+// it, sent the way the daemon sends them: to a configured target by name or
+// address with its credentials and timing, or with the defaults to any
+// other address. Bindings come out as `snmpwalk -On` prints them. OIDs are
+// read once the MIBs are loaded, and so may be given by name.
+#[derive(Subcommand)]
+pub enum Command {
+  #[command(about = "Runs the collector and its API")]
+  Serve,
+  #[command(about = "Gets OIDs")]
+  Get {
+    target: String,
+    #[arg(required = true)]
+    oids: Vec<String>,
+  },
+  #[command(about = "Walks a subtree")]
+  Walk {
+    target: String,
+    oid: String,
+  },
+  #[command(about = "Reads a subtree in GetBulk requests")]
+  Bulk {
+    target: String,
+    oid: String,
+    #[arg(long, default_value_t = snmp::MAX_REPETITIONS)]
+    max_repetitions: u32,
+    #[arg(long, help = "Stops after this many bindings rather than at the end of the subtree")]
+    max_bindings: Option<usize>,
+  },
+  #[command(about = "Sets an OID")]
+  Set {
+    target: String,
+    oid: String,
+    #[arg(help = "The value's type as snmpset has it: i, u, c, t, a, o, s or x")]
+    syntax: char,
+    value: String,
+  },
+}
+
+// Runs a command other than `serve`, printing what the agent answered.
+pub async fn run(command: Command, config: &config::Config) -> Result<(), String> {
+  let bindings = match command {
+    Command::Serve => return Ok(()),
+    Command::Get { target, oids } => {
+      let oids = oids.iter().map(|oid| oid.parse()).collect::<Result<Vec<_>, _>>()?;
+      agent(config, &target)?.get(&oids).await
+    },
+    Command::Walk { target, oid } => agent(config, &target)?.walk(&oid.parse()?).await,
+    Command::Bulk { target, oid, max_repetitions, max_bindings } => {
+      agent(config, &target)?.get_bulk(&oid.parse()?, max_repetitions, max_bindings.unwrap_or(usize::MAX)).await
+    },
+    Command::Set { target, oid, syntax, value } => {
+      let value = parse_value(syntax, &value)?;
+      let timestamp = snmp::Timestamp { collected_at: std::time::SystemTime::now(), sys_up_time: None };
+      agent(config, &target)?.set(vec![snmp::VariableBinding { object_id: oid.parse()?, value, timestamp }]).await
+    },
+  };
+  print!("{}", snmpwalk::format(&bindings.map_err(|error| error.to_string())?));
+  Ok(())
+}
+
+fn agent(config: &config::Config, target: &str) -> Result<snmp::SnmpClient, String> {
+  if let Some(target) = config.named(target) {
+    return Ok(target.agent());
+  }
+  Ok(config.agent(target.parse()?))
+}
+
+fn parse_value(syntax: char, text: &str) -> Result<snmp::ObjectValue, String> {
+  let invalid = || format!("invalid value {} for type {}", text, syntax);
+  Ok(match syntax {
+    'i' => snmp::ObjectValue::Integer32(text.parse().map_err(|_| invalid())?),
+    'u' => snmp::ObjectValue::Unsigned32(text.parse().map_err(|_| invalid())?),
+    'c' => snmp::ObjectValue::Counter32(text.parse().map_err(|_| invalid())?),
+    't' => snmp::ObjectValue::TimeTicks(text.parse().map_err(|_| invalid())?),
+    'a' => snmp::ObjectValue::IpAddress(text.parse::<Ipv4Addr>().map_err(|_| invalid())?),
+    'o' => snmp::ObjectValue::ObjectIdentifier(text.parse().map_err(|_| invalid())?),
+    's' => snmp::ObjectValue::OctetString(text.as_bytes().to_vec().into()),
+    // Octets in hex, optionally separated by spaces or colons.
+    'x' => {
+      let digits = text.chars().filter(|char| !matches!(char, ' ' | ':')).collect::<String>();
+      if digits.len() % 2 != 0 {
+        return Err(invalid());
+      }
+      let octets = (0..digits.len()).step_by(2)
+        .map(|at| u8::from_str_radix(digits.get(at..at + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+      snmp::ObjectValue::OctetString(octets.into())
+    },
+    _ => return Err(format!("unknown type {}", syntax)),
+  })
+}
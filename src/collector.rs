@@ -0,0 +1,189 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex}, time::Duration};
+
+use tokio::{sync::{broadcast, Semaphore}, time::Instant};
+
+use crate::{config, pipeline, profile, rate, reload, sink, snmp, source, stats};
+
+// The source the reboot events of a target are handed to sinks under.
+const REBOOT_SOURCE: &str = "reboot";
+
+// The samples of the most recent collection of every source, per target.
+// Every collection recorded is also handed to subscribers as it comes.
+pub struct Latest {
+  samples: Mutex<HashMap<String, HashMap<String, Vec<profile::Sample>>>>,
+  collections: broadcast::Sender<Arc<Collection>>,
+}
+
+// The samples of one collection of a source on a target.
+pub struct Collection {
+  pub target: String,
+  pub source: String,
+  pub samples: Vec<profile::Sample>,
+}
+
+// Collections a subscriber may fall behind by before missing some.
+const SUBSCRIBER_BACKLOG: usize = 1024;
+
+impl Default for Latest {
+
+  fn default() -> Self {
+    Latest { samples: Mutex::default(), collections: broadcast::channel(SUBSCRIBER_BACKLOG).0 }
+  }
+}
+
+impl Latest {
+
+  pub fn record(&self, target: &str, source: &str, samples: Vec<profile::Sample>) {
+    if self.collections.receiver_count() > 0 {
+      let collection = Collection { target: target.to_string(), source: source.to_string(), samples: samples.clone() };
+      let _ = self.collections.send(Arc::new(collection));
+    }
+    self.samples.lock().unwrap()
+      .entry(target.to_string())
+      .or_default()
+      .insert(source.to_string(), samples);
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<Arc<Collection>> {
+    self.collections.subscribe()
+  }
+
+  pub fn samples(&self, target: &str) -> Vec<profile::Sample> {
+    self.samples.lock().unwrap()
+      .get(target)
+      .map(|sources| sources.values().flatten().cloned().collect())
+      .unwrap_or_default()
+  }
+}
+
+// Collects the sources of every target (its profiles and checks) at the
+// configured interval, or a profile's own, and hands the samples, once
+// through the source's pipeline, to the sinks they are routed to and to
+// `latest`. Each target runs on its own so that one slow agent does not hold
+// up the others. When the agent's sysUpTime shows it restarted, a
+// `deviceReboot` sample goes to the sinks as well, through the pipeline of
+// the `reboot` source. A collection runs once one of the `workers` is free.
+// Collection goes on until the schedule returned is dropped.
+pub fn spawn(
+  config: &config::Config,
+  profiles: &[profile::Profile],
+  sources: &source::Registry,
+  sinks: Arc<Vec<sink::Output>>,
+  latest: Arc<Latest>,
+  workers: Arc<Semaphore>,
+) -> Result<reload::Schedule, source::Error> {
+  let schedule = reload::Schedule::default();
+  if (sinks.is_empty() && config.thresholds.is_empty()) || config.collection.interval == 0 {
+    return Ok(schedule);
+  }
+  // Every source is built before any is collected, so that a configuration
+  // with one that cannot be starts nothing.
+  let mut collected = Vec::new();
+  for target in &config.targets {
+    let target_sources = sources.build(target, profiles)?;
+    if !target_sources.is_empty() {
+      collected.push((target, target_sources));
+    }
+  }
+  for (target, target_sources) in collected {
+    let pipelines = target_sources.iter()
+      .map(|source| [source.stages(), config.pipeline(source.name())].concat())
+      .collect::<Vec<_>>();
+    let intervals = target_sources.iter()
+      .map(|source| source.interval().filter(|interval| *interval > 0).unwrap_or(config.collection.interval))
+      .collect::<Vec<_>>();
+    // Ticks come often enough for every source to be collected on time.
+    let tick = intervals.iter().copied().reduce(gcd).unwrap_or(config.collection.interval);
+    let reboot_stages = config.pipeline(REBOOT_SOURCE).to_vec();
+    let (target, sinks, latest, workers) = (target.clone(), sinks.clone(), latest.clone(), workers.clone());
+    let mut stopped = schedule.stopped();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(Duration::from_secs(tick));
+      let mut uptime: Option<snmp::Timestamp> = None;
+      for ticks in 0u64.. {
+        tokio::select! {
+          _ = interval.tick() => {},
+          _ = stopped.changed() => break,
+        }
+        for ((source, stages), seconds) in target_sources.iter().zip(&pipelines).zip(&intervals) {
+          if (ticks * tick) % seconds != 0 {
+            continue;
+          }
+          let period = Duration::from_secs(*seconds);
+          // A collection still waiting for a worker, or unanswered, when the
+          // next one is due is given up.
+          stats::count(&stats::STATS.collections);
+          let deadline = Instant::now() + period;
+          let collection = async {
+            let _worker = workers.acquire().await;
+            // Checks other than SNMP do not observe the deadline, hence both.
+            snmp::within(deadline, source.collect(&target)).await
+          };
+          let samples = match tokio::time::timeout(period, collection).await {
+            Ok(Ok(samples)) => samples,
+            Ok(Err(error)) => {
+              stats::count(&stats::STATS.collection_failures);
+              eprintln!("Cannot collect {} on {}: {}", source.name(), target.name, error);
+              continue;
+            },
+            Err(_elapsed) => {
+              stats::count(&stats::STATS.collection_failures);
+              eprintln!("Cannot collect {} on {}: no response", source.name(), target.name);
+              continue;
+            },
+          };
+          let answered = samples.iter().map(|sample| sample.timestamp).find(|at| at.sys_up_time.is_some());
+          if let Some(answered) = answered {
+            if uptime.is_some_and(|earlier| rate::rebooted(&earlier, &answered)) {
+              eprintln!("Target {} restarted", target.name);
+              let routed = pipeline::run(&reboot_stages, &target, vec![reboot(answered)]);
+              deliver(&sinks, &target, REBOOT_SOURCE, &routed).await;
+            }
+            uptime = Some(answered);
+          }
+          let routed = pipeline::run(stages, &target, samples);
+          latest.record(&target.name, source.name(), routed.iter().map(|routed| routed.sample.clone()).collect());
+          deliver(&sinks, &target, source.name(), &routed).await;
+        }
+      }
+    });
+  }
+  Ok(schedule)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+  match b {
+    0 => a,
+    b => gcd(b, a % b),
+  }
+}
+
+// The event of an agent found restarted; its value is the agent's uptime in
+// seconds when it was noticed.
+fn reboot(timestamp: snmp::Timestamp) -> profile::Sample {
+  profile::Sample {
+    name: "deviceReboot".to_string(),
+    kind: profile::MetricKind::Gauge,
+    labels: BTreeMap::new(),
+    value: f64::from(timestamp.sys_up_time.unwrap_or_default()) / 100.0,
+    timestamp,
+  }
+}
+
+pub async fn deliver(sinks: &[sink::Output], target: &config::TargetConfig, source: &str, routed: &[pipeline::Routed]) {
+  for output in sinks {
+    let samples = routed.iter()
+      .filter(|routed| routed.goes_to(&output.name))
+      .map(|routed| routed.sample.clone())
+      .collect::<Vec<_>>();
+    if samples.is_empty() {
+      continue;
+    }
+    let batch = sink::Batch { target, source, samples: &samples };
+    stats::count(&stats::STATS.sink_writes);
+    if let Err(error) = output.sink.write(&batch).await {
+      stats::count(&stats::STATS.sink_failures);
+      eprintln!("Sink {} failed for {} on {}: {}", output.name, source, target.name, error);
+    }
+  }
+}
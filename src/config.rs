@@ -0,0 +1,709 @@
+use std::{collections::BTreeMap, fmt::Display, fs, net::{IpAddr, SocketAddr, SocketAddrV6}, path::{Path, PathBuf}, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{nagios, pipeline, profile, snmp};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+  // The community of agents without one of their own, or else
+  // `snmp::DEFAULT_COMMUNITY`.
+  #[serde(default)]
+  pub community: Option<String>,
+  // The timing of agents without their own, as for targets.
+  #[serde(default)]
+  pub timeout_ms: Option<u64>,
+  #[serde(default)]
+  pub retries: Option<u32>,
+  #[serde(default)]
+  pub max_outstanding: Option<usize>,
+  // PDUs sent to agents per second at most, whoever asks for them; unset
+  // leaves them unbounded.
+  #[serde(default)]
+  pub max_pdus_per_second: Option<f64>,
+  #[serde(default)]
+  pub targets: Vec<TargetConfig>,
+  #[serde(default)]
+  pub drift: DriftConfig,
+  #[serde(default)]
+  pub collection: CollectionConfig,
+  #[serde(default)]
+  pub jobs: Vec<JobConfig>,
+  #[serde(default)]
+  pub sinks: Vec<SinkConfig>,
+  #[serde(default)]
+  pub pipelines: Vec<PipelineConfig>,
+  // Checks served at `/check/{target}/{name}` from the collected samples.
+  #[serde(default)]
+  pub thresholds: Vec<nagios::Check>,
+  #[serde(default)]
+  pub agentx: AgentxConfig,
+  #[serde(default)]
+  pub proxy: ProxyConfig,
+  #[serde(default)]
+  pub traps: TrapConfig,
+  #[serde(default)]
+  pub http: HttpConfig,
+}
+
+// The HTTP API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+  pub listen: SocketAddr,
+  // Seconds a request may spend waiting on agents; a `Request-Timeout`
+  // header can shorten it.
+  pub timeout: u64,
+  // Milliseconds the answer to a get is given again to the same request
+  // rather than asking the agent; 0 asks every time, though the same get
+  // arriving while one is being asked still waits for its answer.
+  pub cache_ttl_ms: u64,
+  // Seconds walks and discovery scans started in the background are kept
+  // after they end.
+  pub walk_retention: u64,
+  // The fewest seconds between the gets of `/agents/{ip}/live`, whatever the
+  // client asks for.
+  pub min_live_interval: u64,
+  // Bearer tokens requests must carry in `Authorization`; with none the API
+  // is open to anyone who can reach it.
+  pub tokens: Vec<ApiToken>,
+  // HTTPS rather than HTTP, when set.
+  pub tls: Option<ApiTlsConfig>,
+  // Whether the steps of each request, down to the messages exchanged with
+  // agents, are logged under its `X-Request-Id`.
+  pub trace: bool,
+}
+
+// The PEM files of the certificate chain and key the API is served with.
+// With `client_ca_file` clients must present a certificate signed by one of
+// its authorities.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiTlsConfig {
+  pub certificate_file: PathBuf,
+  pub key_file: PathBuf,
+  #[serde(default)]
+  pub client_ca_file: Option<PathBuf>,
+}
+
+// A token and what it may do: `read` everything but SETs and reloads, which
+// need `write`, the default.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiToken {
+  pub token: String,
+  #[serde(default)]
+  pub scope: Scope,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+  Read,
+  #[default]
+  Write,
+}
+
+// An SNMP listener forwarding requests to targets by community; unset
+// `listen` disables it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProxyConfig {
+  #[serde(default)]
+  pub listen: Option<SocketAddr>,
+  #[serde(default)]
+  pub routes: Vec<ProxyRoute>,
+}
+
+// Requests carrying `community` go to the target named `target`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyRoute {
+  pub community: String,
+  pub target: String,
+}
+
+// Where traps are received, usually `0.0.0.0:162`; unset `listen` disables
+// the receiver.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TrapConfig {
+  pub listen: Option<SocketAddr>,
+  // Traps retained for the HTTP API.
+  pub keep: usize,
+  pub webhooks: Vec<WebhookConfig>,
+}
+
+// Traps posted as JSON arrays to `url`, up to `batch_size` at once and at
+// most `linger_ms` after the first of them arrived. A failed post is tried
+// again `retries` times, a second later and then twice as long each time;
+// traps arriving meanwhile wait, and are lost beyond a few hundred.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+  pub url: String,
+  #[serde(default)]
+  pub headers: BTreeMap<String, String>,
+  #[serde(default = "default_batch_size")]
+  pub batch_size: usize,
+  #[serde(default = "default_linger_ms")]
+  pub linger_ms: u64,
+  #[serde(default = "default_retries")]
+  pub retries: u32,
+  // Seconds to wait for each post.
+  #[serde(default = "default_webhook_timeout")]
+  pub timeout: u64,
+}
+
+fn default_batch_size() -> usize {
+  50
+}
+
+fn default_linger_ms() -> u64 {
+  1000
+}
+
+fn default_retries() -> u32 {
+  3
+}
+
+fn default_webhook_timeout() -> u64 {
+  10
+}
+
+// The master agent to register the collector's statistics with, a socket
+// path such as `/var/agentx/master` or `host:port`; unset disables AgentX.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AgentxConfig {
+  pub master: Option<String>,
+  pub subtree: snmp::ObjectIdentifier,
+}
+
+// Scheduled collection of the profiles and checks listed on each target.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CollectionConfig {
+  // Seconds between collections.
+  pub interval: u64,
+  // Collections, of sources and of jobs, running at once.
+  pub workers: usize,
+}
+
+// OIDs polled on schedule apart from profiles: instances fetched and
+// subtrees walked, each numeric value a sample named after its object. The
+// targets are those named, or else those with the given tags, every one
+// with neither. Samples go through the pipeline named after the job.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobConfig {
+  pub name: String,
+  #[serde(default)]
+  pub targets: Vec<String>,
+  #[serde(default)]
+  pub tags: BTreeMap<String, String>,
+  #[serde(default)]
+  pub oids: Vec<snmp::ObjectIdentifier>,
+  #[serde(default)]
+  pub walks: Vec<snmp::ObjectIdentifier>,
+  // Seconds between polls, the collection interval unless given.
+  #[serde(default)]
+  pub interval: Option<u64>,
+}
+
+// An output destination; `kind` selects it from the `sink::Registry` and
+// the remaining keys are its options. Pipelines route samples to sinks by
+// `name`, which defaults to the kind.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SinkConfig {
+  pub kind: String,
+  #[serde(default)]
+  pub name: Option<String>,
+  #[serde(flatten)]
+  pub options: toml::Table,
+}
+
+// Processing stages applied in order to the samples of one source (a
+// profile, or a check by its name such as `tcp:22`) on every target.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineConfig {
+  pub source: String,
+  pub stages: Vec<pipeline::Stage>,
+}
+
+// Configuration-like subtrees snapshotted on every target to track drift.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DriftConfig {
+  // Seconds between snapshots; zero turns snapshotting off.
+  pub interval: u64,
+  pub subtrees: Vec<snmp::ObjectIdentifier>,
+  // Versions retained per target.
+  pub keep: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TargetConfig {
+  pub name: String,
+  pub address: Address,
+  // Subtrees that may be requested through the proxy endpoints. An empty
+  // allowlist permits everything that is not denied.
+  #[serde(default)]
+  pub allow: Vec<snmp::ObjectIdentifier>,
+  #[serde(default)]
+  pub deny: Vec<snmp::ObjectIdentifier>,
+  // Values for profile template variables, a single value or a list.
+  #[serde(default, deserialize_with = "deserialize_variables")]
+  pub variables: profile::Variables,
+  // Free-form grouping such as `site = "ams"`, used to select devices.
+  #[serde(default)]
+  pub tags: BTreeMap<String, String>,
+  // Profiles collected on schedule and handed to the sinks.
+  #[serde(default)]
+  pub profiles: Vec<String>,
+  // Non-SNMP checks collected alongside the profiles.
+  #[serde(default)]
+  pub checks: Vec<CheckConfig>,
+  #[serde(default)]
+  pub community: Option<String>,
+  // The SNMP version of the community, "2c" or, for agents that speak
+  // nothing newer, "1".
+  #[serde(default)]
+  pub version: SnmpVersion,
+  // Milliseconds to wait for an answer before asking again, and how many
+  // times to ask again; the wait doubles each time.
+  #[serde(default)]
+  pub timeout_ms: Option<u64>,
+  #[serde(default)]
+  pub retries: Option<u32>,
+  // Requests that may await the agent's answers at once, for small devices
+  // that drop requests under load; unset leaves them unbounded.
+  #[serde(default)]
+  pub max_outstanding: Option<usize>,
+  // SNMPv3 credentials, used instead of the community when given.
+  #[serde(default)]
+  pub usm: Option<UsmConfig>,
+  // SNMPv3 over TLS, instead of USM or the community when given.
+  #[serde(default)]
+  pub tls: Option<TlsConfig>,
+  // The SNMPv3 context asked, such as a VRF's on routers, and the engine it
+  // belongs to when not the agent's own.
+  #[serde(default)]
+  pub context_name: Option<String>,
+  #[serde(default)]
+  pub context_engine_id: Option<EngineId>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum SnmpVersion {
+  #[serde(rename = "1")]
+  V1,
+  #[default]
+  #[serde(rename = "2c")]
+  V2c,
+}
+
+// An SNMPv3 user. Privacy needs authentication.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UsmConfig {
+  pub user: String,
+  #[serde(default)]
+  pub auth_protocol: Option<snmp::AuthProtocol>,
+  #[serde(default)]
+  pub auth_password: String,
+  #[serde(default)]
+  pub privacy_protocol: Option<snmp::PrivacyProtocol>,
+  #[serde(default)]
+  pub privacy_password: String,
+}
+
+// The PEM files a target is reached over TLS with; the agent's certificate
+// must be signed by one of `ca_file` and name `server_name`, or else the
+// address. Agents listen on port 10161 unless told otherwise, over TCP, or
+// over UDP with DTLS for a `udp` transport.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+  pub ca_file: PathBuf,
+  pub certificate_file: PathBuf,
+  pub key_file: PathBuf,
+  #[serde(default)]
+  pub server_name: Option<String>,
+  #[serde(default = "default_tls_port")]
+  pub port: u16,
+  #[serde(default)]
+  pub transport: snmp::TlsTransport,
+}
+
+fn default_tls_port() -> u16 {
+  10161
+}
+
+// An SNMP engine ID in hex, as in `80001f8880e9630000d61ff449`, with or
+// without colons between the octets.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct EngineId(pub Vec<u8>);
+
+impl FromStr for EngineId {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let invalid = || format!("invalid engine ID {}", text);
+    let digits = text.strip_prefix("0x").unwrap_or(text).replace(':', "");
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+      return Err(invalid());
+    }
+    (0..digits.len()).step_by(2)
+      .map(|at| u8::from_str_radix(&digits[at..at + 2], 16).map_err(|_| invalid()))
+      .collect::<Result<_, _>>()
+      .map(EngineId)
+  }
+}
+
+impl TryFrom<String> for EngineId {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+// An agent address. IPv6 link-local addresses need the zone they are
+// reached through, by interface name or index: `fe80::1%eth0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Address {
+  pub ip: IpAddr,
+  pub scope_id: u32,
+}
+
+impl Address {
+
+  pub fn socket(&self, port: u16) -> SocketAddr {
+    match self.ip {
+      IpAddr::V4(ip) => SocketAddr::new(ip.into(), port),
+      IpAddr::V6(ip) => SocketAddrV6::new(ip, port, 0, self.scope_id).into(),
+    }
+  }
+}
+
+impl From<IpAddr> for Address {
+
+  fn from(ip: IpAddr) -> Self {
+    Address { ip, scope_id: 0 }
+  }
+}
+
+impl FromStr for Address {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let invalid = || format!("invalid address {}", text);
+    let Some((ip, zone)) = text.split_once('%') else {
+      return text.parse::<IpAddr>().map(Address::from).map_err(|_| invalid());
+    };
+    let ip = ip.parse::<std::net::Ipv6Addr>().map_err(|_| invalid())?;
+    let scope_id = match zone.parse::<u32>() {
+      Ok(index) => index,
+      Err(_) => interface_index(zone).ok_or_else(|| format!("unknown interface {}", zone))?,
+    };
+    Ok(Address { ip: ip.into(), scope_id })
+  }
+}
+
+impl TryFrom<String> for Address {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+impl Display for Address {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.scope_id {
+      0 => write!(f, "{}", self.ip),
+      scope_id => write!(f, "{}%{}", self.ip, scope_id),
+    }
+  }
+}
+
+fn interface_index(name: &str) -> Option<u32> {
+  if name.contains('/') {
+    return None;
+  }
+  fs::read_to_string(Path::new("/sys/class/net").join(name).join("ifindex"))
+    .ok()?
+    .trim()
+    .parse()
+    .ok()
+}
+
+// A check run against the target; `kind` selects it from the
+// `source::Registry` and the remaining keys are its options.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CheckConfig {
+  pub kind: String,
+  #[serde(flatten)]
+  pub options: toml::Table,
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Parse(toml::de::Error),
+  Yaml(serde_yaml::Error),
+  Invalid(String),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(error) => write!(f, "cannot read configuration: {}", error),
+      Error::Parse(error) => write!(f, "invalid configuration: {}", error),
+      Error::Yaml(error) => write!(f, "invalid configuration: {}", error),
+      Error::Invalid(error) => write!(f, "invalid configuration: {}", error),
+    }
+  }
+}
+
+impl Default for DriftConfig {
+
+  fn default() -> Self {
+    DriftConfig {
+      interval: 3600,
+      subtrees: [
+        "1.3.6.1.2.1.1.4", // sysContact
+        "1.3.6.1.2.1.1.6", // sysLocation
+        "1.3.6.1.2.1.31.1.1.1.18", // ifAlias
+        "1.3.6.1.4.1.9.9.46.1.3.1.1.4", // vtpVlanName
+      ]
+        .map(|oid| oid.parse().expect("drift OIDs are valid"))
+        .to_vec(),
+      keep: 50,
+    }
+  }
+}
+
+impl Default for TrapConfig {
+
+  fn default() -> Self {
+    TrapConfig { listen: None, keep: 1000, webhooks: Vec::new() }
+  }
+}
+
+impl Default for AgentxConfig {
+
+  fn default() -> Self {
+    AgentxConfig {
+      master: None,
+      // netSnmpPlaypen; sites with an enterprise number should use their own.
+      subtree: "1.3.6.1.4.1.8072.9999.9999.1".parse().expect("the default subtree is valid"),
+    }
+  }
+}
+
+impl Default for CollectionConfig {
+
+  fn default() -> Self {
+    CollectionConfig { interval: 60, workers: 16 }
+  }
+}
+
+impl Default for HttpConfig {
+
+  fn default() -> Self {
+    HttpConfig { listen: ([127, 0, 0, 1], 8080).into(), timeout: 30, cache_ttl_ms: 0, walk_retention: 600, min_live_interval: 5, tokens: vec![], tls: None, trace: false }
+  }
+}
+
+impl Config {
+
+  // The target at an address; one asked without a zone matches whatever
+  // zone the target is configured with.
+  pub fn target(&self, address: &Address) -> Option<&TargetConfig> {
+    self.targets.iter()
+      .find(|target| target.address.ip == address.ip && (address.scope_id == 0 || target.address.scope_id == address.scope_id))
+  }
+
+  // How to reach an agent: as the configured target, or with the defaults.
+  pub fn agent(&self, address: Address) -> snmp::SnmpClient {
+    self.target(&address).map_or_else(|| self.unconfigured(address).agent(), TargetConfig::agent)
+  }
+
+  // A target for an agent not configured, with the default community.
+  pub fn unconfigured(&self, address: Address) -> TargetConfig {
+    TargetConfig {
+      name: address.to_string(),
+      address,
+      allow: Vec::new(),
+      deny: Vec::new(),
+      variables: profile::Variables::default(),
+      tags: BTreeMap::new(),
+      profiles: Vec::new(),
+      checks: Vec::new(),
+      community: self.community.clone(),
+      version: SnmpVersion::default(),
+      timeout_ms: self.timeout_ms,
+      retries: self.retries,
+      max_outstanding: self.max_outstanding,
+      usm: None,
+      tls: None,
+      context_name: None,
+      context_engine_id: None,
+    }
+  }
+
+  pub fn named(&self, name: &str) -> Option<&TargetConfig> {
+    self.targets.iter().find(|target| target.name == name)
+  }
+
+  // The targets a job polls.
+  pub fn job_targets<'a>(&'a self, job: &'a JobConfig) -> impl Iterator<Item = &'a TargetConfig> + 'a {
+    self.targets.iter()
+      .filter(|target| match job.targets.is_empty() {
+        true => job.tags.iter().all(|(tag, value)| target.tags.get(tag) == Some(value)),
+        false => job.targets.contains(&target.name),
+      })
+  }
+
+  pub fn pipeline(&self, source: &str) -> &[pipeline::Stage] {
+    self.pipelines.iter()
+      .find(|pipeline| pipeline.source == source)
+      .map_or(&[], |pipeline| &pipeline.stages)
+  }
+}
+
+impl TargetConfig {
+
+  pub fn agent(&self) -> snmp::SnmpClient {
+    let builder = snmp::SnmpClient::builder().community(self.community.as_deref().unwrap_or(snmp::DEFAULT_COMMUNITY).as_bytes().to_vec());
+    let builder = match (&self.usm, self.version) {
+      (Some(usm), _) => {
+        let builder = builder.version(snmp::Version::V3).user(usm.user.clone().into_bytes());
+        let builder = match usm.auth_protocol {
+          Some(protocol) => builder.auth(protocol, usm.auth_password.clone().into_bytes()),
+          None => builder,
+        };
+        match usm.privacy_protocol {
+          Some(protocol) => builder.privacy(protocol, usm.privacy_password.clone().into_bytes()),
+          None => builder,
+        }
+      },
+      (None, SnmpVersion::V1) => builder.version(snmp::Version::V1),
+      (None, SnmpVersion::V2c) => builder,
+    };
+    let builder = match self.timeout_ms {
+      Some(timeout) => builder.timeout(Duration::from_millis(timeout)),
+      None => builder,
+    };
+    let builder = match self.retries {
+      Some(retries) => builder.retries(retries),
+      None => builder,
+    };
+    let builder = match self.max_outstanding {
+      Some(limit) => builder.outstanding(limit),
+      None => builder,
+    };
+    let builder = match &self.context_name {
+      Some(context) => builder.context(context.clone().into_bytes()),
+      None => builder,
+    };
+    let builder = match &self.context_engine_id {
+      Some(EngineId(engine_id)) => builder.context_engine(engine_id.clone()),
+      None => builder,
+    };
+    match &self.tls {
+      Some(tls) => builder
+        .tls(snmp::Certificates {
+          authorities: tls.ca_file.clone(),
+          certificate: tls.certificate_file.clone(),
+          key: tls.key_file.clone(),
+          server_name: tls.server_name.clone(),
+          transport: tls.transport,
+        })
+        .build(self.address.socket(tls.port)),
+      None => builder.build(self.address.socket(161)),
+    }
+  }
+
+  pub fn permits(&self, oid: &snmp::ObjectIdentifier) -> bool {
+    (self.allow.is_empty() || self.allow.iter().any(|allowed| oid.starts_with(allowed)))
+      && !self.deny.iter().any(|denied| oid.starts_with(denied))
+  }
+
+  // A walk may start above an allowed subtree (its results are filtered with
+  // `permits`), but never inside a denied one.
+  pub fn may_traverse(&self, root: &snmp::ObjectIdentifier) -> bool {
+    self.permits(root)
+      || (!self.deny.iter().any(|denied| root.starts_with(denied))
+        && self.allow.iter().any(|allowed| allowed.starts_with(root)))
+  }
+}
+
+fn deserialize_variables<'de, D>(deserializer: D) -> Result<profile::Variables, D::Error>
+  where D: serde::Deserializer<'de>
+{
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Values {
+    One(String),
+    Many(Vec<String>),
+  }
+
+  Ok(
+    BTreeMap::<String, Values>::deserialize(deserializer)?
+      .into_iter()
+      .map(|(name, values)| match values {
+        Values::One(value) => (name, vec![value]),
+        Values::Many(values) => (name, values),
+      })
+      .collect()
+  )
+}
+
+// Reads a TOML file, or a YAML one of the same structure when it is named
+// `.yaml` or `.yml`.
+pub fn load(path: &Path) -> Result<Config, Error> {
+  let text = fs::read_to_string(path).map_err(Error::Io)?;
+  let mut config = match path.extension().and_then(|extension| extension.to_str()) {
+    Some("yaml" | "yml") => serde_yaml::from_str::<Config>(&text).map_err(Error::Yaml)?,
+    _ => toml::from_str::<Config>(&text).map_err(Error::Parse)?,
+  };
+  for target in &mut config.targets {
+    if target.community.is_none() {
+      target.community = config.community.clone();
+    }
+    if target.timeout_ms.is_none() {
+      target.timeout_ms = config.timeout_ms;
+    }
+    if target.retries.is_none() {
+      target.retries = config.retries;
+    }
+    if target.max_outstanding.is_none() {
+      target.max_outstanding = config.max_outstanding;
+    }
+  }
+  if config.max_outstanding == Some(0) || config.targets.iter().any(|target| target.max_outstanding == Some(0)) {
+    return Err(Error::Invalid("max_outstanding must be at least 1".to_string()));
+  }
+  if config.max_pdus_per_second.is_some_and(|per_second| !(per_second > 0.0 && per_second.is_finite())) {
+    return Err(Error::Invalid("max_pdus_per_second must be positive".to_string()));
+  }
+  if config.http.tokens.iter().any(|token| token.token.is_empty()) {
+    return Err(Error::Invalid("API tokens must not be empty".to_string()));
+  }
+  if cfg!(not(feature = "tls")) && config.http.tls.is_some() {
+    return Err(Error::Invalid("HTTPS support is not compiled in".to_string()));
+  }
+  for target in &config.targets {
+    if target.usm.as_ref().is_some_and(|usm| usm.privacy_protocol.is_some() && usm.auth_protocol.is_none()) {
+      return Err(Error::Invalid(format!("target {} has privacy without authentication", target.name)));
+    }
+    if target.usm.is_some() && target.tls.is_some() {
+      return Err(Error::Invalid(format!("target {} has both USM and TLS", target.name)));
+    }
+  }
+  for job in &config.jobs {
+    if let Some(unknown) = job.targets.iter().find(|name| config.named(name).is_none()) {
+      return Err(Error::Invalid(format!("job {} polls unknown target {}", job.name, unknown)));
+    }
+  }
+  Ok(config)
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::snmp;
+
+const SYS_DESCR: &str = "1.3.6.1.2.1.1.1.0";
+const SYS_OBJECT_ID: &str = "1.3.6.1.2.1.1.2.0";
+const SYS_NAME: &str = "1.3.6.1.2.1.1.5.0";
+// What identifies an agent: its sysDescr, sysObjectID and sysName.
+pub const SYSTEM: [&str; 3] = [SYS_DESCR, SYS_OBJECT_ID, SYS_NAME];
+const SYS_OR_ID: &str = "1.3.6.1.2.1.1.9.1.2";
+const SYS_OR_DESCR: &str = "1.3.6.1.2.1.1.9.1.3";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+  pub sys_descr: Option<String>,
+  pub sys_object_id: Option<snmp::ObjectIdentifier>,
+  pub sys_name: Option<String>,
+  pub capabilities: Vec<Capability>,
+}
+
+// One sysORTable entry: a MIB module (or compliance statement) the agent
+// claims to implement.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+  pub id: snmp::ObjectIdentifier,
+  pub description: Option<String>,
+}
+
+impl DeviceInfo {
+
+  // Agents without a sysORTable tell us nothing, so only a populated table
+  // is taken as proof that a module is missing.
+  pub fn implements(&self, module: &snmp::ObjectIdentifier) -> bool {
+    self.capabilities.is_empty()
+      || self.capabilities.iter().any(|capability| capability.id.starts_with(module))
+  }
+}
+
+pub async fn probe(target: &snmp::SnmpClient) -> snmp::Result<DeviceInfo> {
+  let system = identify(target).await?;
+  let or_id = SYS_OR_ID.parse().expect("sysORTable OIDs are valid");
+  let or_descr = SYS_OR_DESCR.parse().expect("sysORTable OIDs are valid");
+  let descriptions = target.walk(&or_descr).await?;
+  let capabilities = target.walk(&or_id).await?
+    .into_iter()
+    .filter_map(|binding| {
+      let snmp::ObjectValue::ObjectIdentifier(id) = binding.value else {
+        return None;
+      };
+      let index = binding.object_id.strip_prefix(&or_id)?;
+      let description = descriptions.iter()
+        .find(|description| description.object_id.strip_prefix(&or_descr) == Some(index))
+        .map(|description| description.value.to_string());
+      Some(Capability { id, description })
+    })
+    .collect();
+  Ok(DeviceInfo { capabilities, ..system })
+}
+
+// The system group alone, in one get, as discovery asks every address of a
+// range; the capabilities are left empty.
+pub async fn identify(target: &snmp::SnmpClient) -> snmp::Result<DeviceInfo> {
+  let oids = SYSTEM
+    .map(|oid| oid.parse::<snmp::ObjectIdentifier>().expect("system group OIDs are valid"));
+  let system = target.get(&oids).await?;
+  let value = |oid: &snmp::ObjectIdentifier| system.iter()
+    .find(|binding| binding.object_id == *oid)
+    .map(|binding| &binding.value)
+    .filter(|value| !value.is_exception());
+  Ok(DeviceInfo {
+    sys_descr: value(&oids[0]).map(|value| value.to_string()),
+    sys_object_id: match value(&oids[1]) {
+      Some(snmp::ObjectValue::ObjectIdentifier(id)) => Some(id.clone()),
+      _ => None,
+    },
+    sys_name: value(&oids[2]).map(|value| value.to_string()),
+    capabilities: Vec::new(),
+  })
+}
+
+// Device information learned on first contact with each agent, or found
+// by discovery. Agents only discovered are probed in full when first asked
+// for.
+#[derive(Default)]
+pub struct Inventory {
+  devices: Mutex<HashMap<SocketAddr, Known>>,
+}
+
+struct Known {
+  info: DeviceInfo,
+  probed: bool,
+}
+
+impl Inventory {
+
+  pub async fn get(&self, target: &snmp::SnmpClient) -> snmp::Result<DeviceInfo> {
+    let address = *target.address();
+    if let Some(known) = self.devices.lock().unwrap().get(&address).filter(|known| known.probed) {
+      return Ok(known.info.clone());
+    }
+    let info = probe(target).await?;
+    self.devices.lock().unwrap().insert(address, Known { info: info.clone(), probed: true });
+    Ok(info)
+  }
+
+  // Records the system group of an agent found answering, keeping the
+  // capabilities of one already probed.
+  pub fn record(&self, address: SocketAddr, system: DeviceInfo) {
+    let mut devices = self.devices.lock().unwrap();
+    match devices.get_mut(&address) {
+      Some(known) => known.info = DeviceInfo { capabilities: std::mem::take(&mut known.info.capabilities), ..system },
+      None => {
+        devices.insert(address, Known { info: system, probed: false });
+      },
+    }
+  }
+
+  // Every agent known, by address.
+  pub fn devices(&self) -> Vec<(SocketAddr, DeviceInfo)> {
+    let mut devices = self.devices.lock().unwrap().iter()
+      .map(|(address, known)| (*address, known.info.clone()))
+      .collect::<Vec<_>>();
+    devices.sort_by_key(|(address, _)| *address);
+    devices
+  }
+}
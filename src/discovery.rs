@@ -0,0 +1,245 @@
+use std::{collections::BTreeMap, fmt::Display, net::{IpAddr, Ipv4Addr, Ipv6Addr}, str::FromStr, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::{task::AbortHandle, time::Instant};
+
+use crate::{config, device, snmp};
+
+// The most addresses one scan sweeps, a /16 of IPv4.
+const MAX_HOSTS: u128 = 65536;
+// Addresses probed at once; the rate limit of PDUs applies all the same.
+const AT_ONCE: usize = 64;
+
+// A range of addresses in CIDR notation, `10.0.0.0/24`, or one address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Network {
+  pub ip: IpAddr,
+  pub prefix: u8,
+}
+
+impl Network {
+
+  // The bits of addresses that tell hosts apart, no more than 16.
+  fn host_bits(&self) -> u32 {
+    match self.ip {
+      IpAddr::V4(_) => u32::from(32 - self.prefix),
+      IpAddr::V6(_) => u32::from(128 - self.prefix),
+    }
+  }
+
+  // The addresses of hosts in the range, leaving out the network and
+  // broadcast addresses of IPv4 ranges that have them.
+  pub fn hosts(&self) -> impl Iterator<Item = IpAddr> + Send {
+    let host_bits = self.host_bits();
+    let (first, v4) = match self.ip {
+      IpAddr::V4(ip) => ((u32::from(ip) >> host_bits << host_bits) as u128, true),
+      IpAddr::V6(ip) => (u128::from(ip) >> host_bits << host_bits, false),
+    };
+    let span = 1u128 << host_bits;
+    let (skip, take) = match v4 && host_bits >= 2 {
+      true => (1, span - 2),
+      false => (0, span),
+    };
+    (first + skip..first + skip + take).map(move |ip| match v4 {
+      true => IpAddr::V4(Ipv4Addr::from(ip as u32)),
+      false => IpAddr::V6(Ipv6Addr::from(ip)),
+    })
+  }
+
+  pub fn size(&self) -> usize {
+    match (self.ip, self.host_bits()) {
+      (IpAddr::V4(_), host_bits @ 2..) => (1 << host_bits) - 2,
+      (_, host_bits) => 1 << host_bits,
+    }
+  }
+}
+
+impl FromStr for Network {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let invalid = || format!("invalid network {}", text);
+    let (ip, prefix) = match text.split_once('/') {
+      Some((ip, prefix)) => (ip, Some(prefix)),
+      None => (text, None),
+    };
+    let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+      Some(prefix) => prefix.parse::<u8>().ok().filter(|prefix| *prefix <= bits).ok_or_else(invalid)?,
+      None => bits,
+    };
+    if 1u128.checked_shl(u32::from(bits - prefix)).is_none_or(|span| span > MAX_HOSTS) {
+      return Err(format!("network {} has more than {} addresses", text, MAX_HOSTS));
+    }
+    Ok(Network { ip, prefix })
+  }
+}
+
+impl TryFrom<String> for Network {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+impl Display for Network {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}/{}", self.ip, self.prefix)
+  }
+}
+
+// Scans run in the background, sweeping a range for agents with a get of
+// their system group. Agents answering are recorded in the inventory. Each
+// scan is looked up by its ID while it runs, and for `retention` after it
+// ends.
+#[derive(Default)]
+pub struct Scans {
+  scans: Mutex<Scanned>,
+}
+
+#[derive(Default)]
+struct Scanned {
+  next_id: u64,
+  scans: BTreeMap<u64, Scan>,
+}
+
+struct Scan {
+  job: Job,
+  ended: Option<Instant>,
+  abort: Option<AbortHandle>,
+}
+
+// Where a scan stands: `probed` of the `total` addresses have answered or
+// given up, and `found` are those that answered, in the order they did.
+#[derive(Clone)]
+pub struct Job {
+  pub id: u64,
+  pub network: Network,
+  pub started_at: u64,
+  pub ended_at: Option<u64>,
+  pub probed: usize,
+  pub total: usize,
+  pub found: Vec<Found>,
+  pub outcome: Outcome,
+}
+
+#[derive(Clone)]
+pub struct Found {
+  pub address: config::Address,
+  pub info: device::DeviceInfo,
+}
+
+#[derive(Clone, Copy)]
+pub enum Outcome {
+  Running,
+  Done,
+  Cancelled,
+}
+
+impl Scans {
+
+  // Starts sweeping `network`, returning the scan's ID. `agent` gives the
+  // agent at each address as it is to be asked, or None for addresses not to
+  // be probed.
+  pub fn start(
+    self: &Arc<Self>,
+    network: Network,
+    agent: impl Fn(config::Address) -> Option<snmp::SnmpClient> + Send + 'static,
+    inventory: Arc<device::Inventory>,
+    retention: Duration,
+  ) -> u64 {
+    let mut scans = self.scans.lock().unwrap();
+    scans.scans.retain(|_, scan| scan.ended.is_none_or(|ended| ended.elapsed() < retention));
+    scans.next_id += 1;
+    let id = scans.next_id;
+    let job = Job {
+      id,
+      network,
+      started_at: now(),
+      ended_at: None,
+      probed: 0,
+      total: network.size(),
+      found: Vec::new(),
+      outcome: Outcome::Running,
+    };
+    let this = self.clone();
+    let task = tokio::spawn(async move {
+      let probes = futures_util::stream::iter(network.hosts())
+        .map(move |ip| {
+          let address = config::Address::from(ip);
+          let agent = agent(address);
+          async move {
+            let info = match agent {
+              Some(agent) => device::identify(&agent).await.ok().map(|info| (*agent.address(), info)),
+              None => None,
+            };
+            (address, info)
+          }
+        })
+        .buffer_unordered(AT_ONCE);
+      probes.for_each(|(address, info)| {
+        if let Some((socket, info)) = &info {
+          inventory.record(*socket, info.clone());
+        }
+        this.update(id, |job| {
+          job.probed += 1;
+          if let Some((_, info)) = info {
+            job.found.push(Found { address, info });
+          }
+        });
+        std::future::ready(())
+      })
+      .await;
+      this.end(id, Outcome::Done);
+    });
+    scans.scans.insert(id, Scan { job, ended: None, abort: Some(task.abort_handle()) });
+    id
+  }
+
+  pub fn get(&self, id: u64) -> Option<Job> {
+    self.scans.lock().unwrap().scans.get(&id).map(|scan| scan.job.clone())
+  }
+
+  // Stops a running scan, which is then kept as cancelled with what it found
+  // so far, or forgets one that ended. False if there is no such scan.
+  pub fn cancel(&self, id: u64) -> bool {
+    let mut scans = self.scans.lock().unwrap();
+    let Some(scan) = scans.scans.get_mut(&id) else {
+      return false;
+    };
+    if scan.ended.is_some() {
+      scans.scans.remove(&id);
+      return true;
+    }
+    if let Some(abort) = scan.abort.take() {
+      abort.abort();
+    }
+    drop(scans);
+    self.end(id, Outcome::Cancelled);
+    true
+  }
+
+  fn update(&self, id: u64, change: impl FnOnce(&mut Job)) {
+    if let Some(scan) = self.scans.lock().unwrap().scans.get_mut(&id).filter(|scan| scan.ended.is_none()) {
+      change(&mut scan.job);
+    }
+  }
+
+  fn end(&self, id: u64, outcome: Outcome) {
+    if let Some(scan) = self.scans.lock().unwrap().scans.get_mut(&id).filter(|scan| scan.ended.is_none()) {
+      scan.job.outcome = outcome;
+      scan.job.ended_at = Some(now());
+      scan.ended = Some(Instant::now());
+      scan.abort = None;
+    }
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
@@ -0,0 +1,147 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap, VecDeque}, sync::{Arc, Mutex}, time::{Duration, SystemTime}};
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{config, reload, snmp};
+
+// The values of a target's configuration-like OIDs at one point in time.
+// A new version is only recorded when something changed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+  pub version: u32,
+  pub taken_at: u64,
+  pub values: BTreeMap<snmp::ObjectIdentifier, String>,
+}
+
+// How one snapshot differs from the one before it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+  pub version: u32,
+  pub taken_at: u64,
+  pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+  pub oid: snmp::ObjectIdentifier,
+  pub before: Option<String>,
+  pub after: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Store {
+  snapshots: Mutex<HashMap<String, VecDeque<Snapshot>>>,
+}
+
+impl Store {
+
+  fn record(&self, target: &str, values: BTreeMap<snmp::ObjectIdentifier, String>, keep: usize) {
+    let mut snapshots = self.snapshots.lock().unwrap();
+    let history = snapshots.entry(target.to_string()).or_default();
+    if history.back().is_some_and(|latest| latest.values == values) {
+      return;
+    }
+    let version = history.back().map_or(1, |latest| latest.version + 1);
+    history.push_back(Snapshot { version, taken_at: now(), values });
+    while history.len() > keep.max(1) {
+      history.pop_front();
+    }
+  }
+
+  pub fn versions(&self, target: &str) -> Vec<Snapshot> {
+    self.snapshots.lock().unwrap()
+      .get(target)
+      .map(|history| history.iter().cloned().collect())
+      .unwrap_or_default()
+  }
+
+  pub fn snapshot(&self, target: &str, version: u32) -> Option<Snapshot> {
+    self.snapshots.lock().unwrap()
+      .get(target)?
+      .iter()
+      .find(|snapshot| snapshot.version == version)
+      .cloned()
+  }
+
+  // Changes between consecutive retained snapshots, oldest first. The first
+  // snapshot has nothing to compare against and is left out.
+  pub fn history(&self, target: &str) -> Vec<Revision> {
+    let versions = self.versions(target);
+    versions.windows(2)
+      .map(|pair| Revision {
+        version: pair[1].version,
+        taken_at: pair[1].taken_at,
+        changes: diff(&pair[0].values, &pair[1].values),
+      })
+      .collect()
+  }
+}
+
+fn diff(
+  before: &BTreeMap<snmp::ObjectIdentifier, String>,
+  after: &BTreeMap<snmp::ObjectIdentifier, String>,
+) -> Vec<Change> {
+  before.keys().chain(after.keys())
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .filter(|oid| before.get(*oid) != after.get(*oid))
+    .map(|oid| Change {
+      oid: oid.clone(),
+      before: before.get(oid).cloned(),
+      after: after.get(oid).cloned(),
+    })
+    .collect()
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or_default()
+}
+
+async fn take(
+  target: &snmp::SnmpClient,
+  subtrees: &[snmp::ObjectIdentifier],
+) -> snmp::Result<BTreeMap<snmp::ObjectIdentifier, String>> {
+  let mut values = BTreeMap::new();
+  for subtree in subtrees {
+    for binding in target.walk(subtree).await? {
+      values.insert(binding.object_id, binding.value.to_string());
+    }
+  }
+  Ok(values)
+}
+
+// Snapshots every configured target on its own schedule, so that one slow
+// agent does not hold up the others, until the schedule returned is dropped.
+pub fn spawn(config: &config::Config, store: Arc<Store>) -> reload::Schedule {
+  let schedule = reload::Schedule::default();
+  let drift = config.drift.clone();
+  if drift.subtrees.is_empty() || drift.interval == 0 {
+    return schedule;
+  }
+  for target in &config.targets {
+    let (name, target) = (target.name.clone(), target.agent());
+    let (drift, store) = (drift.clone(), store.clone());
+    let mut stopped = schedule.stopped();
+    tokio::spawn(async move {
+      let period = Duration::from_secs(drift.interval);
+      let mut interval = tokio::time::interval(period);
+      loop {
+        tokio::select! {
+          _ = interval.tick() => {},
+          _ = stopped.changed() => break,
+        }
+        // A snapshot still unanswered when the next one is due is given up.
+        match snmp::within(Instant::now() + period, take(&target, &drift.subtrees)).await {
+          Ok(values) => store.record(&name, values, drift.keep),
+          Err(error) => eprintln!("Cannot snapshot {}: {}", name, error),
+        }
+      }
+    });
+  }
+  schedule
+}
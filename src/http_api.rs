@@ -1,47 +1,1161 @@
-use std::{net::{IpAddr, SocketAddr}, collections::HashMap};
+use std::{convert::Infallible, collections::{BTreeMap, HashMap}, fmt::Display, hash::{Hash, Hasher}, str::FromStr, sync::Arc, time::Duration};
+
+use base64::Engine;
+use futures_util::StreamExt;
+use hyper::service::Service;
+use num_traits::ToPrimitive;
 
 use serde::{de, Deserialize, Serialize, ser::SerializeStruct};
-use warp::Filter;
+use tokio::time::Instant;
+use warp::{Filter, Reply};
+
+use crate::{aggregate, collector, config, device, discovery, drift, interface, nagios, profile, prometheus, https, rate, reload, responses, snmp, snmpwalk, stats, stream, trace, trap, walks};
 
-use crate::snmp;
+struct State {
+  reloader: Arc<reload::Reloader>,
+  profiles: Vec<profile::Profile>,
+  devices: Arc<device::Inventory>,
+  rates: rate::Rates,
+  statics: profile::Cache,
+  responses: responses::Responses,
+  walks: Arc<walks::Walks>,
+  scans: Arc<discovery::Scans>,
+  snapshots: Arc<drift::Store>,
+  latest: Arc<collector::Latest>,
+  traps: Arc<trap::Store>,
+}
 
-pub async fn serve() {
+pub async fn serve(
+  reloader: Arc<reload::Reloader>,
+  profiles: Vec<profile::Profile>,
+  snapshots: Arc<drift::Store>,
+  latest: Arc<collector::Latest>,
+  traps: Arc<trap::Store>,
+) {
+  let state = Arc::new(State {
+    reloader,
+    profiles,
+    devices: Arc::new(device::Inventory::default()),
+    rates: rate::Rates::default(),
+    statics: profile::Cache::default(),
+    responses: responses::Responses::default(),
+    walks: Arc::new(walks::Walks::default()),
+    scans: Arc::new(discovery::Scans::default()),
+    snapshots,
+    latest,
+    traps,
+  });
+  let reloader = state.reloader.clone();
+  let (listen, tls) = (reloader.config().http.listen, reloader.config().http.tls.clone());
+  let state = warp::any().map(move || state.clone());
+  let if_none_match = warp::header::optional::<String>("if-none-match");
+  let scope = warp::header::optional::<String>("authorization")
+    .and(state.clone())
+    .and_then(authenticate);
   let agent = warp::path("agents")
-    .and(warp::path::param::<IpAddr>());
+    .and(warp::path::param::<AgentAddress>())
+    .map(|AgentAddress(address)| address);
+  let device_info = agent.and(warp::path::end())
+    .and(warp::get())
+    .and(if_none_match)
+    .and(state.clone())
+    .and_then(handle_device_request);
   let snmp_request = agent.and(warp::path("request"))
     .and(warp::post())
-    .and(warp::body::json::<SnmpRequest>())
+    .and(warp::query::<ResponseOptions>())
+    .and(warp::query::<Page>())
+    .and(warp::body::json::<RequestBody>())
+    .and(scope.clone())
+    .and(state.clone())
     .and_then(handle_snmp_request);
-  let routes = snmp_request;
-  warp::serve(routes).run(([127, 0, 0, 1], 8080)).await
+  let fan_out = warp::path!("request")
+    .and(warp::post())
+    .and(warp::query::<ResponseOptions>())
+    .and(warp::body::json::<FanOutBody>())
+    .and(state.clone())
+    .and_then(handle_fan_out_request);
+  let live = agent.and(warp::path("live"))
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(warp::query::<LiveQuery>())
+    .and(state.clone())
+    .and_then(handle_live_request);
+  let walk_start = agent.and(warp::path("jobs"))
+    .and(warp::path::end())
+    .and(warp::post())
+    .and(warp::body::json::<WalkBody>())
+    .and(state.clone())
+    .and_then(handle_walk_start_request);
+  let walk = warp::path!("jobs" / u64)
+    .and(warp::get())
+    .and(warp::query::<ResponseOptions>())
+    .and(state.clone())
+    .and_then(handle_walk_request);
+  let walk_cancel = warp::path!("jobs" / u64)
+    .and(warp::delete())
+    .and(state.clone())
+    .and_then(handle_walk_cancel_request);
+  let scan_start = warp::path!("discovery" / "scan")
+    .and(warp::post())
+    .and(warp::body::json::<ScanBody>())
+    .and(scope.clone())
+    .and(state.clone())
+    .and_then(handle_scan_start_request);
+  let scan = warp::path!("discovery" / "scans" / u64)
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_scan_request);
+  let scan_cancel = warp::path!("discovery" / "scans" / u64)
+    .and(warp::delete())
+    .and(state.clone())
+    .map(|id, state: Arc<State>| match state.scans.cancel(id) {
+      true => warp::http::StatusCode::NO_CONTENT,
+      false => warp::http::StatusCode::NOT_FOUND,
+    });
+  let devices = warp::path!("devices")
+    .and(warp::get())
+    .and(state.clone())
+    .map(|state: Arc<State>| warp::reply::json(&state.devices.devices().into_iter()
+      .map(|(address, info)| KnownDevice { address: address.ip().to_string(), info })
+      .collect::<Vec<_>>()));
+  let profile_request = agent.and(warp::path("profiles"))
+    .and(warp::path::param::<String>())
+    .and(warp::get())
+    .and(if_none_match)
+    .and(state.clone())
+    .and_then(handle_profile_request);
+  let agent_profiles = agent.and(warp::path("profiles"))
+    .and(warp::path::end())
+    .and(warp::get())
+    .and(if_none_match)
+    .and(state.clone())
+    .and_then(handle_agent_profiles_request);
+  let interfaces = warp::path!("targets" / String / "interfaces")
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_interfaces_request);
+  let interface_inventory = warp::path!("targets" / String / "interfaces" / "inventory")
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_interface_inventory_request);
+  let snapshot_list = warp::path!("targets" / String / "snapshots")
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_snapshots_request);
+  let snapshot = warp::path!("targets" / String / "snapshots" / u32)
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_snapshot_request);
+  let drift_history = warp::path!("targets" / String / "drift")
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_drift_request);
+  let traps = warp::path!("traps")
+    .and(warp::get())
+    .and(state.clone())
+    .map(|state: Arc<State>| warp::reply::json(&state.traps.recent()));
+  let aggregation = warp::path!("aggregate" / String / String)
+    .and(warp::get())
+    .and(warp::query::<AggregateQuery>())
+    .and(state.clone())
+    .and_then(handle_aggregate_request);
+  let check = warp::path!("check" / String / String)
+    .and(warp::get())
+    .and(state.clone())
+    .and_then(handle_check_request);
+  let streaming = warp::path!("stream")
+    .and(warp::ws())
+    .and(state.clone())
+    .map(|ws: warp::ws::Ws, state: Arc<State>| ws.on_upgrade(move |socket| stream::session(socket, state.latest.clone())));
+  let metrics = warp::path!("metrics")
+    .and(warp::get())
+    .and(state.clone())
+    .map(|state: Arc<State>| {
+      let config = state.reloader.config();
+      let text = prometheus::exposition(config.targets.iter().map(|target| (target, state.latest.samples(&target.name))));
+      warp::reply::with_header(text, "content-type", "text/plain; version=0.0.4")
+    });
+  let internal_metrics = warp::path!("internal" / "metrics")
+    .and(warp::get())
+    .map(|| warp::reply::with_header(prometheus::internal(), "content-type", "text/plain; version=0.0.4"));
+  let reload = warp::path!("admin" / "reload")
+    .and(warp::post())
+    .and(scope.clone())
+    .and(state.clone())
+    .map(|scope: config::Scope, state: Arc<State>| match scope {
+      config::Scope::Read => warp::reply::with_status("Token may not reload\n".to_string(), warp::http::StatusCode::FORBIDDEN),
+      config::Scope::Write => match state.reloader.reload() {
+        Ok(()) => warp::reply::with_status("Configuration reloaded\n".to_string(), warp::http::StatusCode::OK),
+        Err(error) => warp::reply::with_status(format!("{}\n", error), warp::http::StatusCode::INTERNAL_SERVER_ERROR),
+      },
+    });
+  let profile_list = warp::path!("profiles")
+    .and(warp::get())
+    .and(if_none_match)
+    .and(state)
+    .map(|if_none_match: Option<String>, state: Arc<State>| json_with_etag(
+      &state.profiles.iter().map(|profile| &profile.name).collect::<Vec<_>>(),
+      if_none_match.as_deref(),
+    ));
+  // In groups, each boxed, so that the types of the chain stay shallow
+  // enough for the compiler.
+  let agents = snmp_request
+    .or(fan_out)
+    .or(device_info)
+    .or(live)
+    .or(profile_request)
+    .or(agent_profiles)
+    .boxed();
+  let jobs = walk_start
+    .or(walk)
+    .or(walk_cancel)
+    .or(scan_start)
+    .or(scan)
+    .or(scan_cancel)
+    .or(devices)
+    .boxed();
+  let targets = interfaces
+    .or(interface_inventory)
+    .or(snapshot_list)
+    .or(snapshot)
+    .or(drift_history)
+    .or(aggregation)
+    .or(check)
+    .boxed();
+  let admin = traps
+    .or(streaming)
+    .or(metrics)
+    .or(internal_metrics)
+    .or(reload)
+    .or(profile_list)
+    .boxed();
+  let api = agents
+    .or(jobs)
+    .or(targets)
+    .or(admin);
+  // Nothing is answered without a token when tokens are configured.
+  let routes = scope.map(|_scope| ()).untuple_one()
+    .and(api)
+    .recover(recover);
+  // Every request runs under its deadline, and is dropped, along with the
+  // requests to agents still outstanding, when the client goes away. It is
+  // traced under its ID, which the response carries back.
+  let service = warp::service(routes);
+  let service = hyper::service::service_fn(move |request: hyper::Request<hyper::Body>| {
+    let config = reloader.config();
+    let timeout = Duration::from_secs(config.http.timeout);
+    let timeout = request.headers().get("request-timeout")
+      .and_then(|value| value.to_str().ok()?.parse().ok())
+      .map_or(timeout, |seconds: u64| timeout.min(Duration::from_secs(seconds)));
+    let id = trace::request_id(request.headers().get(trace::HEADER).and_then(|value| value.to_str().ok()));
+    let mut service = service.clone();
+    stats::count(&stats::STATS.http_requests);
+    trace::within(id.clone(), config.http.trace, snmp::within(Instant::now() + timeout, async move {
+      trace::event(format_args!("{} {}", request.method(), request.uri().path()));
+      let started = Instant::now();
+      let mut response = service.call(request).await;
+      stats::STATS.http_latency.observe(started.elapsed());
+      if let Ok(response) = &mut response {
+        trace::event(format_args!("answered {}", response.status().as_u16()));
+        if let Ok(id) = warp::http::HeaderValue::from_str(&id) {
+          response.headers_mut().insert(trace::HEADER, id);
+        }
+      }
+      response
+    }))
+  });
+  if let Some(tls) = tls {
+    if let Err(error) = https::serve(listen, &tls, service).await {
+      eprintln!("HTTPS server failed: {}", error);
+    }
+    return;
+  }
+  let make_service = hyper::service::make_service_fn(move |_connection| {
+    let service = service.clone();
+    async move { Ok::<_, Infallible>(service) }
+  });
+  if let Err(error) = hyper::Server::bind(&listen).serve(make_service).await {
+    eprintln!("HTTP server failed: {}", error);
+  }
 }
 
 async fn handle_snmp_request(
-  ip_address: IpAddr,
-  request: SnmpRequest,
-) -> Result<warp::reply::Json, warp::reject::Rejection> {
-  let target = snmp::Target::Community {
-    address: SocketAddr::new(ip_address, 161),
-    community: "vitalumos".into(),
-  };
-  let bindings = match request {
-    SnmpRequest::Get { oids } => {
-      snmp::get(&target, &oids)
+  address: config::Address,
+  options: ResponseOptions,
+  page: Page,
+  body: RequestBody,
+  scope: config::Scope,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let RequestBody { mut request, credentials, context_name, context_engine_id } = body;
+  if matches!(request, SnmpRequest::Set { .. }) && scope < config::Scope::Write {
+    return Ok(warp::reply::with_status(
+      "Token may not set",
+      warp::http::StatusCode::FORBIDDEN,
+    ).into_response());
+  }
+  let policy = config.target(&address);
+  let Some(target) = agent_asked(&config, address, credentials, context_name, context_engine_id) else {
+    return Ok(warp::reply::with_status(
+      "Privacy needs authentication",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response());
+  };
+  if let SnmpRequest::Get { oids, cells } = &mut request {
+    for cell in cells.drain(..) {
+      let index = cell.index.iter()
+        .map(|value| (profile::IndexKind::detect(value), value.as_str()))
+        .collect::<Vec<_>>();
+      let Some(oid) = profile::cell(&cell.column, &index) else {
+        return Ok(warp::reply::with_status(
+          "Invalid row index",
+          warp::http::StatusCode::BAD_REQUEST,
+        ).into_response());
+      };
+      oids.push(oid);
+    }
+  }
+  let permitted = match (&request, policy) {
+    (_, None) => true,
+    (SnmpRequest::Get { oids, .. }, Some(policy)) => oids.iter().all(|oid| policy.permits(oid)),
+    (SnmpRequest::GetNext { oids }, Some(policy)) => oids.iter().all(|oid| policy.may_traverse(oid)),
+    (SnmpRequest::GetBulk { oid, non_repeaters, .. }, Some(policy)) => {
+      non_repeaters.iter().all(|oid| policy.permits(oid)) && policy.may_traverse(oid)
+    },
+    (SnmpRequest::Walk { oid }, Some(policy)) => policy.may_traverse(oid),
+    (SnmpRequest::MixedGetBulk { scalars, columns, .. }, Some(policy)) => {
+      scalars.iter().all(|oid| policy.permits(oid)) && columns.iter().all(|oid| policy.may_traverse(oid))
+    },
+    (SnmpRequest::Set { bindings }, Some(policy)) => bindings.iter().all(|binding| policy.permits(&binding.oid)),
+  };
+  if !permitted {
+    return Ok(warp::reply::with_status(
+      "OID not permitted for this agent",
+      warp::http::StatusCode::FORBIDDEN,
+    ).into_response());
+  }
+  if let (SnmpRequest::Walk { oid }, ResponseFormat::Lines) = (&request, options.format) {
+    return Ok(walk_lines(target, oid, page, policy.cloned(), options));
+  }
+  let table_root = match (&request, options.format) {
+    (_, ResponseFormat::List | ResponseFormat::Map | ResponseFormat::Text) => None,
+    (_, ResponseFormat::Lines) => {
+      return Ok(warp::reply::with_status(
+        "The lines format needs a Walk request",
+        warp::http::StatusCode::BAD_REQUEST,
+      ).into_response());
+    },
+    (SnmpRequest::GetBulk { oid, .. } | SnmpRequest::Walk { oid }, ResponseFormat::Table) => Some(oid.clone()),
+    (
+      SnmpRequest::Get { .. } | SnmpRequest::GetNext { .. } | SnmpRequest::MixedGetBulk { .. } | SnmpRequest::Set { .. },
+      ResponseFormat::Table,
+    ) => {
+      return Ok(warp::reply::with_status(
+        "The table format needs a GetBulk or Walk request",
+        warp::http::StatusCode::BAD_REQUEST,
+      ).into_response());
+    },
+  };
+  let limit = page.limit.map(|limit| limit.max(1));
+  // Where a client paging through the results goes on from, when there are
+  // more than the limit.
+  let mut next = None;
+  let (bindings, mut errors) = match request {
+    SnmpRequest::Get { oids, .. } => {
+      let mut bindings = Vec::new();
+      let mut errors = Vec::new();
+      let mut failure = None;
+      let ttl = Duration::from_millis(config.http.cache_ttl_ms);
+      for (oid, value) in state.responses.get_each(&target, &oids, ttl).await {
+        match value {
+          Ok(binding) => bindings.push(binding),
+          Err(error) => {
+            errors.push(BindingError { oid: options.key(&oid), error: error.to_string() });
+            failure.get_or_insert(error);
+          },
+        }
+      }
+      // Nothing read fails the request as the first OID failed.
+      if let Some(error) = failure.filter(|_| bindings.is_empty()) {
+        return Err(failed(address)(error));
+      }
+      (bindings, errors)
+    },
+    SnmpRequest::GetNext { oids } => {
+      let bindings = target.get_next(&oids)
+        .await
+        .map_err(failed(address))?;
+      (bindings, Vec::new())
+    },
+    // Paged, the rows asked for go on from the cursor and are no more than
+    // the limit; the agent is taken to have more when it sent that many.
+    SnmpRequest::GetBulk { oid, non_repeaters, max_repetitions, max_bindings } => {
+      let from = page.after_oid.unwrap_or_else(|| oid.clone());
+      if !from.starts_with(&oid) {
+        return Ok(warp::reply::with_status(
+          "after_oid is not in the subtree asked for",
+          warp::http::StatusCode::BAD_REQUEST,
+        ).into_response());
+      }
+      if policy.is_some_and(|policy| !policy.may_traverse(&from)) {
+        return Ok(warp::reply::with_status(
+          "after_oid is not permitted for this agent",
+          warp::http::StatusCode::BAD_REQUEST,
+        ).into_response());
+      }
+      let max_repetitions = limit.map_or(max_repetitions, |limit| max_repetitions.min(limit as u32));
+      let mut bindings = target.get_bulk_mixed_after(&non_repeaters, std::slice::from_ref(&oid), std::slice::from_ref(&from), max_repetitions)
         .await
-        .map_err(|snmp_error| warp::reject::not_found())? // TODO: better error handling
+        .map_err(failed(address))?;
+      let scalars = non_repeaters.len().min(bindings.len());
+      // As many rows as are wanted, and whether the agent may have more.
+      let wanted = max_bindings.map_or(max_repetitions as usize, |max_bindings| limit.map_or(max_bindings, |limit| max_bindings.min(limit)));
+      let more = |bindings: &[snmp::VariableBinding]| bindings.len() - scalars >= max_repetitions as usize
+        && bindings.last().is_some_and(|binding| !binding.value.is_exception());
+      if bindings.len() - scalars < wanted && more(&bindings) {
+        let last = bindings[bindings.len() - 1].object_id.clone();
+        let rest = target.get_bulk_after(&oid, &last, max_repetitions, wanted - (bindings.len() - scalars))
+          .await
+          .map_err(failed(address))?;
+        bindings.extend(rest);
+      }
+      if limit.is_some() && bindings.len() - scalars == wanted && more(&bindings) {
+        next = bindings.last().map(|binding| binding.object_id.clone());
+      }
+      (bindings, Vec::new())
     },
-    SnmpRequest::GetBulk { oid } => {
-      snmp::get_bulk(&target, &oid)
+    SnmpRequest::Walk { oid } => {
+      let after = page.after_oid.unwrap_or_else(|| oid.clone());
+      if !after.starts_with(&oid) {
+        return Ok(warp::reply::with_status(
+          "after_oid is not in the subtree walked",
+          warp::http::StatusCode::BAD_REQUEST,
+        ).into_response());
+      }
+      if policy.is_some_and(|policy| !policy.may_traverse(&after)) {
+        return Ok(warp::reply::with_status(
+          "after_oid is not permitted for this agent",
+          warp::http::StatusCode::BAD_REQUEST,
+        ).into_response());
+      }
+      // One more than the limit tells whether there are more.
+      let mut bindings = target.walk_page(&oid, &after, limit.map_or(usize::MAX, |limit| limit + 1))
         .await
-        .map_err(|snmp_error| warp::reject::not_found())? // TODO: better error handling
+        .map_err(failed(address))?;
+      if let Some(limit) = limit.filter(|limit| bindings.len() > *limit) {
+        bindings.truncate(limit);
+        next = bindings.last().map(|binding| binding.object_id.clone());
+      }
+      (bindings, Vec::new())
     },
+    SnmpRequest::MixedGetBulk { scalars, columns, max_repetitions } => {
+      let bindings = target.get_bulk_mixed(&scalars, &columns, max_repetitions)
+        .await
+        .map_err(failed(address))?;
+      (bindings, Vec::new())
+    },
+    SnmpRequest::Set { bindings } => {
+      let oids = bindings.iter().map(|binding| binding.oid.clone()).collect::<Vec<_>>();
+      let bindings = bindings.into_iter()
+        .map(|SetBinding { oid, value }| snmp::VariableBinding {
+          object_id: oid,
+          value,
+          timestamp: snmp::Timestamp { collected_at: std::time::SystemTime::now(), sys_up_time: None },
+        })
+        .collect();
+      match target.set(bindings).await {
+        Ok(bindings) => (bindings, Vec::new()),
+        Err(error) => match error.cause() {
+          // The refusal is reported against the binding the agent blames.
+          refusal @ snmp::Error::Agent { oid, .. } => {
+            let blamed = oid.as_ref().or(oids.first());
+            (Vec::new(), blamed.map(|oid| BindingError { oid: options.key(oid), error: refusal.to_string() }).into_iter().collect())
+          },
+          _ => return Err(failed(address)(error)),
+        },
+      }
+    },
+  };
+  let paged = |response: warp::reply::Response| match &next {
+    Some(next) => warp::reply::with_header(response, "next-after-oid", next.to_string()).into_response(),
+    None => response,
+  };
+  let bindings = bindings.into_iter()
+    .filter(|binding| policy.is_none_or(|policy| policy.permits(&binding.object_id)));
+  if let ResponseFormat::Text = options.format {
+    let bindings = bindings.collect::<Vec<_>>();
+    return Ok(paged(snmpwalk::format(&bindings).into_response()));
+  }
+  let (exceptions, bindings): (Vec<_>, Vec<_>) = bindings
+    .partition(|binding| binding.value.is_exception());
+  errors.extend(exceptions.into_iter()
+    .map(|binding| BindingError { error: binding.value.to_string(), oid: options.key(&binding.object_id) }));
+  if let Some(entry) = table_root {
+    return Ok(paged(warp::reply::json(&TableResponse::new(&entry, bindings.into_iter(), options.octets)).into_response()));
+  }
+  if let ResponseFormat::Map = options.format {
+    let response: GetResponse = GetResponse {
+      bindings: bindings.into_iter()
+        .map(|snmp::VariableBinding { object_id, value, timestamp }| (options.key(&object_id), TimedValue::new(&object_id, value, timestamp, options.octets)))
+        .collect::<HashMap<String, TimedValue>>(),
+      errors,
+    };
+    return Ok(paged(warp::reply::json(&response).into_response()));
+  }
+  let response = ListResponse {
+    bindings: bindings.into_iter()
+      .map(|snmp::VariableBinding { object_id, value, timestamp }| ListBinding {
+        oid: options.key(&object_id),
+        value: TimedValue::new(&object_id, value, timestamp, options.octets),
+      })
+      .collect(),
+    errors,
+    next: next.as_ref().map(|next| next.to_string()),
+  };
+  Ok(paged(warp::reply::json(&response).into_response()))
+}
+
+// A walk of `oid` streamed as lines, read from the agent only as fast as
+// the client takes them, and no further once it goes away or `limit`
+// bindings are sent. It is not bound by the request's deadline, only by
+// the target's timeout for each answer.
+fn walk_lines(
+  target: snmp::SnmpClient,
+  oid: &snmp::ObjectIdentifier,
+  page: Page,
+  policy: Option<config::TargetConfig>,
+  options: ResponseOptions,
+) -> warp::reply::Response {
+  let after = page.after_oid.unwrap_or_else(|| oid.clone());
+  if !after.starts_with(oid) {
+    return warp::reply::with_status(
+      "after_oid is not in the subtree walked",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response();
+  }
+  let lines = target.walk_stream_after(oid, &after)
+    .take(page.limit.map_or(usize::MAX, |limit| limit.max(1)))
+    .filter_map(move |binding| {
+      let line = match binding {
+        Ok(binding) if policy.as_ref().is_some_and(|policy| !policy.permits(&binding.object_id)) => None,
+        Ok(binding) if binding.value.is_exception() => serde_json::to_string(&BindingError {
+          error: binding.value.to_string(),
+          oid: options.key(&binding.object_id),
+        }).ok(),
+        Ok(snmp::VariableBinding { object_id, value, timestamp }) => serde_json::to_string(&ListBinding {
+          oid: options.key(&object_id),
+          value: TimedValue::new(&object_id, value, timestamp, options.octets),
+        }).ok(),
+        Err(error) => Some(serde_json::json!({ "error": error.to_string() }).to_string()),
+      };
+      std::future::ready(line.map(|line| Ok::<_, Infallible>(line + "\n")))
+    });
+  let mut response = warp::reply::Response::new(hyper::Body::wrap_stream(lines));
+  response.headers_mut().insert(warp::http::header::CONTENT_TYPE, warp::http::HeaderValue::from_static("application/x-ndjson"));
+  response
+}
+
+// Gets the same OIDs from every agent listed at once, answering with what
+// each returned, or why it failed, keyed by its address. Agents are asked
+// with their configured credentials, and those whose policy keeps any of
+// the OIDs from clients are not asked at all.
+async fn handle_fan_out_request(
+  options: ResponseOptions,
+  body: FanOutBody,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  if let ResponseFormat::Table | ResponseFormat::Text | ResponseFormat::Lines = options.format {
+    return Ok(warp::reply::with_status(
+      "Requests to several agents are answered as a list or a map",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response());
+  }
+  let config = state.reloader.config();
+  let ttl = Duration::from_millis(config.http.cache_ttl_ms);
+  let FanOutBody { agents, oids } = body;
+  let answers = futures_util::future::join_all(agents.iter().map(|address| {
+    let (config, state, oids) = (&config, &state, &oids);
+    async move {
+      if config.target(address).is_some_and(|policy| !oids.iter().all(|oid| policy.permits(oid))) {
+        return None;
+      }
+      Some(state.responses.get_each(&config.agent(*address), oids, ttl).await)
+    }
+  }))
+  .await;
+  let keys = agents.iter().map(ToString::to_string).collect::<Vec<_>>();
+  let outcomes = keys.iter().zip(answers)
+    .map(|(key, answer)| {
+      let Some(answer) = answer else {
+        let refused = ErrorResponse {
+          error: "notPermitted",
+          detail: "OID not permitted for this agent".to_string(),
+          target: key,
+          status: None,
+          oid: None,
+        };
+        return (key.as_str(), AgentOutcome::Failed(refused));
+      };
+      let mut bindings = Vec::new();
+      let mut errors = Vec::new();
+      let mut first_error = None;
+      for (oid, value) in answer {
+        match value {
+          Ok(binding) => bindings.push(binding),
+          Err(error) => {
+            errors.push(BindingError { oid: options.key(&oid), error: error.to_string() });
+            first_error.get_or_insert(error);
+          },
+        }
+      }
+      // As for one agent, nothing read fails it as the first OID failed.
+      if let Some(error) = first_error.filter(|_| bindings.is_empty()) {
+        return (key.as_str(), AgentOutcome::Failed(failure(&error, key).1));
+      }
+      let (exceptions, bindings): (Vec<_>, Vec<_>) = bindings.into_iter()
+        .partition(|binding| binding.value.is_exception());
+      errors.extend(exceptions.into_iter()
+        .map(|binding| BindingError { error: binding.value.to_string(), oid: options.key(&binding.object_id) }));
+      let outcome = match options.format {
+        ResponseFormat::Map => AgentOutcome::Map(GetResponse {
+          bindings: bindings.into_iter()
+            .map(|snmp::VariableBinding { object_id, value, timestamp }| (options.key(&object_id), TimedValue::new(&object_id, value, timestamp, options.octets)))
+            .collect(),
+          errors,
+        }),
+        _ => AgentOutcome::List(ListResponse {
+          bindings: bindings.into_iter()
+            .map(|snmp::VariableBinding { object_id, value, timestamp }| ListBinding {
+              oid: options.key(&object_id),
+              value: TimedValue::new(&object_id, value, timestamp, options.octets),
+            })
+            .collect(),
+          errors,
+          next: None,
+        }),
+      };
+      (key.as_str(), outcome)
+    })
+    .collect::<BTreeMap<_, _>>();
+  Ok(warp::reply::json(&outcomes).into_response())
+}
+
+// The agent at `address` as a request asks for it, with its own
+// credentials or context instead of the target's; None for privacy without
+// authentication.
+fn agent_asked(
+  config: &config::Config,
+  address: config::Address,
+  credentials: Option<Credentials>,
+  context_name: Option<String>,
+  context_engine_id: Option<config::EngineId>,
+) -> Option<snmp::SnmpClient> {
+  let mut target = config.target(&address).cloned().unwrap_or_else(|| config.unconfigured(address));
+  if let Some(credentials) = credentials {
+    target = credentials.apply(target)?;
+  }
+  target.context_name = context_name.or(target.context_name);
+  target.context_engine_id = context_engine_id.or(target.context_engine_id);
+  Some(target.agent())
+}
+
+// Starts a walk in the background, answering with its ID and where to
+// follow it.
+async fn handle_walk_start_request(
+  address: config::Address,
+  body: WalkBody,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let WalkBody { oid, credentials, context_name, context_engine_id } = body;
+  if config.target(&address).is_some_and(|policy| !policy.may_traverse(&oid)) {
+    return Ok(warp::reply::with_status(
+      "OID not permitted for this agent",
+      warp::http::StatusCode::FORBIDDEN,
+    ).into_response());
+  }
+  let Some(target) = agent_asked(&config, address, credentials, context_name, context_engine_id) else {
+    return Ok(warp::reply::with_status(
+      "Privacy needs authentication",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response());
+  };
+  let retention = Duration::from_secs(config.http.walk_retention);
+  let id = state.walks.start(address, target, oid, retention);
+  let reply = warp::reply::json(&serde_json::json!({ "id": id.to_string() }));
+  let reply = warp::reply::with_header(reply, "location", format!("/jobs/{}", id));
+  Ok(warp::reply::with_status(reply, warp::http::StatusCode::ACCEPTED).into_response())
+}
+
+// A background walk's progress, and its bindings once done, filtered by the
+// agent's policy as it stands now.
+async fn handle_walk_request(
+  id: u64,
+  options: ResponseOptions,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  // Not rejected, since warp would rather answer that DELETE is allowed.
+  let Some(job) = state.walks.get(id) else {
+    return Ok(warp::http::StatusCode::NOT_FOUND.into_response());
+  };
+  let config = state.reloader.config();
+  let policy = config.target(&job.address);
+  let (status, bindings, error) = match job.outcome {
+    walks::Outcome::Running => ("running", None, None),
+    walks::Outcome::Done(bindings) => {
+      let bindings = bindings.iter()
+        .filter(|binding| policy.is_none_or(|policy| policy.permits(&binding.object_id)))
+        .map(|binding| ListBinding {
+          oid: options.key(&binding.object_id),
+          value: TimedValue::new(&binding.object_id, binding.value.clone(), binding.timestamp, options.octets),
+        })
+        .collect();
+      ("done", Some(bindings), None)
+    },
+    walks::Outcome::Failed(error) => ("failed", None, Some(error)),
+    walks::Outcome::Cancelled => ("cancelled", None, None),
+  };
+  Ok(warp::reply::json(&WalkJob {
+    id: job.id.to_string(),
+    agent: job.address.to_string(),
+    oid: options.key(&job.oid),
+    status,
+    started_at: job.started_at,
+    ended_at: job.ended_at,
+    walked: job.walked,
+    last_oid: job.last_oid.map(|oid| options.key(&oid)),
+    error,
+    bindings,
+  }).into_response())
+}
+
+async fn handle_walk_cancel_request(
+  id: u64,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  match state.walks.cancel(id) {
+    true => Ok(warp::http::StatusCode::NO_CONTENT),
+    false => Ok(warp::http::StatusCode::NOT_FOUND),
+  }
+}
+
+// Starts sweeping a range for agents in the background, answering with the
+// scan's ID and where to follow it. Addresses are asked with the
+// credentials configured for them, or those given, and not at all when
+// their policy keeps the system group from clients.
+async fn handle_scan_start_request(
+  body: ScanBody,
+  scope: config::Scope,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  if scope < config::Scope::Write {
+    return Ok(warp::reply::with_status(
+      "Token may not scan",
+      warp::http::StatusCode::FORBIDDEN,
+    ).into_response());
+  }
+  let config = state.reloader.config();
+  let ScanBody { network, credentials } = body;
+  if credentials.as_ref().is_some_and(|credentials| credentials.privacy_protocol.is_some() && credentials.auth_protocol.is_none()) {
+    return Ok(warp::reply::with_status(
+      "Privacy needs authentication",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response());
+  }
+  let retention = Duration::from_secs(config.http.walk_retention);
+  let agent = move |address: config::Address| {
+    let system = device::SYSTEM.map(|oid| oid.parse::<snmp::ObjectIdentifier>().expect("system group OIDs are valid"));
+    if config.target(&address).is_some_and(|policy| !system.iter().all(|oid| policy.permits(oid))) {
+      return None;
+    }
+    agent_asked(&config, address, credentials.clone(), None, None)
+  };
+  let id = state.scans.start(network, agent, state.devices.clone(), retention);
+  let reply = warp::reply::json(&serde_json::json!({ "id": id.to_string() }));
+  let reply = warp::reply::with_header(reply, "location", format!("/discovery/scans/{}", id));
+  Ok(warp::reply::with_status(reply, warp::http::StatusCode::ACCEPTED).into_response())
+}
+
+// A scan's progress, with the agents found so far.
+async fn handle_scan_request(
+  id: u64,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let Some(job) = state.scans.get(id) else {
+    return Ok(warp::http::StatusCode::NOT_FOUND.into_response());
+  };
+  Ok(warp::reply::json(&ScanJob {
+    id: job.id.to_string(),
+    network: job.network.to_string(),
+    status: match job.outcome {
+      discovery::Outcome::Running => "running",
+      discovery::Outcome::Done => "done",
+      discovery::Outcome::Cancelled => "cancelled",
+    },
+    started_at: job.started_at,
+    ended_at: job.ended_at,
+    probed: job.probed,
+    total: job.total,
+    found: job.found.into_iter()
+      .map(|found| KnownDevice { address: found.address.to_string(), info: found.info })
+      .collect(),
+  }).into_response())
+}
+
+// Gets `oids` every `interval` seconds, no more often than the minimum
+// configured, and sends each result as a `values` event until the client
+// goes away. Each get is given until the next is due.
+async fn handle_live_request(
+  address: config::Address,
+  query: LiveQuery,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let Ok(oids) = query.oids.split(',').map(|oid| oid.trim().parse()).collect::<Result<Vec<snmp::ObjectIdentifier>, _>>() else {
+    return Ok(warp::reply::with_status(
+      "Invalid OID",
+      warp::http::StatusCode::BAD_REQUEST,
+    ).into_response());
+  };
+  if config.target(&address).is_some_and(|policy| !oids.iter().all(|oid| policy.permits(oid))) {
+    return Ok(warp::reply::with_status(
+      "OID not permitted for this agent",
+      warp::http::StatusCode::FORBIDDEN,
+    ).into_response());
+  }
+  let period = Duration::from_secs(query.interval.unwrap_or(0).max(config.http.min_live_interval).max(1));
+  let ttl = Duration::from_millis(config.http.cache_ttl_ms);
+  let options = ResponseOptions { format: ResponseFormat::List, resolve: query.resolve, octets: query.octets };
+  let target = config.agent(address);
+  let mut ticks = tokio::time::interval(period);
+  ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+  let events = futures_util::stream::unfold((ticks, 0u64), move |(mut ticks, sent)| {
+    let (state, target, oids, options) = (state.clone(), target.clone(), oids.clone(), options);
+    async move {
+      ticks.tick().await;
+      let answer = snmp::within(Instant::now() + period, state.responses.get_each(&target, &oids, ttl)).await;
+      let mut response = ListResponse { bindings: Vec::new(), errors: Vec::new(), next: None };
+      for (oid, value) in answer {
+        match value {
+          Ok(binding) if binding.value.is_exception() => {
+            response.errors.push(BindingError { oid: options.key(&oid), error: binding.value.to_string() });
+          },
+          Ok(snmp::VariableBinding { object_id, value, timestamp }) => response.bindings.push(ListBinding {
+            oid: options.key(&object_id),
+            value: TimedValue::new(&object_id, value, timestamp, options.octets),
+          }),
+          Err(error) => response.errors.push(BindingError { oid: options.key(&oid), error: error.to_string() }),
+        }
+      }
+      let event = warp::sse::Event::default()
+        .event("values")
+        .id(sent.to_string())
+        .json_data(&response)
+        .unwrap_or_else(|error| warp::sse::Event::default().event("error").data(error.to_string()));
+      Some((Ok::<_, Infallible>(event), (ticks, sent + 1)))
+    }
+  });
+  Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)).into_response())
+}
+
+async fn handle_device_request(
+  address: config::Address,
+  if_none_match: Option<String>,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let info = state.devices.get(&config.agent(address))
+    .await
+    .map_err(failed(address))?;
+  Ok(json_with_etag(&info, if_none_match.as_deref()))
+}
+
+async fn handle_profile_request(
+  address: config::Address,
+  profile_name: String,
+  if_none_match: Option<String>,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let profile = profile::find(&state.profiles, &profile_name)
+    .ok_or_else(warp::reject::not_found)?;
+  let target = config.agent(address);
+  let info = state.devices.get(&target)
+    .await
+    .map_err(failed(address))?;
+  if !profile.supported_by(&info) {
+    return Ok(json_with_etag(&Vec::<profile::Sample>::new(), if_none_match.as_deref()));
+  }
+  let variables = config.target(&address)
+    .map(|target| target.variables.clone())
+    .unwrap_or_default();
+  let samples = profile::collect(&target, profile, &variables, &state.statics)
+    .await
+    .map_err(failed(address))?;
+  Ok(json_with_etag(&samples, if_none_match.as_deref()))
+}
+
+async fn handle_agent_profiles_request(
+  address: config::Address,
+  if_none_match: Option<String>,
+  state: Arc<State>,
+) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let info = state.devices.get(&config.agent(address))
+    .await
+    .map_err(failed(address))?;
+  let names = state.profiles.iter()
+    .filter(|profile| profile.supported_by(&info))
+    .map(|profile| &profile.name)
+    .collect::<Vec<_>>();
+  Ok(json_with_etag(&names, if_none_match.as_deref()))
+}
+
+async fn handle_interfaces_request(
+  target_name: String,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let target = config.named(&target_name)
+    .ok_or_else(warp::reject::not_found)?;
+  let interfaces = interface::collect(&target.agent(), &state.rates)
+    .await
+    .map_err(failed(&target_name))?;
+  Ok(warp::reply::json(&interfaces))
+}
+
+async fn handle_interface_inventory_request(
+  target_name: String,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let target = config.named(&target_name)
+    .ok_or_else(warp::reject::not_found)?;
+  let interfaces = interface::discover_interfaces(&target.agent())
+    .await
+    .map_err(failed(&target_name))?;
+  Ok(warp::reply::json(&interfaces))
+}
+
+async fn handle_snapshots_request(
+  target_name: String,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  config.named(&target_name)
+    .ok_or_else(warp::reject::not_found)?;
+  Ok(warp::reply::json(&state.snapshots.versions(&target_name)))
+}
+
+async fn handle_snapshot_request(
+  target_name: String,
+  version: u32,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let snapshot = state.snapshots.snapshot(&target_name, version)
+    .ok_or_else(warp::reject::not_found)?;
+  Ok(warp::reply::json(&snapshot))
+}
+
+async fn handle_drift_request(
+  target_name: String,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  config.named(&target_name)
+    .ok_or_else(warp::reject::not_found)?;
+  Ok(warp::reply::json(&state.snapshots.history(&target_name)))
+}
+
+// Nagios plugin output for a configured threshold check, evaluated against
+// the target's latest collected samples.
+async fn handle_check_request(
+  target_name: String,
+  check_name: String,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  config.named(&target_name)
+    .ok_or_else(warp::reject::not_found)?;
+  let check = config.thresholds.iter()
+    .find(|check| check.name == check_name)
+    .ok_or_else(warp::reject::not_found)?;
+  Ok(nagios::evaluate(check, &state.latest.samples(&target_name)) + "\n")
+}
+
+// Aggregates one metric of a profile across the targets selected by
+// `tag.<name>=<value>` parameters, with `op` choosing the operation and `by`
+// naming a tag to group the result by.
+async fn handle_aggregate_request(
+  profile_name: String,
+  metric: String,
+  query: AggregateQuery,
+  state: Arc<State>,
+) -> Result<impl warp::Reply, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  let profile = profile::find(&state.profiles, &profile_name)
+    .ok_or_else(warp::reject::not_found)?;
+  let tags = query.selectors.iter()
+    .filter_map(|(key, value)| Some((key.strip_prefix("tag.")?.to_string(), value.clone())))
+    .collect::<BTreeMap<_, _>>();
+  let by = query.by.as_ref();
+  let mut aggregation = aggregate::Aggregation::default();
+  for target in aggregate::select(&config, &tags) {
+    let group = by.and_then(|tag| target.tags.get(tag)).cloned();
+    let samples = profile::collect(&target.agent(), profile, &target.variables, &state.statics)
+      .await
+      .ok();
+    aggregation.add(group, &metric, samples);
+  }
+  Ok(warp::reply::json(&aggregation.finish(query.op)))
+}
+
+// An SNMP failure answering a request for `target`, an agent's address or
+// a target's name, which `recover` answers with its own status.
+#[derive(Debug)]
+struct Failure {
+  error: snmp::Error,
+  target: String,
+}
+
+impl warp::reject::Reject for Failure {}
+
+fn failed(target: impl Display) -> impl FnOnce(snmp::Error) -> warp::reject::Rejection {
+  move |error| warp::reject::custom(Failure { error, target: target.to_string() })
+}
+
+// A request without a token, or with one not configured.
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// The scope of the bearer token in `authorization`, which is any when no
+// tokens are configured. Tokens are compared in full whatever their
+// prefixes, so that the time taken says nothing of how much matched.
+async fn authenticate(authorization: Option<String>, state: Arc<State>) -> Result<config::Scope, warp::reject::Rejection> {
+  let config = state.reloader.config();
+  if config.http.tokens.is_empty() {
+    return Ok(config::Scope::Write);
+  }
+  let presented = authorization.as_deref()
+    .and_then(|authorization| authorization.split_once(' '))
+    .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+    .map(|(_, token)| token.trim())
+    .ok_or_else(|| warp::reject::custom(Unauthorized))?;
+  config.http.tokens.iter()
+    .filter(|token| token.token.len() == presented.len()
+      && token.token.bytes().zip(presented.bytes()).fold(0, |differ, (a, b)| differ | (a ^ b)) == 0)
+    .map(|token| token.scope)
+    .max()
+    .ok_or_else(|| warp::reject::custom(Unauthorized))
+}
+
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+  error: &'static str,
+  detail: String,
+  target: &'a str,
+  // The error-status of an agent's refusal, and the OID it blames.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  status: Option<&'static str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  oid: Option<String>,
+}
+
+// Failures as `{"error": "timeout", "detail": "No response in time.",
+// "target": "10.0.0.1"}`: agents not answering are a gateway timeout, agents
+// unreachable a bad gateway, and requests that cannot be put in SNMP terms,
+// such as for an OID no MIB names, a bad request. An agent's refusal comes
+// with its error-status, and is answered by what it says of the request:
+// an OID the agent lacks is not found, one it keeps from the client
+// forbidden, and a value it will not take unprocessable; failures of the
+// agent itself are a bad gateway. Other rejections are left to warp.
+async fn recover(rejection: warp::reject::Rejection) -> Result<warp::reply::Response, warp::reject::Rejection> {
+  if rejection.find::<Unauthorized>().is_some() {
+    let response = warp::reply::with_status("Missing or unknown bearer token", warp::http::StatusCode::UNAUTHORIZED);
+    return Ok(warp::reply::with_header(response, "www-authenticate", "Bearer").into_response());
+  }
+  let Some(Failure { error, target }) = rejection.find::<Failure>() else {
+    return Err(rejection);
   };
-  let response: GetResponse = GetResponse(
-    bindings.iter()
-      .map(|snmp::VariableBinding { object_id, value }| (object_id.clone(), value.clone()))
-      .collect::<HashMap<snmp::ObjectIdentifier, snmp::ObjectValue>>()
-  );
-  Ok(warp::reply::json(&response))
+  let (status, response) = failure(error, target);
+  Ok(warp::reply::with_status(warp::reply::json(&response), status).into_response())
+}
+
+// The status and body a failure of the request to `target` is answered with.
+fn failure<'a>(error: &snmp::Error, target: &'a str) -> (warp::http::StatusCode, ErrorResponse<'a>) {
+  let (status, kind) = match error.cause() {
+    snmp::Error::Timeout() => (warp::http::StatusCode::GATEWAY_TIMEOUT, "timeout"),
+    snmp::Error::Agent { status, .. } => (refusal_status(*status), "agentError"),
+    snmp::Error::Connection(_) => (warp::http::StatusCode::BAD_GATEWAY, "connection"),
+    snmp::Error::Security() => (warp::http::StatusCode::BAD_GATEWAY, "security"),
+    snmp::Error::Serialization(_) => (warp::http::StatusCode::BAD_REQUEST, "invalidRequest"),
+    snmp::Error::Malformed(_) => (warp::http::StatusCode::BAD_GATEWAY, "malformedResponse"),
+    snmp::Error::Unsupported() => (warp::http::StatusCode::NOT_IMPLEMENTED, "unsupported"),
+    snmp::Error::Request(_) => unreachable!("causes are not requests"),
+  };
+  let (agent_status, oid) = match error.cause() {
+    snmp::Error::Agent { status, oid, .. } => (snmp::status_name(*status), oid.as_ref().map(ToString::to_string)),
+    _ => (None, None),
+  };
+  (status, ErrorResponse { error: kind, detail: error.to_string(), target, status: agent_status, oid })
+}
+
+// The HTTP status answering an agent's refusal with error-status `status`.
+fn refusal_status(status: u32) -> warp::http::StatusCode {
+  match snmp::status_name(status) {
+    Some("noSuchName") => warp::http::StatusCode::NOT_FOUND,
+    Some("noAccess" | "readOnly" | "notWritable" | "authorizationError" | "noCreation") => warp::http::StatusCode::FORBIDDEN,
+    Some(
+      "badValue" | "wrongType" | "wrongLength" | "wrongEncoding" | "wrongValue" | "inconsistentValue" | "inconsistentName"
+    ) => warp::http::StatusCode::UNPROCESSABLE_ENTITY,
+    _ => warp::http::StatusCode::BAD_GATEWAY,
+  }
+}
+
+// The octets of a string of hex digits.
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+  if !text.len().is_multiple_of(2) || !text.is_ascii() {
+    return None;
+  }
+  (0..text.len()).step_by(2)
+    .map(|at| u8::from_str_radix(&text[at..at + 2], 16).ok())
+    .collect()
+}
+
+// Serializes `value` with an ETag derived from the body, answering 304 Not
+// Modified when the client already holds that representation.
+fn json_with_etag<T: Serialize>(value: &T, if_none_match: Option<&str>) -> warp::reply::Response {
+  let body = match serde_json::to_vec(value) {
+    Ok(body) => body,
+    Err(_) => return warp::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+  };
+  let mut hasher = std::collections::hash_map::DefaultHasher::new();
+  body.hash(&mut hasher);
+  let etag = format!("\"{:016x}\"", hasher.finish());
+  let matches = if_none_match.is_some_and(|header| header.split(',')
+    .map(|tag| tag.trim())
+    .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag));
+  let response = if matches {
+    warp::http::Response::builder()
+      .status(warp::http::StatusCode::NOT_MODIFIED)
+      .body(warp::hyper::Body::empty())
+  } else {
+    warp::http::Response::builder()
+      .header(warp::http::header::CONTENT_TYPE, "application/json")
+      .body(warp::hyper::Body::from(body))
+  };
+  match response {
+    Ok(mut response) => {
+      if let Ok(etag) = warp::http::HeaderValue::from_str(&etag) {
+        response.headers_mut().insert(warp::http::header::ETAG, etag);
+      }
+      response
+    },
+    Err(_) => warp::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+  }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -49,15 +1163,405 @@ async fn handle_snmp_request(
 pub enum SnmpRequest {
   Get {
     // agent_configuration: String,
+    #[serde(default)]
     oids: Vec<snmp::ObjectIdentifier>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cells: Vec<Cell>,
   },
+  // The lexicographic successor of each OID, for agents that mishandle
+  // GetBulk.
+  GetNext {
+    oids: Vec<snmp::ObjectIdentifier>,
+  },
+  // Instances in `nonRepeaters` are asked for once, alongside up to
+  // `maxRepetitions` rows of the subtree. With `maxBindings` further
+  // requests go on from the last row until the subtree ends or that many
+  // rows are read.
   GetBulk {
     oid: snmp::ObjectIdentifier,
+    #[serde(default, rename = "nonRepeaters")]
+    non_repeaters: Vec<snmp::ObjectIdentifier>,
+    #[serde(default = "default_max_repetitions", rename = "maxRepetitions")]
+    max_repetitions: u32,
+    #[serde(default, rename = "maxBindings", skip_serializing_if = "Option::is_none")]
+    max_bindings: Option<usize>,
   },
+  // The whole subtree, where GetBulk stops after one request unless told
+  // otherwise.
+  Walk {
+    oid: snmp::ObjectIdentifier,
+  },
+  // Scalar instances and the next rows of table columns in one PDU.
+  MixedGetBulk {
+    #[serde(default)]
+    scalars: Vec<snmp::ObjectIdentifier>,
+    #[serde(default)]
+    columns: Vec<snmp::ObjectIdentifier>,
+    #[serde(default = "default_max_repetitions", rename = "maxRepetitions")]
+    max_repetitions: u32,
+  },
+  // Values in the form responses give them, such as
+  // `{"oid": "1.3.6.1.2.1.1.5.0", "syntax": "OctetString", "value": "ups1"}`.
+  Set {
+    bindings: Vec<SetBinding>,
+  },
+}
+
+// `contextName` and `contextEngineId` choose another SNMPv3 context than
+// the target's.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestBody {
+  #[serde(flatten)]
+  request: SnmpRequest,
+  #[serde(default)]
+  credentials: Option<Credentials>,
+  #[serde(default)]
+  context_name: Option<String>,
+  #[serde(default)]
+  context_engine_id: Option<config::EngineId>,
+}
+
+// The same OIDs from several agents:
+// `{"agents": ["10.0.0.1", "10.0.0.2"], "oids": ["1.3.6.1.2.1.1.5.0"]}`.
+#[derive(Deserialize)]
+struct FanOutBody {
+  agents: Vec<config::Address>,
+  oids: Vec<snmp::ObjectIdentifier>,
+}
+
+// A walk to run in the background, with credentials and context as for
+// requests.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WalkBody {
+  oid: snmp::ObjectIdentifier,
+  #[serde(default)]
+  credentials: Option<Credentials>,
+  #[serde(default)]
+  context_name: Option<String>,
+  #[serde(default)]
+  context_engine_id: Option<config::EngineId>,
+}
+
+// A range to sweep for agents, `{"network": "10.0.0.0/24"}`, with
+// credentials as for requests.
+#[derive(Deserialize)]
+struct ScanBody {
+  network: discovery::Network,
+  #[serde(default)]
+  credentials: Option<Credentials>,
 }
 
+// Credentials to use instead of the configured ones, a community or an
+// SNMPv3 user: `{"community": "s3cret", "version": "1"}` or `{"user":
+// "monitor", "authProtocol": "sha256", "authPassword": "..."}`. The
+// target's policy applies all the same.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Credentials {
+  #[serde(default)]
+  community: Option<String>,
+  #[serde(default)]
+  version: Option<config::SnmpVersion>,
+  #[serde(default)]
+  user: Option<String>,
+  #[serde(default)]
+  auth_protocol: Option<snmp::AuthProtocol>,
+  #[serde(default)]
+  auth_password: String,
+  #[serde(default)]
+  privacy_protocol: Option<snmp::PrivacyProtocol>,
+  #[serde(default)]
+  privacy_password: String,
+}
+
+impl Credentials {
+
+  // The target as reached with these credentials; None for privacy without
+  // authentication.
+  fn apply(self, mut target: config::TargetConfig) -> Option<config::TargetConfig> {
+    if self.privacy_protocol.is_some() && self.auth_protocol.is_none() {
+      return None;
+    }
+    target.tls = None;
+    target.usm = self.user.map(|user| config::UsmConfig {
+      user,
+      auth_protocol: self.auth_protocol,
+      auth_password: self.auth_password,
+      privacy_protocol: self.privacy_protocol,
+      privacy_password: self.privacy_password,
+    });
+    target.community = self.community.or(target.community);
+    target.version = self.version.unwrap_or(target.version);
+    Some(target)
+  }
+}
+
+fn default_max_repetitions() -> u32 {
+  snmp::MAX_REPETITIONS
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SetBinding {
+  pub oid: snmp::ObjectIdentifier,
+  #[serde(flatten)]
+  pub value: snmp::ObjectValue,
+}
+
+// A table cell addressed by its column and decoded row index, such as
+// `["7"]` for an ifIndex or `["00:1a:2b:3c:4d:5e"]` for a MAC-keyed row.
+#[derive(Deserialize, Serialize)]
+pub struct Cell {
+  pub column: snmp::ObjectIdentifier,
+  pub index: Vec<String>,
+}
+
+// A page of the results of a walk or GetBulk: those past `after_oid`, and
+// no more than `limit`.
+#[derive(Deserialize)]
+struct Page {
+  #[serde(default)]
+  after_oid: Option<snmp::ObjectIdentifier>,
+  #[serde(default)]
+  limit: Option<usize>,
+}
+
+// `oids` separated by commas, and the seconds between gets.
+#[derive(Deserialize)]
+struct LiveQuery {
+  oids: String,
+  #[serde(default)]
+  interval: Option<u64>,
+  #[serde(default)]
+  resolve: bool,
+  #[serde(default)]
+  octets: OctetEncoding,
+}
+
+#[derive(Deserialize)]
+struct AggregateQuery {
+  #[serde(default)]
+  op: aggregate::Operation,
+  by: Option<String>,
+  #[serde(flatten)]
+  selectors: HashMap<String, String>,
+}
+
+// An agent address in a path. Warp does not decode path segments, so the
+// zone of a scoped address comes escaped: `/agents/fe80::1%25eth0`.
+struct AgentAddress(config::Address);
+
+impl FromStr for AgentAddress {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    text.replacen("%25", "%", 1).parse().map(AgentAddress)
+  }
+}
+
+#[derive(Deserialize, Clone, Copy)]
+struct ResponseOptions {
+  #[serde(default)]
+  format: ResponseFormat,
+  // Whether to key bindings by MIB name, `IF-MIB::ifInOctets.3`, where the
+  // installed MIBs name the OID.
+  #[serde(default)]
+  resolve: bool,
+  #[serde(default)]
+  octets: OctetEncoding,
+}
+
+// How octet strings are written: as text when they are UTF-8 and in hex
+// when not, unless the client asks for hex or base64 throughout. Encoded
+// strings say how in `encoding`.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+enum OctetEncoding {
+  #[default]
+  Auto,
+  Hex,
+  Base64,
+}
+
+impl ResponseOptions {
+
+  fn key(&self, oid: &snmp::ObjectIdentifier) -> String {
+    self.resolve.then(|| oid.mib_name()).flatten().unwrap_or_else(|| oid.to_string())
+  }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+enum ResponseFormat {
+  // Bindings in the order the agent returned them, duplicates included.
+  #[default]
+  List,
+  // An object keyed by OID, where order is lost and duplicates collapse.
+  Map,
+  // Rows keyed by instance index, each mapping column arcs to values. The
+  // requested OID is taken as the table entry (ifEntry rather than ifTable).
+  Table,
+  // Plain text in the style of `snmpwalk -On`.
+  Text,
+  // One JSON object per line, a binding or an error, sent as the agent
+  // answers, for walks too long to hold. It ends with an `error` line if the
+  // walk fails part way.
+  Lines,
+}
+
+// Bindings keyed by OID. OIDs that could not be read are listed under
+// `errors` rather than failing the whole request.
 #[derive(Serialize)]
-struct GetResponse(HashMap<snmp::ObjectIdentifier, snmp::ObjectValue>);
+struct GetResponse {
+  #[serde(flatten)]
+  bindings: HashMap<String, TimedValue>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  errors: Vec<BindingError>,
+}
+
+// What one of the agents of a fan-out answered, in the format asked for,
+// or why it could not.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum AgentOutcome<'a> {
+  List(ListResponse),
+  Map(GetResponse),
+  Failed(ErrorResponse<'a>),
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+  bindings: Vec<ListBinding>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  errors: Vec<BindingError>,
+  // The `after_oid` of the next page, when there is one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next: Option<String>,
+}
+
+// `status` is running, done, failed or cancelled; `walked` and `lastOid`
+// tell how far it got.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WalkJob {
+  id: String,
+  agent: String,
+  oid: String,
+  status: &'static str,
+  started_at: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ended_at: Option<u64>,
+  walked: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  last_oid: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  bindings: Option<Vec<ListBinding>>,
+}
+
+// `status` is running, done or cancelled; `probed` of the `total`
+// addresses have answered or given up, and `found` answered.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ScanJob {
+  id: String,
+  network: String,
+  status: &'static str,
+  started_at: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  ended_at: Option<u64>,
+  probed: usize,
+  total: usize,
+  found: Vec<KnownDevice>,
+}
+
+// An agent of the inventory, with what its system group says.
+#[derive(Serialize)]
+struct KnownDevice {
+  address: String,
+  #[serde(flatten)]
+  info: device::DeviceInfo,
+}
+
+#[derive(Serialize)]
+struct ListBinding {
+  oid: String,
+  #[serde(flatten)]
+  value: TimedValue,
+}
+
+#[derive(Serialize)]
+struct TimedValue {
+  #[serde(flatten)]
+  value: EncodedValue,
+  // The value as the MIBs have it displayed, alongside the raw one.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  display: Option<String>,
+  // The name of an enumerated value.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  label: Option<&'static str>,
+  // The names of the bits set in a BITS value.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  flags: Option<Vec<&'static str>>,
+  #[serde(flatten)]
+  timestamp: snmp::Timestamp,
+}
+
+impl TimedValue {
+
+  fn new(oid: &snmp::ObjectIdentifier, value: snmp::ObjectValue, timestamp: snmp::Timestamp, octets: OctetEncoding) -> TimedValue {
+    TimedValue {
+      display: value.hinted(oid),
+      label: value.label(oid),
+      flags: value.flags(oid),
+      value: EncodedValue(value, octets),
+      timestamp,
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct BindingError {
+  oid: String,
+  error: String,
+}
+
+struct TableResponse(BTreeMap<Vec<u32>, BTreeMap<u32, TimedValue>>);
+
+impl TableResponse {
+
+  fn new(
+    entry: &snmp::ObjectIdentifier,
+    bindings: impl Iterator<Item = snmp::VariableBinding>,
+    octets: OctetEncoding,
+  ) -> TableResponse {
+    let mut rows: BTreeMap<Vec<u32>, BTreeMap<u32, TimedValue>> = BTreeMap::new();
+    for binding in bindings {
+      let Some((column, index)) = binding.object_id.strip_prefix(entry)
+        .and_then(|suffix| suffix.split_first()) else {
+        continue;
+      };
+      rows.entry(index.to_vec())
+        .or_default()
+        .insert(*column, TimedValue::new(&binding.object_id, binding.value, binding.timestamp, octets));
+    }
+    TableResponse(rows)
+  }
+}
+
+impl Serialize for TableResponse {
+
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer
+  {
+    serializer.collect_map(self.0.iter().map(|(index, row)| {
+      let index = index.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join(".");
+      (index, row)
+    }))
+  }
+}
 
 impl Serialize for snmp::ObjectIdentifier {
 
@@ -78,54 +1582,226 @@ impl<'de> Deserialize<'de> for snmp::ObjectIdentifier {
   }
 }
 
-impl Serialize for snmp::ObjectValue {
+// Milliseconds since the Unix epoch, plus sysUpTime in ticks when known.
+impl Serialize for snmp::Timestamp {
 
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: serde::Serializer
   {
-    let mut obj = serializer.serialize_struct("ObjectValue", 2)?;
-    match self {
-      snmp::ObjectValue::Integer(value) => {
-        // obj.serialize_field("syntax", "Integer")?;
-        // obj.serialize_field("value", value.to_bytes_be())?; // TODO: this might be wrong
-      },
-      snmp::ObjectValue::OctetString(value) => {
-        obj.serialize_field("syntax", "OctetString")?;
-        obj.serialize_field("value", &(String::from_utf8(value.to_vec()).unwrap()))?;
-      },
-      snmp::ObjectValue::ObjectIdentifier(value) => {
-        obj.serialize_field("syntax", "ObjectIdentifier")?;
-        obj.serialize_field("value", &(value.to_string()))?;
-      },
-      snmp::ObjectValue::Integer32(value) => {
-        obj.serialize_field("syntax", "Integer32")?;
-        obj.serialize_field("value", value)?;
-      },
-      snmp::ObjectValue::IpAddress(value) => {
-        obj.serialize_field("syntax", "IpAddress")?;
-        obj.serialize_field("value", &(value.to_string()))?;
-      },
-      snmp::ObjectValue::Counter32(value) => {
-        obj.serialize_field("syntax", "Counter32")?;
-        obj.serialize_field("value", value)?;
-      },
-      snmp::ObjectValue::Unsigned32(value) => {
-        obj.serialize_field("syntax", "Unsigned32")?;
-        obj.serialize_field("value", value)?;
-      },
-      snmp::ObjectValue::TimeTicks(value) => {
-        obj.serialize_field("syntax", "TimeTicks")?;
-        obj.serialize_field("value", value)?;
-      },
-      snmp::ObjectValue::Opaque(value) => {
-        obj.serialize_field("syntax", "Opaque")?;
-        obj.serialize_field("value", value)?;
-      },
-      snmp::ObjectValue::Counter64(value) => {
-        obj.serialize_field("syntax", "Counter64")?;
-        obj.serialize_field("value", value)?;
-      },
+    let millis = self.collected_at.duration_since(std::time::UNIX_EPOCH)
+      .map(|elapsed| elapsed.as_millis() as u64)
+      .unwrap_or_default();
+    let mut obj = serializer.serialize_struct("Timestamp", 2)?;
+    obj.serialize_field("timestamp", &millis)?;
+    if let Some(ticks) = self.sys_up_time {
+      obj.serialize_field("sysUpTime", &ticks)?;
     }
     obj.end()
   }
 }
+
+// The inverse of the serialization below, for values to be set and for
+// fixtures; exceptions are read back too, though no agent takes them.
+impl<'de> Deserialize<'de> for snmp::ObjectValue {
+
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: serde::Deserializer<'de>
+  {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Octets {
+      Text(String),
+      Binary(Vec<u8>),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Integer {
+      Signed(i64),
+      Unsigned(u64),
+      Decimal(String),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(tag = "syntax", content = "value")]
+    enum Value {
+      Integer(Integer),
+      OctetString(Octets),
+      ObjectIdentifier(snmp::ObjectIdentifier),
+      Integer32(i32),
+      IpAddress(std::net::Ipv4Addr),
+      // With the zone index after a `%`, as in `fe80::1%3`.
+      Ipv6Address(String),
+      Counter32(u32),
+      Unsigned32(u32),
+      TimeTicks(u32),
+      Opaque(Vec<u8>),
+      Counter64(u64),
+      Float(f32),
+      Double(f64),
+      Null,
+      #[serde(rename = "noSuchObject")]
+      NoSuchObject,
+      #[serde(rename = "noSuchInstance")]
+      NoSuchInstance,
+      #[serde(rename = "endOfMibView")]
+      EndOfMibView,
+    }
+
+    // Hex and base64 strings say so beside the value.
+    #[derive(Deserialize)]
+    struct Encoded {
+      #[serde(flatten)]
+      value: Value,
+      #[serde(default)]
+      encoding: Option<OctetEncoding>,
+    }
+
+    let Encoded { value, encoding } = Encoded::deserialize(deserializer)?;
+    Ok(match value {
+      Value::Integer(Integer::Signed(value)) => snmp::ObjectValue::Integer(value.into()),
+      Value::Integer(Integer::Unsigned(value)) => snmp::ObjectValue::Integer(value.into()),
+      Value::Integer(Integer::Decimal(text)) => snmp::ObjectValue::Integer(
+        text.parse().map_err(|_| de::Error::custom(format!("invalid integer {}", text)))?,
+      ),
+      Value::OctetString(Octets::Text(value)) => match encoding {
+        None | Some(OctetEncoding::Auto) => snmp::ObjectValue::OctetString(value.into_bytes().into()),
+        Some(OctetEncoding::Hex) => snmp::ObjectValue::OctetString(from_hex(&value).ok_or_else(|| de::Error::custom("invalid hex string"))?.into()),
+        Some(OctetEncoding::Base64) => snmp::ObjectValue::OctetString(
+          base64::engine::general_purpose::STANDARD.decode(&value).map_err(de::Error::custom)?.into(),
+        ),
+      },
+      Value::OctetString(Octets::Binary(value)) => snmp::ObjectValue::OctetString(value.into()),
+      Value::ObjectIdentifier(value) => snmp::ObjectValue::ObjectIdentifier(value),
+      Value::Integer32(value) => snmp::ObjectValue::Integer32(value),
+      Value::IpAddress(value) => snmp::ObjectValue::IpAddress(value),
+      Value::Ipv6Address(text) => {
+        let invalid = || de::Error::custom(format!("invalid IPv6 address {}", text));
+        let (address, zone) = match text.split_once('%') {
+          Some((address, zone)) => (address, Some(zone.parse().map_err(|_| invalid())?)),
+          None => (text.as_str(), None),
+        };
+        snmp::ObjectValue::Ipv6Address(address.parse().map_err(|_| invalid())?, zone)
+      },
+      Value::Counter32(value) => snmp::ObjectValue::Counter32(value),
+      Value::Unsigned32(value) => snmp::ObjectValue::Unsigned32(value),
+      Value::TimeTicks(value) => snmp::ObjectValue::TimeTicks(value),
+      Value::Opaque(value) => snmp::ObjectValue::Opaque(value),
+      Value::Counter64(value) => snmp::ObjectValue::Counter64(value),
+      Value::Float(value) => snmp::ObjectValue::Float(value),
+      Value::Double(value) => snmp::ObjectValue::Double(value),
+      Value::Null => snmp::ObjectValue::Null,
+      Value::NoSuchObject => snmp::ObjectValue::NoSuchObject,
+      Value::NoSuchInstance => snmp::ObjectValue::NoSuchInstance,
+      Value::EndOfMibView => snmp::ObjectValue::EndOfMibView,
+    })
+  }
+}
+
+impl Serialize for snmp::ObjectValue {
+
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer
+  {
+    serialize_value(self, OctetEncoding::Auto, serializer)
+  }
+}
+
+// A value with octet strings written as a client asked.
+struct EncodedValue(snmp::ObjectValue, OctetEncoding);
+
+impl Serialize for EncodedValue {
+
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer
+  {
+    serialize_value(&self.0, self.1, serializer)
+  }
+}
+
+fn serialize_value<S>(value: &snmp::ObjectValue, octets: OctetEncoding, serializer: S) -> Result<S::Ok, S::Error>
+  where S: serde::Serializer
+{
+  let mut obj = serializer.serialize_struct("ObjectValue", 3)?;
+  match value {
+    // A number when it fits one JSON parsers take exactly, else the
+    // decimal digits in a string.
+    snmp::ObjectValue::Integer(value) => {
+      obj.serialize_field("syntax", "Integer")?;
+      match (value.to_i64(), value.to_u64()) {
+        (Some(value), _) => obj.serialize_field("value", &value)?,
+        (None, Some(value)) => obj.serialize_field("value", &value)?,
+        (None, None) => obj.serialize_field("value", &value.to_string())?,
+      }
+    },
+    // Binary strings, such as MAC addresses and InetAddress values outside
+    // a profile, are never written as text.
+    snmp::ObjectValue::OctetString(value) => {
+      obj.serialize_field("syntax", "OctetString")?;
+      match (octets, std::str::from_utf8(value)) {
+        (OctetEncoding::Auto, Ok(text)) => obj.serialize_field("value", text)?,
+        (OctetEncoding::Auto | OctetEncoding::Hex, _) => {
+          obj.serialize_field("value", &value.iter().map(|octet| format!("{:02x}", octet)).collect::<String>())?;
+          obj.serialize_field("encoding", "hex")?;
+        },
+        (OctetEncoding::Base64, _) => {
+          obj.serialize_field("value", &base64::engine::general_purpose::STANDARD.encode(value))?;
+          obj.serialize_field("encoding", "base64")?;
+        },
+      }
+    },
+    snmp::ObjectValue::ObjectIdentifier(value) => {
+      obj.serialize_field("syntax", "ObjectIdentifier")?;
+      obj.serialize_field("value", &(value.to_string()))?;
+    },
+    snmp::ObjectValue::Integer32(value) => {
+      obj.serialize_field("syntax", "Integer32")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::IpAddress(value) => {
+      obj.serialize_field("syntax", "IpAddress")?;
+      obj.serialize_field("value", &(value.to_string()))?;
+    },
+    snmp::ObjectValue::Ipv6Address(..) => {
+      obj.serialize_field("syntax", "Ipv6Address")?;
+      obj.serialize_field("value", &(value.to_string()))?;
+    },
+    snmp::ObjectValue::Counter32(value) => {
+      obj.serialize_field("syntax", "Counter32")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Unsigned32(value) => {
+      obj.serialize_field("syntax", "Unsigned32")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::TimeTicks(value) => {
+      obj.serialize_field("syntax", "TimeTicks")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Opaque(value) => {
+      obj.serialize_field("syntax", "Opaque")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Counter64(value) => {
+      obj.serialize_field("syntax", "Counter64")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Float(value) => {
+      obj.serialize_field("syntax", "Float")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Double(value) => {
+      obj.serialize_field("syntax", "Double")?;
+      obj.serialize_field("value", value)?;
+    },
+    snmp::ObjectValue::Null => {
+      obj.serialize_field("syntax", "Null")?;
+    },
+    snmp::ObjectValue::NoSuchObject
+      | snmp::ObjectValue::NoSuchInstance
+      | snmp::ObjectValue::EndOfMibView => {
+      obj.serialize_field("syntax", &(value.to_string()))?;
+    },
+  }
+  obj.end()
+}
@@ -0,0 +1,83 @@
+use std::{fmt::Display, sync::OnceLock, time::Duration};
+
+use hyper::{body::HttpBody, client::HttpConnector, Body, Client, Request};
+
+// Outgoing HTTP for sinks and notifications. `https` URLs need the `tls`
+// feature.
+#[derive(Debug)]
+pub enum Error {
+  Url(String),
+  Unsupported,
+  Http(hyper::Error),
+  Timeout,
+  Status(u16, String),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Url(url) => write!(f, "invalid URL {}", url),
+      Error::Unsupported => write!(f, "HTTPS support is not compiled in"),
+      Error::Http(error) => write!(f, "{}", error),
+      Error::Timeout => write!(f, "no response"),
+      Error::Status(status, body) => write!(f, "HTTP status {}: {}", status, body),
+    }
+  }
+}
+
+#[cfg(feature = "tls")]
+type Connector = hyper_rustls::HttpsConnector<HttpConnector>;
+#[cfg(not(feature = "tls"))]
+type Connector = HttpConnector;
+
+fn client() -> &'static Client<Connector> {
+  static CLIENT: OnceLock<Client<Connector>> = OnceLock::new();
+  CLIENT.get_or_init(|| {
+    #[cfg(feature = "tls")]
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+      .with_webpki_roots()
+      .https_or_http()
+      .enable_http1()
+      .build();
+    #[cfg(not(feature = "tls"))]
+    let connector = HttpConnector::new();
+    Client::builder().build(connector)
+  })
+}
+
+// Posts `body` and returns the response body of a 2xx answer; any other
+// status is an error carrying the start of the body.
+pub async fn post(
+  url: &str,
+  headers: &[(&str, &str)],
+  body: Vec<u8>,
+  timeout: Duration,
+) -> Result<Vec<u8>, Error> {
+  let uri = url.parse::<hyper::Uri>().map_err(|_| Error::Url(url.to_string()))?;
+  if cfg!(not(feature = "tls")) && uri.scheme_str() == Some("https") {
+    return Err(Error::Unsupported);
+  }
+  let mut request = Request::post(uri);
+  for (name, value) in headers {
+    request = request.header(*name, *value);
+  }
+  let request = request.body(Body::from(body)).map_err(|_| Error::Url(url.to_string()))?;
+  let exchange = async {
+    let response = client().request(request).await.map_err(Error::Http)?;
+    let status = response.status();
+    let mut body = response.into_body();
+    let mut content = Vec::new();
+    while let Some(chunk) = body.data().await {
+      content.extend(chunk.map_err(Error::Http)?);
+    }
+    match status.is_success() {
+      true => Ok(content),
+      false => Err(Error::Status(
+        status.as_u16(),
+        String::from_utf8_lossy(&content[..content.len().min(200)]).into_owned(),
+      )),
+    }
+  };
+  tokio::time::timeout(timeout, exchange).await.map_err(|_elapsed| Error::Timeout)?
+}
@@ -0,0 +1,105 @@
+use std::{convert::Infallible, io, net::SocketAddr};
+
+use hyper::{service::Service, Body, Request, Response};
+
+use crate::config;
+
+// Serves `service` over TLS on `listen`, each connection in a task of its
+// own, upgrades such as WebSockets included.
+pub async fn serve<S>(listen: SocketAddr, tls: &config::ApiTlsConfig, service: S) -> io::Result<()>
+where
+  S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone + Send + 'static,
+  S::Future: Send + 'static,
+{
+  server::serve(listen, tls, service).await
+}
+
+#[cfg(feature = "tls")]
+mod server {
+  use std::{convert::Infallible, fs::File, io::{self, BufReader}, net::SocketAddr, path::Path, sync::Arc, time::Duration};
+
+  use hyper::{server::conn::Http, service::Service, Body, Request, Response};
+  use tokio::net::TcpListener;
+  use tokio_rustls::{rustls, TlsAcceptor};
+
+  use crate::config;
+
+  // How long a client has to complete its handshake.
+  const HANDSHAKE: Duration = Duration::from_secs(10);
+
+  pub(super) async fn serve<S>(listen: SocketAddr, tls: &config::ApiTlsConfig, service: S) -> io::Result<()>
+  where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+  {
+    let acceptor = TlsAcceptor::from(server_config(tls)?);
+    let listener = TcpListener::bind(listen).await?;
+    loop {
+      let stream = match listener.accept().await {
+        Ok((stream, _peer)) => stream,
+        // Such as running out of file descriptors, which may pass.
+        Err(error) => {
+          eprintln!("Cannot accept HTTPS connection: {}", error);
+          tokio::time::sleep(Duration::from_secs(1)).await;
+          continue;
+        },
+      };
+      let (acceptor, service) = (acceptor.clone(), service.clone());
+      tokio::spawn(async move {
+        let Ok(Ok(stream)) = tokio::time::timeout(HANDSHAKE, acceptor.accept(stream)).await else {
+          return;
+        };
+        // Clients going away mid-request are no failure of ours.
+        let _ = Http::new().serve_connection(stream, service).with_upgrades().await;
+      });
+    }
+  }
+
+  fn server_config(tls: &config::ApiTlsConfig) -> io::Result<Arc<rustls::ServerConfig>> {
+    let invalid = |what: &str, path: &Path| io::Error::new(io::ErrorKind::InvalidData, format!("invalid {} in {}", what, path.display()));
+    let pem = |path: &Path| File::open(path).map(BufReader::new);
+    let chain = rustls_pemfile::certs(&mut pem(&tls.certificate_file)?)?
+      .into_iter()
+      .map(rustls::Certificate)
+      .collect();
+    let mut keys = pem(&tls.key_file)?;
+    let key = std::iter::from_fn(|| rustls_pemfile::read_one(&mut keys).transpose())
+      .find_map(|item| match item {
+        Ok(rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key)) => Some(key),
+        _ => None,
+      })
+      .ok_or_else(|| invalid("key", &tls.key_file))?;
+    let builder = rustls::ServerConfig::builder().with_safe_defaults();
+    let builder = match &tls.client_ca_file {
+      Some(path) => {
+        let mut authorities = rustls::RootCertStore::empty();
+        for certificate in rustls_pemfile::certs(&mut pem(path)?)? {
+          authorities.add(&rustls::Certificate(certificate)).map_err(|_tls_error| invalid("certificate", path))?;
+        }
+        builder.with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(authorities).boxed())
+      },
+      None => builder.with_no_client_auth(),
+    };
+    let config = builder
+      .with_single_cert(chain, rustls::PrivateKey(key))
+      .map_err(|_tls_error| invalid("certificate or key", &tls.certificate_file))?;
+    Ok(Arc::new(config))
+  }
+}
+
+#[cfg(not(feature = "tls"))]
+mod server {
+  use std::{convert::Infallible, io, net::SocketAddr};
+
+  use hyper::{service::Service, Body, Request, Response};
+
+  use crate::config;
+
+  pub(super) async fn serve<S>(_listen: SocketAddr, _tls: &config::ApiTlsConfig, _service: S) -> io::Result<()>
+  where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+  {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "HTTPS support is not compiled in"))
+  }
+}
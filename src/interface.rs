@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{profile, rate, snmp};
+
+const IF_NAME: &str = "1.3.6.1.2.1.31.1.1.1.1";
+const IF_HC_IN_OCTETS: &str = "1.3.6.1.2.1.31.1.1.1.6";
+const IF_HC_OUT_OCTETS: &str = "1.3.6.1.2.1.31.1.1.1.10";
+const IF_HIGH_SPEED: &str = "1.3.6.1.2.1.31.1.1.1.15";
+const IF_IN_OCTETS: &str = "1.3.6.1.2.1.2.2.1.10";
+const IF_OUT_OCTETS: &str = "1.3.6.1.2.1.2.2.1.16";
+const IF_TABLE: &str = "1.3.6.1.2.1.2.2.1";
+const IF_X_TABLE: &str = "1.3.6.1.2.1.31.1.1.1";
+
+// Current traffic on one interface. Rates need two readings, so they are
+// absent the first time an agent is asked; utilization also needs a speed.
+// `counterBits` tells whether the rates come from the 64-bit ifHC* counters
+// or, on interfaces without them, from the 32-bit ifTable ones.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Traffic {
+  pub index: u32,
+  pub name: Option<String>,
+  pub speed_mbps: Option<u64>,
+  pub in_bps: Option<f64>,
+  pub out_bps: Option<f64>,
+  pub in_utilization: Option<f64>,
+  pub out_utilization: Option<f64>,
+  pub counter_bits: Option<u8>,
+}
+
+// One ifTable row merged with its ifXTable row. The counters are the 64-bit
+// ifHC* ones where the agent has them, as `counterBits` tells, and the
+// speed is ifHighSpeed's where ifSpeed cannot hold it.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interface {
+  pub index: u32,
+  pub descr: Option<String>,
+  pub name: Option<String>,
+  pub alias: Option<String>,
+  // The IANAifType number, 6 for ethernetCsmacd.
+  pub if_type: Option<u32>,
+  pub mtu: Option<u32>,
+  pub speed_bps: Option<u64>,
+  pub phys_address: Option<String>,
+  pub admin_status: Option<Status>,
+  pub oper_status: Option<Status>,
+  pub in_octets: Option<u64>,
+  pub out_octets: Option<u64>,
+  pub in_unicast_packets: Option<u64>,
+  pub out_unicast_packets: Option<u64>,
+  pub in_discards: Option<u64>,
+  pub out_discards: Option<u64>,
+  pub in_errors: Option<u64>,
+  pub out_errors: Option<u64>,
+  pub counter_bits: Option<u8>,
+}
+
+// ifAdminStatus and ifOperStatus; the former only takes the first three.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Status {
+  Up,
+  Down,
+  Testing,
+  Unknown,
+  Dormant,
+  NotPresent,
+  LowerLayerDown,
+}
+
+impl Status {
+
+  fn new(value: &snmp::ObjectValue) -> Option<Status> {
+    Some(match value.as_f64()? as i64 {
+      1 => Status::Up,
+      2 => Status::Down,
+      3 => Status::Testing,
+      4 => Status::Unknown,
+      5 => Status::Dormant,
+      6 => Status::NotPresent,
+      7 => Status::LowerLayerDown,
+      _ => return None,
+    })
+  }
+}
+
+// Every interface of the agent, walking ifTable and, where the agent has
+// it, ifXTable.
+pub async fn discover_interfaces(target: &snmp::SnmpClient) -> snmp::Result<Vec<Interface>> {
+  let mut interfaces: BTreeMap<u32, Interface> = BTreeMap::new();
+  for (column, index, value) in rows(target, IF_TABLE).await? {
+    let interface = interfaces.entry(index).or_insert_with(|| Interface { index, ..Interface::default() });
+    let number = value.as_f64();
+    match column {
+      2 => interface.descr = Some(text(&value)),
+      3 => interface.if_type = number.map(|number| number as u32),
+      4 => interface.mtu = number.map(|number| number as u32),
+      5 => interface.speed_bps = number.map(|number| number as u64),
+      6 => interface.phys_address = Some(hex(&value)).filter(|address| !address.is_empty()),
+      7 => interface.admin_status = Status::new(&value),
+      8 => interface.oper_status = Status::new(&value),
+      10 => interface.in_octets = number.map(|number| number as u64),
+      11 => interface.in_unicast_packets = number.map(|number| number as u64),
+      13 => interface.in_discards = number.map(|number| number as u64),
+      14 => interface.in_errors = number.map(|number| number as u64),
+      16 => interface.out_octets = number.map(|number| number as u64),
+      17 => interface.out_unicast_packets = number.map(|number| number as u64),
+      19 => interface.out_discards = number.map(|number| number as u64),
+      20 => interface.out_errors = number.map(|number| number as u64),
+      _ => {},
+    }
+    if (10..=20).contains(&column) {
+      interface.counter_bits = Some(32);
+    }
+  }
+  for (column, index, value) in rows(target, IF_X_TABLE).await? {
+    let Some(interface) = interfaces.get_mut(&index) else {
+      continue;
+    };
+    let number = value.as_f64().map(|number| number as u64);
+    match column {
+      1 => interface.name = Some(text(&value)),
+      18 => interface.alias = Some(text(&value)).filter(|alias| !alias.is_empty()),
+      // ifSpeed tops out at 4294967295.
+      15 if interface.speed_bps.is_none_or(|speed| speed == u32::MAX as u64) => {
+        interface.speed_bps = number.map(|mbps| mbps * 1_000_000);
+      },
+      // Interfaces too slow for HC counters keep the 32-bit ones.
+      6 | 7 | 10 | 11 if number.is_some() => {
+        let counter = match column {
+          6 => &mut interface.in_octets,
+          7 => &mut interface.in_unicast_packets,
+          10 => &mut interface.out_octets,
+          _ => &mut interface.out_unicast_packets,
+        };
+        *counter = number;
+        interface.counter_bits = Some(64);
+      },
+      _ => {},
+    }
+  }
+  Ok(interfaces.into_values().collect())
+}
+
+fn text(value: &snmp::ObjectValue) -> String {
+  match value {
+    snmp::ObjectValue::OctetString(octets) => String::from_utf8_lossy(octets).trim_end_matches('\0').to_string(),
+    value => value.to_string(),
+  }
+}
+
+fn hex(value: &snmp::ObjectValue) -> String {
+  match value {
+    snmp::ObjectValue::OctetString(octets) => octets.iter().map(|octet| format!("{:02x}", octet)).collect::<Vec<_>>().join(":"),
+    value => value.to_string(),
+  }
+}
+
+// The column, index and value of every cell of a table.
+async fn rows(target: &snmp::SnmpClient, entry: &str) -> snmp::Result<Vec<(u32, u32, snmp::ObjectValue)>> {
+  let entry = oid(entry);
+  Ok(
+    target.walk(&entry).await?
+      .into_iter()
+      .filter(|binding| !binding.value.is_exception())
+      .filter_map(|binding| match binding.object_id.strip_prefix(&entry)? {
+        &[column, index] => Some((column, index, binding.value)),
+        _ => None,
+      })
+      .collect()
+  )
+}
+
+pub async fn collect(
+  target: &snmp::SnmpClient,
+  rates: &rate::Rates,
+) -> snmp::Result<Vec<Traffic>> {
+  let mut interfaces: BTreeMap<u32, Traffic> = BTreeMap::new();
+  for (index, binding) in walk(target, IF_NAME).await? {
+    entry(&mut interfaces, index).name = Some(binding.value.to_string());
+  }
+  for (index, binding) in walk(target, IF_HIGH_SPEED).await? {
+    entry(&mut interfaces, index).speed_mbps = binding.value.as_f64().map(|speed| speed as u64);
+  }
+  // Agents without ifXTable, and interfaces too slow for HC counters, only
+  // have the 32-bit ones.
+  let indexes = interfaces.keys().map(|index| vec![*index]).collect::<HashSet<_>>();
+  for (inbound, column, fallback) in [(true, IF_HC_IN_OCTETS, IF_IN_OCTETS), (false, IF_HC_OUT_OCTETS, IF_OUT_OCTETS)] {
+    let (column, fallback) = (oid(column), oid(fallback));
+    for (index, binding) in profile::walk_with_fallback(target, &column, &fallback, &indexes).await? {
+      let &[index] = index.as_slice() else {
+        continue;
+      };
+      let interface = entry(&mut interfaces, index);
+      let bps = rates.update(target, &binding).map(|rate| rate * 8.0);
+      match inbound {
+        true => interface.in_bps = bps,
+        false => interface.out_bps = bps,
+      }
+      interface.counter_bits = profile::counter_bits(&binding.value).or(interface.counter_bits);
+    }
+  }
+  Ok(
+    interfaces.into_values()
+      .map(|mut interface| {
+        let speed = interface.speed_mbps
+          .filter(|speed| *speed > 0)
+          .map(|speed| speed as f64 * 1_000_000.0);
+        let utilization = |bps: Option<f64>| Some(bps? / speed? * 100.0);
+        interface.in_utilization = utilization(interface.in_bps);
+        interface.out_utilization = utilization(interface.out_bps);
+        interface
+      })
+      .collect()
+  )
+}
+
+fn entry(interfaces: &mut BTreeMap<u32, Traffic>, index: u32) -> &mut Traffic {
+  interfaces.entry(index).or_insert_with(|| Traffic { index, ..Traffic::default() })
+}
+
+fn oid(oid: &str) -> snmp::ObjectIdentifier {
+  oid.parse().expect("IF-MIB OIDs are valid")
+}
+
+async fn walk(target: &snmp::SnmpClient, column: &str) -> snmp::Result<Vec<(u32, snmp::VariableBinding)>> {
+  let column = oid(column);
+  Ok(
+    target.walk(&column).await?
+      .into_iter()
+      .filter_map(|binding| match binding.object_id.strip_prefix(&column)? {
+        &[index] => Some((index, binding)),
+        _ => None,
+      })
+      .collect()
+  )
+}
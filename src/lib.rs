@@ -0,0 +1,31 @@
+pub mod snmp;
+pub mod mib;
+pub mod config;
+pub mod device;
+pub mod profile;
+pub mod rate;
+pub mod interface;
+pub mod aggregate;
+pub mod drift;
+pub mod http_client;
+pub mod sink;
+pub mod source;
+pub mod pipeline;
+pub mod collector;
+pub mod scheduler;
+pub mod nagios;
+pub mod prometheus;
+pub mod snmpwalk;
+pub mod stats;
+pub mod trace;
+pub mod agentx;
+pub mod proxy;
+pub mod trap;
+pub mod reload;
+pub mod responses;
+pub mod stream;
+pub mod walks;
+pub mod discovery;
+pub mod https;
+pub mod http_api;
+pub mod cli;
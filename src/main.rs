@@ -1,7 +1,66 @@
-mod snmp;
-mod http_api;
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+
+use snmp_sender::{agentx, cli, collector, config, drift, http_api, mib, profile, proxy, reload, sink, source, stats, trap};
 
 #[tokio::main]
 async fn main() {
-  http_api::serve().await;
+  let command = cli::Cli::parse().command.unwrap_or(cli::Command::Serve);
+  stats::start();
+  // Before the configuration and the command's OIDs, which may be given by
+  // name.
+  let mib_dir = std::env::var_os("SNMP_COLLECTOR_MIBS")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("mibs"));
+  mib::install(mib::load_dir(&mib_dir));
+  let path = std::env::var_os("SNMP_COLLECTOR_CONFIG").map(PathBuf::from);
+  let config = match &path {
+    Some(path) => config::load(path).unwrap_or_else(|error| {
+      eprintln!("Cannot load {}: {}", path.display(), error);
+      std::process::exit(1);
+    }),
+    None => config::Config::default(),
+  };
+  if !matches!(command, cli::Command::Serve) {
+    if let Err(error) = cli::run(command, &config).await {
+      eprintln!("{}", error);
+      std::process::exit(1);
+    }
+    return;
+  }
+  let pack_dir = std::env::var_os("SNMP_COLLECTOR_PACKS")
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("packs"));
+  let profiles = profile::load(&pack_dir);
+  let latest = Arc::new(collector::Latest::default());
+  let snapshots = Arc::new(drift::Store::default());
+  agentx::spawn(&config, profiles.len());
+  let traps = Arc::new(trap::Store::default());
+  trap::spawn(&config, &profiles, traps.clone()).unwrap_or_else(|error| {
+    eprintln!("Cannot set up the trap receiver: {}", error);
+    std::process::exit(1);
+  });
+  let reloader = reload::Reloader::start(
+    path,
+    config,
+    profiles.clone(),
+    sink::Registry::default(),
+    source::Registry::default(),
+    latest.clone(),
+    snapshots.clone(),
+  );
+  let reloader = Arc::new(reloader.unwrap_or_else(|error| {
+    eprintln!("Cannot set up collection: {}", error);
+    std::process::exit(1);
+  }));
+  reload::on_hangup(reloader.clone()).unwrap_or_else(|error| {
+    eprintln!("Cannot listen for SIGHUP: {}", error);
+    std::process::exit(1);
+  });
+  proxy::spawn(reloader.clone()).unwrap_or_else(|error| {
+    eprintln!("Cannot set up the SNMP proxy: {}", error);
+    std::process::exit(1);
+  });
+  http_api::serve(reloader, profiles, snapshots, latest, traps).await;
 }
@@ -0,0 +1,295 @@
+use std::{collections::{BTreeMap, HashMap}, fs, path::{Path, PathBuf}, sync::OnceLock};
+
+mod hint;
+mod parse;
+
+// The OIDs SNMPv2-SMI and its SMIv1 predecessors assign, which most MIB
+// directories lack since every module imports them.
+const SMI: [(&str, &[u32]); 17] = [
+  ("ccitt", &[0]),
+  ("zeroDotZero", &[0, 0]),
+  ("iso", &[1]),
+  ("joint-iso-ccitt", &[2]),
+  ("org", &[1, 3]),
+  ("dod", &[1, 3, 6]),
+  ("internet", &[1, 3, 6, 1]),
+  ("directory", &[1, 3, 6, 1, 1]),
+  ("mgmt", &[1, 3, 6, 1, 2]),
+  ("mib-2", &[1, 3, 6, 1, 2, 1]),
+  ("transmission", &[1, 3, 6, 1, 2, 1, 10]),
+  ("experimental", &[1, 3, 6, 1, 3]),
+  ("private", &[1, 3, 6, 1, 4]),
+  ("enterprises", &[1, 3, 6, 1, 4, 1]),
+  ("security", &[1, 3, 6, 1, 5]),
+  ("snmpV2", &[1, 3, 6, 1, 6]),
+  ("snmpModules", &[1, 3, 6, 1, 6, 3]),
+];
+const SMI_MODULE: &str = "SNMPv2-SMI";
+// The display hints of SNMPv2-TC, for directories that lack it.
+const TC: [(&str, &str); 4] = [
+  ("DisplayString", "255a"),
+  ("PhysAddress", "1x:"),
+  ("MacAddress", "1x:"),
+  ("DateAndTime", "2d-1d-1d,1d:1d:1d.1d,1a1d:1d"),
+];
+// How far one textual convention may be defined in terms of another.
+const CONVENTION_DEPTH: usize = 8;
+
+// The names MIB modules give to OIDs, both ways, and what they say of the
+// objects' values. An OID named by several modules, as RFC1213-MIB and
+// IF-MIB both name the interface objects, goes by the name of the module
+// loaded first.
+#[derive(Debug, Default)]
+pub struct Index {
+  names: BTreeMap<Vec<u32>, (String, String)>,
+  oids: HashMap<String, Vec<u32>>,
+  qualified: HashMap<(String, String), Vec<u32>>,
+  objects: HashMap<Vec<u32>, Object>,
+}
+
+#[derive(Debug, Default)]
+struct Object {
+  // The DISPLAY-HINT of the object's textual convention.
+  hint: Option<String>,
+  // The names of an enumerated INTEGER's values, as ifOperStatus has up(1)
+  // and down(2), or with `bits` those of the bits of BITS, numbered from
+  // the first octet's most significant.
+  labels: HashMap<i64, String>,
+  bits: bool,
+}
+
+impl Index {
+
+  // Names the OID, unless named already.
+  fn name_first(&mut self, module: &str, name: &str, oid: &[u32]) {
+    self.names.entry(oid.to_vec()).or_insert_with(|| (module.to_string(), name.to_string()));
+    self.oids.entry(name.to_string()).or_insert_with(|| oid.to_vec());
+  }
+
+  // The OID as the most specific name above it and the arcs below, as in
+  // `IF-MIB::ifInOctets.3`.
+  pub fn name(&self, oid: &[u32]) -> Option<String> {
+    let (length, (module, name)) = (1..=oid.len()).rev()
+      .find_map(|length| Some((length, self.names.get(&oid[..length])?)))?;
+    let mut text = format!("{}::{}", module, name);
+    for arc in &oid[length..] {
+      text.push_str(&format!(".{}", arc));
+    }
+    Some(text)
+  }
+
+  // The OID of `ifInOctets.3` or `IF-MIB::ifInOctets.3`.
+  pub fn resolve(&self, text: &str) -> Option<Vec<u32>> {
+    let (module, text) = match text.split_once("::") {
+      Some((module, text)) => (Some(module), text),
+      None => (None, text),
+    };
+    let mut segments = text.split('.');
+    let name = segments.next()?;
+    let oid = match module {
+      Some(module) => self.qualified.get(&(module.to_string(), name.to_string()))?,
+      None => self.oids.get(name)?,
+    };
+    let mut oid = oid.clone();
+    for segment in segments {
+      oid.push(segment.parse().ok()?);
+    }
+    Some(oid)
+  }
+
+  // The object an instance OID such as ifPhysAddress.3 belongs to.
+  fn object(&self, oid: &[u32]) -> Option<&Object> {
+    (1..=oid.len()).rev().find_map(|length| self.objects.get(&oid[..length]))
+  }
+
+  // The name of the OBJECT-TYPE an instance OID belongs to, and the arcs of
+  // the instance: `ifPhysAddress` and `[3]` for ifPhysAddress.3.
+  pub fn object_name<'a>(&self, oid: &'a [u32]) -> Option<(&str, &'a [u32])> {
+    let length = (1..=oid.len()).rev().find(|length| self.objects.contains_key(&oid[..*length]))?;
+    let (_module, name) = self.names.get(&oid[..length])?;
+    Some((name, &oid[length..]))
+  }
+
+  // The instance's octets as its DISPLAY-HINT lays them out.
+  pub fn display_octets(&self, oid: &[u32], octets: &[u8]) -> Option<String> {
+    hint::octets(self.object(oid)?.hint.as_ref()?, octets)
+  }
+
+  pub fn display_integer(&self, oid: &[u32], value: i64) -> Option<String> {
+    hint::integer(self.object(oid)?.hint.as_ref()?, value)
+  }
+
+  pub fn label(&self, oid: &[u32], value: i64) -> Option<&str> {
+    let object = self.object(oid).filter(|object| !object.bits)?;
+    object.labels.get(&value).map(String::as_str)
+  }
+
+  // The names of the bits set in the octets of a BITS instance, in bit
+  // order; bits the MIB does not name are left out.
+  pub fn flags(&self, oid: &[u32], octets: &[u8]) -> Option<Vec<&str>> {
+    let object = self.object(oid).filter(|object| object.bits)?;
+    let mut flags = object.labels.iter()
+      .filter(|(bit, _)| usize::try_from(**bit).ok()
+        .and_then(|bit| Some(octets.get(bit / 8)? & (0x80 >> (bit % 8)) != 0))
+        .unwrap_or(false))
+      .collect::<Vec<_>>();
+    flags.sort();
+    Some(flags.into_iter().map(|(_, label)| label.as_str()).collect())
+  }
+}
+
+static INDEX: OnceLock<Index> = OnceLock::new();
+
+// Makes the names known to `name` and `resolve`, and so to the parsing of
+// OIDs; only the first index installed counts.
+pub fn install(index: Index) {
+  let _ = INDEX.set(index);
+}
+
+pub fn name(oid: &[u32]) -> Option<String> {
+  INDEX.get()?.name(oid)
+}
+
+pub fn resolve(text: &str) -> Option<Vec<u32>> {
+  INDEX.get()?.resolve(text)
+}
+
+pub fn object_name(oid: &[u32]) -> Option<(&'static str, &[u32])> {
+  INDEX.get()?.object_name(oid)
+}
+
+pub fn display_octets(oid: &[u32], octets: &[u8]) -> Option<String> {
+  INDEX.get()?.display_octets(oid, octets)
+}
+
+pub fn display_integer(oid: &[u32], value: i64) -> Option<String> {
+  INDEX.get()?.display_integer(oid, value)
+}
+
+pub fn label(oid: &[u32], value: i64) -> Option<&'static str> {
+  INDEX.get()?.label(oid, value)
+}
+
+pub fn flags(oid: &[u32], octets: &[u8]) -> Option<Vec<&'static str>> {
+  INDEX.get()?.flags(oid, octets)
+}
+
+// Reads every file in the directory as MIB modules, in file name order. A
+// missing directory simply means there are no MIBs; definitions whose
+// parents no module defines are left out.
+pub fn load_dir(dir: &Path) -> Index {
+  let mut paths = match fs::read_dir(dir) {
+    Ok(entries) => entries
+      .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|path| path.is_file())
+      .collect::<Vec<PathBuf>>(),
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+    Err(error) => {
+      eprintln!("Cannot read MIB directory {}: {}", dir.display(), error);
+      Vec::new()
+    },
+  };
+  paths.sort();
+  let modules = paths.iter()
+    .filter_map(|path| match fs::read(path) {
+      Ok(text) => Some(parse::modules(&String::from_utf8_lossy(&text))),
+      Err(error) => {
+        eprintln!("Skipping MIB {}: {}", path.display(), error);
+        None
+      },
+    })
+    .flatten()
+    .collect::<Vec<_>>();
+  index(&modules)
+}
+
+// Resolves the modules' definitions against each other: a parent name is
+// looked up in the module itself, then in the module it is imported from,
+// then anywhere. Definitions may come in any order, hence the rounds; names
+// are chosen in module order once all are resolved.
+fn index(modules: &[parse::Module]) -> Index {
+  let mut index = Index::default();
+  for (name, oid) in SMI {
+    index.qualified.insert((SMI_MODULE.to_string(), name.to_string()), oid.to_vec());
+    index.name_first(SMI_MODULE, name, oid);
+  }
+  let mut pending = modules.iter()
+    .flat_map(|module| module.definitions.iter().map(move |definition| (module, definition)))
+    .collect::<Vec<_>>();
+  loop {
+    let before = pending.len();
+    pending.retain(|(module, definition)| {
+      let parent = match &definition.parent {
+        parse::Parent::Root(arc) => Some(vec![*arc]),
+        parse::Parent::Name(parent) => index.qualified.get(&(module.name.clone(), parent.clone()))
+          .or_else(|| index.qualified.get(&(module.imports.get(parent)?.clone(), parent.clone())))
+          .or_else(|| index.oids.get(parent))
+          .cloned(),
+      };
+      let Some(mut oid) = parent else {
+        return true;
+      };
+      oid.extend(&definition.arcs);
+      index.oids.entry(definition.name.clone()).or_insert_with(|| oid.clone());
+      index.qualified.insert((module.name.clone(), definition.name.clone()), oid);
+      false
+    });
+    if pending.is_empty() || pending.len() == before {
+      break;
+    }
+  }
+  index.oids.retain(|name, _| SMI.iter().any(|(smi, _)| smi == name));
+  for module in modules {
+    for definition in &module.definitions {
+      if let Some(oid) = index.qualified.get(&(module.name.clone(), definition.name.clone())).cloned() {
+        index.name_first(&module.name, &definition.name, &oid);
+        if let Some(syntax) = &definition.syntax {
+          index.objects.entry(oid).or_insert_with(|| object(modules, module, syntax));
+        }
+      }
+    }
+  }
+  index
+}
+
+// What the object's syntax makes of its values, following textual
+// conventions defined in terms of others to the first with a hint, and the
+// first with an enumeration. As with parent names, a convention is looked
+// for in the module itself, then in the module it is imported from, then
+// anywhere.
+fn object(modules: &[parse::Module], module: &parse::Module, syntax: &parse::Syntax) -> Object {
+  let mut object = Object::default();
+  let with_labels = |object: &mut Object, syntax: &parse::Syntax| if object.labels.is_empty() {
+    object.labels = syntax.labels.iter().cloned().collect();
+    object.bits = syntax.name == "BITS";
+  };
+  with_labels(&mut object, syntax);
+  let (mut module, mut name) = (module, syntax.name.as_str());
+  for _ in 0..CONVENTION_DEPTH {
+    let found = module.conventions.get(name).map(|convention| (module, convention))
+      .or_else(|| {
+        let from = module.imports.get(name)?;
+        let module = modules.iter().find(|module| &module.name == from)?;
+        Some((module, module.conventions.get(name)?))
+      })
+      .or_else(|| modules.iter().find_map(|module| Some((module, module.conventions.get(name)?))));
+    let Some((defined_in, convention)) = found else {
+      if object.hint.is_none() {
+        object.hint = TC.iter().find(|(convention, _)| *convention == name).map(|(_, hint)| hint.to_string());
+      }
+      break;
+    };
+    if object.hint.is_none() {
+      object.hint = convention.hint.clone();
+    }
+    let Some(syntax) = &convention.syntax else {
+      break;
+    };
+    with_labels(&mut object, syntax);
+    if object.hint.is_some() && !object.labels.is_empty() {
+      break;
+    }
+    (module, name) = (defined_in, syntax.name.as_str());
+  }
+  object
+}
@@ -0,0 +1,105 @@
+// DISPLAY-HINT rendering as RFC 2579 describes it.
+
+// One part of an octet string hint, such as the `1x:` of a MAC address or
+// the `*1d.` of a repeated group.
+struct Spec {
+  repeat: bool,
+  length: usize,
+  format: char,
+  separator: Option<char>,
+  terminator: Option<char>,
+}
+
+fn specs(hint: &str) -> Option<Vec<Spec>> {
+  let chars = hint.chars().collect::<Vec<_>>();
+  let is_delimiter = |at: usize| chars.get(at).is_some_and(|char| !char.is_ascii_digit() && *char != '*');
+  let mut specs = Vec::new();
+  let mut at = 0;
+  while at < chars.len() {
+    let repeat = chars[at] == '*';
+    if repeat {
+      at += 1;
+    }
+    let digits = chars[at..].iter().take_while(|char| char.is_ascii_digit()).count();
+    let length = chars[at..at + digits].iter().collect::<String>().parse().ok().filter(|length| *length > 0)?;
+    at += digits;
+    let format = *chars.get(at).filter(|format| "dxoat".contains(**format))?;
+    at += 1;
+    let separator = is_delimiter(at).then(|| chars[at]);
+    if separator.is_some() {
+      at += 1;
+    }
+    let terminator = (repeat && is_delimiter(at)).then(|| chars[at]);
+    if terminator.is_some() {
+      at += 1;
+    }
+    specs.push(Spec { repeat, length, format, separator, terminator });
+  }
+  (!specs.is_empty()).then_some(specs)
+}
+
+// The octets as the hint lays them out; the last part of the hint applies
+// to whatever octets remain. None for hints that cannot be read.
+pub fn octets(hint: &str, octets: &[u8]) -> Option<String> {
+  let specs = specs(hint)?;
+  let mut text = String::new();
+  let mut rest = octets;
+  let mut next = 0;
+  while !rest.is_empty() {
+    let spec = &specs[next.min(specs.len() - 1)];
+    next += 1;
+    let count = match spec.repeat {
+      true => {
+        let (count, after) = rest.split_first()?;
+        rest = after;
+        *count as usize
+      },
+      false => 1,
+    };
+    for done in 1..=count {
+      if rest.is_empty() {
+        break;
+      }
+      let (chunk, after) = rest.split_at(spec.length.min(rest.len()));
+      rest = after;
+      let number = || chunk.iter().try_fold(0u128, |number, octet| number.checked_mul(256)?.checked_add(*octet as u128));
+      match spec.format {
+        'd' => text.push_str(&number()?.to_string()),
+        'x' => text.push_str(&format!("{:0width$x}", number()?, width = chunk.len() * 2)),
+        'o' => text.push_str(&format!("{:o}", number()?)),
+        _ => text.push_str(&String::from_utf8_lossy(chunk)),
+      }
+      // Neither comes after the last octet.
+      let delimiter = match done == count {
+        true => spec.terminator.or(spec.separator),
+        false => spec.separator,
+      };
+      if let Some(delimiter) = delimiter.filter(|_| !rest.is_empty()) {
+        text.push(delimiter);
+      }
+    }
+  }
+  Some(text)
+}
+
+// The integer as `d`, `d-2` (hundredths), `x`, `o` or `b` have it.
+pub fn integer(hint: &str, value: i64) -> Option<String> {
+  let sign = if value < 0 { "-" } else { "" };
+  let magnitude = value.unsigned_abs();
+  Some(match hint.split_once('-') {
+    Some(("d", places)) => {
+      let places = places.parse::<usize>().ok()?;
+      let digits = format!("{:0width$}", magnitude, width = places + 1);
+      let (whole, fraction) = digits.split_at(digits.len() - places);
+      match places {
+        0 => format!("{}{}", sign, whole),
+        _ => format!("{}{}.{}", sign, whole, fraction),
+      }
+    },
+    None if hint == "d" => value.to_string(),
+    None if hint == "x" => format!("{}{:x}", sign, magnitude),
+    None if hint == "o" => format!("{}{:o}", sign, magnitude),
+    None if hint == "b" => format!("{}{:b}", sign, magnitude),
+    _ => return None,
+  })
+}
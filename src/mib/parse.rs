@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+// The macros whose invocations assign an OID to the name before them.
+const MACROS: [&str; 8] = [
+  "OBJECT-TYPE",
+  "MODULE-IDENTITY",
+  "OBJECT-IDENTITY",
+  "NOTIFICATION-TYPE",
+  "OBJECT-GROUP",
+  "NOTIFICATION-GROUP",
+  "MODULE-COMPLIANCE",
+  "AGENT-CAPABILITIES",
+];
+
+// A MIB module as far as naming and rendering go: what it imports from
+// where, the OIDs it assigns, each relative to a parent name or number, and
+// the textual conventions it defines.
+pub struct Module {
+  pub name: String,
+  pub imports: HashMap<String, String>,
+  pub definitions: Vec<Definition>,
+  pub conventions: HashMap<String, Convention>,
+}
+
+pub struct Definition {
+  pub name: String,
+  pub parent: Parent,
+  pub arcs: Vec<u32>,
+  // The SYNTAX of an OBJECT-TYPE.
+  pub syntax: Option<Syntax>,
+}
+
+pub struct Convention {
+  pub hint: Option<String>,
+  pub syntax: Option<Syntax>,
+}
+
+// A type as named in a SYNTAX clause, constraints left out, with the
+// labels of an enumerated INTEGER or the named bits of BITS.
+pub struct Syntax {
+  pub name: String,
+  pub labels: Vec<(i64, String)>,
+}
+
+pub enum Parent {
+  Name(String),
+  Root(u32),
+}
+
+// Reads the modules of a MIB file. What is not understood is skipped, so a
+// file with macros of its own still yields the OIDs it assigns.
+pub fn modules(text: &str) -> Vec<Module> {
+  let tokens = tokenize(text);
+  let mut modules = Vec::new();
+  let mut at = 0;
+  while at + 3 < tokens.len() {
+    if tokens[at + 1] != "DEFINITIONS" {
+      at += 1;
+      continue;
+    }
+    let Some(begin) = tokens[at..].iter().position(|token| token == "BEGIN").map(|begin| at + begin) else {
+      break;
+    };
+    let end = tokens[begin..].iter().position(|token| token == "END").map_or(tokens.len(), |end| begin + end);
+    modules.push(module(&tokens[at], &tokens[begin + 1..end]));
+    at = end + 1;
+  }
+  modules
+}
+
+fn module(name: &str, tokens: &[String]) -> Module {
+  let mut module = Module {
+    name: name.to_string(),
+    imports: HashMap::new(),
+    definitions: Vec::new(),
+    conventions: HashMap::new(),
+  };
+  let mut at = 0;
+  if tokens.first().is_some_and(|token| token == "IMPORTS") {
+    let end = tokens.iter().position(|token| token == ";").unwrap_or(tokens.len());
+    let mut names = Vec::new();
+    let mut imports = tokens[1..end].iter();
+    while let Some(token) = imports.next() {
+      match token.as_str() {
+        "," => {},
+        "FROM" => {
+          let Some(from) = imports.next() else {
+            break;
+          };
+          module.imports.extend(names.drain(..).map(|name| (name, from.clone())));
+        },
+        _ => names.push(token.clone()),
+      }
+    }
+    at = end;
+  }
+  while at + 1 < tokens.len() {
+    if tokens[at + 1] == "::=" && tokens.get(at + 2).is_some_and(|token| token == "TEXTUAL-CONVENTION") {
+      let (convention, end) = convention(tokens, at + 3);
+      module.conventions.insert(tokens[at].clone(), convention);
+      at = end;
+      continue;
+    }
+    let assigns = match tokens[at + 1].as_str() {
+      "OBJECT" => tokens.get(at + 2).is_some_and(|token| token == "IDENTIFIER")
+        && tokens.get(at + 3).is_some_and(|token| token == "::="),
+      keyword => MACROS.contains(&keyword),
+    };
+    if !assigns || !tokens[at].starts_with(|first: char| first.is_ascii_lowercase()) {
+      at += 1;
+      continue;
+    }
+    let Some(value) = tokens[at..].windows(2).position(|pair| pair[0] == "::=" && pair[1] == "{").map(|value| at + value + 2) else {
+      break;
+    };
+    let end = tokens[value..].iter().position(|token| token == "}").map_or(tokens.len(), |end| value + end);
+    if let Some(mut definition) = definition(&tokens[at], &tokens[value..end]) {
+      if tokens[at + 1] == "OBJECT-TYPE" {
+        definition.syntax = tokens[at..value].iter().position(|token| token == "SYNTAX")
+          .and_then(|clause| syntax(&tokens[..value], at + clause + 1))
+          .map(|(syntax, _)| syntax);
+      }
+      module.definitions.push(definition);
+    }
+    at = end;
+  }
+  module
+}
+
+// An OID value such as `{ ifEntry 10 }` or `{ iso org(3) dod(6) 1 }`.
+fn definition(name: &str, value: &[String]) -> Option<Definition> {
+  let mut components = Vec::new();
+  let mut at = 0;
+  while at < value.len() {
+    // `name(number)` stands for the number once past the first component.
+    if value.get(at + 1).is_some_and(|token| token == "(") && value.get(at + 3).is_some_and(|token| token == ")") {
+      components.push(if components.is_empty() { value[at].clone() } else { value[at + 2].clone() });
+      at += 4;
+    } else {
+      components.push(value[at].clone());
+      at += 1;
+    }
+  }
+  let (first, rest) = components.split_first()?;
+  let parent = match first.parse() {
+    Ok(number) => Parent::Root(number),
+    Err(_) => Parent::Name(first.clone()),
+  };
+  let arcs = rest.iter().map(|arc| arc.parse().ok()).collect::<Option<Vec<u32>>>()?;
+  Some(Definition { name: name.to_string(), parent, arcs, syntax: None })
+}
+
+// The clauses of a TEXTUAL-CONVENTION from its first, up to the end of its
+// SYNTAX, and where it ends.
+fn convention(tokens: &[String], mut at: usize) -> (Convention, usize) {
+  let mut convention = Convention { hint: None, syntax: None };
+  while at < tokens.len() {
+    match tokens[at].as_str() {
+      "DISPLAY-HINT" => {
+        convention.hint = tokens.get(at + 1).map(|hint| hint.trim_matches('"').to_string());
+        at += 2;
+      },
+      "SYNTAX" => {
+        if let Some((syntax, end)) = syntax(tokens, at + 1) {
+          convention.syntax = Some(syntax);
+          at = end;
+        }
+        break;
+      },
+      _ => at += 1,
+    }
+  }
+  (convention, at)
+}
+
+// The type at `at`, as in `OCTET STRING (SIZE (6))`, `INTEGER { up(1),
+// down(2) }` or `BITS { sendPause(0), recvPause(1) }`, and where its name,
+// or its enumeration, ends.
+fn syntax(tokens: &[String], at: usize) -> Option<(Syntax, usize)> {
+  let first = tokens.get(at)?;
+  let (name, mut end) = match (first.as_str(), tokens.get(at + 1).map(String::as_str)) {
+    ("OCTET", Some("STRING")) | ("OBJECT", Some("IDENTIFIER")) => (format!("{} {}", first, tokens[at + 1]), at + 2),
+    _ => (first.clone(), at + 1),
+  };
+  let mut labels = Vec::new();
+  if (name == "INTEGER" || name == "BITS") && tokens.get(end).is_some_and(|token| token == "{") {
+    let close = tokens[end..].iter().position(|token| token == "}").map_or(tokens.len(), |close| end + close);
+    for item in tokens[end + 1..close].split(|token| token == ",") {
+      // `label(number)`, the number perhaps negative.
+      if let [label, open, number @ .., shut] = item {
+        let number = number.concat().parse();
+        if let (true, Ok(number)) = (open == "(" && shut == ")", number) {
+          labels.push((number, label.clone()));
+        }
+      }
+    }
+    end = close + 1;
+  }
+  Some((Syntax { name, labels }, end))
+}
+
+// Splits ASN.1 into identifiers, numbers, quoted strings and punctuation,
+// leaving out comments, which run from `--` to the end of the line or to
+// the next `--`.
+fn tokenize(text: &str) -> Vec<String> {
+  let chars = text.chars().collect::<Vec<_>>();
+  let at_pair = |at: usize, pair: &str| chars[at..].iter().take(2).copied().eq(pair.chars());
+  let mut tokens = Vec::new();
+  let mut at = 0;
+  while at < chars.len() {
+    let start = at;
+    at += 1;
+    match chars[start] {
+      char if char.is_whitespace() => {},
+      '-' if at_pair(start, "--") => {
+        at = start + 2;
+        while at < chars.len() && chars[at] != '\n' && !at_pair(at, "--") {
+          at += 1;
+        }
+        if at_pair(at, "--") {
+          at += 2;
+        }
+      },
+      quote @ ('"' | '\'') => {
+        while at < chars.len() && chars[at] != quote {
+          at += 1;
+        }
+        at += 1;
+        // The radix of '0F'H and '1010'B strings.
+        if quote == '\'' && chars.get(at).is_some_and(char::is_ascii_alphabetic) {
+          at += 1;
+        }
+        tokens.push(chars[start..at.min(chars.len())].iter().collect());
+      },
+      ':' if at_pair(start, "::") => {
+        at = start + 2;
+        if chars.get(at) == Some(&'=') {
+          at += 1;
+        }
+        tokens.push(chars[start..at].iter().collect());
+      },
+      char if char.is_ascii_alphanumeric() => {
+        // Names may hold single hyphens; two start a comment.
+        while at < chars.len() && (chars[at].is_ascii_alphanumeric() || chars[at] == '_' || (chars[at] == '-' && !at_pair(at, "--"))) {
+          at += 1;
+        }
+        tokens.push(chars[start..at].iter().collect());
+      },
+      char => tokens.push(char.to_string()),
+    }
+  }
+  tokens
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Abridged from RFC 2863.
+  const IF_MIB: &str = r#"
+IF-MIB DEFINITIONS ::= BEGIN
+
+IMPORTS
+    MODULE-IDENTITY, OBJECT-TYPE, Integer32, mib-2 FROM SNMPv2-SMI
+    TEXTUAL-CONVENTION, DisplayString FROM SNMPv2-TC;
+
+ifMIB MODULE-IDENTITY
+    LAST-UPDATED "200006140000Z"
+    ORGANIZATION "IETF Interfaces MIB Working Group"
+    DESCRIPTION  "The MIB module to describe generic objects for network
+                 interface sub-layers."
+    ::= { mib-2 31 }
+
+InterfaceIndex ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "d"
+    STATUS       current
+    DESCRIPTION  "A unique value, greater than zero, for each interface."
+    SYNTAX       Integer32 (1..2147483647)
+
+interfaces   OBJECT IDENTIFIER ::= { mib-2 2 }
+
+ifTable OBJECT-TYPE
+    SYNTAX      SEQUENCE OF IfEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "A list of interface entries."
+    ::= { interfaces 2 }
+
+ifEntry OBJECT-TYPE
+    SYNTAX      IfEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "An entry containing management information."
+    INDEX   { ifIndex }
+    ::= { ifTable 1 }
+
+IfEntry ::=
+    SEQUENCE {
+        ifIndex                 InterfaceIndex,
+        ifDescr                 DisplayString,
+        ifOperStatus            INTEGER
+    }
+
+ifIndex OBJECT-TYPE
+    SYNTAX      InterfaceIndex
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "A unique value, greater than zero, for each interface."
+    ::= { ifEntry 1 }
+
+ifDescr OBJECT-TYPE
+    SYNTAX      DisplayString (SIZE (0..255))
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "A textual string containing information about the
+                -- not a comment inside a string
+                interface."
+    ::= { ifEntry 2 }
+
+ifOperStatus OBJECT-TYPE
+    SYNTAX  INTEGER {
+                up(1),        -- ready to pass packets
+                down(2),
+                testing(3),   -- in some test mode
+                unknown(4),   -- status can not be determined
+                dormant(5),
+                notPresent(6),
+                lowerLayerDown(7)
+            }
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "The current operational state of the interface."
+    ::= { ifEntry 8 }
+
+END
+"#;
+
+  #[test]
+  fn tokenizes_asn1() {
+    let tokens = tokenize("a-b OBJECT IDENTIFIER ::= { iso(1) 3 } -- comment\nx -- inline -- y \"q -- r\" '0F'H");
+    assert_eq!(tokens, ["a-b", "OBJECT", "IDENTIFIER", "::=", "{", "iso", "(", "1", ")", "3", "}", "x", "y", "\"q -- r\"", "'0F'H"]);
+  }
+
+  #[test]
+  fn reads_modules() {
+    let modules = modules(IF_MIB);
+    let [module] = modules.as_slice() else { panic!("{} modules", modules.len()) };
+    assert_eq!(module.name, "IF-MIB");
+    assert_eq!(module.imports["mib-2"], "SNMPv2-SMI");
+    assert_eq!(module.imports["DisplayString"], "SNMPv2-TC");
+    let definitions = module.definitions.iter()
+      .map(|definition| match &definition.parent {
+        Parent::Name(parent) => (definition.name.as_str(), parent.as_str(), definition.arcs.clone()),
+        Parent::Root(_) => panic!("{} under a root", definition.name),
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(definitions, [
+      ("ifMIB", "mib-2", vec![31]),
+      ("interfaces", "mib-2", vec![2]),
+      ("ifTable", "interfaces", vec![2]),
+      ("ifEntry", "ifTable", vec![1]),
+      ("ifIndex", "ifEntry", vec![1]),
+      ("ifDescr", "ifEntry", vec![2]),
+      ("ifOperStatus", "ifEntry", vec![8]),
+    ]);
+  }
+
+  #[test]
+  fn reads_syntaxes() {
+    let module = modules(IF_MIB).remove(0);
+    let syntax = |name: &str| module.definitions.iter().find(|definition| definition.name == name).and_then(|definition| definition.syntax.as_ref());
+    assert_eq!(syntax("ifIndex").map(|syntax| syntax.name.as_str()), Some("InterfaceIndex"));
+    assert_eq!(syntax("ifDescr").map(|syntax| syntax.name.as_str()), Some("DisplayString"));
+    let status = syntax("ifOperStatus").unwrap();
+    assert_eq!(status.name, "INTEGER");
+    assert_eq!(status.labels[0], (1, "up".to_string()));
+    assert_eq!(status.labels[6], (7, "lowerLayerDown".to_string()));
+    assert_eq!(status.labels.len(), 7);
+    let index = &module.conventions["InterfaceIndex"];
+    assert_eq!(index.hint.as_deref(), Some("d"));
+    assert_eq!(index.syntax.as_ref().map(|syntax| syntax.name.as_str()), Some("Integer32"));
+  }
+
+  #[test]
+  fn reads_root_definitions() {
+    let internet = definition("internet", &tokenize("iso org(3) dod(6) 1")).unwrap();
+    assert!(matches!(internet.parent, Parent::Name(ref parent) if parent == "iso"));
+    assert_eq!(internet.arcs, [3, 6, 1]);
+    let org = definition("org", &tokenize("1 3")).unwrap();
+    assert!(matches!(org.parent, Parent::Root(1)));
+    assert_eq!(org.arcs, [3]);
+  }
+}
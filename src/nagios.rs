@@ -0,0 +1,140 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::profile;
+
+// A check evaluated on request against the latest collected samples of a
+// target, for monitoring systems speaking the Nagios plugin API.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Check {
+  pub name: String,
+  pub metric: String,
+  // Only samples carrying these label values are evaluated.
+  #[serde(default)]
+  pub labels: BTreeMap<String, String>,
+  #[serde(default)]
+  pub warning: Option<Range>,
+  #[serde(default)]
+  pub critical: Option<Range>,
+}
+
+// A threshold range in the Nagios plugin syntax: `10` alerts outside 0..10,
+// `10:` below 10, `~:10` above 10, `10:20` outside 10..20 and `@10:20`
+// inside it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Range {
+  text: String,
+  start: f64,
+  end: f64,
+  inside: bool,
+}
+
+impl Range {
+
+  fn alerts(&self, value: f64) -> bool {
+    let within = self.start <= value && value <= self.end;
+    within == self.inside
+  }
+}
+
+impl FromStr for Range {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let (inside, range) = match text.strip_prefix('@') {
+      Some(range) => (true, range),
+      None => (false, text),
+    };
+    let (start, end) = range.split_once(':').unwrap_or(("0", range));
+    let bound = |bound: &str, unbounded: f64| match bound {
+      "" | "~" => Ok(unbounded),
+      bound => bound.parse::<f64>().map_err(|_| format!("invalid threshold range {}", text)),
+    };
+    let (start, end) = (bound(start, f64::NEG_INFINITY)?, bound(end, f64::INFINITY)?);
+    if start > end {
+      return Err(format!("invalid threshold range {}", text));
+    }
+    Ok(Range { text: text.to_string(), start, end, inside })
+  }
+}
+
+impl TryFrom<String> for Range {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+  Ok,
+  Warning,
+  Critical,
+  Unknown,
+}
+
+impl Display for State {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      State::Ok => "OK",
+      State::Warning => "WARNING",
+      State::Critical => "CRITICAL",
+      State::Unknown => "UNKNOWN",
+    })
+  }
+}
+
+// The plugin output line: the state of the worst matching sample, followed
+// by one performance data entry per sample.
+pub fn evaluate(check: &Check, samples: &[profile::Sample]) -> String {
+  let samples = samples.iter()
+    .filter(|sample| sample.name == check.metric)
+    .filter(|sample| check.labels.iter().all(|(label, value)| sample.labels.get(label) == Some(value)))
+    .collect::<Vec<_>>();
+  if samples.is_empty() {
+    return format!("{} - no samples of {}", State::Unknown, check.metric);
+  }
+  let state_of = |value: f64| {
+    if check.critical.as_ref().is_some_and(|range| range.alerts(value)) {
+      State::Critical
+    } else if check.warning.as_ref().is_some_and(|range| range.alerts(value)) {
+      State::Warning
+    } else {
+      State::Ok
+    }
+  };
+  let (state, worst) = samples.iter()
+    .map(|sample| (state_of(sample.value), sample))
+    .max_by_key(|(state, _)| *state)
+    .expect("samples are not empty");
+  let (warning, critical) = (threshold(&check.warning), threshold(&check.critical));
+  let performance = samples.iter()
+    .map(|sample| format!(
+      "'{}'={};{};{};;",
+      label(sample),
+      sample.value,
+      warning,
+      critical,
+    ))
+    .collect::<Vec<_>>()
+    .join(" ");
+  format!("{} - {} = {} | {}", state, label(worst), worst.value, performance)
+}
+
+fn threshold(range: &Option<Range>) -> &str {
+  range.as_ref().map_or("", |range| &range.text)
+}
+
+// The sample name with its label values, e.g. `ifInErrors[eth0]`; quotes
+// and equals signs cannot appear in performance data labels.
+fn label(sample: &profile::Sample) -> String {
+  let label = match sample.labels.is_empty() {
+    true => sample.name.clone(),
+    false => format!("{}[{}]", sample.name, sample.labels.values().cloned().collect::<Vec<_>>().join(",")),
+  };
+  label.replace(['\'', '='], "_")
+}
@@ -0,0 +1,200 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::{Mutex, OnceLock}};
+
+use serde::Deserialize;
+
+use crate::{config, profile, rate, snmp};
+
+pub mod expression;
+
+// One processing step applied to the samples of a source before they reach
+// the sinks. `names` restricts a stage to matching sample names, where a
+// trailing `*` matches any suffix; an empty list matches every sample.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+  // Keeps matching samples, or drops them with `drop = true`. `labels`
+  // further requires the given label values.
+  Filter {
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    drop: bool,
+  },
+  Scale {
+    #[serde(default)]
+    names: Vec<String>,
+    factor: f64,
+  },
+  // Renames samples and labels; `prefix` is prepended to sample names.
+  Rename {
+    #[serde(default)]
+    names: BTreeMap<String, String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    prefix: Option<String>,
+  },
+  // Adds fixed labels and, with `tags`, the target's tags and name.
+  Enrich {
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    tags: bool,
+  },
+  // Adds samples named `name` computed by `expression` from the samples
+  // already there, such as `(ifInErrors + ifOutErrors) / interval`, one
+  // for each label set of the samples referred to, a single sample of a
+  // name going with every one.
+  Derive {
+    name: String,
+    expression: expression::Expression,
+    #[serde(default)]
+    kind: profile::MetricKind,
+  },
+  // Sends matching samples to the named sinks only.
+  Route {
+    #[serde(default)]
+    names: Vec<String>,
+    sinks: Vec<String>,
+  },
+}
+
+// A sample on its way out, with the sinks it is restricted to, if any.
+pub struct Routed {
+  pub sample: profile::Sample,
+  pub sinks: Option<BTreeSet<String>>,
+}
+
+impl Routed {
+
+  pub fn goes_to(&self, sink: &str) -> bool {
+    self.sinks.as_ref().is_none_or(|sinks| sinks.contains(sink))
+  }
+}
+
+fn matches(names: &[String], name: &str) -> bool {
+  names.is_empty() || names.iter().any(|pattern| match pattern.strip_suffix('*') {
+    Some(prefix) => name.starts_with(prefix),
+    None => pattern == name,
+  })
+}
+
+pub fn run(
+  stages: &[Stage],
+  target: &config::TargetConfig,
+  samples: Vec<profile::Sample>,
+) -> Vec<Routed> {
+  let mut routed = samples.into_iter()
+    .map(|sample| Routed { sample, sinks: None })
+    .collect::<Vec<_>>();
+  for stage in stages {
+    match stage {
+      Stage::Filter { names, labels, drop } => routed.retain(|Routed { sample, .. }| {
+        let selected = matches(names, &sample.name)
+          && labels.iter().all(|(label, value)| sample.labels.get(label) == Some(value));
+        selected != *drop
+      }),
+      Stage::Scale { names, factor } => routed.iter_mut()
+        .filter(|routed| matches(names, &routed.sample.name))
+        .for_each(|routed| routed.sample.value *= factor),
+      Stage::Rename { names, labels, prefix } => routed.iter_mut().for_each(|Routed { sample, .. }| {
+        if let Some(name) = names.get(&sample.name) {
+          sample.name = name.clone();
+        }
+        for (from, to) in labels {
+          if let Some(value) = sample.labels.remove(from) {
+            sample.labels.insert(to.clone(), value);
+          }
+        }
+        if let Some(prefix) = prefix {
+          sample.name.insert_str(0, prefix);
+        }
+      }),
+      Stage::Enrich { labels, tags } => routed.iter_mut().for_each(|Routed { sample, .. }| {
+        if *tags {
+          sample.labels.insert("target".to_string(), target.name.clone());
+          sample.labels.extend(target.tags.clone());
+        }
+        sample.labels.extend(labels.clone());
+      }),
+      Stage::Derive { name, expression, kind } => {
+        let derived = derive(name, expression, *kind, target, &routed);
+        routed.extend(derived.into_iter().map(|sample| Routed { sample, sinks: None }));
+      },
+      Stage::Route { names, sinks } => routed.iter_mut()
+        .filter(|routed| matches(names, &routed.sample.name))
+        .for_each(|routed| routed.sinks = Some(sinks.iter().cloned().collect())),
+    }
+  }
+  routed
+}
+
+// The values a derived sample was last computed from, for `delta` and
+// `interval`, by target, derived name and label set.
+type Previous = HashMap<(String, String, BTreeMap<String, String>), (snmp::Timestamp, HashMap<String, f64>)>;
+
+static PREVIOUS: OnceLock<Mutex<Previous>> = OnceLock::new();
+
+fn derive(
+  name: &str,
+  expression: &expression::Expression,
+  kind: profile::MetricKind,
+  target: &config::TargetConfig,
+  routed: &[Routed],
+) -> Vec<profile::Sample> {
+  let names = expression.names();
+  let mut by_name: HashMap<&str, Vec<&profile::Sample>> = HashMap::new();
+  for Routed { sample, .. } in routed.iter().filter(|routed| names.contains(&routed.sample.name.as_str())) {
+    by_name.entry(&sample.name).or_default().push(sample);
+  }
+  // Tables give the label sets; without any, the scalars are taken together
+  // under the labels of the first.
+  let mut label_sets = by_name.values()
+    .filter(|samples| samples.len() > 1)
+    .flatten()
+    .map(|sample| &sample.labels)
+    .collect::<BTreeSet<_>>();
+  if label_sets.is_empty() {
+    label_sets.extend(names.iter().find_map(|name| by_name.get(name)).map(|samples| &samples[0].labels));
+  }
+  let mut previous = PREVIOUS.get_or_init(Default::default).lock().unwrap();
+  let mut derived = Vec::new();
+  for labels in label_sets {
+    let current = names.iter()
+      .filter_map(|name| {
+        let samples = by_name.get(name)?;
+        let sample = samples.iter()
+          .find(|sample| sample.labels == *labels)
+          .or(samples.first().filter(|_| samples.len() == 1))?;
+        Some((name.to_string(), *sample))
+      })
+      .collect::<HashMap<_, _>>();
+    let Some(timestamp) = current.values().map(|sample| sample.timestamp).max_by_key(|timestamp| timestamp.collected_at) else {
+      continue;
+    };
+    let key = (target.name.clone(), name.to_string(), labels.clone());
+    let values = current.iter().map(|(name, sample)| (name.clone(), sample.value)).collect();
+    // Nothing is compared across a restart of the agent.
+    let earlier = previous.insert(key, (timestamp, values))
+      .filter(|(earlier, _)| !rate::rebooted(earlier, &timestamp));
+    let value = expression.evaluate(&|variable| match variable {
+      expression::Variable::Sample(name) => current.get(name).map(|sample| sample.value),
+      expression::Variable::Delta(name) => {
+        let sample = current.get(name)?;
+        let delta = sample.value - earlier.as_ref()?.1.get(name)?;
+        // A counter that went back wrapped or was reset.
+        Some(delta).filter(|delta| *delta >= 0.0 || sample.kind != profile::MetricKind::Counter)
+      },
+      expression::Variable::Interval => {
+        let earlier = earlier.as_ref()?.0.collected_at;
+        Some(timestamp.collected_at.duration_since(earlier).ok()?.as_secs_f64()).filter(|seconds| *seconds > 0.0)
+      },
+    });
+    if let Some(value) = value {
+      derived.push(profile::Sample { name: name.to_string(), kind, labels: labels.clone(), value, timestamp });
+    }
+  }
+  derived
+}
@@ -0,0 +1,331 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::Deserialize;
+
+// Arithmetic over the samples of one poll: sample names, numbers, `+ - * /`
+// and parentheses, `interval` for the seconds since the previous poll, and
+// the functions `delta(name)` and `rate(name)`, the change of a sample
+// since that poll and that per second, and `abs`, `min` and `max`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Expression {
+  text: String,
+  root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+  Number(f64),
+  Sample(String),
+  Interval,
+  Delta(String),
+  Rate(String),
+  Negate(Box<Node>),
+  Binary(char, Box<Node>, Box<Node>),
+  Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+  Abs,
+  Min,
+  Max,
+}
+
+// What an expression refers to besides numbers, as asked when evaluating.
+pub enum Variable<'a> {
+  Sample(&'a str),
+  Delta(&'a str),
+  Interval,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  Name(String),
+  Symbol(char),
+}
+
+impl Expression {
+
+  // The sample names referred to, in order of appearance.
+  pub fn names(&self) -> Vec<&str> {
+    let mut names = Vec::new();
+    self.root.names(&mut names);
+    names
+  }
+
+  // The value, or None if something referred to has no value or the
+  // result is not a finite number.
+  pub fn evaluate(&self, resolve: &dyn Fn(Variable<'_>) -> Option<f64>) -> Option<f64> {
+    self.root.evaluate(resolve).filter(|value| value.is_finite())
+  }
+}
+
+impl Node {
+
+  fn names<'a>(&'a self, names: &mut Vec<&'a str>) {
+    match self {
+      Node::Sample(name) | Node::Delta(name) | Node::Rate(name) => {
+        if !names.contains(&name.as_str()) {
+          names.push(name);
+        }
+      },
+      Node::Negate(node) => node.names(names),
+      Node::Binary(_, left, right) => {
+        left.names(names);
+        right.names(names);
+      },
+      Node::Call(_, arguments) => arguments.iter().for_each(|argument| argument.names(names)),
+      Node::Number(_) | Node::Interval => {},
+    }
+  }
+
+  fn evaluate(&self, resolve: &dyn Fn(Variable<'_>) -> Option<f64>) -> Option<f64> {
+    Some(match self {
+      Node::Number(number) => *number,
+      Node::Sample(name) => resolve(Variable::Sample(name))?,
+      Node::Interval => resolve(Variable::Interval)?,
+      Node::Delta(name) => resolve(Variable::Delta(name))?,
+      Node::Rate(name) => resolve(Variable::Delta(name))? / resolve(Variable::Interval)?,
+      Node::Negate(node) => -node.evaluate(resolve)?,
+      Node::Binary(operator, left, right) => {
+        let (left, right) = (left.evaluate(resolve)?, right.evaluate(resolve)?);
+        match operator {
+          '+' => left + right,
+          '-' => left - right,
+          '*' => left * right,
+          _ => left / right,
+        }
+      },
+      Node::Call(function, arguments) => {
+        let values = arguments.iter().map(|argument| argument.evaluate(resolve)).collect::<Option<Vec<_>>>()?;
+        match function {
+          Function::Abs => values[0].abs(),
+          Function::Min => values.into_iter().fold(f64::INFINITY, f64::min),
+          Function::Max => values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+        }
+      },
+    })
+  }
+}
+
+fn tokens(text: &str) -> Result<Vec<Token>, String> {
+  let chars = text.chars().collect::<Vec<_>>();
+  let mut tokens = Vec::new();
+  let mut at = 0;
+  while at < chars.len() {
+    let char = chars[at];
+    let start = at;
+    if char.is_whitespace() {
+      at += 1;
+    } else if char.is_ascii_digit() || char == '.' {
+      while at < chars.len() && (chars[at].is_ascii_digit() || chars[at] == '.') {
+        at += 1;
+      }
+      let number = chars[start..at].iter().collect::<String>();
+      tokens.push(Token::Number(number.parse().map_err(|_| format!("invalid number {}", number))?));
+    } else if char.is_alphabetic() || char == '_' {
+      // Names of samples named by OID, such as `mib_2.ifInOctets`, keep
+      // their dots.
+      while at < chars.len() && (chars[at].is_alphanumeric() || "_.:".contains(chars[at])) {
+        at += 1;
+      }
+      tokens.push(Token::Name(chars[start..at].iter().collect()));
+    } else if "+-*/(),".contains(char) {
+      tokens.push(Token::Symbol(char));
+      at += 1;
+    } else {
+      return Err(format!("unexpected {} at {}", char, at));
+    }
+  }
+  Ok(tokens)
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  at: usize,
+}
+
+impl Parser {
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.at)
+  }
+
+  fn eat(&mut self, symbol: char) -> bool {
+    let eaten = self.peek() == Some(&Token::Symbol(symbol));
+    if eaten {
+      self.at += 1;
+    }
+    eaten
+  }
+
+  fn expect(&mut self, symbol: char) -> Result<(), String> {
+    match self.eat(symbol) {
+      true => Ok(()),
+      false => Err(format!("expected {}", symbol)),
+    }
+  }
+
+  fn sum(&mut self) -> Result<Node, String> {
+    let mut node = self.product()?;
+    while let Some(operator) = ['+', '-'].into_iter().find(|operator| self.eat(*operator)) {
+      node = Node::Binary(operator, Box::new(node), Box::new(self.product()?));
+    }
+    Ok(node)
+  }
+
+  fn product(&mut self) -> Result<Node, String> {
+    let mut node = self.unary()?;
+    while let Some(operator) = ['*', '/'].into_iter().find(|operator| self.eat(*operator)) {
+      node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+    }
+    Ok(node)
+  }
+
+  fn unary(&mut self) -> Result<Node, String> {
+    match self.eat('-') {
+      true => Ok(Node::Negate(Box::new(self.unary()?))),
+      false => self.primary(),
+    }
+  }
+
+  fn primary(&mut self) -> Result<Node, String> {
+    let token = self.peek().cloned().ok_or("unexpected end")?;
+    self.at += 1;
+    match token {
+      Token::Number(number) => Ok(Node::Number(number)),
+      Token::Symbol('(') => {
+        let node = self.sum()?;
+        self.expect(')')?;
+        Ok(node)
+      },
+      Token::Name(name) if self.eat('(') => self.call(&name),
+      Token::Name(name) if name == "interval" => Ok(Node::Interval),
+      Token::Name(name) => Ok(Node::Sample(name)),
+      Token::Symbol(symbol) => Err(format!("unexpected {}", symbol)),
+    }
+  }
+
+  // A function call after its opening parenthesis.
+  fn call(&mut self, function: &str) -> Result<Node, String> {
+    if function == "delta" || function == "rate" {
+      let Some(Token::Name(name)) = self.peek().cloned() else {
+        return Err(format!("{} takes a sample name", function));
+      };
+      self.at += 1;
+      self.expect(')')?;
+      return Ok(match function {
+        "delta" => Node::Delta(name),
+        _ => Node::Rate(name),
+      });
+    }
+    let function = match function {
+      "abs" => Function::Abs,
+      "min" => Function::Min,
+      "max" => Function::Max,
+      function => return Err(format!("unknown function {}", function)),
+    };
+    let mut arguments = vec![self.sum()?];
+    while self.eat(',') {
+      arguments.push(self.sum()?);
+    }
+    self.expect(')')?;
+    if matches!(function, Function::Abs) && arguments.len() != 1 {
+      return Err("abs takes one argument".to_string());
+    }
+    Ok(Node::Call(function, arguments))
+  }
+}
+
+impl FromStr for Expression {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let invalid = |error: String| format!("invalid expression {}: {}", text, error);
+    let mut parser = Parser { tokens: tokens(text).map_err(invalid)?, at: 0 };
+    let root = parser.sum().map_err(invalid)?;
+    if parser.at < parser.tokens.len() {
+      return Err(invalid("trailing input".to_string()));
+    }
+    Ok(Expression { text: text.to_string(), root })
+  }
+}
+
+impl TryFrom<String> for Expression {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+impl Display for Expression {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.text)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn evaluate(text: &str) -> Option<f64> {
+    let expression = text.parse::<Expression>().unwrap();
+    expression.evaluate(&|variable| match variable {
+      Variable::Sample("ifInOctets") => Some(300.0),
+      Variable::Sample("ifOutOctets") => Some(100.0),
+      Variable::Delta("ifInOctets") => Some(600.0),
+      Variable::Interval => Some(60.0),
+      _ => None,
+    })
+  }
+
+  #[test]
+  fn applies_precedence() {
+    assert_eq!(evaluate("1 + 2 * 3"), Some(7.0));
+    assert_eq!(evaluate("(1 + 2) * 3"), Some(9.0));
+    assert_eq!(evaluate("8 - 4 - 2"), Some(2.0));
+    assert_eq!(evaluate("8 / 4 / 2"), Some(1.0));
+    assert_eq!(evaluate("-2 * 3 + 10"), Some(4.0));
+    assert_eq!(evaluate("ifInOctets - ifOutOctets * 2"), Some(100.0));
+  }
+
+  #[test]
+  fn calls_functions() {
+    assert_eq!(evaluate("abs(ifOutOctets - ifInOctets)"), Some(200.0));
+    assert_eq!(evaluate("min(ifInOctets, ifOutOctets, 50)"), Some(50.0));
+    assert_eq!(evaluate("max(ifInOctets, ifOutOctets)"), Some(300.0));
+    assert_eq!(evaluate("rate(ifInOctets) * 8"), Some(80.0));
+    assert_eq!(evaluate("delta(ifInOctets) / interval"), Some(10.0));
+  }
+
+  #[test]
+  fn checks_abs_arity() {
+    assert!("abs(1, 2)".parse::<Expression>().is_err());
+    assert!("abs()".parse::<Expression>().is_err());
+    assert!("abs(1)".parse::<Expression>().is_ok());
+  }
+
+  #[test]
+  fn has_no_value_dividing_by_zero() {
+    assert_eq!(evaluate("ifInOctets / 0"), None);
+    assert_eq!(evaluate("0 / 0"), None);
+    assert_eq!(evaluate("ifInOctets / (ifOutOctets - 100)"), None);
+  }
+
+  #[test]
+  fn has_no_value_for_missing_samples() {
+    assert_eq!(evaluate("ifInOctets + ifInErrors"), None);
+    assert_eq!(evaluate("rate(ifOutOctets)"), None);
+  }
+
+  #[test]
+  fn rejects_malformed_expressions() {
+    for text in ["1 +", "(1 + 2", "1 2", "foo(1)", "rate(1)", "1 % 2"] {
+      assert!(text.parse::<Expression>().is_err(), "{}", text);
+    }
+  }
+}
@@ -0,0 +1,911 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, fmt::Display, path::Path, str::FromStr, sync::Mutex, time::{Duration, Instant}};
+
+use serde::{de, Deserialize, Serialize};
+
+use crate::{config, device, pipeline, snmp};
+
+mod bridge;
+mod host;
+mod interfaces;
+mod optics;
+pub mod pack;
+pub mod plugin;
+mod printer;
+pub mod script;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+  pub name: String,
+  // Collect the whole profile once per operational VLAN, addressing each
+  // VLAN's bridge instance through Cisco's community@vlan indexing, or the
+  // vlan-<number> context over SNMPv3.
+  #[serde(default)]
+  pub per_vlan: bool,
+  // The SNMPv3 context the profile is collected from, such as a VRF's on
+  // routers, and the engine it belongs to when not the agent's own. They
+  // take the place of the target's; community targets have no contexts.
+  #[serde(default)]
+  pub context_name: Option<String>,
+  #[serde(default)]
+  pub context_engine_id: Option<config::EngineId>,
+  // sysORTable entries (MIB modules) the agent must claim for the profile
+  // to be collected; see `device::DeviceInfo::implements`.
+  #[serde(default)]
+  pub requires: Vec<snmp::ObjectIdentifier>,
+  // Variables discovered by walking the agent; targets may also set them in
+  // the configuration, which takes precedence.
+  #[serde(default)]
+  pub variables: Vec<Variable>,
+  #[serde(default)]
+  pub scalars: Vec<Metric>,
+  #[serde(default)]
+  pub tables: Vec<Table>,
+  // Applied in order once the scalars and tables have been collected.
+  #[serde(default)]
+  pub plugins: Vec<plugin::Plugin>,
+  #[serde(default)]
+  pub scripts: script::Scripts,
+  // Seconds between collections, the collection interval unless given.
+  #[serde(default)]
+  pub interval: Option<u64>,
+  // Applied to the samples before the pipeline of the profile's source.
+  #[serde(default)]
+  pub stages: Vec<pipeline::Stage>,
+}
+
+// The values of a variable are the instance suffixes found under `walk`,
+// e.g. every ifIndex when walking ifIndex itself.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Variable {
+  pub name: String,
+  pub walk: snmp::ObjectIdentifier,
+}
+
+// Values assigned to variables, each one or more arcs in dotted notation.
+pub type Variables = BTreeMap<String, Vec<String>>;
+
+// An OID which may contain `{name}` placeholders standing for one or more
+// arcs, such as `1.3.6.1.2.1.2.2.1.10.{ifIndex}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidTemplate(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+  Arc(u32),
+  Variable(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metric {
+  pub name: String,
+  pub oid: OidTemplate,
+  #[serde(default)]
+  pub kind: MetricKind,
+  #[serde(default)]
+  pub scale: Option<f64>,
+  // A column of the same table whose value in each row multiplies the
+  // metric's, such as hrStorageAllocationUnits for hrStorageUsed.
+  #[serde(default)]
+  pub scale_by: Option<OidTemplate>,
+  // Names for integer values (ifOperStatus 1 = "up"). Label columns are
+  // rendered with the name; metrics keep the number and gain a label named
+  // after the metric.
+  #[serde(default, rename = "enum")]
+  pub enum_values: BTreeMap<String, String>,
+  // How label columns holding octet strings are to be read.
+  #[serde(default)]
+  pub syntax: Option<Syntax>,
+  #[serde(default)]
+  pub class: Class,
+  // A column of the same table read for rows lacking `oid`, such as the
+  // 32-bit ifInOctets of interfaces without ifHCInOctets. Samples of the
+  // metric are labelled `counterBits` with the width of the counter read.
+  #[serde(default)]
+  pub fallback: Option<OidTemplate>,
+}
+
+// Static objects (descriptions, names, configured speeds) rarely change and
+// are only fetched again once their cached values are STATIC_REFRESH old;
+// dynamic ones, counters and the like, are fetched on every collection. A
+// lookup's class applies to its `via` and `parent` columns as well.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Class {
+  #[default]
+  Dynamic,
+  Static,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Syntax {
+  InetAddress,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+  #[default]
+  Gauge,
+  Counter,
+}
+
+// A conceptual table: every row produces one sample per metric column,
+// labelled with the decoded index and the values of the label columns.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Table {
+  pub indexes: Vec<Index>,
+  #[serde(default)]
+  pub labels: Vec<Metric>,
+  #[serde(default)]
+  pub lookups: Vec<Lookup>,
+  pub metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Index {
+  pub name: String,
+  #[serde(default)]
+  pub kind: IndexKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexKind {
+  #[default]
+  Integer,
+  MacAddress,
+  IpAddress,
+  // An InetAddressType arc followed by a length-prefixed InetAddress, as
+  // in the ipAddressTable.
+  InetAddress,
+}
+
+// A label taken from a column of another table. The row index is first
+// translated through the `via` columns in order: each maps an index to a
+// value naming the next index, either directly as an integer or as an
+// instance OID such as `ifIndex.7`, whose last arc is used. When the
+// translation fails and a `parent` column is given (entPhysicalContainedIn
+// for instance), it is retried from the parent row, walking up the tree.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Lookup {
+  pub label: Metric,
+  #[serde(default)]
+  pub via: Vec<snmp::ObjectIdentifier>,
+  #[serde(default)]
+  pub parent: Option<snmp::ObjectIdentifier>,
+}
+
+const MAX_PARENT_DEPTH: usize = 8;
+
+// The label telling which width of counter a metric with a fallback was
+// read from.
+const COUNTER_BITS: &str = "counterBits";
+
+const STATIC_REFRESH: Duration = Duration::from_secs(3600);
+
+// Values of static objects by agent and OID: the instances under a column,
+// or the single instance of a scalar under an empty index.
+#[derive(Default)]
+pub struct Cache {
+  values: Mutex<HashMap<(snmp::Target, snmp::ObjectIdentifier), (Instant, TimedColumn)>>,
+}
+
+type TimedColumn = Vec<(Vec<u32>, snmp::ObjectValue, snmp::Timestamp)>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+  pub name: String,
+  pub kind: MetricKind,
+  pub labels: BTreeMap<String, String>,
+  pub value: f64,
+  #[serde(flatten)]
+  pub timestamp: snmp::Timestamp,
+}
+
+impl Profile {
+
+  pub fn supported_by(&self, device: &device::DeviceInfo) -> bool {
+    self.requires.iter().all(|module| device.implements(module))
+  }
+
+  fn metrics(&self) -> impl Iterator<Item = &Metric> {
+    self.scalars.iter()
+      .chain(self.tables.iter().flat_map(|table| table.metrics()))
+  }
+
+  fn variable_names(&self) -> Vec<&str> {
+    let mut names = self.metrics()
+      .flat_map(|metric| metric.oid.variables())
+      .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    names
+  }
+}
+
+impl Table {
+
+  fn metrics(&self) -> impl Iterator<Item = &Metric> {
+    self.labels.iter()
+      .chain(self.lookups.iter().map(|lookup| &lookup.label))
+      .chain(self.metrics.iter())
+  }
+}
+
+impl OidTemplate {
+
+  fn variables(&self) -> impl Iterator<Item = &str> {
+    self.0.iter().filter_map(|segment| match segment {
+      Segment::Arc(_) => None,
+      Segment::Variable(name) => Some(name.as_str()),
+    })
+  }
+
+  pub fn resolve(&self, values: &BTreeMap<String, String>) -> Option<snmp::ObjectIdentifier> {
+    let mut arcs = Vec::new();
+    for segment in &self.0 {
+      match segment {
+        Segment::Arc(arc) => arcs.push(arc.to_string()),
+        Segment::Variable(name) => arcs.push(values.get(name)?.clone()),
+      }
+    }
+    arcs.join(".").parse().ok()
+  }
+}
+
+impl FromStr for OidTemplate {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    s.trim_start_matches('.')
+      .split('.')
+      .map(|segment| match segment.strip_prefix('{').and_then(|name| name.strip_suffix('}')) {
+        Some(name) if !name.is_empty() => Ok(Segment::Variable(name.to_string())),
+        _ => segment.parse()
+          .map(Segment::Arc)
+          .map_err(|_| format!("invalid OID segment `{}`", segment)),
+      })
+      .collect::<Result<Vec<_>, _>>()
+      .map(OidTemplate)
+  }
+}
+
+impl Display for OidTemplate {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let segments = self.0.iter()
+      .map(|segment| match segment {
+        Segment::Arc(arc) => arc.to_string(),
+        Segment::Variable(name) => format!("{{{}}}", name),
+      })
+      .collect::<Vec<_>>();
+    write!(f, "{}", segments.join("."))
+  }
+}
+
+impl<'de> Deserialize<'de> for OidTemplate {
+
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: serde::Deserializer<'de>
+  {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(de::Error::custom)
+  }
+}
+
+impl Metric {
+
+  fn new(name: &str, oid: &str) -> Metric {
+    Metric {
+      name: name.to_string(),
+      oid: oid.parse().expect("built-in profile OIDs are valid"),
+      kind: MetricKind::Gauge,
+      scale: None,
+      scale_by: None,
+      enum_values: BTreeMap::new(),
+      syntax: None,
+      class: Class::Dynamic,
+      fallback: None,
+    }
+  }
+
+  fn counter(name: &str, oid: &str) -> Metric {
+    Metric { kind: MetricKind::Counter, ..Metric::new(name, oid) }
+  }
+
+  fn static_column(name: &str, oid: &str) -> Metric {
+    Metric { class: Class::Static, ..Metric::new(name, oid) }
+  }
+
+  fn sample(
+    &self,
+    value: &snmp::ObjectValue,
+    timestamp: snmp::Timestamp,
+    mut labels: BTreeMap<String, String>,
+  ) -> Option<Sample> {
+    let number = value.as_f64()?;
+    if let Some(name) = self.enum_values.get(&value.to_string()) {
+      labels.insert(self.name.clone(), name.clone());
+    }
+    Some(Sample {
+      name: self.name.clone(),
+      kind: self.kind,
+      labels,
+      value: number * self.scale.unwrap_or(1.0),
+      timestamp,
+    })
+  }
+
+  fn label(&self, value: &snmp::ObjectValue) -> String {
+    let text = match self.syntax {
+      Some(Syntax::InetAddress) => value.to_inet_address(None).unwrap_or_else(|| value.clone()).to_string(),
+      None => value.to_string(),
+    };
+    self.enum_values.get(&text).cloned().unwrap_or(text)
+  }
+}
+
+impl Cache {
+
+  fn get(&self, target: &snmp::SnmpClient, oid: &snmp::ObjectIdentifier) -> Option<TimedColumn> {
+    self.values.lock().unwrap()
+      .get(&(target.target().clone(), oid.clone()))
+      .filter(|(fetched, _)| fetched.elapsed() < STATIC_REFRESH)
+      .map(|(_, values)| values.clone())
+  }
+
+  fn put(&self, target: &snmp::SnmpClient, oid: &snmp::ObjectIdentifier, values: TimedColumn) {
+    self.values.lock().unwrap().insert((target.target().clone(), oid.clone()), (Instant::now(), values));
+  }
+
+  async fn walk(
+    &self,
+    target: &snmp::SnmpClient,
+    column: &snmp::ObjectIdentifier,
+    class: Class,
+  ) -> snmp::Result<TimedColumn> {
+    if class == Class::Dynamic {
+      return walk_column_timed(target, column).await;
+    }
+    if let Some(values) = self.get(target, column) {
+      return Ok(values);
+    }
+    let values = walk_column_timed(target, column).await?;
+    self.put(target, column, values.clone());
+    Ok(values)
+  }
+}
+
+impl Index {
+
+  fn integer(name: &str) -> Index {
+    Index { name: name.to_string(), kind: IndexKind::Integer }
+  }
+
+  fn mac_address(name: &str) -> Index {
+    Index { name: name.to_string(), kind: IndexKind::MacAddress }
+  }
+}
+
+impl IndexKind {
+
+  // Index values in the form `index_labels` renders them: MAC addresses
+  // are six colon-separated octets, other colons mean an IPv6 address, and
+  // integers and IPv4 addresses are plain dotted arcs.
+  pub fn detect(value: &str) -> IndexKind {
+    if IndexKind::MacAddress.encode(value).is_some() {
+      IndexKind::MacAddress
+    } else if value.contains(':') {
+      IndexKind::InetAddress
+    } else {
+      IndexKind::Integer
+    }
+  }
+
+  pub fn encode(&self, value: &str) -> Option<Vec<u32>> {
+    match self {
+      IndexKind::Integer => value.split('.')
+        .map(|arc| arc.parse().ok())
+        .collect(),
+      IndexKind::IpAddress => value.parse::<std::net::Ipv4Addr>()
+        .ok()
+        .map(|address| address.octets().map(u32::from).to_vec()),
+      IndexKind::MacAddress => {
+        let octets = value.split([':', '-'])
+          .map(|octet| u32::from_str_radix(octet, 16).ok().filter(|octet| *octet <= 0xff))
+          .collect::<Option<Vec<_>>>()?;
+        Some(octets).filter(|octets| octets.len() == 6)
+      },
+      IndexKind::InetAddress => {
+        let address = value.parse::<config::Address>().ok()?;
+        let (address_type, mut octets) = match address.ip {
+          std::net::IpAddr::V4(ip) => (1, ip.octets().to_vec()),
+          std::net::IpAddr::V6(ip) if address.scope_id == 0 => (2, ip.octets().to_vec()),
+          std::net::IpAddr::V6(ip) => (4, ip.octets().to_vec()),
+        };
+        if address_type == 4 {
+          octets.extend(address.scope_id.to_be_bytes());
+        }
+        Some([address_type, octets.len() as u32].into_iter().chain(octets.into_iter().map(u32::from)).collect())
+      },
+    }
+  }
+}
+
+// The instance OID of a table cell, from the column OID and the row's
+// decoded index values.
+pub fn cell(column: &snmp::ObjectIdentifier, index: &[(IndexKind, &str)]) -> Option<snmp::ObjectIdentifier> {
+  let mut arcs = Vec::new();
+  for (kind, value) in index {
+    arcs.extend(kind.encode(value)?);
+  }
+  Some(column.child(&arcs))
+}
+
+pub fn builtin() -> Vec<Profile> {
+  vec![
+    bridge::profile(),
+    host::profile(),
+    interfaces::profile(),
+    optics::profile(),
+    printer::profile(),
+  ]
+}
+
+// Built-in profiles followed by the OID packs found in `pack_dir`; a pack
+// named like a built-in profile replaces it.
+pub fn load(pack_dir: &Path) -> Vec<Profile> {
+  let mut profiles = builtin();
+  for pack in pack::load_dir(pack_dir) {
+    profiles.retain(|profile| profile.name != pack.name);
+    profiles.push(pack);
+  }
+  profiles
+}
+
+pub fn find<'a>(profiles: &'a [Profile], name: &str) -> Option<&'a Profile> {
+  profiles.iter().find(|profile| profile.name == name)
+}
+
+const VTP_VLAN_STATE: &str = "1.3.6.1.4.1.9.9.46.1.3.1.1.2";
+const VTP_VLAN_STATE_OPERATIONAL: f64 = 1.0;
+
+// Cisco's community string indexing: `community@vlan` reads the VLAN's
+// bridge instance.
+fn vlan_community(community: &snmp::OctetString, vlan: u32) -> snmp::OctetString {
+  let mut vlan_community = community.to_vec();
+  vlan_community.extend(format!("@{}", vlan).as_bytes());
+  vlan_community.into()
+}
+
+pub async fn collect(
+  target: &snmp::SnmpClient,
+  profile: &Profile,
+  variables: &Variables,
+  cache: &Cache,
+) -> snmp::Result<Vec<Sample>> {
+  let pre_export = profile.scripts.pre_export.as_deref();
+  let context = profile.context_name.as_ref().map(|context| context.clone().into_bytes().into());
+  let context_engine = profile.context_engine_id.as_ref().map(|config::EngineId(engine_id)| engine_id.clone().into());
+  let target = &in_context(target.target(), context, context_engine).into();
+  if !profile.per_vlan {
+    return Ok(script::samples(pre_export, collect_instances(target, profile, variables, cache).await?));
+  }
+  let mut samples = Vec::new();
+  for vlan in vlans(target).await? {
+    let vlan_target = match target.target() {
+      snmp::Target::Community { address, community, timing } => snmp::Target::Community {
+        address: *address,
+        community: vlan_community(community, vlan),
+        timing: *timing,
+      },
+      snmp::Target::CommunityV1 { address, community, timing } => snmp::Target::CommunityV1 {
+        address: *address,
+        community: vlan_community(community, vlan),
+        timing: *timing,
+      },
+      // SNMPv3 agents expose the VLAN's bridge instance as a context.
+      target @ (snmp::Target::Usm { .. } | snmp::Target::Tls { .. }) => {
+        in_context(target, Some(format!("vlan-{}", vlan).into_bytes().into()), None)
+      },
+    };
+    for mut sample in collect_instances(&vlan_target.into(), profile, variables, cache).await? {
+      sample.labels.insert("vlan".to_string(), vlan.to_string());
+      samples.push(sample);
+    }
+  }
+  Ok(script::samples(pre_export, samples))
+}
+
+// The target asking `context` of `context_engine` where given, and what it
+// asked before otherwise. Community targets have no contexts and are left
+// as they are.
+fn in_context(
+  target: &snmp::Target,
+  context: Option<snmp::OctetString>,
+  context_engine: Option<snmp::OctetString>,
+) -> snmp::Target {
+  let mut target = target.clone();
+  match &mut target {
+    snmp::Target::Usm { context: asked, context_engine: engine, .. }
+      | snmp::Target::Tls { context: asked, context_engine: engine, .. } => {
+      if let Some(context) = context {
+        *asked = context;
+      }
+      if let Some(context_engine) = context_engine {
+        *engine = Some(context_engine);
+      }
+    },
+    snmp::Target::Community { .. } | snmp::Target::CommunityV1 { .. } => {},
+  }
+  target
+}
+
+// Scalars and tables are collected once for every combination of the
+// values of the variables they use, labelled with those values.
+async fn collect_instances(
+  target: &snmp::SnmpClient,
+  profile: &Profile,
+  configured: &Variables,
+  cache: &Cache,
+) -> snmp::Result<Vec<Sample>> {
+  let mut values = Variables::new();
+  for name in profile.variable_names() {
+    let discovered = match configured.get(name) {
+      Some(configured) => configured.clone(),
+      None => discover(target, profile, name).await?,
+    };
+    values.insert(name.to_string(), discovered);
+  }
+  let mut samples = Vec::new();
+  let scalars = profile.scalars.iter()
+    .flat_map(|metric| {
+      let names = metric.oid.variables().collect::<Vec<_>>();
+      combinations(&names, &values).into_iter()
+        .filter_map(move |instance| Some((metric, metric.oid.resolve(&instance)?, instance)))
+    })
+    .collect::<Vec<_>>();
+  let mut bindings = Vec::new();
+  for (metric, oid, _) in &scalars {
+    if metric.class == Class::Static {
+      if let Some(cached) = cache.get(target, oid) {
+        bindings.extend(cached.into_iter().map(|(_, value, timestamp)| snmp::VariableBinding {
+          object_id: oid.clone(),
+          value,
+          timestamp,
+        }));
+      }
+    }
+  }
+  let oids = scalars.iter()
+    .map(|(_, oid, _)| oid.clone())
+    .filter(|oid| !bindings.iter().any(|binding| binding.object_id == *oid))
+    .collect::<Vec<_>>();
+  if !oids.is_empty() {
+    for binding in target.get(&oids).await? {
+      if scalars.iter().any(|(metric, oid, _)| metric.class == Class::Static && *oid == binding.object_id) {
+        cache.put(target, &binding.object_id, vec![(Vec::new(), binding.value.clone(), binding.timestamp)]);
+      }
+      bindings.push(binding);
+    }
+  }
+  for (metric, oid, instance) in scalars {
+    let sample = bindings.iter()
+      .find(|binding| binding.object_id == oid)
+      .and_then(|binding| metric.sample(&binding.value, binding.timestamp, instance));
+    samples.extend(sample);
+  }
+  for table in &profile.tables {
+    let mut names = table.metrics()
+      .flat_map(|metric| metric.oid.variables())
+      .collect::<Vec<_>>();
+    names.sort_unstable();
+    names.dedup();
+    for instance in combinations(&names, &values) {
+      for mut sample in collect_table(target, table, &instance, cache).await? {
+        sample.labels.extend(instance.clone());
+        samples.push(sample);
+      }
+    }
+  }
+  let mut samples = script::samples(profile.scripts.post_decode.as_deref(), samples);
+  for plugin in &profile.plugins {
+    let mut bindings = Vec::new();
+    for subtree in &plugin.walk {
+      bindings.extend(target.walk(subtree).await?);
+    }
+    match plugin.apply(&bindings, &samples) {
+      Ok(transformed) => samples = transformed,
+      Err(error) => eprintln!("Plugin {} of profile {}: {}", plugin.module.display(), profile.name, error),
+    }
+  }
+  Ok(samples)
+}
+
+fn combinations(names: &[&str], values: &Variables) -> Vec<BTreeMap<String, String>> {
+  let mut instances = vec![BTreeMap::new()];
+  for name in names {
+    let Some(values) = values.get(*name) else {
+      return Vec::new();
+    };
+    instances = instances.into_iter()
+      .flat_map(|instance| values.iter().map(move |value| {
+        let mut instance = instance.clone();
+        instance.insert(name.to_string(), value.clone());
+        instance
+      }))
+      .collect();
+  }
+  instances
+}
+
+async fn discover(
+  target: &snmp::SnmpClient,
+  profile: &Profile,
+  name: &str,
+) -> snmp::Result<Vec<String>> {
+  let Some(variable) = profile.variables.iter().find(|variable| variable.name == name) else {
+    eprintln!("Profile {} uses undefined variable {}", profile.name, name);
+    return Ok(Vec::new());
+  };
+  Ok(
+    walk_column(target, &variable.walk).await?
+      .into_iter()
+      .map(|(index, _)| index.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join("."))
+      .collect()
+  )
+}
+
+// VLANs learned from CISCO-VTP-MIB, indexed by management domain and VLAN
+// number. The FDDI and Token Ring defaults (1002-1005) have no bridge
+// instance of their own.
+async fn vlans(target: &snmp::SnmpClient) -> snmp::Result<Vec<u32>> {
+  let column = VTP_VLAN_STATE.parse().expect("VTP OIDs are valid");
+  let mut vlans = walk_column(target, &column).await?
+    .into_iter()
+    .filter(|(_, state)| state.as_f64() == Some(VTP_VLAN_STATE_OPERATIONAL))
+    .filter_map(|(index, _)| index.last().copied())
+    .filter(|vlan| !(1002..=1005).contains(vlan))
+    .collect::<Vec<_>>();
+  vlans.sort_unstable();
+  vlans.dedup();
+  Ok(vlans)
+}
+
+fn resolve<'a>(
+  metrics: &'a [Metric],
+  values: &BTreeMap<String, String>,
+) -> Vec<(&'a Metric, snmp::ObjectIdentifier)> {
+  metrics.iter()
+    .filter_map(|metric| Some((metric, metric.oid.resolve(values)?)))
+    .collect()
+}
+
+async fn collect_table(
+  target: &snmp::SnmpClient,
+  table: &Table,
+  values: &BTreeMap<String, String>,
+  cache: &Cache,
+) -> snmp::Result<Vec<Sample>> {
+  let metrics = resolve(&table.metrics, values);
+  let fallbacks = metrics.iter()
+    .map(|(metric, _)| metric.fallback.as_ref().and_then(|fallback| fallback.resolve(values)))
+    .collect::<Vec<_>>();
+  let mut rows = Vec::new();
+  for ((metric, oid), fallback) in metrics.iter().zip(&fallbacks) {
+    rows.push(match fallback {
+      Some(_) => Vec::new(),
+      None => cache.walk(target, oid, metric.class).await?,
+    });
+  }
+  // Columns with a fallback are read once the other columns tell which rows
+  // there are.
+  let indexes = rows.iter()
+    .flatten()
+    .map(|(index, _, _)| index.clone())
+    .collect::<HashSet<_>>();
+  for (((_, oid), fallback), column) in metrics.iter().zip(&fallbacks).zip(&mut rows) {
+    if let Some(fallback) = fallback {
+      *column = walk_with_fallback(target, oid, fallback, &indexes).await?
+        .into_iter()
+        .map(|(index, binding)| (index, binding.value, binding.timestamp))
+        .collect();
+    }
+  }
+  let mut labels_by_index: HashMap<Vec<u32>, BTreeMap<String, String>> = rows.iter()
+    .flatten()
+    .map(|(index, _, _)| (index.clone(), BTreeMap::new()))
+    .collect();
+  for (column, oid) in resolve(&table.labels, values) {
+    for (index, value, _) in cache.walk(target, &oid, column.class).await? {
+      labels_by_index.entry(index)
+        .or_default()
+        .insert(column.name.clone(), column.label(&value));
+    }
+  }
+  for lookup in &table.lookups {
+    let Some(label_oid) = lookup.label.oid.resolve(values) else {
+      continue;
+    };
+    let class = lookup.label.class;
+    let mut mappings = Vec::new();
+    for column in &lookup.via {
+      mappings.push(untimed(cache.walk(target, column, class).await?));
+    }
+    let parents = match &lookup.parent {
+      Some(column) => untimed(cache.walk(target, column, class).await?),
+      None => Vec::new(),
+    };
+    let label_values = untimed(cache.walk(target, &label_oid, class).await?);
+    for (index, row_labels) in labels_by_index.iter_mut() {
+      let mut index = index.clone();
+      let mut lookup_index = translate_index(&index, &mappings);
+      for _ in 0..MAX_PARENT_DEPTH {
+        if lookup_index.is_some() {
+          break;
+        }
+        let Some(parent) = translate_index(&index, std::slice::from_ref(&parents)) else {
+          break;
+        };
+        index = parent;
+        lookup_index = translate_index(&index, &mappings);
+      }
+      let Some(lookup_index) = lookup_index else {
+        continue;
+      };
+      if let Some((_, value)) = label_values.iter().find(|(index, _)| *index == lookup_index) {
+        row_labels.insert(lookup.label.name.clone(), lookup.label.label(value));
+      }
+    }
+  }
+  let mut samples = Vec::new();
+  for ((column, _), column_values) in metrics.iter().zip(rows) {
+    // Units change as rarely as descriptions do.
+    let units = match column.scale_by.as_ref().and_then(|oid| oid.resolve(values)) {
+      Some(oid) => Some(untimed(cache.walk(target, &oid, Class::Static).await?).into_iter().collect::<HashMap<_, _>>()),
+      None => None,
+    };
+    for (index, value, timestamp) in column_values {
+      let unit = match &units {
+        Some(units) => match units.get(&index).and_then(snmp::ObjectValue::as_f64) {
+          Some(unit) => unit,
+          None => continue,
+        },
+        None => 1.0,
+      };
+      let mut labels = index_labels(&table.indexes, &index);
+      if let Some(row_labels) = labels_by_index.get(&index) {
+        labels.extend(row_labels.clone());
+      }
+      if let Some(bits) = counter_bits(&value).filter(|_| column.fallback.is_some()) {
+        labels.insert(COUNTER_BITS.to_string(), bits.to_string());
+      }
+      samples.extend(column.sample(&value, timestamp, labels).map(|mut sample| {
+        sample.value *= unit;
+        sample
+      }));
+    }
+  }
+  Ok(samples)
+}
+
+// The instances of `column` by index, and for rows lacking one, or holding
+// an exception such as noSuchInstance, those of `fallback`: the 32-bit
+// ifInOctets of interfaces without ifHCInOctets. The fallback is only walked
+// when `column` has none of the rows, or lacks one of `indexes`, the rows
+// known otherwise.
+pub async fn walk_with_fallback(
+  target: &snmp::SnmpClient,
+  column: &snmp::ObjectIdentifier,
+  fallback: &snmp::ObjectIdentifier,
+  indexes: &HashSet<Vec<u32>>,
+) -> snmp::Result<Vec<(Vec<u32>, snmp::VariableBinding)>> {
+  let rows = |column: &snmp::ObjectIdentifier, bindings: Vec<snmp::VariableBinding>| bindings.into_iter()
+    .filter(|binding| !binding.value.is_exception())
+    .filter_map(|binding| Some((binding.object_id.strip_prefix(column)?.to_vec(), binding)))
+    .collect::<BTreeMap<_, _>>();
+  let mut found = rows(column, target.walk(column).await?);
+  if found.is_empty() || indexes.iter().any(|index| !found.contains_key(index)) {
+    for (index, binding) in rows(fallback, target.walk(fallback).await?) {
+      found.entry(index).or_insert(binding);
+    }
+  }
+  Ok(found.into_iter().collect())
+}
+
+// The width of a counter value, 32 or 64 bits.
+pub fn counter_bits(value: &snmp::ObjectValue) -> Option<u8> {
+  match value {
+    snmp::ObjectValue::Counter32(_) => Some(32),
+    snmp::ObjectValue::Counter64(_) => Some(64),
+    _ => None,
+  }
+}
+
+async fn walk_column(
+  target: &snmp::SnmpClient,
+  column: &snmp::ObjectIdentifier,
+) -> snmp::Result<Vec<(Vec<u32>, snmp::ObjectValue)>> {
+  Ok(untimed(walk_column_timed(target, column).await?))
+}
+
+fn untimed(column: TimedColumn) -> Vec<(Vec<u32>, snmp::ObjectValue)> {
+  column.into_iter()
+    .map(|(index, value, _)| (index, value))
+    .collect()
+}
+
+async fn walk_column_timed(
+  target: &snmp::SnmpClient,
+  column: &snmp::ObjectIdentifier,
+) -> snmp::Result<Vec<(Vec<u32>, snmp::ObjectValue, snmp::Timestamp)>> {
+  Ok(
+    target.walk(column).await?
+      .into_iter()
+      .filter_map(|binding| {
+        let index = binding.object_id.strip_prefix(column)?.to_vec();
+        Some((index, binding.value, binding.timestamp))
+      })
+      .collect()
+  )
+}
+
+fn translate_index(
+  index: &[u32],
+  mappings: &[Vec<(Vec<u32>, snmp::ObjectValue)>],
+) -> Option<Vec<u32>> {
+  let mut index = index.to_vec();
+  for mapping in mappings {
+    let (_, value) = mapping.iter()
+      .find(|(mapped_index, _)| mapped_index.starts_with(&index))?;
+    index = match value {
+      snmp::ObjectValue::ObjectIdentifier(oid) => vec![*oid.arcs().last()?],
+      value => vec![value.as_f64()? as u32],
+    };
+  }
+  Some(index)
+}
+
+fn inet_address_label(arcs: &[u32]) -> String {
+  let octets = arcs.get(2..)
+    .and_then(|octets| octets.iter().map(|arc| u8::try_from(*arc).ok()).collect::<Option<Vec<_>>>());
+  match octets {
+    Some(octets) => snmp::ObjectValue::OctetString(octets.into())
+      .to_inet_address(arcs.first().copied())
+      .map(|address| address.to_string()),
+    None => None,
+  }
+  .unwrap_or_else(|| arcs.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join("."))
+}
+
+// Each index consumes the arcs its kind encodes; a trailing integer index
+// takes any remaining arcs so that no part of the instance is lost.
+fn index_labels(indexes: &[Index], index: &[u32]) -> BTreeMap<String, String> {
+  let mut labels = BTreeMap::new();
+  let mut arcs = index;
+  for (position, index) in indexes.iter().enumerate() {
+    let width = match index.kind {
+      IndexKind::Integer if position + 1 == indexes.len() => arcs.len(),
+      IndexKind::Integer => 1,
+      IndexKind::MacAddress => 6,
+      IndexKind::IpAddress => 4,
+      IndexKind::InetAddress => arcs.get(1).map_or(arcs.len(), |length| *length as usize + 2),
+    };
+    let (value, rest) = arcs.split_at(width.min(arcs.len()));
+    arcs = rest;
+    let value = match index.kind {
+      IndexKind::Integer | IndexKind::IpAddress => value.iter()
+        .map(|arc| arc.to_string())
+        .collect::<Vec<_>>()
+        .join("."),
+      IndexKind::MacAddress => value.iter()
+        .map(|arc| format!("{:02x}", arc))
+        .collect::<Vec<_>>()
+        .join(":"),
+      IndexKind::InetAddress => inet_address_label(value),
+    };
+    labels.insert(index.name.clone(), value);
+  }
+  labels
+}
@@ -0,0 +1,41 @@
+use super::{Index, Lookup, Metric, Profile, Table, script};
+
+// BRIDGE-MIB forwarding database. Cisco switches only expose the entries of
+// one VLAN per community, so the profile is collected for every VLAN.
+pub fn profile() -> Profile {
+  Profile {
+    name: "bridge".to_string(),
+    per_vlan: true,
+    context_name: None,
+    context_engine_id: None,
+    requires: vec!["1.3.6.1.2.1.17".parse().expect("built-in profile OIDs are valid")],
+    variables: vec![],
+    scalars: vec![
+      Metric::static_column("dot1dBaseNumPorts", "1.3.6.1.2.1.17.1.2.0"),
+    ],
+    plugins: vec![],
+    scripts: script::Scripts::default(),
+    interval: None,
+    stages: vec![],
+    tables: vec![
+      Table {
+        indexes: vec![Index::mac_address("dot1dTpFdbAddress")],
+        labels: vec![],
+        lookups: vec![
+          Lookup {
+            label: Metric::new("ifName", "1.3.6.1.2.1.31.1.1.1.1"),
+            via: vec![
+              "1.3.6.1.2.1.17.4.3.1.2".parse().expect("built-in profile OIDs are valid"),
+              "1.3.6.1.2.1.17.1.4.1.2".parse().expect("built-in profile OIDs are valid"),
+            ],
+            parent: None,
+          },
+        ],
+        metrics: vec![
+          Metric::new("dot1dTpFdbPort", "1.3.6.1.2.1.17.4.3.1.2"),
+          Metric::new("dot1dTpFdbStatus", "1.3.6.1.2.1.17.4.3.1.3"),
+        ],
+      },
+    ],
+  }
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use super::{Index, Metric, Profile, Table, script};
+
+const HR_STORAGE_TYPES: &str = "1.3.6.1.2.1.25.2.1";
+const HR_STORAGE_ALLOCATION_UNITS: &str = "1.3.6.1.2.1.25.2.3.1.4";
+
+// HOST-RESOURCES-MIB (RFC 2790) on servers: processor load, memory and
+// storage, the latter in bytes rather than in allocation units. Memory is
+// both hrMemorySize and the storage rows of type `ram`.
+pub fn profile() -> Profile {
+  Profile {
+    name: "host-resources".to_string(),
+    per_vlan: false,
+    context_name: None,
+    context_engine_id: None,
+    // Agents such as Net-SNMP's do not list the MIB in sysORTable.
+    requires: vec![],
+    variables: vec![],
+    scalars: vec![
+      Metric { scale: Some(0.01), ..Metric::new("hrSystemUptimeSeconds", "1.3.6.1.2.1.25.1.1.0") },
+      Metric::new("hrSystemNumUsers", "1.3.6.1.2.1.25.1.5.0"),
+      Metric::new("hrSystemProcesses", "1.3.6.1.2.1.25.1.6.0"),
+      // In KiB.
+      Metric { scale: Some(1024.0), ..Metric::static_column("hrMemorySizeBytes", "1.3.6.1.2.1.25.2.2.0") },
+    ],
+    plugins: vec![],
+    scripts: script::Scripts::default(),
+    interval: None,
+    stages: vec![],
+    tables: vec![
+      Table {
+        indexes: vec![Index::integer("hrDeviceIndex")],
+        labels: vec![
+          Metric::static_column("hrDeviceDescr", "1.3.6.1.2.1.25.3.2.1.3"),
+        ],
+        lookups: vec![],
+        metrics: vec![
+          Metric::new("hrProcessorLoad", "1.3.6.1.2.1.25.3.3.1.2"),
+        ],
+      },
+      Table {
+        indexes: vec![Index::integer("hrStorageIndex")],
+        labels: vec![
+          Metric::static_column("hrStorageDescr", "1.3.6.1.2.1.25.2.3.1.3"),
+          Metric { enum_values: storage_types(), ..Metric::static_column("hrStorageType", "1.3.6.1.2.1.25.2.3.1.2") },
+        ],
+        lookups: vec![],
+        metrics: vec![
+          storage_bytes("hrStorageSizeBytes", "1.3.6.1.2.1.25.2.3.1.5"),
+          storage_bytes("hrStorageUsedBytes", "1.3.6.1.2.1.25.2.3.1.6"),
+          Metric::counter("hrStorageAllocationFailures", "1.3.6.1.2.1.25.2.3.1.7"),
+        ],
+      },
+    ],
+  }
+}
+
+fn storage_bytes(name: &str, oid: &str) -> Metric {
+  Metric {
+    scale_by: Some(HR_STORAGE_ALLOCATION_UNITS.parse().expect("built-in profile OIDs are valid")),
+    ..Metric::new(name, oid)
+  }
+}
+
+// hrStorageType values are OIDs under hrStorageTypes.
+fn storage_types() -> BTreeMap<String, String> {
+  [
+    "other", "ram", "virtualMemory", "fixedDisk", "removableDisk",
+    "floppyDisk", "compactDisc", "ramDisk", "flashMemory", "networkDisk",
+  ]
+    .into_iter()
+    .enumerate()
+    .map(|(at, name)| (format!("{}.{}", HR_STORAGE_TYPES, at + 1), name.to_string()))
+    .collect()
+}
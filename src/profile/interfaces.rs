@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use super::{Index, Metric, Profile, Table, script};
+
+// IF-MIB interface traffic. Octets and unicast packets come from the 64-bit
+// ifHC* counters, or from the 32-bit ifTable ones on interfaces lacking
+// them, as their `counterBits` label tells.
+pub fn profile() -> Profile {
+  Profile {
+    name: "interfaces".to_string(),
+    per_vlan: false,
+    context_name: None,
+    context_engine_id: None,
+    // Few agents list IF-MIB in sysORTable, though all have it.
+    requires: vec![],
+    variables: vec![],
+    scalars: vec![],
+    plugins: vec![],
+    scripts: script::Scripts::default(),
+    interval: None,
+    stages: vec![],
+    tables: vec![
+      Table {
+        indexes: vec![Index::integer("ifIndex")],
+        labels: vec![
+          Metric::static_column("ifDescr", "1.3.6.1.2.1.2.2.1.2"),
+          Metric::static_column("ifName", "1.3.6.1.2.1.31.1.1.1.1"),
+        ],
+        lookups: vec![],
+        metrics: vec![
+          Metric { enum_values: statuses(), ..Metric::new("ifOperStatus", "1.3.6.1.2.1.2.2.1.8") },
+          Metric::static_column("ifHighSpeed", "1.3.6.1.2.1.31.1.1.1.15"),
+          counter("ifInOctets", "1.3.6.1.2.1.31.1.1.1.6", "1.3.6.1.2.1.2.2.1.10"),
+          counter("ifOutOctets", "1.3.6.1.2.1.31.1.1.1.10", "1.3.6.1.2.1.2.2.1.16"),
+          counter("ifInUcastPkts", "1.3.6.1.2.1.31.1.1.1.7", "1.3.6.1.2.1.2.2.1.11"),
+          counter("ifOutUcastPkts", "1.3.6.1.2.1.31.1.1.1.11", "1.3.6.1.2.1.2.2.1.17"),
+          Metric::counter("ifInDiscards", "1.3.6.1.2.1.2.2.1.13"),
+          Metric::counter("ifInErrors", "1.3.6.1.2.1.2.2.1.14"),
+          Metric::counter("ifOutDiscards", "1.3.6.1.2.1.2.2.1.19"),
+          Metric::counter("ifOutErrors", "1.3.6.1.2.1.2.2.1.20"),
+        ],
+      },
+    ],
+  }
+}
+
+// The 64-bit counter `oid`, or the 32-bit `fallback` where it is missing.
+fn counter(name: &str, oid: &str, fallback: &str) -> Metric {
+  Metric {
+    fallback: Some(fallback.parse().expect("built-in profile OIDs are valid")),
+    ..Metric::counter(name, oid)
+  }
+}
+
+fn statuses() -> BTreeMap<String, String> {
+  ["up", "down", "testing", "unknown", "dormant", "notPresent", "lowerLayerDown"]
+    .into_iter()
+    .enumerate()
+    .map(|(at, name)| ((at + 1).to_string(), name.to_string()))
+    .collect()
+}
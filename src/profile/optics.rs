@@ -0,0 +1,86 @@
+use super::{Index, Lookup, Metric, Profile, Table, script};
+
+const IF_NAME: &str = "1.3.6.1.2.1.31.1.1.1.1";
+const ENT_PHYSICAL_CONTAINED_IN: &str = "1.3.6.1.2.1.47.1.1.1.1.4";
+const ENT_PHYSICAL_NAME: &str = "1.3.6.1.2.1.47.1.1.1.1.7";
+const ENT_ALIAS_MAPPING_IDENTIFIER: &str = "1.3.6.1.2.1.47.1.3.2.1.2";
+
+// Transceiver DOM readings (tx/rx power, laser bias, temperature). Sensors
+// found through the ENTITY-MIB tree are joined to the interface table via
+// the nearest containing entity with an alias mapping (usually the port);
+// Juniper indexes its DOM table by ifIndex.
+pub fn profile() -> Profile {
+  Profile {
+    name: "optics".to_string(),
+    per_vlan: false,
+    context_name: None,
+    context_engine_id: None,
+    requires: vec![],
+    variables: vec![],
+    scalars: vec![],
+    plugins: vec![],
+    scripts: script::Scripts::default(),
+    interval: None,
+    stages: vec![],
+    tables: vec![
+      entity_sensor_table(
+        "entPhySensor",
+        "1.3.6.1.2.1.99.1.1.1",
+        "OperStatus",
+      ),
+      entity_sensor_table(
+        "entSensor",
+        "1.3.6.1.4.1.9.9.91.1.1.1.1",
+        "Status",
+      ),
+      Table {
+        indexes: vec![Index::integer("ifIndex")],
+        labels: vec![],
+        lookups: vec![
+          Lookup {
+            label: Metric::static_column("ifName", IF_NAME),
+            via: vec![],
+            parent: None,
+          },
+        ],
+        metrics: vec![
+          Metric::new("jnxDomCurrentRxLaserPower", "1.3.6.1.4.1.2636.3.60.1.1.1.1.5"),
+          Metric::new("jnxDomCurrentTxLaserBiasCurrent", "1.3.6.1.4.1.2636.3.60.1.1.1.1.6"),
+          Metric::new("jnxDomCurrentTxLaserOutputPower", "1.3.6.1.4.1.2636.3.60.1.1.1.1.7"),
+          Metric::new("jnxDomCurrentModuleTemperature", "1.3.6.1.4.1.2636.3.60.1.1.1.1.8"),
+        ],
+      },
+    ],
+  }
+}
+
+// ENTITY-SENSOR-MIB and CISCO-ENTITY-SENSOR-MIB share the same column
+// layout: type, scale, precision, value and status.
+fn entity_sensor_table(prefix: &str, entry: &str, status: &str) -> Table {
+  let column = |name: &str, arc: u32| Metric::new(
+    &format!("{}{}", prefix, name),
+    &format!("{}.{}", entry, arc),
+  );
+  Table {
+    indexes: vec![Index::integer("entPhysicalIndex")],
+    labels: vec![
+      Metric::static_column("entPhysicalName", ENT_PHYSICAL_NAME),
+    ],
+    lookups: vec![
+      Lookup {
+        label: Metric::static_column("ifName", IF_NAME),
+        via: vec![
+          ENT_ALIAS_MAPPING_IDENTIFIER.parse().expect("built-in profile OIDs are valid"),
+        ],
+        parent: Some(ENT_PHYSICAL_CONTAINED_IN.parse().expect("built-in profile OIDs are valid")),
+      },
+    ],
+    metrics: vec![
+      column("Type", 1),
+      column("Scale", 2),
+      column("Precision", 3),
+      column("Value", 4),
+      column(status, 5),
+    ],
+  }
+}
@@ -0,0 +1,56 @@
+use std::{fmt::Display, fs, path::{Path, PathBuf}};
+
+use super::{script, Profile};
+
+#[derive(Debug)]
+pub enum Error {
+  Io(std::io::Error),
+  Parse(toml::de::Error),
+  Script(script::Error),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Io(error) => write!(f, "cannot read pack: {}", error),
+      Error::Parse(error) => write!(f, "invalid pack: {}", error),
+      Error::Script(error) => write!(f, "invalid pack: {}", error),
+    }
+  }
+}
+
+pub fn load_file(path: &Path) -> Result<Profile, Error> {
+  let text = fs::read_to_string(path).map_err(Error::Io)?;
+  let profile: Profile = toml::from_str(&text).map_err(Error::Parse)?;
+  profile.scripts.check().map_err(Error::Script)?;
+  Ok(profile)
+}
+
+// Loads every `*.toml` pack in the directory, in file name order. A missing
+// directory simply means there are no packs; broken packs are reported and
+// skipped so that one bad file does not take the collector down.
+pub fn load_dir(dir: &Path) -> Vec<Profile> {
+  let entries = match fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+    Err(error) => {
+      eprintln!("Cannot read OID pack directory {}: {}", dir.display(), error);
+      return Vec::new();
+    },
+  };
+  let mut paths = entries
+    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+    .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+    .collect::<Vec<PathBuf>>();
+  paths.sort();
+  paths.iter()
+    .filter_map(|path| match load_file(path) {
+      Ok(profile) => Some(profile),
+      Err(error) => {
+        eprintln!("Skipping OID pack {}: {}", path.display(), error);
+        None
+      },
+    })
+    .collect()
+}
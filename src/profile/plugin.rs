@@ -0,0 +1,156 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, time::SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::{MetricKind, Sample};
+use crate::snmp;
+
+// A WebAssembly module that rewrites the samples of a profile. It is handed
+// the profile's samples together with the bindings found under `walk`, and
+// whatever samples it returns replace them, so a plugin may adjust, drop or
+// derive samples as it sees fit.
+//
+// Both directions are JSON in the module's linear memory. The module must
+// export `memory`, `alloc(len: i32) -> i32`, returning space for the input,
+// and `transform(ptr: i32, len: i32) -> i64`, returning the output's
+// location as `ptr << 32 | len`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Plugin {
+  pub module: PathBuf,
+  #[serde(default)]
+  pub walk: Vec<snmp::ObjectIdentifier>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Unsupported,
+  Runtime(String),
+  Output(serde_json::Error),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Unsupported => write!(f, "built without WebAssembly support"),
+      Error::Runtime(error) => write!(f, "plugin failed: {}", error),
+      Error::Output(error) => write!(f, "invalid plugin output: {}", error),
+    }
+  }
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+  bindings: Vec<Binding>,
+  samples: &'a [Sample],
+}
+
+#[derive(Serialize)]
+struct Binding {
+  oid: snmp::ObjectIdentifier,
+  value: String,
+  number: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+  name: String,
+  #[serde(default)]
+  kind: MetricKind,
+  #[serde(default)]
+  labels: BTreeMap<String, String>,
+  value: f64,
+}
+
+impl Plugin {
+
+  pub fn apply(
+    &self,
+    bindings: &[snmp::VariableBinding],
+    samples: &[Sample],
+  ) -> Result<Vec<Sample>, Error> {
+    let input = Input {
+      bindings: bindings.iter()
+        .map(|binding| Binding {
+          oid: binding.object_id.clone(),
+          value: binding.value.to_string(),
+          number: binding.value.as_f64(),
+        })
+        .collect(),
+      samples,
+    };
+    let input = serde_json::to_vec(&input).map_err(Error::Output)?;
+    let output = runtime::call(&self.module, &input)?;
+    let timestamp = bindings.first()
+      .map(|binding| binding.timestamp)
+      .or_else(|| samples.first().map(|sample| sample.timestamp))
+      .unwrap_or(snmp::Timestamp { collected_at: SystemTime::now(), sys_up_time: None });
+    Ok(
+      serde_json::from_slice::<Vec<Output>>(&output)
+        .map_err(Error::Output)?
+        .into_iter()
+        .map(|output| Sample {
+          name: output.name,
+          kind: output.kind,
+          labels: output.labels,
+          value: output.value,
+          timestamp,
+        })
+        .collect()
+    )
+  }
+}
+
+#[cfg(feature = "wasm")]
+mod runtime {
+  use std::{collections::HashMap, path::{Path, PathBuf}, sync::{Mutex, OnceLock}};
+
+  use super::Error;
+
+  // Modules are compiled once and instantiated afresh for every call, so
+  // plugins cannot keep state between collections.
+  fn module(path: &Path) -> Result<(wasmtime::Engine, wasmtime::Module), Error> {
+    static ENGINE: OnceLock<wasmtime::Engine> = OnceLock::new();
+    static MODULES: OnceLock<Mutex<HashMap<PathBuf, wasmtime::Module>>> = OnceLock::new();
+    let engine = ENGINE.get_or_init(wasmtime::Engine::default);
+    let mut modules = MODULES.get_or_init(Default::default).lock().unwrap();
+    if let Some(module) = modules.get(path) {
+      return Ok((engine.clone(), module.clone()));
+    }
+    let module = wasmtime::Module::from_file(engine, path)
+      .map_err(|error| Error::Runtime(error.to_string()))?;
+    modules.insert(path.to_path_buf(), module.clone());
+    Ok((engine.clone(), module))
+  }
+
+  pub fn call(path: &Path, input: &[u8]) -> Result<Vec<u8>, Error> {
+    let runtime_error = |error: wasmtime::Error| Error::Runtime(error.to_string());
+    let (engine, module) = module(path)?;
+    let mut store = wasmtime::Store::new(&engine, ());
+    let instance = wasmtime::Instance::new(&mut store, &module, &[]).map_err(runtime_error)?;
+    let memory = instance.get_memory(&mut store, "memory")
+      .ok_or_else(|| Error::Runtime("no exported memory".to_string()))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc").map_err(runtime_error)?;
+    let transform = instance.get_typed_func::<(i32, i32), i64>(&mut store, "transform")
+      .map_err(runtime_error)?;
+    let input_len = i32::try_from(input.len())
+      .map_err(|_| Error::Runtime("input too large".to_string()))?;
+    let input_ptr = alloc.call(&mut store, input_len).map_err(runtime_error)?;
+    memory.write(&mut store, input_ptr as u32 as usize, input).map_err(|error| Error::Runtime(error.to_string()))?;
+    let location = transform.call(&mut store, (input_ptr, input_len)).map_err(runtime_error)? as u64;
+    let mut output = vec![0; (location & 0xffff_ffff) as usize];
+    memory.read(&store, (location >> 32) as usize, &mut output).map_err(|error| Error::Runtime(error.to_string()))?;
+    Ok(output)
+  }
+}
+
+#[cfg(not(feature = "wasm"))]
+mod runtime {
+  use std::path::Path;
+
+  use super::Error;
+
+  pub fn call(_path: &Path, _input: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::Unsupported)
+  }
+}
@@ -0,0 +1,64 @@
+use super::{Index, Metric, Profile, Table, script};
+
+// Printer-MIB (RFC 3805) together with the hrPrinterTable from
+// HOST-RESOURCES-MIB, which is where printers report their overall status.
+pub fn profile() -> Profile {
+  Profile {
+    name: "printer".to_string(),
+    per_vlan: false,
+    context_name: None,
+    context_engine_id: None,
+    requires: vec!["1.3.6.1.2.1.43".parse().expect("built-in profile OIDs are valid")],
+    variables: vec![],
+    scalars: vec![],
+    plugins: vec![],
+    scripts: script::Scripts::default(),
+    interval: None,
+    stages: vec![],
+    tables: vec![
+      Table {
+        indexes: vec![Index::integer("hrDeviceIndex")],
+        labels: vec![
+          Metric::static_column("prtGeneralPrinterName", "1.3.6.1.2.1.43.5.1.1.16"),
+        ],
+        lookups: vec![],
+        metrics: vec![
+          Metric::new("hrPrinterStatus", "1.3.6.1.2.1.25.3.5.1.1"),
+        ],
+      },
+      Table {
+        indexes: vec![Index::integer("hrDeviceIndex"), Index::integer("prtMarkerIndex")],
+        labels: vec![],
+        lookups: vec![],
+        metrics: vec![
+          Metric::counter("prtMarkerLifeCount", "1.3.6.1.2.1.43.10.2.1.4"),
+          Metric::counter("prtMarkerPowerOnCount", "1.3.6.1.2.1.43.10.2.1.5"),
+        ],
+      },
+      Table {
+        indexes: vec![Index::integer("hrDeviceIndex"), Index::integer("prtMarkerSuppliesIndex")],
+        labels: vec![
+          Metric::static_column("prtMarkerSuppliesDescription", "1.3.6.1.2.1.43.11.1.1.6"),
+          Metric::static_column("prtMarkerSuppliesType", "1.3.6.1.2.1.43.11.1.1.5"),
+        ],
+        lookups: vec![],
+        metrics: vec![
+          Metric::new("prtMarkerSuppliesMaxCapacity", "1.3.6.1.2.1.43.11.1.1.8"),
+          Metric::new("prtMarkerSuppliesLevel", "1.3.6.1.2.1.43.11.1.1.9"),
+        ],
+      },
+      Table {
+        indexes: vec![Index::integer("hrDeviceIndex"), Index::integer("prtAlertIndex")],
+        labels: vec![
+          Metric::new("prtAlertGroup", "1.3.6.1.2.1.43.18.1.1.4"),
+          Metric::new("prtAlertCode", "1.3.6.1.2.1.43.18.1.1.7"),
+          Metric::new("prtAlertDescription", "1.3.6.1.2.1.43.18.1.1.8"),
+        ],
+        lookups: vec![],
+        metrics: vec![
+          Metric::new("prtAlertSeverityLevel", "1.3.6.1.2.1.43.18.1.1.2"),
+        ],
+      },
+    ],
+  }
+}
@@ -0,0 +1,204 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{MetricKind, Sample};
+use crate::{snmp, trap};
+
+// Rhai snippets run at fixed points of a profile's life. Each sees the item
+// at hand as a variable (`sample` or `trap`) and either returns it, possibly
+// modified, or returns `false` or `()` to drop it; returning `true` keeps it
+// unchanged.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Scripts {
+  // Every sample decoded from the profile's scalars and tables.
+  pub post_decode: Option<String>,
+  // The final samples, after plugins and VLAN labels, as they are handed out.
+  pub pre_export: Option<String>,
+  // Every trap received from an agent the profile applies to.
+  pub trap_received: Option<String>,
+}
+
+impl Scripts {
+
+  // Compiles every script, so that packs with broken ones are turned away
+  // when they load rather than failing on each sample.
+  pub fn check(&self) -> Result<(), Error> {
+    let hooks = [("post_decode", &self.post_decode), ("pre_export", &self.pre_export), ("trap_received", &self.trap_received)];
+    for (hook, script) in hooks {
+      if let Some(script) = script {
+        compile(script).map_err(|error| match error {
+          Error::Script(error) => Error::Script(format!("{}: {}", hook, error)),
+          error => error,
+        })?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug)]
+pub enum Error {
+  Unsupported,
+  Script(String),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Unsupported => write!(f, "built without scripting support"),
+      Error::Script(error) => write!(f, "script failed: {}", error),
+    }
+  }
+}
+
+// The part of a sample scripts may change; the timestamp is kept as is.
+#[derive(Serialize, Deserialize)]
+struct ScriptSample {
+  name: String,
+  kind: MetricKind,
+  labels: BTreeMap<String, String>,
+  value: f64,
+}
+
+// Runs `script` on every sample. A failing script leaves the sample alone.
+pub fn samples(script: Option<&str>, samples: Vec<Sample>) -> Vec<Sample> {
+  let Some(script) = script else {
+    return samples;
+  };
+  if !cfg!(feature = "scripting") {
+    eprintln!("Script skipped: {}", Error::Unsupported);
+    return samples;
+  }
+  samples.into_iter()
+    .filter_map(|sample| {
+      let input = ScriptSample {
+        name: sample.name.clone(),
+        kind: sample.kind,
+        labels: sample.labels.clone(),
+        value: sample.value,
+      };
+      match run(script, "sample", input) {
+        Ok(output) => output.map(|output| Sample {
+          name: output.name,
+          kind: output.kind,
+          labels: output.labels,
+          value: output.value,
+          timestamp: sample.timestamp,
+        }),
+        Err(error) => {
+          eprintln!("Script on {}: {}", sample.name, error);
+          Some(sample)
+        },
+      }
+    })
+    .collect()
+}
+
+// The part of a trap scripts may change: its bindings. Where it came from
+// and what it is are there to be read.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScriptTrap {
+  source: String,
+  community: String,
+  trap_oid: Option<snmp::ObjectIdentifier>,
+  bindings: Vec<ScriptBinding>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScriptBinding {
+  oid: snmp::ObjectIdentifier,
+  #[serde(flatten)]
+  value: snmp::ObjectValue,
+}
+
+// Runs each of `scripts` on a trap in turn, None once one drops it. A
+// failing script leaves the trap alone.
+pub fn trap(scripts: &[String], mut trap: trap::Trap) -> Option<trap::Trap> {
+  for script in scripts {
+    let input = ScriptTrap {
+      source: trap.source.ip().to_string(),
+      community: String::from_utf8_lossy(&trap.notification.community).into_owned(),
+      trap_oid: trap.notification.trap_oid().cloned(),
+      bindings: trap.notification.bindings.iter()
+        .map(|binding| ScriptBinding { oid: binding.object_id.clone(), value: binding.value.clone() })
+        .collect(),
+    };
+    match run(script, "trap", input) {
+      Ok(Some(output)) => {
+        let timestamp = trap.notification.bindings.first()
+          .map_or(snmp::Timestamp { collected_at: std::time::SystemTime::now(), sys_up_time: None }, |binding| binding.timestamp);
+        trap.notification.bindings = output.bindings.into_iter()
+          .map(|ScriptBinding { oid, value }| snmp::VariableBinding { object_id: oid, value, timestamp })
+          .collect();
+      },
+      Ok(None) => return None,
+      Err(error) => eprintln!("Script on trap from {}: {}", trap.source, error),
+    }
+  }
+  Some(trap)
+}
+
+// Runs `script` with `value` bound to `variable`, returning the value the
+// script hands back or `None` when it drops it.
+#[cfg(feature = "scripting")]
+pub fn run<T>(script: &str, variable: &str, value: T) -> Result<Option<T>, Error>
+  where T: Serialize + DeserializeOwned
+{
+  let engine = engine();
+  let ast = compiled(script)?;
+  let mut scope = rhai::Scope::new();
+  let input = rhai::serde::to_dynamic(&value).map_err(|error| Error::Script(error.to_string()))?;
+  scope.push(variable, input);
+  let output = engine.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &ast)
+    .map_err(|error| Error::Script(error.to_string()))?;
+  if output.is_unit() || output.as_bool() == Ok(false) {
+    return Ok(None);
+  }
+  if output.as_bool() == Ok(true) {
+    return Ok(Some(value));
+  }
+  rhai::serde::from_dynamic(&output)
+    .map(Some)
+    .map_err(|error| Error::Script(error.to_string()))
+}
+
+#[cfg(feature = "scripting")]
+fn engine() -> &'static rhai::Engine {
+  static ENGINE: std::sync::OnceLock<rhai::Engine> = std::sync::OnceLock::new();
+  ENGINE.get_or_init(rhai::Engine::new)
+}
+
+// The script compiled, once for all its runs.
+#[cfg(feature = "scripting")]
+fn compiled(script: &str) -> Result<rhai::AST, Error> {
+  use std::{collections::HashMap, sync::{Mutex, OnceLock}};
+
+  static COMPILED: OnceLock<Mutex<HashMap<String, rhai::AST>>> = OnceLock::new();
+  let mut compiled = COMPILED.get_or_init(Default::default).lock().unwrap();
+  if let Some(ast) = compiled.get(script) {
+    return Ok(ast.clone());
+  }
+  let ast = engine().compile(script).map_err(|error| Error::Script(error.to_string()))?;
+  compiled.insert(script.to_string(), ast.clone());
+  Ok(ast)
+}
+
+#[cfg(feature = "scripting")]
+fn compile(script: &str) -> Result<(), Error> {
+  compiled(script).map(|_ast| ())
+}
+
+#[cfg(not(feature = "scripting"))]
+fn compile(_script: &str) -> Result<(), Error> {
+  Err(Error::Unsupported)
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn run<T>(_script: &str, _variable: &str, _value: T) -> Result<Option<T>, Error>
+  where T: Serialize + DeserializeOwned
+{
+  Err(Error::Unsupported)
+}
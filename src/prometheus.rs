@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use crate::{config, profile, stats};
+
+// The latest samples of every target in the Prometheus text format, one
+// series per sample labelled with the target's address as `host`, its name
+// as `target`, and the sample's own labels, as in
+// `ifHCInOctets{host="10.0.0.1",target="sw1",ifIndex="3"} 1234`.
+pub fn exposition<'a>(targets: impl Iterator<Item = (&'a config::TargetConfig, Vec<profile::Sample>)>) -> String {
+  // Series of one name must come together, under their TYPE line, and
+  // each only once, though two sources of a target may both have it.
+  let mut families: BTreeMap<String, (profile::MetricKind, BTreeMap<String, String>)> = BTreeMap::new();
+  for (target, samples) in targets {
+    for sample in samples {
+      let labels = [("host".to_string(), target.address.to_string()), ("target".to_string(), target.name.clone())]
+        .into_iter()
+        .chain(sample.labels.iter()
+          .map(|(label, value)| (name(label, false), value.clone()))
+          .filter(|(label, _)| label != "host" && label != "target"))
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape(&value)))
+        .collect::<Vec<_>>()
+        .join(",");
+      families.entry(name(&sample.name, true))
+        .or_insert_with(|| (sample.kind, BTreeMap::new()))
+        .1
+        .entry(labels)
+        .or_insert_with(|| value(sample.value));
+    }
+  }
+  let mut text = String::new();
+  for (name, (kind, series)) in families {
+    let kind = match kind {
+      profile::MetricKind::Gauge => "gauge",
+      profile::MetricKind::Counter => "counter",
+    };
+    text.push_str(&format!("# TYPE {} {}\n", name, kind));
+    for (labels, value) in series {
+      text.push_str(&format!("{}{{{}}} {}\n", name, labels, value));
+    }
+  }
+  text
+}
+
+// The collector's own counters, gauges and latency histograms.
+pub fn internal() -> String {
+  let stats = &stats::STATS;
+  let mut text = String::new();
+  let counters = [
+    ("snmp_collector_collections_total", &stats.collections),
+    ("snmp_collector_collection_failures_total", &stats.collection_failures),
+    ("snmp_collector_sink_writes_total", &stats.sink_writes),
+    ("snmp_collector_sink_failures_total", &stats.sink_failures),
+    ("snmp_collector_snmp_requests_total", &stats.snmp_requests),
+    ("snmp_collector_snmp_timeouts_total", &stats.snmp_timeouts),
+    ("snmp_collector_snmp_decode_errors_total", &stats.snmp_decode_errors),
+    ("snmp_collector_http_requests_total", &stats.http_requests),
+  ];
+  for (name, counter) in counters {
+    text.push_str(&format!("# TYPE {} counter\n{} {}\n", name, name, stats::read(counter)));
+  }
+  let gauges = [
+    ("snmp_collector_snmp_awaiting", &stats.snmp_awaiting),
+    ("snmp_collector_snmp_queued", &stats.snmp_queued),
+  ];
+  for (name, gauge) in gauges {
+    text.push_str(&format!("# TYPE {} gauge\n{} {}\n", name, name, stats::read(gauge)));
+  }
+  let histograms = [
+    ("snmp_collector_snmp_latency_seconds", &stats.snmp_latency),
+    ("snmp_collector_http_latency_seconds", &stats.http_latency),
+  ];
+  for (name, histogram) in histograms {
+    let (buckets, count, sum) = histogram.read();
+    text.push_str(&format!("# TYPE {} histogram\n", name));
+    for (bound, cumulative) in stats::BUCKETS.iter().zip(buckets) {
+      text.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound, cumulative));
+    }
+    text.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n{}_sum {}\n{}_count {}\n", name, count, name, sum, name, count));
+  }
+  text
+}
+
+// A metric or label name with what Prometheus does not allow replaced, so
+// that a sample named by its OID comes out as `_1_3_6_1_2_1_1_3_0`. Colons
+// are only allowed in metric names.
+fn name(name: &str, metric: bool) -> String {
+  let name = name.chars()
+    .map(|char| match char.is_ascii_alphanumeric() || char == '_' || (metric && char == ':') {
+      true => char,
+      false => '_',
+    })
+    .collect::<String>();
+  match name.starts_with(|first: char| first.is_ascii_digit()) || name.is_empty() {
+    true => format!("_{}", name),
+    false => name,
+  }
+}
+
+fn value(value: f64) -> String {
+  match value {
+    f64::INFINITY => "+Inf".to_string(),
+    f64::NEG_INFINITY => "-Inf".to_string(),
+    value => value.to_string(),
+  }
+}
+
+fn escape(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,116 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use rasn_snmp as model;
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::{config, reload, snmp};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const NO_ACCESS: u32 = 6;
+
+// Answers SNMPv2c requests arriving on `listen` by forwarding them to the
+// target their community is routed to, under that target's own credentials,
+// so that management software limited to one community can reach agents
+// configured otherwise. The target's allow and deny lists apply: objects
+// outside them come back as noSuchObject, or endOfMibView for walks. Set
+// requests are refused. Routes are those of the configuration in force when
+// a request arrives.
+pub fn spawn(reloader: Arc<reload::Reloader>) -> std::io::Result<()> {
+  let config = reloader.config();
+  let Some(listen) = config.proxy.listen else {
+    return Ok(());
+  };
+  for route in &config.proxy.routes {
+    if config.named(&route.target).is_none() {
+      eprintln!("Proxy route for unknown target {}", route.target);
+    }
+  }
+  let socket = Arc::new(std::net::UdpSocket::bind(listen).and_then(|socket| {
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket)
+  })?);
+  tokio::spawn(async move {
+    let mut buffer = [0; 65535];
+    loop {
+      let Ok((length, client)) = socket.recv_from(&mut buffer).await else {
+        continue;
+      };
+      let request = buffer[..length].to_vec();
+      let (socket, config) = (socket.clone(), reloader.config());
+      tokio::spawn(async move {
+        if let Err(error) = forward(&socket, client, &request, &config).await {
+          eprintln!("Cannot proxy request from {}: {}", client, error);
+        }
+      });
+    }
+  });
+  Ok(())
+}
+
+async fn forward(
+  socket: &UdpSocket,
+  client: SocketAddr,
+  request: &[u8],
+  config: &config::Config,
+) -> Result<(), snmp::Error> {
+  let Ok(message) = rasn::ber::decode::<model::v2c::Message<model::v2::Pdus>>(request) else {
+    // Not SNMPv2c; agents drop what they cannot parse, and so does the proxy.
+    return Ok(());
+  };
+  let target = config.proxy.routes.iter()
+    .find(|route| route.community.as_bytes() == message.community.as_ref())
+    .and_then(|route| config.named(&route.target));
+  let Some(target) = target else {
+    return Ok(());
+  };
+  let walks = match &message.data {
+    model::v2::Pdus::GetRequest(_) => false,
+    model::v2::Pdus::GetNextRequest(_) | model::v2::Pdus::GetBulkRequest(_) => true,
+    model::v2::Pdus::SetRequest(request) => {
+      let refused = model::v2::Pdu {
+        error_status: NO_ACCESS,
+        error_index: 1,
+        ..request.0.clone()
+      };
+      return reply(socket, client, &message.community, refused).await;
+    },
+    _ => return Ok(()),
+  };
+  let response = backend(target, message.data).await?;
+  reply(socket, client, &message.community, restrict(response, target, walks)).await
+}
+
+// Sends the request under the target's credentials, SNMPv3 ones included,
+// and returns the PDU of its response.
+async fn backend(target: &config::TargetConfig, data: model::v2::Pdus) -> Result<model::v2::Pdu, snmp::Error> {
+  snmp::within(Instant::now() + TIMEOUT, target.agent().request(data)).await
+}
+
+// Hides the objects the target's policy does not permit.
+fn restrict(mut pdu: model::v2::Pdu, target: &config::TargetConfig, walks: bool) -> model::v2::Pdu {
+  for binding in &mut pdu.variable_bindings {
+    if !target.permits(&binding.name.to_vec().into()) {
+      binding.value = match walks {
+        true => model::v2::VarBindValue::EndOfMibView,
+        false => model::v2::VarBindValue::NoSuchObject,
+      };
+    }
+  }
+  pdu
+}
+
+async fn reply(
+  socket: &UdpSocket,
+  client: SocketAddr,
+  community: &snmp::OctetString,
+  pdu: model::v2::Pdu,
+) -> Result<(), snmp::Error> {
+  let message = model::v2c::Message {
+    version: 1.into(),
+    community: community.clone(),
+    data: model::v2::Response(pdu),
+  };
+  let response = rasn::ber::encode(&message).map_err(snmp::Error::codec)?;
+  socket.send_to(&response, client).await.map_err(snmp::Error::io)?;
+  Ok(())
+}
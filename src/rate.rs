@@ -0,0 +1,97 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Mutex};
+
+use crate::snmp;
+
+// Turns counter readings into per-second rates by remembering the previous
+// reading of every counter instance on every agent.
+#[derive(Default)]
+pub struct Rates {
+  previous: Mutex<HashMap<(SocketAddr, snmp::ObjectIdentifier), Reading>>,
+}
+
+#[derive(Clone, Copy)]
+struct Reading {
+  value: u64,
+  wraps_at: Option<u64>,
+  timestamp: snmp::Timestamp,
+}
+
+impl Reading {
+
+  fn new(value: &snmp::ObjectValue, timestamp: snmp::Timestamp) -> Option<Reading> {
+    let (value, wraps_at) = match value {
+      snmp::ObjectValue::Counter32(value) => (u64::from(*value), Some(1 << 32)),
+      snmp::ObjectValue::Counter64(value) => (*value, None),
+      _ => return None,
+    };
+    Some(Reading { value, wraps_at, timestamp })
+  }
+
+  // Seconds between two readings, on the agent's clock where possible. An
+  // agent that restarted in between has restarted its counters too, so no
+  // rate can be given across the two.
+  fn seconds_since(&self, earlier: &Reading) -> Option<f64> {
+    if rebooted(&earlier.timestamp, &self.timestamp) {
+      return None;
+    }
+    let ticks = match (earlier.timestamp.sys_up_time, self.timestamp.sys_up_time) {
+      (Some(earlier), Some(later)) => later - earlier,
+      _ => 0,
+    };
+    let seconds = match ticks {
+      0 => self.timestamp.collected_at.duration_since(earlier.timestamp.collected_at).ok()?.as_secs_f64(),
+      ticks => f64::from(ticks) / 100.0,
+    };
+    Some(seconds).filter(|seconds| *seconds > 0.0)
+  }
+
+  fn delta_since(&self, earlier: &Reading) -> Option<u64> {
+    match (self.value.checked_sub(earlier.value), self.wraps_at) {
+      (Some(delta), _) => Some(delta),
+      (None, Some(wraps_at)) => Some(wraps_at - earlier.value + self.value),
+      (None, None) => None,
+    }
+  }
+}
+
+// Whether the agent restarted between two responses: its sysUpTime went
+// backwards, or advanced clearly less than the time that passed, as when
+// it was down for a while and has since been up longer than before.
+pub fn rebooted(earlier: &snmp::Timestamp, later: &snmp::Timestamp) -> bool {
+  let (Some(earlier_ticks), Some(later_ticks)) = (earlier.sys_up_time, later.sys_up_time) else {
+    return false;
+  };
+  if later_ticks < earlier_ticks {
+    return true;
+  }
+  let Ok(elapsed) = later.collected_at.duration_since(earlier.collected_at) else {
+    return false;
+  };
+  // Agents answer late and their clocks drift, hence the slack.
+  let slack = elapsed.as_secs_f64() * 0.1 + 5.0;
+  f64::from(later_ticks - earlier_ticks) / 100.0 + slack < elapsed.as_secs_f64()
+}
+
+impl Rates {
+
+  // Records a counter reading and returns its rate per second since the
+  // previous one, if there was one. Non-counter values yield nothing.
+  pub fn update(
+    &self,
+    target: &snmp::SnmpClient,
+    binding: &snmp::VariableBinding,
+  ) -> Option<f64> {
+    let reading = Reading::new(&binding.value, binding.timestamp)?;
+    let key = (*target.address(), binding.object_id.clone());
+    let mut previous = self.previous.lock().unwrap();
+    let earlier = previous.insert(key.clone(), reading)?;
+    if rebooted(&earlier.timestamp, &reading.timestamp) {
+      // Every other counter of the agent restarted as well.
+      previous.retain(|(address, oid), _| *address != key.0 || *oid == key.1);
+      return None;
+    }
+    drop(previous);
+    let seconds = reading.seconds_since(&earlier)?;
+    Some(reading.delta_since(&earlier)? as f64 / seconds)
+  }
+}
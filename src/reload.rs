@@ -0,0 +1,130 @@
+use std::{fmt::Display, path::PathBuf, sync::{Arc, Mutex}};
+
+use tokio::{signal::unix::{signal, SignalKind}, sync::{watch, Semaphore}};
+
+use crate::{collector, config, drift, profile, scheduler, sink, snmp, source};
+
+#[derive(Debug)]
+pub enum Error {
+  // Started without a file, so there is none to read again.
+  NoFile(),
+  Config(config::Error),
+  Sinks(sink::Error),
+  Sources(source::Error),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::NoFile() => write!(f, "no configuration file to reload"),
+      Error::Config(error) => write!(f, "{}", error),
+      Error::Sinks(error) => write!(f, "{}", error),
+      Error::Sources(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+// Held for as long as the tasks started with it are to run on schedule.
+// Once it is dropped, each stops when done with what it is collecting.
+pub struct Schedule {
+  stop: watch::Sender<()>,
+}
+
+impl Default for Schedule {
+
+  fn default() -> Self {
+    Schedule { stop: watch::channel(()).0 }
+  }
+}
+
+impl Schedule {
+
+  // What the tasks wait on between ticks; it reports an error once the
+  // schedule is dropped.
+  pub fn stopped(&self) -> watch::Receiver<()> {
+    self.stop.subscribe()
+  }
+}
+
+// The configuration in force, and what was set up from it. Reading the file
+// again replaces targets, credentials, the PDU rate, sinks, proxy routes
+// and the collection, job and drift schedules; requests already made carry
+// on with the configuration they started under. Listen addresses, and the
+// settings of the trap receiver and of AgentX, are only read at startup.
+pub struct Reloader {
+  path: Option<PathBuf>,
+  profiles: Vec<profile::Profile>,
+  sinks: sink::Registry,
+  sources: source::Registry,
+  latest: Arc<collector::Latest>,
+  snapshots: Arc<drift::Store>,
+  config: Mutex<Arc<config::Config>>,
+  schedules: Mutex<Vec<Schedule>>,
+}
+
+impl Reloader {
+
+  pub fn start(
+    path: Option<PathBuf>,
+    config: config::Config,
+    profiles: Vec<profile::Profile>,
+    sinks: sink::Registry,
+    sources: source::Registry,
+    latest: Arc<collector::Latest>,
+    snapshots: Arc<drift::Store>,
+  ) -> Result<Reloader, Error> {
+    let reloader = Reloader {
+      path,
+      profiles,
+      sinks,
+      sources,
+      latest,
+      snapshots,
+      config: Mutex::new(Arc::new(config::Config::default())),
+      schedules: Mutex::new(Vec::new()),
+    };
+    reloader.apply(config)?;
+    Ok(reloader)
+  }
+
+  pub fn config(&self) -> Arc<config::Config> {
+    self.config.lock().unwrap().clone()
+  }
+
+  // Reads the file again; nothing changes unless all of it can be set up.
+  pub fn reload(&self) -> Result<(), Error> {
+    let path = self.path.as_ref().ok_or(Error::NoFile())?;
+    self.apply(config::load(path).map_err(Error::Config)?)
+  }
+
+  fn apply(&self, config: config::Config) -> Result<(), Error> {
+    // One reload at a time, so that the schedules kept are those of the
+    // configuration kept.
+    let mut schedules = self.schedules.lock().unwrap();
+    let sinks = Arc::new(self.sinks.build(&config.sinks).map_err(Error::Sinks)?);
+    let workers = Arc::new(Semaphore::new(config.collection.workers.max(1)));
+    let collection = collector::spawn(&config, &self.profiles, &self.sources, sinks.clone(), self.latest.clone(), workers.clone())
+      .map_err(Error::Sources)?;
+    let jobs = scheduler::spawn(&config, sinks, self.latest.clone(), workers);
+    let drift = drift::spawn(&config, self.snapshots.clone());
+    snmp::limit_rate(config.max_pdus_per_second);
+    *self.config.lock().unwrap() = Arc::new(config);
+    *schedules = vec![collection, jobs, drift];
+    Ok(())
+  }
+}
+
+// Reloads the configuration whenever the process gets SIGHUP.
+pub fn on_hangup(reloader: Arc<Reloader>) -> std::io::Result<()> {
+  let mut hangups = signal(SignalKind::hangup())?;
+  tokio::spawn(async move {
+    while hangups.recv().await.is_some() {
+      match reloader.reload() {
+        Ok(()) => eprintln!("Configuration reloaded"),
+        Err(error) => eprintln!("Cannot reload the configuration: {}", error),
+      }
+    }
+  });
+  Ok(())
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::{sync::watch, time::Instant};
+
+use crate::snmp;
+
+type Answer = Vec<(snmp::ObjectIdentifier, snmp::Result<snmp::VariableBinding>)>;
+
+type Key = (snmp::Target, Vec<snmp::ObjectIdentifier>);
+
+// Answers to gets by target and OIDs, given again for as long as they are
+// fresh so that agents are not asked the same thing over and over. Answers
+// with any OID failing are not kept; the agent is asked again. The same get
+// arriving while one is being asked waits for its answer rather than
+// asking as well.
+#[derive(Default)]
+pub struct Responses {
+  answers: Mutex<HashMap<Key, (Instant, Answer)>>,
+  asking: Mutex<HashMap<Key, watch::Receiver<Option<Answer>>>>,
+}
+
+enum Turn {
+  Ask(watch::Sender<Option<Answer>>),
+  Wait(watch::Receiver<Option<Answer>>),
+}
+
+// Stops others waiting on an answer once it is given, or given up on.
+struct Asking<'a> {
+  responses: &'a Responses,
+  key: &'a Key,
+}
+
+impl Drop for Asking<'_> {
+
+  fn drop(&mut self) {
+    self.responses.asking.lock().unwrap().remove(self.key);
+  }
+}
+
+impl Responses {
+
+  // `get_each` on the target, or what it answered less than `ttl` ago.
+  pub async fn get_each(&self, target: &snmp::SnmpClient, oids: &[snmp::ObjectIdentifier], ttl: Duration) -> Answer {
+    let key = (target.target().clone(), oids.to_vec());
+    if !ttl.is_zero() {
+      if let Some((_, answer)) = self.answers.lock().unwrap().get(&key).filter(|(at, _)| at.elapsed() < ttl) {
+        return answer.clone();
+      }
+    }
+    let answer = match self.turn(&key) {
+      Turn::Ask(sender) => {
+        let _asking = Asking { responses: self, key: &key };
+        let answer = target.get_each(oids).await;
+        sender.send_replace(Some(answer.clone()));
+        answer
+      },
+      Turn::Wait(mut receiver) => {
+        let answer = receiver.wait_for(Option::is_some).await.ok().and_then(|answer| answer.clone());
+        match answer {
+          Some(answer) => answer,
+          // The request asking was dropped before the agent answered.
+          None => target.get_each(oids).await,
+        }
+      },
+    };
+    if !ttl.is_zero() && answer.iter().all(|(_, value)| value.is_ok()) {
+      let mut answers = self.answers.lock().unwrap();
+      answers.retain(|_, (at, _)| at.elapsed() < ttl);
+      answers.insert(key, (Instant::now(), answer.clone()));
+    }
+    answer
+  }
+
+  fn turn(&self, key: &Key) -> Turn {
+    let mut asking = self.asking.lock().unwrap();
+    if let Some(receiver) = asking.get(key) {
+      return Turn::Wait(receiver.clone());
+    }
+    let (sender, receiver) = watch::channel(None);
+    asking.insert(key.clone(), receiver);
+    Turn::Ask(sender)
+  }
+}
@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use tokio::{sync::Semaphore, time::Instant};
+
+use crate::{collector, config, mib, pipeline, profile, reload, sink, snmp, stats};
+
+// Polls the OIDs of every job on its targets at the job's interval, each
+// poll once one of the `workers` is free, and hands the samples, once
+// through the job's pipeline, to the sinks and to `latest` as the collector
+// does with profiles. Polling goes on until the schedule returned is
+// dropped.
+pub fn spawn(
+  config: &config::Config,
+  sinks: Arc<Vec<sink::Output>>,
+  latest: Arc<collector::Latest>,
+  workers: Arc<Semaphore>,
+) -> reload::Schedule {
+  let schedule = reload::Schedule::default();
+  for job in &config.jobs {
+    let interval = job.interval.unwrap_or(config.collection.interval);
+    let targets = config.job_targets(job).cloned().collect::<Vec<_>>();
+    if interval == 0 || targets.is_empty() {
+      continue;
+    }
+    let period = Duration::from_secs(interval);
+    let job = Arc::new(job.clone());
+    let stages = Arc::new(config.pipeline(&job.name).to_vec());
+    let (sinks, latest, workers) = (sinks.clone(), latest.clone(), workers.clone());
+    let mut stopped = schedule.stopped();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(period);
+      loop {
+        tokio::select! {
+          _ = interval.tick() => {},
+          _ = stopped.changed() => break,
+        }
+        // One target's poll does not wait on another's; each is given up
+        // when the next is due.
+        let deadline = Instant::now() + period;
+        for target in &targets {
+          let (job, stages, target) = (job.clone(), stages.clone(), target.clone());
+          let (sinks, latest, workers) = (sinks.clone(), latest.clone(), workers.clone());
+          tokio::spawn(async move {
+            stats::count(&stats::STATS.collections);
+            let polled = tokio::time::timeout_at(deadline, async {
+              let _worker = workers.acquire().await;
+              snmp::within(deadline, poll(&job, &target)).await
+            });
+            let samples = match polled.await {
+              Ok(Ok(samples)) => samples,
+              Ok(Err(error)) => {
+                stats::count(&stats::STATS.collection_failures);
+                eprintln!("Cannot poll {} on {}: {}", job.name, target.name, error);
+                return;
+              },
+              Err(_elapsed) => {
+                stats::count(&stats::STATS.collection_failures);
+                eprintln!("Cannot poll {} on {}: no response", job.name, target.name);
+                return;
+              },
+            };
+            let routed = pipeline::run(&stages, &target, samples);
+            latest.record(&target.name, &job.name, routed.iter().map(|routed| routed.sample.clone()).collect());
+            collector::deliver(&sinks, &target, &job.name, &routed).await;
+          });
+        }
+      }
+    });
+  }
+  schedule
+}
+
+async fn poll(job: &config::JobConfig, target: &config::TargetConfig) -> Result<Vec<profile::Sample>, snmp::Error> {
+  let agent = target.agent();
+  let mut bindings = Vec::new();
+  if !job.oids.is_empty() {
+    bindings.extend(agent.get(&job.oids).await?);
+  }
+  for root in &job.walks {
+    bindings.extend(agent.walk(root).await?);
+  }
+  Ok(bindings.iter().filter_map(sample).collect())
+}
+
+// A numeric value as a sample named after its object, ifHCInOctets rather
+// than IF-MIB::ifHCInOctets.3, with the instance arcs as the `index` label.
+// Without a MIB naming it, the sample goes by the OID.
+fn sample(binding: &snmp::VariableBinding) -> Option<profile::Sample> {
+  let value = binding.value.as_f64()?;
+  let kind = match binding.value {
+    snmp::ObjectValue::Counter32(_) | snmp::ObjectValue::Counter64(_) => profile::MetricKind::Counter,
+    _ => profile::MetricKind::Gauge,
+  };
+  let (name, labels) = match mib::object_name(binding.object_id.arcs()) {
+    Some((name, [])) => (name.to_string(), BTreeMap::new()),
+    Some((name, index)) => {
+      let index = index.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join(".");
+      (name.to_string(), BTreeMap::from([("index".to_string(), index)]))
+    },
+    None => (binding.object_id.to_string(), BTreeMap::new()),
+  };
+  Some(profile::Sample {
+    name,
+    kind,
+    labels,
+    value,
+    timestamp: binding.timestamp,
+  })
+}
@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fmt::Display, fs, future::Future, io::Write, path::PathBuf, pin::Pin, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, profile};
+
+mod graphite;
+mod influxdb;
+mod kafka;
+mod otlp;
+mod splunk;
+mod statsd;
+mod zabbix;
+
+// The samples one collection of a source produced on one target.
+pub struct Batch<'a> {
+  pub target: &'a config::TargetConfig,
+  pub source: &'a str,
+  pub samples: &'a [profile::Sample],
+}
+
+pub type BoxFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+// An output destination for collected samples.
+pub trait Sink: Send + Sync {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a>;
+}
+
+// A configured sink with the name pipelines route to.
+pub struct Output {
+  pub name: String,
+  pub sink: Box<dyn Sink>,
+}
+
+// Builds a sink from the options of its `[[sinks]]` entry.
+pub type Factory = Box<dyn Fn(&toml::Table) -> Result<Box<dyn Sink>, Error> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum Error {
+  UnknownKind(String),
+  Config(String),
+  Io(std::io::Error),
+  Other(String),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::UnknownKind(kind) => write!(f, "unknown sink kind {}", kind),
+      Error::Config(error) => write!(f, "invalid sink configuration: {}", error),
+      Error::Io(error) => write!(f, "cannot write samples: {}", error),
+      Error::Other(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+// Sink kinds by the name used in the configuration. Crates embedding the
+// collector register their own kinds before building the configured sinks.
+pub struct Registry {
+  factories: HashMap<String, Factory>,
+}
+
+impl Default for Registry {
+
+  fn default() -> Self {
+    let mut registry = Registry { factories: HashMap::new() };
+    registry.register("graphite", Box::new(|options| Ok(Box::new(graphite::Graphite::new(options)?))));
+    registry.register("influxdb", Box::new(|options| Ok(Box::new(influxdb::Influxdb::new(options)?))));
+    registry.register("json_lines", Box::new(|options| Ok(Box::new(JsonLines::new(options)?))));
+    registry.register("kafka", Box::new(|options| Ok(Box::new(kafka::Kafka::new(options)?))));
+    registry.register("otlp", Box::new(|options| Ok(Box::new(otlp::Otlp::new(options)?))));
+    registry.register("splunk", Box::new(|options| Ok(Box::new(splunk::Splunk::new(options)?))));
+    registry.register("statsd", Box::new(|options| Ok(Box::new(statsd::Statsd::new(options)?))));
+    registry.register("zabbix", Box::new(|options| Ok(Box::new(zabbix::Zabbix::new(options)?))));
+    registry
+  }
+}
+
+impl Registry {
+
+  pub fn register(&mut self, kind: &str, factory: Factory) {
+    self.factories.insert(kind.to_string(), factory);
+  }
+
+  pub fn build(&self, sinks: &[config::SinkConfig]) -> Result<Vec<Output>, Error> {
+    sinks.iter()
+      .map(|sink| {
+        let factory = self.factories.get(&sink.kind)
+          .ok_or_else(|| Error::UnknownKind(sink.kind.clone()))?;
+        Ok(Output {
+          name: sink.name.clone().unwrap_or_else(|| sink.kind.clone()),
+          sink: factory(&sink.options)?,
+        })
+      })
+      .collect()
+  }
+}
+
+// Expands `{target}`, `{address}`, `{source}`, `{name}`, `{label.<name>}`
+// and `{tag.<name>}` in a sink's naming template; unknown labels and tags
+// expand to nothing.
+pub fn template(template: &str, batch: &Batch<'_>, sample: &profile::Sample) -> String {
+  template_escaped(template, batch, sample, str::to_string)
+}
+
+// As `template`, with the values filled in passed through `escape`.
+pub fn template_escaped(
+  template: &str,
+  batch: &Batch<'_>,
+  sample: &profile::Sample,
+  escape: impl Fn(&str) -> String,
+) -> String {
+  let address = batch.target.address.to_string();
+  let mut text = String::new();
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+      break;
+    };
+    text.push_str(&rest[..start]);
+    let placeholder = &rest[start + 1..end];
+    let value = match placeholder {
+      "target" => Some(batch.target.name.as_str()),
+      "address" => Some(address.as_str()),
+      "source" => Some(batch.source),
+      "name" => Some(sample.name.as_str()),
+      _ => placeholder.strip_prefix("label.")
+        .and_then(|label| sample.labels.get(label))
+        .or_else(|| placeholder.strip_prefix("tag.").and_then(|tag| batch.target.tags.get(tag)))
+        .map(String::as_str),
+    };
+    text.push_str(&escape(value.unwrap_or_default()));
+    rest = &rest[end + 1..];
+  }
+  text.push_str(rest);
+  text
+}
+
+// One JSON object per sample, appended to `path` or written to stdout.
+struct JsonLines {
+  path: Option<PathBuf>,
+  lock: Mutex<()>,
+}
+
+#[derive(Deserialize)]
+struct JsonLinesOptions {
+  path: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+  target: &'a str,
+  source: &'a str,
+  #[serde(flatten)]
+  sample: &'a profile::Sample,
+}
+
+impl JsonLines {
+
+  fn new(options: &toml::Table) -> Result<JsonLines, Error> {
+    let options = options.clone()
+      .try_into::<JsonLinesOptions>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    Ok(JsonLines { path: options.path, lock: Mutex::new(()) })
+  }
+
+  fn append(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let mut text = Vec::new();
+    for sample in batch.samples {
+      let line = JsonLine { target: &batch.target.name, source: batch.source, sample };
+      serde_json::to_writer(&mut text, &line).map_err(|error| Error::Other(error.to_string()))?;
+      text.push(b'\n');
+    }
+    let _guard = self.lock.lock().unwrap();
+    match &self.path {
+      Some(path) => fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(&text)),
+      None => std::io::stdout().lock().write_all(&text),
+    }
+    .map_err(Error::Io)
+  }
+}
+
+impl Sink for JsonLines {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(async move { self.append(batch) })
+  }
+}
@@ -0,0 +1,127 @@
+use std::{net::SocketAddr, time::{Duration, UNIX_EPOCH}};
+
+use serde::Deserialize;
+use tokio::{io::AsyncWriteExt, net::TcpStream, sync::Mutex};
+
+use super::{template_escaped, Batch, BoxFuture, Error, Sink};
+use crate::profile;
+
+// Graphite's plaintext protocol over TCP, `path value timestamp` a line.
+// The path is `metric`, a template expanded as for the other sinks, such as
+// `snmp.{target}.{name}` for `snmp.sw1.ifHCInOctets`, followed by the
+// sample's label values in label order unless `labels = false`; dots and
+// spaces in what is filled in become underscores, so that each value stays
+// one node of the path. The connection is kept, and made again when a write
+// fails.
+pub struct Graphite {
+  address: SocketAddr,
+  metric: String,
+  labels: bool,
+  timeout: Duration,
+  connection: Mutex<Option<TcpStream>>,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  #[serde(default = "default_address")]
+  address: SocketAddr,
+  #[serde(default = "default_metric")]
+  metric: String,
+  #[serde(default = "default_labels")]
+  labels: bool,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_address() -> SocketAddr {
+  SocketAddr::from(([127, 0, 0, 1], 2003))
+}
+
+fn default_metric() -> String {
+  "snmp.{target}.{name}".to_string()
+}
+
+fn default_labels() -> bool {
+  true
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+impl Graphite {
+
+  pub fn new(options: &toml::Table) -> Result<Graphite, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    Ok(Graphite {
+      address: options.address,
+      metric: options.metric,
+      labels: options.labels,
+      timeout: Duration::from_secs(options.timeout),
+      connection: Mutex::new(None),
+    })
+  }
+
+  fn line(&self, batch: &Batch<'_>, sample: &profile::Sample) -> Option<String> {
+    if !sample.value.is_finite() {
+      return None;
+    }
+    let mut path = template_escaped(&self.metric, batch, sample, node).replace(char::is_whitespace, "_");
+    if self.labels {
+      for value in sample.labels.values() {
+        path.push('.');
+        path.push_str(&node(value));
+      }
+    }
+    let seconds = sample.timestamp.collected_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    Some(format!("{} {} {}\n", path, sample.value, seconds))
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let text = batch.samples.iter()
+      .filter_map(|sample| self.line(batch, sample))
+      .collect::<String>();
+    if text.is_empty() {
+      return Ok(());
+    }
+    let mut connection = self.connection.lock().await;
+    // A kept connection may have been closed by carbon meanwhile, which
+    // shows only when written to; a new one is tried once.
+    match self.write_through(&mut connection, &text).await {
+      Ok(()) => Ok(()),
+      Err(_error) => self.write_through(&mut connection, &text).await,
+    }
+  }
+
+  // Writes over the kept connection, connecting first if there is none, and
+  // drops it when the write fails.
+  async fn write_through(&self, connection: &mut Option<TcpStream>, text: &str) -> Result<(), Error> {
+    if connection.is_none() {
+      let connected = tokio::time::timeout(self.timeout, TcpStream::connect(self.address))
+        .await
+        .unwrap_or_else(|_elapsed| Err(std::io::ErrorKind::TimedOut.into()));
+      *connection = Some(connected.map_err(Error::Io)?);
+    }
+    let stream = connection.as_mut().expect("connected above");
+    let written = tokio::time::timeout(self.timeout, stream.write_all(text.as_bytes()))
+      .await
+      .unwrap_or_else(|_elapsed| Err(std::io::ErrorKind::TimedOut.into()));
+    if written.is_err() {
+      *connection = None;
+    }
+    written.map_err(Error::Io)
+  }
+}
+
+fn node(text: &str) -> String {
+  text.replace(|char: char| char == '.' || char.is_whitespace(), "_")
+}
+
+impl Sink for Graphite {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
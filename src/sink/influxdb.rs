@@ -0,0 +1,210 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex, Weak}, time::{Duration, UNIX_EPOCH}};
+
+use serde::Deserialize;
+
+use super::{template, Batch, BoxFuture, Error, Sink};
+use crate::{http_client, profile};
+
+// InfluxDB's HTTP write API in line protocol: the 2.x API with `bucket`,
+// `org` and `token`, or the 1.x one with `database` and, if needed,
+// `username` and `password`. Each sample is a point of `measurement` with
+// its value as the `value` field, tagged with the target and the sample
+// labels unless `labels = false`, and with `tags`, whose values may be
+// templates. Points are buffered and written `batch_size` at a time, and
+// whatever is buffered every `linger_ms`.
+pub struct Influxdb {
+  url: String,
+  authorization: Option<String>,
+  measurement: String,
+  tags: BTreeMap<String, String>,
+  labels: bool,
+  batch_size: usize,
+  timeout: Duration,
+  // Lines not yet written; the flusher holds it weakly, so it stops once
+  // the sink is dropped.
+  pending: Arc<Mutex<Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  // The server's base URL such as `http://influxdb:8086`.
+  url: String,
+  #[serde(default)]
+  bucket: Option<String>,
+  #[serde(default)]
+  org: Option<String>,
+  #[serde(default)]
+  token: Option<String>,
+  #[serde(default)]
+  database: Option<String>,
+  #[serde(default)]
+  username: Option<String>,
+  #[serde(default)]
+  password: Option<String>,
+  #[serde(default = "default_measurement")]
+  measurement: String,
+  #[serde(default)]
+  tags: BTreeMap<String, String>,
+  #[serde(default = "default_labels")]
+  labels: bool,
+  #[serde(default = "default_batch_size")]
+  batch_size: usize,
+  #[serde(default = "default_linger_ms")]
+  linger_ms: u64,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_measurement() -> String {
+  "{name}".to_string()
+}
+
+fn default_labels() -> bool {
+  true
+}
+
+fn default_batch_size() -> usize {
+  5000
+}
+
+fn default_linger_ms() -> u64 {
+  1000
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+impl Influxdb {
+
+  pub fn new(options: &toml::Table) -> Result<Influxdb, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    let base = options.url.trim_end_matches('/');
+    let (url, authorization) = match (&options.bucket, &options.database) {
+      (Some(bucket), None) => {
+        let org = options.org.as_deref()
+          .ok_or_else(|| Error::Config("an InfluxDB bucket needs an org".to_string()))?;
+        let url = format!("{}/api/v2/write?org={}&bucket={}&precision=ms", base, encode(org), encode(bucket));
+        (url, options.token.map(|token| format!("Token {}", token)))
+      },
+      (None, Some(database)) => {
+        let mut url = format!("{}/write?db={}&precision=ms", base, encode(database));
+        if let (Some(username), Some(password)) = (&options.username, &options.password) {
+          url.push_str(&format!("&u={}&p={}", encode(username), encode(password)));
+        }
+        (url, None)
+      },
+      _ => return Err(Error::Config("InfluxDB needs either a bucket or a database".to_string())),
+    };
+    let influxdb = Influxdb {
+      url,
+      authorization,
+      measurement: options.measurement,
+      tags: options.tags,
+      labels: options.labels,
+      batch_size: options.batch_size.max(1),
+      timeout: Duration::from_secs(options.timeout),
+      pending: Arc::new(Mutex::new(Vec::new())),
+    };
+    influxdb.flush_every(Duration::from_millis(options.linger_ms.max(1)));
+    Ok(influxdb)
+  }
+
+  // Writes what lingered in the buffer, for as long as the sink exists.
+  fn flush_every(&self, linger: Duration) {
+    let pending = Arc::downgrade(&self.pending);
+    let (url, authorization, timeout) = (self.url.clone(), self.authorization.clone(), self.timeout);
+    tokio::spawn(async move {
+      loop {
+        tokio::time::sleep(linger).await;
+        let Some(lines) = Weak::upgrade(&pending).map(|pending| std::mem::take(&mut *pending.lock().unwrap())) else {
+          break;
+        };
+        if lines.is_empty() {
+          continue;
+        }
+        if let Err(error) = post(&url, authorization.as_deref(), &lines, timeout).await {
+          eprintln!("Cannot write {} points to InfluxDB: {}", lines.len(), error);
+        }
+      }
+    });
+  }
+
+  fn line(&self, batch: &Batch<'_>, sample: &profile::Sample) -> Option<String> {
+    if !sample.value.is_finite() {
+      return None;
+    }
+    let mut tags = BTreeMap::new();
+    if self.labels {
+      tags.insert("target".to_string(), batch.target.name.clone());
+      tags.extend(sample.labels.clone());
+    }
+    for (tag, value) in &self.tags {
+      tags.insert(tag.clone(), template(value, batch, sample));
+    }
+    let mut line = escape(&template(&self.measurement, batch, sample), &[',', ' ']);
+    // Empty tag values are not allowed.
+    for (tag, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+      line.push_str(&format!(",{}={}", escape(tag, &[',', '=', ' ']), escape(value, &[',', '=', ' '])));
+    }
+    let millis = sample.timestamp.collected_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    line.push_str(&format!(" value={} {}", sample.value, millis));
+    Some(line)
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let full = {
+      let mut pending = self.pending.lock().unwrap();
+      pending.extend(batch.samples.iter().filter_map(|sample| self.line(batch, sample)));
+      match pending.len() >= self.batch_size {
+        true => std::mem::take(&mut *pending),
+        false => Vec::new(),
+      }
+    };
+    for lines in full.chunks(self.batch_size) {
+      post(&self.url, self.authorization.as_deref(), lines, self.timeout)
+        .await
+        .map_err(|error| Error::Other(format!("InfluxDB: {}", error)))?;
+    }
+    Ok(())
+  }
+}
+
+async fn post(url: &str, authorization: Option<&str>, lines: &[String], timeout: Duration) -> Result<Vec<u8>, http_client::Error> {
+  let headers = std::iter::once(("content-type", "text/plain; charset=utf-8"))
+    .chain(authorization.map(|authorization| ("authorization", authorization)))
+    .collect::<Vec<_>>();
+  http_client::post(url, &headers, lines.join("\n").into_bytes(), timeout).await
+}
+
+// Backslashes the characters with a meaning where the text goes; line
+// breaks cannot be escaped at all.
+fn escape(text: &str, special: &[char]) -> String {
+  let mut escaped = String::new();
+  for char in text.chars().map(|char| if char == '\n' { ' ' } else { char }) {
+    if special.contains(&char) {
+      escaped.push('\\');
+    }
+    escaped.push(char);
+  }
+  escaped
+}
+
+// Percent-encodes a query parameter value.
+fn encode(value: &str) -> String {
+  value.bytes()
+    .map(|byte| match byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+      true => (byte as char).to_string(),
+      false => format!("%{:02X}", byte),
+    })
+    .collect()
+}
+
+impl Sink for Influxdb {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
@@ -0,0 +1,602 @@
+use std::{collections::{BTreeMap, HashMap}, sync::atomic::{AtomicI32, AtomicUsize, Ordering}, time::{Duration, UNIX_EPOCH}};
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, sync::Mutex};
+
+use super::{template, Batch, BoxFuture, Error, Sink};
+use crate::profile;
+
+// Kafka's producer protocol, Produce v3 with v2 record batches and no
+// compression. Messages are JSON, one per sample, or one per poll with its
+// samples under `samples` given `messages = "batch"`; either carries the
+// target's name, address, tags and the source. The key is a template,
+// `{address}` unless set, a poll's message keyed as its first sample;
+// partitions are chosen from it as Java clients do, so that one target's
+// messages keep their order, and round robin for an empty key. Partition
+// leaders are looked up from the first of `brokers` that answers, again
+// after any failure.
+pub struct Kafka {
+  brokers: Vec<String>,
+  topic: String,
+  key: String,
+  messages: Messages,
+  acks: i16,
+  client_id: String,
+  timeout: Duration,
+  state: Mutex<Option<State>>,
+  correlation: AtomicI32,
+  next: AtomicUsize,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  // Bootstrap brokers as `host:port`.
+  brokers: Vec<String>,
+  topic: String,
+  #[serde(default = "default_key")]
+  key: String,
+  #[serde(default)]
+  messages: Messages,
+  // 0 for none, 1 for the leader's, -1 for every in-sync replica's.
+  #[serde(default = "default_acks")]
+  acks: i16,
+  #[serde(default = "default_client_id")]
+  client_id: String,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Messages {
+  #[default]
+  Sample,
+  Batch,
+}
+
+fn default_key() -> String {
+  "{address}".to_string()
+}
+
+fn default_acks() -> i16 {
+  1
+}
+
+fn default_client_id() -> String {
+  "snmp-collector".to_string()
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+// The topic's partition leaders with connections to the brokers.
+struct State {
+  brokers: HashMap<i32, String>,
+  leaders: Vec<i32>,
+  connections: HashMap<i32, TcpStream>,
+}
+
+struct Message {
+  key: Option<Vec<u8>>,
+  value: Vec<u8>,
+  timestamp: i64,
+}
+
+const PRODUCE: i16 = 0;
+const METADATA: i16 = 3;
+
+impl Kafka {
+
+  pub fn new(options: &toml::Table) -> Result<Kafka, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    if options.brokers.is_empty() {
+      return Err(Error::Config("Kafka needs at least one broker".to_string()));
+    }
+    if ![0, 1, -1].contains(&options.acks) {
+      return Err(Error::Config(format!("invalid Kafka acks {}", options.acks)));
+    }
+    Ok(Kafka {
+      brokers: options.brokers,
+      topic: options.topic,
+      key: options.key,
+      messages: options.messages,
+      acks: options.acks,
+      client_id: options.client_id,
+      timeout: Duration::from_secs(options.timeout),
+      state: Mutex::new(None),
+      correlation: AtomicI32::new(0),
+      next: AtomicUsize::new(0),
+    })
+  }
+
+  fn messages(&self, batch: &Batch<'_>) -> Vec<Message> {
+    let envelope = |sample: &profile::Sample| {
+      let mut value = json!(sample);
+      value["target"] = json!(batch.target.name);
+      value["address"] = json!(batch.target.address.to_string());
+      value["tags"] = json!(batch.target.tags);
+      value["source"] = json!(batch.source);
+      value
+    };
+    let message = |sample: &profile::Sample, value: serde_json::Value| Message {
+      key: Some(template(&self.key, batch, sample).into_bytes()).filter(|key| !key.is_empty()),
+      value: value.to_string().into_bytes(),
+      timestamp: millis(sample),
+    };
+    match self.messages {
+      Messages::Sample => batch.samples.iter().map(|sample| message(sample, envelope(sample))).collect(),
+      Messages::Batch => batch.samples.first()
+        .map(|first| {
+          let value = json!({
+            "target": batch.target.name,
+            "address": batch.target.address.to_string(),
+            "tags": batch.target.tags,
+            "source": batch.source,
+            "samples": batch.samples,
+          });
+          message(first, value)
+        })
+        .into_iter()
+        .collect(),
+    }
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let messages = self.messages(batch);
+    if messages.is_empty() {
+      return Ok(());
+    }
+    let mut state = self.state.lock().await;
+    let produced = tokio::time::timeout(self.timeout, self.produce(&mut state, messages))
+      .await
+      .unwrap_or_else(|_elapsed| Err(Error::Io(std::io::ErrorKind::TimedOut.into())));
+    // Leaders move and brokers go away, and a connection given up on may be
+    // halfway through a request; all is found out anew.
+    if produced.is_err() {
+      *state = None;
+    }
+    produced
+  }
+
+  async fn produce(&self, state: &mut Option<State>, messages: Vec<Message>) -> Result<(), Error> {
+    if state.is_none() {
+      *state = Some(self.metadata().await?);
+    }
+    let state = state.as_mut().expect("looked up above");
+    let mut leaders: BTreeMap<i32, BTreeMap<i32, Vec<Message>>> = BTreeMap::new();
+    for message in messages {
+      let partition = match &message.key {
+        Some(key) => (murmur2(key) & 0x7fffffff) as usize % state.leaders.len(),
+        None => self.next.fetch_add(1, Ordering::Relaxed) % state.leaders.len(),
+      };
+      leaders.entry(state.leaders[partition])
+        .or_default()
+        .entry(partition as i32)
+        .or_default()
+        .push(message);
+    }
+    for (leader, partitions) in leaders {
+      let mut body = Writer::default();
+      // No transactional id.
+      body.i16(-1);
+      body.i16(self.acks);
+      body.i32(self.timeout.as_millis() as i32);
+      body.i32(1);
+      body.string(&self.topic);
+      body.i32(partitions.len() as i32);
+      for (partition, messages) in &partitions {
+        body.i32(*partition);
+        let records = record_batch(messages);
+        body.i32(records.len() as i32);
+        body.0.extend(records);
+      }
+      let stream = state.connection(leader).await?;
+      let response = self.exchange(stream, PRODUCE, 3, body.0, self.acks != 0).await?;
+      if let Some(response) = response {
+        produced(&response).ok_or_else(|| Error::Other("invalid Kafka produce response".to_string()))??;
+      }
+    }
+    Ok(())
+  }
+
+  async fn metadata(&self) -> Result<State, Error> {
+    let mut body = Writer::default();
+    body.i32(1);
+    body.string(&self.topic);
+    // Whether the broker may create the topic, if it is set up to.
+    body.i8(1);
+    let mut failure = Error::Other("no Kafka broker".to_string());
+    for broker in &self.brokers {
+      let answer = async {
+        let mut stream = TcpStream::connect(broker).await.map_err(Error::Io)?;
+        let response = self.exchange(&mut stream, METADATA, 4, body.0.clone(), true).await?.unwrap_or_default();
+        metadata(&response, &self.topic).ok_or_else(|| Error::Other("invalid Kafka metadata response".to_string()))?
+      };
+      match answer.await {
+        Ok(state) => return Ok(state),
+        Err(error) => failure = error,
+      }
+    }
+    Err(failure)
+  }
+
+  // Sends one request and reads its response if one is expected.
+  async fn exchange(
+    &self,
+    stream: &mut TcpStream,
+    api_key: i16,
+    api_version: i16,
+    body: Vec<u8>,
+    response: bool,
+  ) -> Result<Option<Vec<u8>>, Error> {
+    let correlation = self.correlation.fetch_add(1, Ordering::Relaxed);
+    let mut request = Writer::default();
+    request.i16(api_key);
+    request.i16(api_version);
+    request.i32(correlation);
+    request.string(&self.client_id);
+    request.0.extend(body);
+    let mut framed = (request.0.len() as i32).to_be_bytes().to_vec();
+    framed.extend(request.0);
+    stream.write_all(&framed).await.map_err(Error::Io)?;
+    if !response {
+      return Ok(None);
+    }
+    let length = stream.read_i32().await.map_err(Error::Io)?;
+    if !(4..=1 << 24).contains(&length) {
+      return Err(Error::Other(format!("invalid Kafka response length {}", length)));
+    }
+    let mut response = vec![0; length as usize];
+    stream.read_exact(&mut response).await.map_err(Error::Io)?;
+    if response[..4] != correlation.to_be_bytes() {
+      return Err(Error::Other("Kafka answered another request".to_string()));
+    }
+    Ok(Some(response.split_off(4)))
+  }
+}
+
+impl State {
+
+  async fn connection(&mut self, node: i32) -> Result<&mut TcpStream, Error> {
+    if !self.connections.contains_key(&node) {
+      let broker = self.brokers.get(&node)
+        .ok_or_else(|| Error::Other(format!("unknown Kafka broker {}", node)))?;
+      let stream = TcpStream::connect(broker).await.map_err(Error::Io)?;
+      self.connections.insert(node, stream);
+    }
+    Ok(self.connections.get_mut(&node).expect("connected above"))
+  }
+}
+
+fn millis(sample: &profile::Sample) -> i64 {
+  sample.timestamp.collected_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+// A v2 record batch of the messages, offsets counted from 0 as producers
+// leave them for the broker to assign.
+fn record_batch(messages: &[Message]) -> Vec<u8> {
+  let first = messages.iter().map(|message| message.timestamp).min().unwrap_or_default();
+  let last = messages.iter().map(|message| message.timestamp).max().unwrap_or_default();
+  // What the checksum covers: attributes onwards.
+  let mut checked = Writer::default();
+  checked.i16(0);
+  checked.i32(messages.len() as i32 - 1);
+  checked.i64(first);
+  checked.i64(last);
+  // No producer id, epoch or sequence, as without idempotence.
+  checked.i64(-1);
+  checked.i16(-1);
+  checked.i32(-1);
+  checked.i32(messages.len() as i32);
+  for (offset, message) in messages.iter().enumerate() {
+    let mut record = Writer::default();
+    record.i8(0);
+    record.varint(message.timestamp - first);
+    record.varint(offset as i64);
+    match &message.key {
+      Some(key) => {
+        record.varint(key.len() as i64);
+        record.0.extend(key);
+      },
+      None => record.varint(-1),
+    }
+    record.varint(message.value.len() as i64);
+    record.0.extend(&message.value);
+    // No headers.
+    record.varint(0);
+    checked.varint(record.0.len() as i64);
+    checked.0.extend(record.0);
+  }
+  let mut batch = Writer::default();
+  batch.i64(0);
+  // The length counts from the partition leader epoch on.
+  batch.i32(4 + 1 + 4 + checked.0.len() as i32);
+  batch.i32(-1);
+  batch.i8(2);
+  batch.i32(crc32c(&checked.0) as i32);
+  batch.0.extend(checked.0);
+  batch.0
+}
+
+// The topic's partition leaders and broker addresses from a v4 metadata
+// response; None if it cannot be read, an error if the topic is not usable.
+fn metadata(response: &[u8], topic: &str) -> Option<Result<State, Error>> {
+  let mut reader = Reader(response);
+  let _throttle_time = reader.i32()?;
+  let mut brokers = HashMap::new();
+  for _ in 0..reader.i32()? {
+    let node = reader.i32()?;
+    let host = reader.string()?.unwrap_or_default();
+    let port = reader.i32()?;
+    let _rack = reader.string()?;
+    brokers.insert(node, format!("{}:{}", host, port));
+  }
+  let _cluster_id = reader.string()?;
+  let _controller = reader.i32()?;
+  for _ in 0..reader.i32()? {
+    let error = reader.i16()?;
+    let name = reader.string()?.unwrap_or_default();
+    let _internal = reader.i8()?;
+    let mut leaders = Vec::new();
+    for _ in 0..reader.i32()? {
+      let _error = reader.i16()?;
+      let partition = reader.i32()?;
+      let leader = reader.i32()?;
+      for _replicas in 0..2 {
+        for _ in 0..reader.i32()? {
+          reader.i32()?;
+        }
+      }
+      let partition = usize::try_from(partition).ok()?;
+      if leaders.len() <= partition {
+        leaders.resize(partition + 1, -1);
+      }
+      leaders[partition] = leader;
+    }
+    if name != topic {
+      continue;
+    }
+    return Some(match (error, leaders.contains(&-1) || leaders.is_empty()) {
+      (0, false) => Ok(State { brokers, leaders, connections: HashMap::new() }),
+      (0, true) => Err(Error::Other(format!("a partition of Kafka topic {} has no leader", topic))),
+      (error, _) => Err(Error::Other(format!("Kafka topic {}: error {}", topic, error))),
+    });
+  }
+  Some(Err(Error::Other(format!("no Kafka topic {}", topic))))
+}
+
+// The first partition error of a v3 produce response.
+fn produced(response: &[u8]) -> Option<Result<(), Error>> {
+  let mut reader = Reader(response);
+  for _ in 0..reader.i32()? {
+    let topic = reader.string()?.unwrap_or_default();
+    for _ in 0..reader.i32()? {
+      let partition = reader.i32()?;
+      let error = reader.i16()?;
+      let _base_offset = reader.i64()?;
+      let _log_append_time = reader.i64()?;
+      if error != 0 {
+        return Some(Err(Error::Other(format!("Kafka refused the messages for {}/{}: error {}", topic, partition, error))));
+      }
+    }
+  }
+  Some(Ok(()))
+}
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+
+  fn i8(&mut self, value: i8) {
+    self.0.extend(value.to_be_bytes());
+  }
+
+  fn i16(&mut self, value: i16) {
+    self.0.extend(value.to_be_bytes());
+  }
+
+  fn i32(&mut self, value: i32) {
+    self.0.extend(value.to_be_bytes());
+  }
+
+  fn i64(&mut self, value: i64) {
+    self.0.extend(value.to_be_bytes());
+  }
+
+  fn string(&mut self, value: &str) {
+    self.i16(value.len() as i16);
+    self.0.extend(value.as_bytes());
+  }
+
+  // Zigzag, seven bits a byte.
+  fn varint(&mut self, value: i64) {
+    let mut value = ((value << 1) ^ (value >> 63)) as u64;
+    while value >= 0x80 {
+      self.0.push(value as u8 | 0x80);
+      value >>= 7;
+    }
+    self.0.push(value as u8);
+  }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+
+  fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+    let (taken, rest) = self.0.split_at_checked(length)?;
+    self.0 = rest;
+    Some(taken)
+  }
+
+  fn i8(&mut self) -> Option<i8> {
+    Some(i8::from_be_bytes(self.take(1)?.try_into().ok()?))
+  }
+
+  fn i16(&mut self) -> Option<i16> {
+    Some(i16::from_be_bytes(self.take(2)?.try_into().ok()?))
+  }
+
+  fn i32(&mut self) -> Option<i32> {
+    Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+  }
+
+  fn i64(&mut self) -> Option<i64> {
+    Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+  }
+
+  // A nullable string.
+  fn string(&mut self) -> Option<Option<String>> {
+    let length = self.i16()?;
+    match usize::try_from(length) {
+      Ok(length) => Some(Some(String::from_utf8_lossy(self.take(length)?).into_owned())),
+      Err(_) => Some(None),
+    }
+  }
+}
+
+// The murmur2 hash of Kafka's default partitioner.
+fn murmur2(data: &[u8]) -> u32 {
+  const M: u32 = 0x5bd1e995;
+  let mut hash = 0x9747b28c ^ data.len() as u32;
+  let mut chunks = data.chunks_exact(4);
+  for chunk in &mut chunks {
+    let mut k = u32::from_le_bytes(chunk.try_into().expect("chunks are four bytes"));
+    k = k.wrapping_mul(M);
+    k ^= k >> 24;
+    k = k.wrapping_mul(M);
+    hash = hash.wrapping_mul(M) ^ k;
+  }
+  let rest = chunks.remainder();
+  if !rest.is_empty() {
+    for (at, byte) in rest.iter().enumerate() {
+      hash ^= (*byte as u32) << (8 * at);
+    }
+    hash = hash.wrapping_mul(M);
+  }
+  hash ^= hash >> 13;
+  hash = hash.wrapping_mul(M);
+  hash ^ (hash >> 15)
+}
+
+// CRC-32C, which record batches are checked with.
+fn crc32c(data: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in data {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = (crc >> 1) ^ (0x82f63b78 & (crc & 1).wrapping_neg());
+    }
+  }
+  !crc
+}
+
+impl Sink for Kafka {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // From Kafka's UtilsTest, as Java's signed ints.
+  #[test]
+  fn hashes_like_kafka() {
+    assert_eq!(murmur2(b"21") as i32, -973932308);
+    assert_eq!(murmur2(b"foobar") as i32, -790332482);
+    assert_eq!(murmur2(b"a-little-bit-long-string") as i32, -985981536);
+    assert_eq!(murmur2(b"a-little-bit-longer-string") as i32, -1486304829);
+    assert_eq!(murmur2(b"lkjh234lh9fiuh90y23oiuhsafujhadof229phr9h19h89h8") as i32, -58897971);
+    assert_eq!(murmur2(b"abc") as i32, 479470107);
+  }
+
+  // The check value of CRC-32C, and RFC 3720 B.4.
+  #[test]
+  fn checksums_crc32c() {
+    assert_eq!(crc32c(b"123456789"), 0xe3069283);
+    assert_eq!(crc32c(&[0; 32]), 0x8a9136aa);
+    assert_eq!(crc32c(&[0xff; 32]), 0x62a8ab43);
+  }
+
+  #[test]
+  fn encodes_record_batches() {
+    let batch = record_batch(&[Message { key: Some(b"k".to_vec()), value: b"v".to_vec(), timestamp: 1000 }]);
+    let mut expected = Writer::default();
+    expected.i64(0);
+    expected.i32(58);
+    expected.i32(-1);
+    expected.i8(2);
+    expected.i32(crc32c(&batch[21..]) as i32);
+    expected.i16(0);
+    expected.i32(0);
+    expected.i64(1000);
+    expected.i64(1000);
+    expected.i64(-1);
+    expected.i16(-1);
+    expected.i32(-1);
+    expected.i32(1);
+    expected.0.extend([0x10, 0, 0, 0, 0x02, b'k', 0x02, b'v', 0]);
+    assert_eq!(batch, expected.0);
+  }
+
+  #[test]
+  fn encodes_varints() {
+    let mut writer = Writer::default();
+    for value in [0, -1, 1, 63, -64, 64, 300] {
+      writer.varint(value);
+    }
+    assert_eq!(writer.0, [0x00, 0x01, 0x02, 0x7e, 0x7f, 0x80, 0x01, 0xd8, 0x04]);
+  }
+
+  // Two brokers and a topic of two partitions, listed out of order.
+  fn metadata_response() -> Vec<u8> {
+    let mut response = Writer::default();
+    response.i32(0);
+    response.i32(2);
+    for (node, host) in [(1, "kafka-1"), (2, "kafka-2")] {
+      response.i32(node);
+      response.string(host);
+      response.i32(9092);
+      response.i16(-1);
+    }
+    response.string("cluster");
+    response.i32(1);
+    response.i32(1);
+    response.i16(0);
+    response.string("metrics");
+    response.i8(0);
+    response.i32(2);
+    for (partition, leader) in [(1, 1), (0, 2)] {
+      response.i16(0);
+      response.i32(partition);
+      response.i32(leader);
+      response.i32(1);
+      response.i32(leader);
+      response.i32(0);
+    }
+    response.0
+  }
+
+  #[test]
+  fn decodes_metadata() {
+    let Some(Ok(state)) = metadata(&metadata_response(), "metrics") else { panic!("metadata not decoded") };
+    assert_eq!(state.leaders, [2, 1]);
+    assert_eq!(state.brokers[&1], "kafka-1:9092");
+    assert_eq!(state.brokers[&2], "kafka-2:9092");
+  }
+
+  #[test]
+  fn reports_missing_topics() {
+    assert!(matches!(metadata(&metadata_response(), "traps"), Some(Err(_))));
+    let response = metadata_response();
+    assert!(metadata(&response[..response.len() - 1], "metrics").is_none());
+  }
+}
@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, sync::{Arc, Weak}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{Batch, BoxFuture, Error, Sink};
+use crate::{http_client, profile, stats};
+
+// OTLP/HTTP with the JSON encoding, to an OpenTelemetry collector's
+// `/v1/metrics`. Each target is a resource with `host.name`, `host.ip`,
+// `snmp.source` and its tags as attributes, along with `resource`; its
+// counters are cumulative monotonic sums, everything else gauges. With
+// `self_metrics` set, the collector's own counters are exported as a
+// resource of their own every that many seconds. gRPC is not spoken; a
+// collector's OTLP/HTTP receiver takes the same data.
+pub struct Otlp {
+  url: String,
+  headers: Vec<(String, String)>,
+  resource: BTreeMap<String, String>,
+  timeout: Duration,
+  // The flusher of self metrics holds it weakly, so it stops once the sink
+  // is dropped.
+  alive: Arc<()>,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  // The receiver's base URL such as `http://otel-collector:4318`.
+  #[serde(default = "default_endpoint")]
+  endpoint: String,
+  #[serde(default)]
+  headers: BTreeMap<String, String>,
+  #[serde(default = "default_resource")]
+  resource: BTreeMap<String, String>,
+  #[serde(default)]
+  self_metrics: u64,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_endpoint() -> String {
+  "http://127.0.0.1:4318".to_string()
+}
+
+fn default_resource() -> BTreeMap<String, String> {
+  BTreeMap::from([("service.name".to_string(), "snmp-collector".to_string())])
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+impl Otlp {
+
+  pub fn new(options: &toml::Table) -> Result<Otlp, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    let otlp = Otlp {
+      url: format!("{}/v1/metrics", options.endpoint.trim_end_matches('/')),
+      headers: options.headers.into_iter().collect(),
+      resource: options.resource,
+      timeout: Duration::from_secs(options.timeout),
+      alive: Arc::new(()),
+    };
+    if options.self_metrics > 0 {
+      otlp.export_stats_every(Duration::from_secs(options.self_metrics));
+    }
+    Ok(otlp)
+  }
+
+  fn export_stats_every(&self, period: Duration) {
+    let alive = Arc::downgrade(&self.alive);
+    let (url, headers, resource, timeout) = (self.url.clone(), self.headers.clone(), self.resource.clone(), self.timeout);
+    tokio::spawn(async move {
+      loop {
+        tokio::time::sleep(period).await;
+        if Weak::upgrade(&alive).is_none() {
+          break;
+        }
+        let body = json!({ "resourceMetrics": [resource_metrics(attributes(&resource), self_metrics())] });
+        if let Err(error) = post(&url, &headers, &body, timeout).await {
+          eprintln!("Cannot export self metrics over OTLP: {}", error);
+        }
+      }
+    });
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    // A metric carries the points of all its samples.
+    let mut metrics: BTreeMap<&str, (profile::MetricKind, Vec<Value>)> = BTreeMap::new();
+    for sample in batch.samples.iter().filter(|sample| sample.value.is_finite()) {
+      metrics.entry(&sample.name)
+        .or_insert_with(|| (sample.kind, Vec::new()))
+        .1
+        .push(point(sample));
+    }
+    if metrics.is_empty() {
+      return Ok(());
+    }
+    let metrics = metrics.into_iter()
+      .map(|(name, (kind, points))| metric(name, kind, points))
+      .collect();
+    let mut resource = self.resource.clone();
+    resource.insert("host.name".to_string(), batch.target.name.clone());
+    resource.insert("host.ip".to_string(), batch.target.address.to_string());
+    resource.insert("snmp.source".to_string(), batch.source.to_string());
+    resource.extend(batch.target.tags.clone());
+    let body = json!({ "resourceMetrics": [resource_metrics(attributes(&resource), metrics)] });
+    post(&self.url, &self.headers, &body, self.timeout)
+      .await
+      .map_err(|error| Error::Other(format!("OTLP: {}", error)))?;
+    Ok(())
+  }
+}
+
+fn resource_metrics(attributes: Value, metrics: Vec<Value>) -> Value {
+  json!({
+    "resource": { "attributes": attributes },
+    "scopeMetrics": [{
+      "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+      "metrics": metrics,
+    }],
+  })
+}
+
+fn metric(name: &str, kind: profile::MetricKind, points: Vec<Value>) -> Value {
+  match kind {
+    // Cumulative.
+    profile::MetricKind::Counter => json!({
+      "name": name,
+      "sum": { "aggregationTemporality": 2, "isMonotonic": true, "dataPoints": points },
+    }),
+    profile::MetricKind::Gauge => json!({ "name": name, "gauge": { "dataPoints": points } }),
+  }
+}
+
+fn point(sample: &profile::Sample) -> Value {
+  let mut point = json!({
+    "attributes": attributes(&sample.labels),
+    "timeUnixNano": nanos(sample.timestamp.collected_at),
+    "asDouble": sample.value,
+  });
+  // Counters of an agent count from when it restarted.
+  if let Some(ticks) = sample.timestamp.sys_up_time {
+    let started = sample.timestamp.collected_at - Duration::from_millis(ticks as u64 * 10);
+    point["startTimeUnixNano"] = json!(nanos(started));
+  }
+  point
+}
+
+fn self_metrics() -> Vec<Value> {
+  let now = nanos(SystemTime::now());
+  let started = nanos(SystemTime::now() - Duration::from_millis(stats::uptime() as u64 * 10));
+  [
+    ("snmp_collector.collections", &stats::STATS.collections),
+    ("snmp_collector.collection_failures", &stats::STATS.collection_failures),
+    ("snmp_collector.sink_writes", &stats::STATS.sink_writes),
+    ("snmp_collector.sink_failures", &stats::STATS.sink_failures),
+  ]
+    .into_iter()
+    .map(|(name, counter)| {
+      let point = json!({ "startTimeUnixNano": started, "timeUnixNano": now, "asInt": stats::read(counter).to_string() });
+      metric(name, profile::MetricKind::Counter, vec![point])
+    })
+    .collect()
+}
+
+fn attributes(attributes: &BTreeMap<String, String>) -> Value {
+  attributes.iter()
+    .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+    .collect()
+}
+
+// 64-bit integers go as strings in OTLP's JSON.
+fn nanos(time: SystemTime) -> String {
+  time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+async fn post(url: &str, headers: &[(String, String)], body: &Value, timeout: Duration) -> Result<Vec<u8>, http_client::Error> {
+  let headers = std::iter::once(("content-type", "application/json"))
+    .chain(headers.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+    .collect::<Vec<_>>();
+  http_client::post(url, &headers, body.to_string().into_bytes(), timeout).await
+}
+
+impl Sink for Otlp {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
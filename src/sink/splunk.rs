@@ -0,0 +1,125 @@
+use std::{collections::BTreeMap, time::{Duration, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{Batch, BoxFuture, Error, Sink};
+use crate::{http_client, profile};
+
+// Splunk HTTP Event Collector. Samples become events of `sourcetype`, or
+// metric events for metrics indexes with `metrics = true`, posted
+// `batch_size` at a time.
+pub struct Splunk {
+  url: String,
+  authorization: String,
+  index: Option<String>,
+  sourcetype: String,
+  metrics: bool,
+  batch_size: usize,
+  timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  // The HEC base URL such as `https://splunk:8088`.
+  url: String,
+  token: String,
+  #[serde(default)]
+  index: Option<String>,
+  #[serde(default = "default_sourcetype")]
+  sourcetype: String,
+  #[serde(default)]
+  metrics: bool,
+  #[serde(default = "default_batch_size")]
+  batch_size: usize,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_sourcetype() -> String {
+  "snmp".to_string()
+}
+
+fn default_batch_size() -> usize {
+  100
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+  time: f64,
+  host: &'a str,
+  source: &'a str,
+  sourcetype: &'a str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  index: Option<&'a str>,
+  event: Value,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  fields: Option<BTreeMap<String, Value>>,
+}
+
+impl Splunk {
+
+  pub fn new(options: &toml::Table) -> Result<Splunk, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    Ok(Splunk {
+      url: format!("{}/services/collector/event", options.url.trim_end_matches('/')),
+      authorization: format!("Splunk {}", options.token),
+      index: options.index,
+      sourcetype: options.sourcetype,
+      metrics: options.metrics,
+      batch_size: options.batch_size.max(1),
+      timeout: Duration::from_secs(options.timeout),
+    })
+  }
+
+  fn event<'a>(&'a self, batch: &'a Batch<'a>, sample: &profile::Sample) -> Event<'a> {
+    let time = sample.timestamp.collected_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+    let (event, fields) = match self.metrics {
+      true => {
+        let mut fields = sample.labels.iter()
+          .map(|(label, value)| (label.clone(), json!(value)))
+          .collect::<BTreeMap<_, _>>();
+        fields.insert(format!("metric_name:{}", sample.name), json!(sample.value));
+        (json!("metric"), Some(fields))
+      },
+      false => (json!(sample), None),
+    };
+    Event {
+      time,
+      host: &batch.target.name,
+      source: batch.source,
+      sourcetype: &self.sourcetype,
+      index: self.index.as_deref(),
+      event,
+      fields,
+    }
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    for samples in batch.samples.chunks(self.batch_size) {
+      // HEC takes concatenated events rather than an array.
+      let mut body = Vec::new();
+      for sample in samples {
+        serde_json::to_writer(&mut body, &self.event(batch, sample))
+          .map_err(|error| Error::Other(error.to_string()))?;
+      }
+      http_client::post(&self.url, &[("authorization", &self.authorization)], body, self.timeout)
+        .await
+        .map_err(|error| Error::Other(format!("Splunk HEC: {}", error)))?;
+    }
+    Ok(())
+  }
+}
+
+impl Sink for Splunk {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
@@ -0,0 +1,129 @@
+use std::{collections::{BTreeMap, HashMap}, net::SocketAddr, sync::Mutex};
+
+use serde::Deserialize;
+use tokio::net::UdpSocket;
+
+use super::{template, Batch, BoxFuture, Error, Sink};
+use crate::{profile, rate, snmp};
+
+// Keeps datagrams below the usual path MTU.
+const MAX_DATAGRAM: usize = 1432;
+
+// StatsD over UDP. Gauges are sent as they are; counters are cumulative on
+// the device and sent as the increase since the previous collection, so the
+// first collection of a counter only sets its baseline. DogStatsD tags carry
+// the target and the sample labels unless `tags = false`.
+pub struct Statsd {
+  address: SocketAddr,
+  metric: String,
+  tags: bool,
+  previous: Mutex<HashMap<Series, (f64, snmp::Timestamp)>>,
+}
+
+// A counter by target, sample name and labels.
+type Series = (String, String, BTreeMap<String, String>);
+
+#[derive(Deserialize)]
+struct Options {
+  #[serde(default = "default_address")]
+  address: SocketAddr,
+  #[serde(default = "default_metric")]
+  metric: String,
+  #[serde(default = "default_tags")]
+  tags: bool,
+}
+
+fn default_address() -> SocketAddr {
+  SocketAddr::from(([127, 0, 0, 1], 8125))
+}
+
+fn default_metric() -> String {
+  "{name}".to_string()
+}
+
+fn default_tags() -> bool {
+  true
+}
+
+impl Statsd {
+
+  pub fn new(options: &toml::Table) -> Result<Statsd, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    Ok(Statsd {
+      address: options.address,
+      metric: options.metric,
+      tags: options.tags,
+      previous: Mutex::new(HashMap::new()),
+    })
+  }
+
+  fn line(&self, batch: &Batch<'_>, sample: &profile::Sample) -> Option<String> {
+    let (value, kind) = match sample.kind {
+      profile::MetricKind::Gauge => (sample.value, "g"),
+      profile::MetricKind::Counter => {
+        let key = (batch.target.name.clone(), sample.name.clone(), sample.labels.clone());
+        let (previous, at) = self.previous.lock().unwrap().insert(key, (sample.value, sample.timestamp))?;
+        // A counter that went down, or whose agent restarted, was reset; it
+        // counts again from here.
+        if rate::rebooted(&at, &sample.timestamp) {
+          return None;
+        }
+        (sample.value - previous, "c")
+      },
+    };
+    if value < 0.0 {
+      return None;
+    }
+    let name = sanitize(&template(&self.metric, batch, sample));
+    let mut line = format!("{}:{}|{}", name, value, kind);
+    if self.tags {
+      let tags = std::iter::once(("target", batch.target.name.as_str()))
+        .chain(sample.labels.iter().map(|(label, value)| (label.as_str(), value.as_str())))
+        .map(|(tag, value)| format!("{}:{}", sanitize(tag), sanitize(value)))
+        .collect::<Vec<_>>();
+      line.push_str("|#");
+      line.push_str(&tags.join(","));
+    }
+    Some(line)
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let lines = batch.samples.iter()
+      .filter_map(|sample| self.line(batch, sample))
+      .collect::<Vec<_>>();
+    let socket = UdpSocket::bind(match self.address {
+      SocketAddr::V4(_) => "0.0.0.0:0",
+      SocketAddr::V6(_) => "[::]:0",
+    }).await.map_err(Error::Io)?;
+    let mut datagram = String::new();
+    for line in lines {
+      if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+        socket.send_to(datagram.as_bytes(), self.address).await.map_err(Error::Io)?;
+        datagram.clear();
+      }
+      if !datagram.is_empty() {
+        datagram.push('\n');
+      }
+      datagram.push_str(&line);
+    }
+    if !datagram.is_empty() {
+      socket.send_to(datagram.as_bytes(), self.address).await.map_err(Error::Io)?;
+    }
+    Ok(())
+  }
+}
+
+// Characters with a meaning in the line protocol cannot appear in names,
+// tags or tag values.
+fn sanitize(text: &str) -> String {
+  text.replace([':', '|', '@', '#', ',', '\n'], "_")
+}
+
+impl Sink for Statsd {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(self.send(batch))
+  }
+}
@@ -0,0 +1,124 @@
+use std::{net::SocketAddr, time::{Duration, UNIX_EPOCH}};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+use super::{template, Batch, BoxFuture, Error, Sink};
+
+// Zabbix sender (trapper) protocol: the samples become values of trapper
+// items, whose host and key are templates over the sample and its target.
+pub struct Zabbix {
+  server: SocketAddr,
+  host: String,
+  key: String,
+  timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct Options {
+  server: SocketAddr,
+  #[serde(default = "default_host")]
+  host: String,
+  #[serde(default = "default_key")]
+  key: String,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_host() -> String {
+  "{target}".to_string()
+}
+
+fn default_key() -> String {
+  "{name}".to_string()
+}
+
+fn default_timeout() -> u64 {
+  10
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+  request: &'a str,
+  data: Vec<Item>,
+}
+
+#[derive(Serialize)]
+struct Item {
+  host: String,
+  key: String,
+  value: String,
+  clock: u64,
+  ns: u32,
+}
+
+#[derive(Deserialize)]
+struct Response {
+  response: String,
+  #[serde(default)]
+  info: String,
+}
+
+const HEADER: &[u8] = b"ZBXD\x01";
+
+impl Zabbix {
+
+  pub fn new(options: &toml::Table) -> Result<Zabbix, Error> {
+    let options = options.clone()
+      .try_into::<Options>()
+      .map_err(|error| Error::Config(error.to_string()))?;
+    Ok(Zabbix {
+      server: options.server,
+      host: options.host,
+      key: options.key,
+      timeout: Duration::from_secs(options.timeout),
+    })
+  }
+
+  async fn send(&self, batch: &Batch<'_>) -> Result<(), Error> {
+    let data = batch.samples.iter()
+      .map(|sample| {
+        let collected_at = sample.timestamp.collected_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Item {
+          host: template(&self.host, batch, sample),
+          key: template(&self.key, batch, sample),
+          value: sample.value.to_string(),
+          clock: collected_at.as_secs(),
+          ns: collected_at.subsec_nanos(),
+        }
+      })
+      .collect();
+    let body = serde_json::to_vec(&Request { request: "sender data", data })
+      .map_err(|error| Error::Other(error.to_string()))?;
+    let mut message = HEADER.to_vec();
+    message.extend((body.len() as u64).to_le_bytes());
+    message.extend(body);
+    let mut stream = TcpStream::connect(self.server).await.map_err(Error::Io)?;
+    stream.write_all(&message).await.map_err(Error::Io)?;
+    let mut header = [0; 13];
+    stream.read_exact(&mut header).await.map_err(Error::Io)?;
+    if !header.starts_with(HEADER) {
+      return Err(Error::Other("not a Zabbix response".to_string()));
+    }
+    let length = u64::from_le_bytes(header[5..].try_into().expect("the length is eight bytes"));
+    let mut body = vec![0; length.min(1 << 20) as usize];
+    stream.read_exact(&mut body).await.map_err(Error::Io)?;
+    let response = serde_json::from_slice::<Response>(&body)
+      .map_err(|error| Error::Other(format!("invalid Zabbix response: {}", error)))?;
+    match response.response.as_str() {
+      "success" => Ok(()),
+      _ => Err(Error::Other(format!("Zabbix refused the values: {}", response.info))),
+    }
+  }
+}
+
+impl Sink for Zabbix {
+
+  fn write<'a>(&'a self, batch: &'a Batch<'a>) -> BoxFuture<'a> {
+    Box::pin(async move {
+      tokio::time::timeout(self.timeout, self.send(batch))
+        .await
+        .map_err(|_elapsed| Error::Other("Zabbix server did not answer".to_string()))?
+    })
+  }
+}
@@ -1,19 +1,70 @@
 use rasn_snmp as model;
-use std::{net::{SocketAddr, Ipv4Addr}, str::FromStr, fmt::Display};
-use tokio::net::UdpSocket;
+use std::{collections::HashMap, future::Future, hash::{Hash, Hasher}, net::{SocketAddr, Ipv4Addr, Ipv6Addr}, str::FromStr, fmt::Display, sync::{Arc, Mutex, OnceLock, RwLock}, time::{Duration, SystemTime}};
+use num_traits::ToPrimitive;
+use futures_util::{Stream, StreamExt};
+use rasn_smi::v1::ToOpaque;
+use tokio::{sync::{OwnedSemaphorePermit, Semaphore}, time::Instant};
 
 pub use rasn::types::OctetString;
+pub use client::{Builder, SnmpClient, Version, DEFAULT_COMMUNITY};
+pub use notification::{decode_notification, Notification};
+pub use rate::limit as limit_rate;
+pub use tls::{Certificates, TlsTransport};
+pub use usm::{AuthProtocol, PrivacyProtocol};
+use usm::next_id;
+
+use crate::{stats, trace};
+
+mod client;
+mod dispatch;
+mod notification;
+mod rate;
+mod tls;
+mod usm;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+const SYS_UP_TIME: [u32; 9] = [1, 3, 6, 1, 2, 1, 1, 3, 0];
+// What GetBulk requests ask for unless told otherwise.
+pub const MAX_REPETITIONS: u32 = 20;
+
+tokio::task_local! {
+  static DEADLINE: Instant;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ObjectIdentifier(rasn::types::ObjectIdentifier);
 
 impl ObjectIdentifier {
 
-  fn starts_with(&self, prefix: &ObjectIdentifier) -> bool {
+  pub fn starts_with(&self, prefix: &ObjectIdentifier) -> bool {
     self.0.starts_with(prefix.0.as_ref())
   }
+
+  pub fn arcs(&self) -> &[u32] {
+    &self.0
+  }
+
+  pub fn strip_prefix(&self, prefix: &ObjectIdentifier) -> Option<&[u32]> {
+    self.0.strip_prefix(prefix.0.as_ref())
+  }
+
+  pub fn child(&self, arcs: &[u32]) -> ObjectIdentifier {
+    let arcs = self.0.iter().chain(arcs).copied().collect::<Vec<_>>();
+    ObjectIdentifier(rasn::types::ObjectIdentifier::new_unchecked(arcs.into()))
+  }
+
+  // The OID by the names of the installed MIBs, as in `IF-MIB::ifInOctets.3`.
+  pub fn mib_name(&self) -> Option<String> {
+    crate::mib::name(&self.0)
+  }
+}
+
+impl From<Vec<u32>> for ObjectIdentifier {
+
+  fn from(arcs: Vec<u32>) -> Self {
+    ObjectIdentifier(rasn::types::ObjectIdentifier::new_unchecked(arcs.into()))
+  }
 }
 
 impl Display for ObjectIdentifier {
@@ -33,172 +84,1097 @@ impl Display for ObjectIdentifier {
 }
 
 impl FromStr for ObjectIdentifier {
-  type Err = Error;
+  type Err = String;
 
-  // TODO: rewrite so that it is safe
-  fn from_str(s: &str) -> std::prelude::v1::Result<Self, Self::Err> {
-    let x = s.split(".")
-      .map(|segment| segment.parse::<u32>().unwrap())
-      .collect::<Vec<u32>>();
-    Ok(ObjectIdentifier(rasn::types::ObjectIdentifier::new_unchecked(x.into())))
+  // Numeric OIDs may begin with a dot, as in `.1.3.6.1`, and take two arcs
+  // at least: the first 0, 1 or 2, and under the first two the second below
+  // 40. Names such as `IF-MIB::ifInOctets.3` are looked up in the MIBs.
+  fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+    if text.starts_with(|first: char| first.is_ascii_alphabetic()) {
+      return crate::mib::resolve(text).map(ObjectIdentifier::from).ok_or_else(|| format!("unknown OID name {}", text));
+    }
+    let invalid = |reason: &str| format!("invalid OID {}: {}", text, reason);
+    let arcs = text.strip_prefix('.').unwrap_or(text)
+      .split('.')
+      .map(|arc| arc.parse::<u32>().map_err(|_| invalid(&format!("arc {:?} is not a number", arc))))
+      .collect::<std::result::Result<Vec<u32>, _>>()?;
+    match arcs[..] {
+      [] | [_] => Err(invalid("fewer than two arcs")),
+      [first, ..] if first > 2 => Err(invalid("the first arc is not 0, 1 or 2")),
+      [first, second, ..] if first < 2 && second >= 40 => Err(invalid("the second arc is 40 or more")),
+      _ => Ok(ObjectIdentifier::from(arcs)),
+    }
   }
 }
 
-#[derive(Debug, Clone, Hash)]
+#[derive(Debug, Clone)]
 pub enum ObjectValue {
   Integer(rasn::types::Integer),
   OctetString(rasn::types::OctetString),
   ObjectIdentifier(ObjectIdentifier),
   Integer32(i32),
   IpAddress(Ipv4Addr),
+  // An IPv6 InetAddress with its zone index, if any. Agents send these as
+  // octet strings; see `ObjectValue::to_inet_address`.
+  Ipv6Address(Ipv6Addr, Option<u32>),
   Counter32(u32),
   Unsigned32(u32),
   TimeTicks(u32),
   Opaque(Vec<u8>),
   Counter64(u64),
+  // Floating-point values, which agents send wrapped in Opaque.
+  Float(f32),
+  Double(f64),
+  // The NULL of a binding without a value, which some agents answer with
+  // where they should report an exception.
+  Null,
+  // Exceptions an agent reports in place of a value.
+  NoSuchObject,
+  NoSuchInstance,
+  EndOfMibView,
 }
 
+impl ObjectValue {
+
+  pub fn as_f64(&self) -> Option<f64> {
+    match self {
+      ObjectValue::Integer(value) => value.to_f64(),
+      ObjectValue::Integer32(value) => Some(*value as f64),
+      ObjectValue::Counter32(value)
+        | ObjectValue::Unsigned32(value)
+        | ObjectValue::TimeTicks(value) => Some(*value as f64),
+      ObjectValue::Counter64(value) => Some(*value as f64),
+      ObjectValue::Float(value) => Some(*value as f64),
+      ObjectValue::Double(value) => Some(*value),
+      ObjectValue::OctetString(value) => std::str::from_utf8(value).ok()?.trim().parse().ok(),
+      ObjectValue::ObjectIdentifier(_)
+        | ObjectValue::IpAddress(_)
+        | ObjectValue::Ipv6Address(..)
+        | ObjectValue::Opaque(_)
+        | ObjectValue::Null
+        | ObjectValue::NoSuchObject
+        | ObjectValue::NoSuchInstance
+        | ObjectValue::EndOfMibView => None,
+    }
+  }
+
+  pub fn is_exception(&self) -> bool {
+    matches!(self, ObjectValue::NoSuchObject | ObjectValue::NoSuchInstance | ObjectValue::EndOfMibView)
+  }
+
+  // Reads an octet string as an InetAddress (RFC 4001) of the given
+  // InetAddressType, or of the type its length implies when that is not
+  // known. IPv4 zone indexes are dropped; dns and unknown types are left
+  // alone.
+  pub fn to_inet_address(&self, address_type: Option<u32>) -> Option<ObjectValue> {
+    let ObjectValue::OctetString(octets) = self else {
+      return None;
+    };
+    let address_type = address_type.or(match octets.len() {
+      4 => Some(1),
+      16 => Some(2),
+      20 => Some(4),
+      _ => None,
+    })?;
+    let zone = |octets: &[u8]| Some(u32::from_be_bytes(octets.try_into().ok()?));
+    match (address_type, octets.len()) {
+      (1, 4) | (3, 8) => Some(ObjectValue::IpAddress(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))),
+      (2, 16) => Some(ObjectValue::Ipv6Address(<[u8; 16]>::try_from(&octets[..]).ok()?.into(), None)),
+      (4, 20) => Some(ObjectValue::Ipv6Address(
+        <[u8; 16]>::try_from(&octets[..16]).ok()?.into(),
+        zone(&octets[16..]).filter(|zone| *zone != 0),
+      )),
+      _ => None,
+    }
+  }
+
+  // The value of the instance `oid` as the DISPLAY-HINT of the installed
+  // MIBs renders it, such as `00:1a:2b:3c:4d:5e`; None unless that tells
+  // more than the value itself.
+  pub fn hinted(&self, oid: &ObjectIdentifier) -> Option<String> {
+    let text = match self {
+      ObjectValue::OctetString(value) => crate::mib::display_octets(&oid.0, value)?,
+      ObjectValue::Integer(value) => crate::mib::display_integer(&oid.0, value.to_i64()?)?,
+      ObjectValue::Integer32(value) => crate::mib::display_integer(&oid.0, *value as i64)?,
+      ObjectValue::Unsigned32(value) => crate::mib::display_integer(&oid.0, *value as i64)?,
+      _ => return None,
+    };
+    (text != self.to_string()).then_some(text)
+  }
+
+  // The name the installed MIBs give the value of the instance `oid`, such
+  // as `up` for an ifOperStatus of 1.
+  pub fn label(&self, oid: &ObjectIdentifier) -> Option<&'static str> {
+    let value = match self {
+      ObjectValue::Integer(value) => value.to_i64()?,
+      ObjectValue::Integer32(value) => *value as i64,
+      _ => return None,
+    };
+    crate::mib::label(&oid.0, value)
+  }
+
+  // The names of the bits set in the value of the instance `oid`, where the
+  // installed MIBs give it BITS syntax, as `["sendPause", "recvPause"]`
+  // for `BITS { sendPause(0), recvPause(1) }` with both set.
+  pub fn flags(&self, oid: &ObjectIdentifier) -> Option<Vec<&'static str>> {
+    let ObjectValue::OctetString(octets) = self else {
+      return None;
+    };
+    crate::mib::flags(&oid.0, octets)
+  }
+}
+
+impl Display for ObjectValue {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ObjectValue::Integer(value) => write!(f, "{}", value),
+      ObjectValue::OctetString(value) => write!(f, "{}", String::from_utf8_lossy(value)),
+      ObjectValue::ObjectIdentifier(value) => write!(f, "{}", value),
+      ObjectValue::Integer32(value) => write!(f, "{}", value),
+      ObjectValue::IpAddress(value) => write!(f, "{}", value),
+      ObjectValue::Ipv6Address(value, None) => write!(f, "{}", value),
+      ObjectValue::Ipv6Address(value, Some(zone)) => write!(f, "{}%{}", value, zone),
+      ObjectValue::Counter32(value)
+        | ObjectValue::Unsigned32(value)
+        | ObjectValue::TimeTicks(value) => write!(f, "{}", value),
+      ObjectValue::Opaque(value) => {
+        for octet in value {
+          write!(f, "{:02x}", octet)?;
+        }
+        Ok(())
+      },
+      ObjectValue::Counter64(value) => write!(f, "{}", value),
+      ObjectValue::Float(value) => write!(f, "{}", value),
+      ObjectValue::Double(value) => write!(f, "{}", value),
+      ObjectValue::Null => write!(f, "NULL"),
+      ObjectValue::NoSuchObject => write!(f, "noSuchObject"),
+      ObjectValue::NoSuchInstance => write!(f, "noSuchInstance"),
+      ObjectValue::EndOfMibView => write!(f, "endOfMibView"),
+    }
+  }
+}
+
+// Floating-point values hash by their bits, having no `Hash` of their own.
+impl Hash for ObjectValue {
+
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    std::mem::discriminant(self).hash(state);
+    match self {
+      ObjectValue::Integer(value) => value.hash(state),
+      ObjectValue::OctetString(value) => value.hash(state),
+      ObjectValue::ObjectIdentifier(value) => value.hash(state),
+      ObjectValue::Integer32(value) => value.hash(state),
+      ObjectValue::IpAddress(value) => value.hash(state),
+      ObjectValue::Ipv6Address(value, zone) => (value, zone).hash(state),
+      ObjectValue::Counter32(value)
+        | ObjectValue::Unsigned32(value)
+        | ObjectValue::TimeTicks(value) => value.hash(state),
+      ObjectValue::Opaque(value) => value.hash(state),
+      ObjectValue::Counter64(value) => value.hash(state),
+      ObjectValue::Float(value) => value.to_bits().hash(state),
+      ObjectValue::Double(value) => value.to_bits().hash(state),
+      ObjectValue::Null
+        | ObjectValue::NoSuchObject
+        | ObjectValue::NoSuchInstance
+        | ObjectValue::EndOfMibView => {},
+    }
+  }
+}
+
+#[derive(Clone)]
 pub struct VariableBinding {
   pub object_id: ObjectIdentifier,
   pub value: ObjectValue,
+  pub timestamp: Timestamp,
 }
 
-#[derive(Clone, Debug)]
+// When a response arrived, along with the agent's own clock at that moment,
+// so that values served late (or from a cache) can still be aligned.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp {
+  pub collected_at: SystemTime,
+  pub sys_up_time: Option<u32>,
+}
+
+impl Timestamp {
+
+  fn from_response(bindings: &[model::v2::VarBind]) -> Timestamp {
+    let sys_up_time = bindings.iter()
+      .find(|binding| binding.name[..] == SYS_UP_TIME)
+      .and_then(|binding| match convert(&binding.name, &binding.value) {
+        ObjectValue::TimeTicks(ticks) => Some(ticks),
+        _ => None,
+      });
+    Timestamp { collected_at: SystemTime::now(), sys_up_time }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Target {
   Community {
     address: SocketAddr,
     community: OctetString,
+    timing: Timing,
+  },
+  // An agent speaking SNMPv1 only, which knows neither GetBulk nor the
+  // noSuchObject family of exceptions.
+  CommunityV1 {
+    address: SocketAddr,
+    community: OctetString,
+    timing: Timing,
+  },
+  // An SNMPv3 user; the passwords are localized to the agent's engine.
+  Usm {
+    address: SocketAddr,
+    user: OctetString,
+    auth: Option<(AuthProtocol, OctetString)>,
+    privacy: Option<(PrivacyProtocol, OctetString)>,
+    context: OctetString,
+    context_engine: Option<OctetString>,
+    timing: Timing,
+  },
+  // SNMPv3 over TLS, the certificates standing in for USM credentials.
+  Tls {
+    address: SocketAddr,
+    certificates: Certificates,
+    context: OctetString,
+    context_engine: Option<OctetString>,
+    timing: Timing,
   },
 }
 
+// How long to wait for an agent to answer before asking again, and how many
+// times to ask again. Each wait is twice the one before. With `outstanding`
+// set, no more requests than that await the agent's answers at once; the
+// rest wait their turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Timing {
+  pub timeout: Duration,
+  pub retries: u32,
+  pub outstanding: Option<usize>,
+}
+
+impl Default for Timing {
+
+  fn default() -> Self {
+    Timing { timeout: Duration::from_secs(2), retries: 2, outstanding: None }
+  }
+}
+
 impl Target {
 
-  fn get_address(&self) -> &SocketAddr {
+  pub fn get_address(&self) -> &SocketAddr {
     match self {
-      Target::Community { address, .. } => address,
+      Target::Community { address, .. }
+        | Target::CommunityV1 { address, .. }
+        | Target::Usm { address, .. }
+        | Target::Tls { address, .. } => address,
+    }
+  }
+
+  fn timing(&self) -> &Timing {
+    match self {
+      Target::Community { timing, .. }
+        | Target::CommunityV1 { timing, .. }
+        | Target::Usm { timing, .. }
+        | Target::Tls { timing, .. } => timing,
     }
   }
 }
 
-#[derive(Debug)]
+// What failed of a request to an agent. Failures of the network and of
+// encoding carry the error underneath, when there is one; the client wraps
+// whatever failed in `Request`, saying which request to which agent.
+// `Serialization` is a request that could not be encoded, `Malformed` a
+// response that could not be decoded or made no sense.
+#[derive(Debug, Clone)]
 pub enum Error {
-  Connection(),
-  Serialization(),
+  Connection(Option<Arc<std::io::Error>>),
+  Serialization(Option<Arc<CodecError>>),
+  Malformed(Option<Arc<CodecError>>),
+  Timeout(),
+  Security(),
+  Unsupported(),
+  // The agent's error-status, the 1-based error-index of the binding it
+  // blames, and that binding's OID when the index points at one.
+  Agent { status: u32, index: u32, oid: Option<ObjectIdentifier> },
+  Request(Box<Context>),
 }
 
-impl Display for Error { // TODO: write better error descriptions
+// The request an error befell: what it asked of which agent, for what OIDs.
+#[derive(Debug, Clone)]
+pub struct Context {
+  pub address: SocketAddr,
+  pub operation: &'static str,
+  pub oids: Vec<ObjectIdentifier>,
+  pub error: Error,
+}
 
-  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Error {
+
+  pub fn io(error: std::io::Error) -> Error {
+    Error::Connection(Some(Arc::new(error)))
+  }
+
+  pub fn codec(error: impl Into<CodecError>) -> Error {
+    Error::Serialization(Some(Arc::new(error.into())))
+  }
+
+  pub fn malformed(error: impl Into<CodecError>) -> Error {
+    Error::Malformed(Some(Arc::new(error.into())))
+  }
+
+  // The failure itself, without the requests it is wrapped in.
+  pub fn cause(&self) -> &Error {
     match self {
-      Error::Connection() => write!(f, "Connection problem."),
-      Error::Serialization() => write!(f, "Serialization problem."),
+      Error::Request(context) => context.error.cause(),
+      error => error,
     }
   }
+
+  pub(crate) fn context(self, address: &SocketAddr, operation: &'static str, oids: &[ObjectIdentifier]) -> Error {
+    Error::Request(Box::new(Context { address: *address, operation, oids: oids.to_vec(), error: self }))
+  }
 }
 
-pub async fn get(
-  target: &Target,
-  oids: &Vec<ObjectIdentifier>,
-) -> Result<Vec<VariableBinding>> {
-  let socket = UdpSocket::bind("[::]:0")
-    .await
-    .map_err(|io_error| Error::Connection())?;
-  let message = match target {
-    Target::Community { community, .. } => model::v2c::Message {
-      version: 1.into(), // TODO
-      community: community.clone(),
-      data: model::v2::GetRequest(
-        model::v2::Pdu {
-          request_id: 1,
-          error_status: model::v2::Pdu::ERROR_STATUS_NO_ERROR,
-          error_index: 0,
-          variable_bindings: oids.iter()
-            .map(|oid| model::v2::VarBind {
-              name: oid.0.clone(),
-              value: model::v2::VarBindValue::Unspecified,
-            })
-            .collect(),
+// Why a message could not be encoded or decoded. rasn's own errors are not
+// `std::error::Error`s, and print backtraces, so only their kind is kept.
+#[derive(Debug)]
+pub struct CodecError(String);
+
+impl From<rasn::error::DecodeError> for CodecError {
+
+  fn from(error: rasn::error::DecodeError) -> Self {
+    CodecError(format!("cannot decode: {}", error.kind))
+  }
+}
+
+impl From<rasn::error::EncodeError> for CodecError {
+
+  fn from(error: rasn::error::EncodeError) -> Self {
+    CodecError(format!("cannot encode: {}", error.kind))
+  }
+}
+
+impl Display for CodecError {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl std::error::Error for CodecError {}
+
+const ERROR_STATUS_NAMES: [&str; 19] = [
+  "noError", "tooBig", "noSuchName", "badValue", "readOnly", "genErr", "noAccess", "wrongType",
+  "wrongLength", "wrongEncoding", "wrongValue", "noCreation", "inconsistentValue",
+  "resourceUnavailable", "commitFailed", "undoFailed", "authorizationError", "notWritable",
+  "inconsistentName",
+];
+
+// The name RFC 3416 gives an error-status, such as `noSuchName`.
+pub fn status_name(status: u32) -> Option<&'static str> {
+  ERROR_STATUS_NAMES.get(status as usize).copied()
+}
+
+// The agent's refusal in `response`, which has a non-zero error-status.
+fn refusal(response: &model::v2::Pdu) -> Error {
+  let oid = usize::try_from(response.error_index).ok()
+    .and_then(|index| index.checked_sub(1))
+    .and_then(|position| response.variable_bindings.get(position))
+    .map(|binding| ObjectIdentifier(binding.name.clone()));
+  Error::Agent { status: response.error_status, index: response.error_index, oid }
+}
+
+// How many OIDs of a failed request its description names.
+const DESCRIBED_OIDS: usize = 3;
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Connection(None) => write!(f, "Connection problem."),
+      Error::Connection(Some(error)) => write!(f, "Connection problem: {}.", error),
+      Error::Serialization(None) => write!(f, "Serialization problem."),
+      Error::Serialization(Some(error)) => write!(f, "Serialization problem: {}.", error),
+      Error::Malformed(None) => write!(f, "Malformed response."),
+      Error::Malformed(Some(error)) => write!(f, "Malformed response: {}.", error),
+      Error::Timeout() => write!(f, "No response in time."),
+      Error::Security() => write!(f, "Security problem."),
+      Error::Unsupported() => write!(f, "SNMP over TLS support is not compiled in."),
+      Error::Agent { status, index, oid } => {
+        match status_name(*status) {
+          Some(name) => write!(f, "Agent error {}", name)?,
+          None => write!(f, "Agent error {}", status)?,
+        }
+        match oid {
+          Some(oid) => write!(f, " at binding {} ({}).", index, oid),
+          None => write!(f, " at binding {}.", index),
+        }
+      },
+      Error::Request(context) => {
+        write!(f, "{} to {}", context.operation, context.address)?;
+        for (position, oid) in context.oids.iter().take(DESCRIBED_OIDS).enumerate() {
+          write!(f, "{}{}", if position == 0 { " of " } else { ", " }, oid)?;
+        }
+        if context.oids.len() > DESCRIBED_OIDS {
+          write!(f, " and {} more", context.oids.len() - DESCRIBED_OIDS)?;
         }
-      ),
+        write!(f, ": {}", context.error)
+      },
+    }
+  }
+}
+
+impl std::error::Error for Error {
+
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Connection(Some(error)) => Some(error.as_ref()),
+      Error::Serialization(Some(error)) | Error::Malformed(Some(error)) => Some(error.as_ref()),
+      Error::Request(context) => Some(&context.error),
+      _ => None,
+    }
+  }
+}
+
+// Runs `future` with requests to agents bounded by `deadline`, or by the
+// deadline it already runs under if that one is earlier. Requests still
+// unanswered then are abandoned, and further ones fail at once.
+pub async fn within<F: Future>(deadline: Instant, future: F) -> F::Output {
+  let deadline = DEADLINE.try_with(|outer| *outer.min(&deadline)).unwrap_or(deadline);
+  DEADLINE.scope(deadline, future).await
+}
+
+fn expired() -> bool {
+  DEADLINE.try_with(|deadline| *deadline <= Instant::now()).unwrap_or(false)
+}
+
+// The requests awaiting answers by agent, of targets with `outstanding` set.
+// Targets of one address share the limit of the first asking.
+static OUTSTANDING: OnceLock<Mutex<HashMap<SocketAddr, Arc<Semaphore>>>> = OnceLock::new();
+
+// Waits until fewer than `limit` requests await the agent's answers, or the
+// deadline passes.
+async fn turn(address: &SocketAddr, limit: usize) -> Result<OwnedSemaphorePermit> {
+  let semaphore = OUTSTANDING.get_or_init(Default::default)
+    .lock()
+    .unwrap()
+    .entry(*address)
+    .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+    .clone();
+  let permit = match DEADLINE.try_with(|deadline| *deadline) {
+    Ok(deadline) => tokio::time::timeout_at(deadline, semaphore.acquire_owned())
+      .await
+      .map_err(|_elapsed| Error::Timeout())?,
+    Err(_outside) => semaphore.acquire_owned().await,
+  };
+  permit.map_err(|_closed| Error::Connection(None))
+}
+
+// Sends `message`, the request with ID `id`, to the agent and returns its
+// answer, sending it again whenever the timeout passes without one until the
+// retries run out. Answers `answer` does not recognize are skipped.
+async fn send_receive<T>(
+  address: &SocketAddr,
+  id: i32,
+  message: &[u8],
+  timing: &Timing,
+  answer: impl Fn(&[u8]) -> Option<T>,
+) -> Result<T> {
+  let mut exchange = dispatch::Exchange::new(address, id)?;
+  let mut timeout = timing.timeout;
+  let mut retries = timing.retries;
+  loop {
+    exchange.send(message, address).await?;
+    trace::event(format_args!("sent {} bytes to {} as {}", message.len(), address, id));
+    let until = Instant::now() + timeout;
+    let until = DEADLINE.try_with(|deadline| *deadline.min(&until)).unwrap_or(until);
+    loop {
+      match tokio::time::timeout_at(until, exchange.receive()).await {
+        Ok(Some(datagram)) => {
+          trace::event(format_args!("received {} bytes from {}", datagram.len(), address));
+          if let Some(answer) = answer(&datagram) {
+            return Ok(answer);
+          }
+          trace::event(format_args!("skipped an answer not decoded as one to {}", id));
+        },
+        Ok(None) => return Err(Error::Connection(None)),
+        Err(_elapsed) if retries > 0 && !expired() => {
+          trace::event(format_args!("no answer from {} in {}ms, {} retries left", address, timeout.as_millis(), retries));
+          break;
+        },
+        Err(_elapsed) => {
+          stats::count(&stats::STATS.snmp_timeouts);
+          return Err(Error::Timeout());
+        },
+      }
+    }
+    retries -= 1;
+    timeout *= 2;
+  }
+}
+
+// Sends one request PDU to the agent and returns the PDU of its response.
+// The request goes out under an ID of its own, so that answers to other
+// requests are told apart, and the caller's is put back on the response.
+async fn request(target: &Target, data: model::v2::Pdus) -> Result<model::v2::Pdu> {
+  let (mut data, asked_id) = (data, next_id());
+  let caller_id = match &mut data {
+    model::v2::Pdus::GetRequest(model::v2::GetRequest(pdu))
+      | model::v2::Pdus::GetNextRequest(model::v2::GetNextRequest(pdu))
+      | model::v2::Pdus::SetRequest(model::v2::SetRequest(pdu)) => std::mem::replace(&mut pdu.request_id, asked_id),
+    model::v2::Pdus::GetBulkRequest(model::v2::GetBulkRequest(pdu)) => std::mem::replace(&mut pdu.request_id, asked_id),
+    _ => return Err(Error::Serialization(None)),
+  };
+  if expired() {
+    return Err(Error::Timeout());
+  }
+  let _turn = match target.timing().outstanding {
+    Some(limit) => {
+      let _queued = stats::hold(&stats::STATS.snmp_queued);
+      Some(turn(target.get_address(), limit).await?)
     },
+    None => None,
   };
-  let serialized_message = rasn::ber::encode(&message)
-    .map_err(|encode_error| Error::Serialization())?;
-  socket.send_to(&serialized_message, target.get_address()) // TODO: check sent bytes count
-    .await
-    .map_err(|io_error| Error::Connection())?;
-  let mut response_buffer = [0; 1024];
-  let (byte_count, origin) = socket.recv_from(&mut response_buffer)
-    .await
-    .map_err(|io_error| Error::Connection())?;
+  let started = Instant::now();
   let response = match target {
-    Target::Community { .. } => rasn::ber::decode::<model::v2c::Message<model::v2::Response>>(&response_buffer)
-      .map_err(|decode_error| Error::Serialization())?,
+    // Get, GetNext and Response PDUs are encoded alike in SNMPv1 and v2c,
+    // bar the values v1 lacks; only the version differs.
+    Target::Community { address, community, timing } | Target::CommunityV1 { address, community, timing } => {
+      let message = model::v2c::Message {
+        version: match target {
+          Target::CommunityV1 { .. } => 0.into(),
+          _ => 1.into(),
+        },
+        community: community.clone(),
+        data,
+      };
+      let serialized_message = rasn::ber::encode(&message)
+        .map_err(Error::codec)?;
+      let answer = |datagram: &[u8]| rasn::ber::decode::<model::v2c::Message<model::v2::Response>>(datagram).ok()
+        .map(|response| response.data.0)
+        .filter(|response| response.request_id == asked_id);
+      send_receive(address, asked_id, &serialized_message, timing, answer).await
+    },
+    Target::Usm { address, user, auth, privacy, context, context_engine, timing } => {
+      let user = usm::User {
+        name: user,
+        auth: auth.as_ref(),
+        privacy: privacy.as_ref(),
+        context,
+        context_engine: context_engine.as_ref(),
+      };
+      usm::request(address, &user, timing, data).await
+    },
+    Target::Tls { address, certificates, context, context_engine, timing } => {
+      tls::request(address, certificates, context, context_engine.as_ref(), timing, data).await
+    },
   };
+  let response = match response {
+    Ok(response) => response,
+    Err(error) => {
+      trace::event(format_args!("request to {} failed after {}ms: {}", target.get_address(), started.elapsed().as_millis(), error));
+      return Err(error);
+    },
+  };
+  trace::event(format_args!(
+    "decoded {} bindings from {} with error-status {} in {}ms",
+    response.variable_bindings.len(),
+    target.get_address(),
+    response.error_status,
+    started.elapsed().as_millis(),
+  ));
+  stats::STATS.snmp_latency.observe(started.elapsed());
+  Ok(model::v2::Pdu { request_id: caller_id, ..response })
+}
+
+async fn get(
+  target: &Target,
+  oids: &[ObjectIdentifier],
+) -> Result<Vec<VariableBinding>> {
+  let sys_up_time = ObjectIdentifier(rasn::types::ObjectIdentifier::new_unchecked(SYS_UP_TIME.to_vec().into()));
+  // The request goes out sorted and without duplicates, in one PDU however
+  // messy the list; `positions` maps each requested OID to its binding.
+  let mut unique = oids.to_vec();
+  unique.sort_unstable();
+  unique.dedup();
+  let positions = oids.iter()
+    .filter_map(|oid| unique.binary_search(oid).ok())
+    .collect::<Vec<_>>();
+  if unique.binary_search(&sys_up_time).is_err() {
+    unique.push(sys_up_time);
+  }
+  let oids = unique;
+  let bindings = &fetch(target, &oids, false).await?;
+  let timestamp = Timestamp::from_response(bindings);
   Ok(
-    response.data.0.variable_bindings.iter()
+    positions.iter()
+      .filter_map(|position| bindings.get(*position))
       .map(|binding| VariableBinding {
         object_id: ObjectIdentifier(binding.name.clone()),
-        value: convert(&binding.value),
+        value: convert(&binding.name, &binding.value),
+        timestamp,
       })
       .collect()
   )
 }
 
-pub async fn get_bulk(
+// The bindings the agent returns for `oids` in one Get or, with `next`,
+// GetNext. An SNMPv1 agent fails the whole request with noSuchName for one
+// OID it lacks; that OID is answered as noSuchObject (endOfMibView for
+// GetNext) and the others are asked for again. Any other error-status
+// fails the request as `Error::Agent`.
+async fn fetch(target: &Target, oids: &[ObjectIdentifier], next: bool) -> Result<Vec<model::v2::VarBind>> {
+  let pdus = |oids: &[&ObjectIdentifier]| {
+    let pdu = model::v2::Pdu {
+      request_id: 1,
+      error_status: model::v2::Pdu::ERROR_STATUS_NO_ERROR,
+      error_index: 0,
+      variable_bindings: oids.iter()
+        .map(|oid| model::v2::VarBind {
+          name: oid.0.clone(),
+          value: model::v2::VarBindValue::Unspecified,
+        })
+        .collect(),
+    };
+    match next {
+      true => model::v2::Pdus::GetNextRequest(model::v2::GetNextRequest(pdu)),
+      false => model::v2::Pdus::GetRequest(model::v2::GetRequest(pdu)),
+    }
+  };
+  if !matches!(target, Target::CommunityV1 { .. }) {
+    let response = request(target, pdus(&oids.iter().collect::<Vec<_>>())).await?;
+    if response.error_status != model::v2::Pdu::ERROR_STATUS_NO_ERROR {
+      return Err(refusal(&response));
+    }
+    return Ok(response.variable_bindings);
+  }
+  let missing = match next {
+    true => model::v2::VarBindValue::EndOfMibView,
+    false => model::v2::VarBindValue::NoSuchObject,
+  };
+  let mut bindings = oids.iter()
+    .map(|oid| model::v2::VarBind { name: oid.0.clone(), value: missing.clone() })
+    .collect::<Vec<_>>();
+  let mut asked = (0..oids.len()).collect::<Vec<_>>();
+  while !asked.is_empty() {
+    let response = request(target, pdus(&asked.iter().map(|&position| &oids[position]).collect::<Vec<_>>())).await?;
+    if response.error_status == model::v2::Pdu::ERROR_STATUS_NO_SUCH_NAME {
+      let failed = usize::try_from(response.error_index).ok()
+        .and_then(|index| index.checked_sub(1))
+        .filter(|index| *index < asked.len())
+        .ok_or(Error::Malformed(None))?;
+      asked.remove(failed);
+      continue;
+    }
+    if response.error_status != model::v2::Pdu::ERROR_STATUS_NO_ERROR {
+      return Err(refusal(&response));
+    }
+    for (position, binding) in asked.iter().zip(response.variable_bindings) {
+      bindings[*position] = binding;
+    }
+    break;
+  }
+  Ok(bindings)
+}
+
+// The successors of `oids` in one GetNext, in the order asked; sysUpTime
+// rides along for the timestamp.
+async fn get_next(
   target: &Target,
-  oid: &ObjectIdentifier,
+  oids: &[ObjectIdentifier],
 ) -> Result<Vec<VariableBinding>> {
-  let socket = UdpSocket::bind("[::]:0")
-    .await
-    .map_err(|io_error| Error::Connection())?;
-  let message = match target {
-    Target::Community { community, .. } => model::v2c::Message {
-      version: 1.into(), // TODO
-      community: community.clone(),
-      data: model::v2::GetBulkRequest(
-        model::v2::BulkPdu {
-          request_id: 1,
-          non_repeaters: 0,
-          max_repetitions: 20, // TODO: should be configurable
-          variable_bindings: vec![
-            model::v2::VarBind {
-              name: oid.0.clone(),
-              value: model::v2::VarBindValue::Unspecified,
-            },
-          ],
-        }
-      ),
-    },
-  };
-  println!("SNMP Request: {:?}", message);
-  let serialized_message = rasn::ber::encode(&message)
-    .map_err(|encode_error| Error::Serialization())?;
-  socket.send_to(&serialized_message, target.get_address()) // TODO: check sent bytes count
-    .await
-    .map_err(|io_error| Error::Connection())?;
-  let mut response_buffer = [0; 2048];
-  let (byte_count, origin) = socket.recv_from(&mut response_buffer)
-    .await
-    .map_err(|io_error| Error::Connection())?;
-  println!("Binary response [{:?}]: {:?}", byte_count, response_buffer);
-  let response = match target {
-    Target::Community { .. } => rasn::ber::decode::<model::v2c::Message<model::v2::Response>>(&response_buffer)
-      .map_err(|decode_error| Error::Serialization())?,
+  let asked = oids.iter().cloned()
+    .chain([SYS_UP_TIME[..8].to_vec().into()])
+    .collect::<Vec<_>>();
+  let bindings = fetch(target, &asked, true).await?;
+  let timestamp = Timestamp::from_response(&bindings[oids.len()..]);
+  Ok(
+    bindings.iter()
+      .take(oids.len())
+      .map(|binding| VariableBinding {
+        object_id: ObjectIdentifier(binding.name.clone()),
+        value: convert(&binding.name, &binding.value),
+        timestamp,
+      })
+      .collect()
+  )
+}
+
+// The subtree under `root` past `after`, in GetBulk requests of
+// `max_repetitions` each, every one going on from the last binding of the
+// one before, until the subtree ends or `max_bindings` are read.
+async fn get_bulk(
+  target: &Target,
+  root: &ObjectIdentifier,
+  after: &ObjectIdentifier,
+  max_repetitions: u32,
+  max_bindings: usize,
+) -> Result<Vec<VariableBinding>> {
+  walk(target, root, after, max_bindings, max_repetitions.max(1), &|_walked| {}).await
+}
+
+// One GetBulk fetching `scalars`, instances such as sysName.0, as
+// non-repeaters alongside the next rows of the `columns`, each going on
+// past the OID in `after` at the same position. A scalar the agent does not
+// have comes back as noSuchObject; column bindings past the end of their
+// column are left out.
+async fn get_bulk_mixed(
+  target: &Target,
+  scalars: &[ObjectIdentifier],
+  columns: &[ObjectIdentifier],
+  after: &[ObjectIdentifier],
+  max_repetitions: u32,
+) -> Result<Vec<VariableBinding>> {
+  // Non-repeaters are answered like GetNext, so each scalar is asked for by
+  // its object, whose successor is the instance wanted.
+  let parent = |oid: &ObjectIdentifier| oid.arcs().split_last().map_or(Vec::new(), |(_, parent)| parent.to_vec());
+  let first = [SYS_UP_TIME[..8].to_vec().into()].into_iter()
+    .chain(scalars.iter().map(|oid| ObjectIdentifier::from(parent(oid))))
+    .chain(after.iter().cloned())
+    .collect::<Vec<_>>();
+  // sysUpTime rides along as a non-repeater.
+  let bindings = bulk(target, &first, 1 + scalars.len(), max_repetitions).await?;
+  let timestamp = Timestamp::from_response(bindings.get(..1).unwrap_or(&[]));
+  let scalar_bindings = scalars.iter().enumerate()
+    .map(|(position, oid)| {
+      let value = bindings.get(position + 1)
+        .filter(|binding| binding.name[..] == *oid.arcs())
+        .map_or(ObjectValue::NoSuchObject, |binding| convert(&binding.name, &binding.value));
+      VariableBinding { object_id: oid.clone(), value, timestamp }
+    })
+    .collect::<Vec<_>>();
+  // The repetitions follow row by row, one binding per column.
+  let column_bindings = bindings.iter()
+    .skip(1 + scalars.len())
+    .zip(columns.iter().cycle())
+    .map(|(binding, column)| (column, VariableBinding {
+      object_id: ObjectIdentifier(binding.name.clone()),
+      value: convert(&binding.name, &binding.value),
+      timestamp,
+    }))
+    .filter(|(column, binding)| binding.object_id.starts_with(column) && !binding.value.is_exception())
+    .map(|(_, binding)| binding);
+  Ok(scalar_bindings.into_iter().chain(column_bindings).collect())
+}
+
+// The bindings under `root` past `after`, up to `limit`, in GetBulk
+// requests of `max_repetitions`. The walk ends when answers leave the
+// subtree, reach the end of the MIB view or stop advancing; `progress` gets
+// the count walked after each answer.
+async fn walk(
+  target: &Target,
+  root: &ObjectIdentifier,
+  after: &ObjectIdentifier,
+  limit: usize,
+  max_repetitions: u32,
+  progress: &(dyn Fn(&[VariableBinding]) + Sync),
+) -> Result<Vec<VariableBinding>> {
+  let mut walked = Vec::new();
+  let mut from = after.clone();
+  while walked.len() < limit {
+    let repetitions = (limit - walked.len()).min(max_repetitions as usize) as u32;
+    let (mut page, ended) = walk_step(target, root, &from, repetitions).await?;
+    page.truncate(limit - walked.len());
+    walked.extend(page);
+    if ended {
+      return Ok(walked);
+    }
+    progress(&walked);
+    from = walked[walked.len() - 1].object_id.clone();
+  }
+  Ok(walked)
+}
+
+// The walk of `root` as a stream of its bindings, yielded as each answer
+// arrives. The next GetBulk is only sent once the bindings of the one before
+// are taken, so that a caller dropping the stream asks the agent for no
+// more. It ends after the first error.
+pub fn walk_stream(target: &Target, root: &ObjectIdentifier) -> impl Stream<Item = Result<VariableBinding>> + Send + 'static {
+  walk_stream_after(target, root, root)
+}
+
+// Like `walk_stream`, going on past `after` rather than from the start.
+fn walk_stream_after(
+  target: &Target,
+  root: &ObjectIdentifier,
+  after: &ObjectIdentifier,
+) -> impl Stream<Item = Result<VariableBinding>> + Send + 'static {
+  let (target, root) = (target.clone(), root.clone());
+  futures_util::stream::unfold(Some(after.clone()), move |from| {
+    let (target, root) = (target.clone(), root.clone());
+    async move {
+      let from = from?;
+      let (page, next) = match walk_step(&target, &root, &from, MAX_REPETITIONS).await {
+        Ok((page, ended)) => {
+          let next = page.last().filter(|_| !ended).map(|binding| binding.object_id.clone());
+          (page.into_iter().map(Ok).collect(), next)
+        },
+        Err(error) => (vec![Err(error)], None),
+      };
+      Some((futures_util::stream::iter(page), next))
+    }
+  })
+  .flatten()
+}
+
+// One GetBulk of a walk of `root`, for the next `repetitions` bindings past
+// `from`, and whether the walk ends with it: its last binding left the
+// subtree, reached the end of the MIB view or did not advance, or there was
+// none.
+async fn walk_step(
+  target: &Target,
+  root: &ObjectIdentifier,
+  from: &ObjectIdentifier,
+  repetitions: u32,
+) -> Result<(Vec<VariableBinding>, bool)> {
+  let bindings = bulk(target, &[SYS_UP_TIME[..8].to_vec().into(), from.clone()], 1, repetitions).await?;
+  let timestamp = Timestamp::from_response(bindings.get(..1).unwrap_or(&[]));
+  let mut walked: Vec<VariableBinding> = Vec::new();
+  for binding in bindings.get(1..).unwrap_or(&[]) {
+    let object_id = ObjectIdentifier(binding.name.clone());
+    let value = convert(&binding.name, &binding.value);
+    let last = walked.last().map_or(from, |binding| &binding.object_id);
+    if !object_id.starts_with(root) || value.is_exception() || object_id <= *last {
+      return Ok((walked, true));
+    }
+    walked.push(VariableBinding { object_id, value, timestamp });
+  }
+  let ended = walked.is_empty();
+  Ok((walked, ended))
+}
+
+// The rows of a conceptual table, keyed by their index and then by column
+// number, from a walk of each of `columns`, or of the whole entry when none
+// are given. Rows lacking some column simply lack it here.
+async fn get_table(
+  target: &Target,
+  table: &ObjectIdentifier,
+  columns: &[u32],
+) -> Result<HashMap<Vec<u32>, HashMap<u32, ObjectValue>>> {
+  // By SMI convention the entry is the table's only child.
+  let entry = table.child(&[1]);
+  let subtrees = match columns {
+    [] => vec![entry.clone()],
+    columns => columns.iter().map(|column| entry.child(&[*column])).collect(),
   };
-  println!("SNMP Response: {:?}", response);
+  let mut rows: HashMap<Vec<u32>, HashMap<u32, ObjectValue>> = HashMap::new();
+  for subtree in &subtrees {
+    for binding in walk(target, subtree, subtree, usize::MAX, MAX_REPETITIONS, &|_walked| {}).await? {
+      let Some((column, index)) = binding.object_id.strip_prefix(&entry).and_then(|suffix| suffix.split_first()) else {
+        continue;
+      };
+      rows.entry(index.to_vec()).or_default().insert(*column, binding.value);
+    }
+  }
+  Ok(rows)
+}
+
+// The response to a GetBulk of `oids`, the first `non_repeaters` of them
+// asked for once and the others repeatedly.
+async fn bulk(
+  target: &Target,
+  oids: &[ObjectIdentifier],
+  non_repeaters: usize,
+  max_repetitions: u32,
+) -> Result<Vec<model::v2::VarBind>> {
+  if let Target::CommunityV1 { .. } = target {
+    return get_next_rows(target, oids, oids.len() - non_repeaters, max_repetitions).await;
+  }
+  // An agent whose answer would not fit in a datagram says tooBig; fewer
+  // repetitions are asked for until it fits.
+  let mut max_repetitions = max_repetitions;
+  loop {
+    let data = model::v2::Pdus::GetBulkRequest(model::v2::GetBulkRequest(
+      model::v2::BulkPdu {
+        request_id: 1,
+        non_repeaters: non_repeaters as u32,
+        max_repetitions,
+        variable_bindings: oids.iter()
+          .map(|oid| model::v2::VarBind {
+            name: oid.0.clone(),
+            value: model::v2::VarBindValue::Unspecified,
+          })
+          .collect(),
+      }
+    ));
+    let response = request(target, data).await?;
+    match response.error_status {
+      model::v2::Pdu::ERROR_STATUS_TOO_BIG if max_repetitions > 1 => max_repetitions /= 2,
+      model::v2::Pdu::ERROR_STATUS_NO_ERROR => return Ok(response.variable_bindings),
+      _ => return Err(refusal(&response)),
+    }
+  }
+}
+
+// What a GetBulk of `oids`, whose last `repeaters` are columns, would
+// return, read from an SNMPv1 agent with one GetNext per row. It stops once
+// a row has left every column.
+async fn get_next_rows(
+  target: &Target,
+  oids: &[ObjectIdentifier],
+  repeaters: usize,
+  max_repetitions: u32,
+) -> Result<Vec<model::v2::VarBind>> {
+  let columns = &oids[oids.len() - repeaters..];
+  let within = |row: &[model::v2::VarBind]| row.iter().zip(columns)
+    .any(|(binding, column)| binding.name.starts_with(column.arcs())
+      && !convert(&binding.name, &binding.value).is_exception());
+  let mut bindings = fetch(target, oids, true).await?;
+  let mut row = bindings[bindings.len() - repeaters..].to_vec();
+  for _ in 1..max_repetitions {
+    if !within(&row) {
+      break;
+    }
+    let next = row.iter().map(|binding| ObjectIdentifier(binding.name.clone())).collect::<Vec<_>>();
+    row = fetch(target, &next, true).await?;
+    bindings.extend(row.iter().cloned());
+  }
+  Ok(bindings)
+}
+
+// Writes the bindings in one SetRequest, returning the agent's response.
+// Agents apply a Set entirely or not at all; a refusal is `Error::Agent`.
+async fn set(
+  target: &Target,
+  bindings: Vec<VariableBinding>,
+) -> Result<Vec<VariableBinding>> {
+  let variable_bindings = bindings.iter()
+    .map(|binding| Ok(model::v2::VarBind {
+      name: binding.object_id.0.clone(),
+      value: unconvert(&binding.value).ok_or(Error::Serialization(None))?,
+    }))
+    .collect::<Result<Vec<_>>>()?;
+  let response = request(target, model::v2::Pdus::SetRequest(model::v2::SetRequest(
+    model::v2::Pdu {
+      request_id: 1,
+      error_status: model::v2::Pdu::ERROR_STATUS_NO_ERROR,
+      error_index: 0,
+      variable_bindings,
+    }
+  ))).await?;
+  if response.error_status != model::v2::Pdu::ERROR_STATUS_NO_ERROR {
+    return Err(refusal(&response));
+  }
+  let timestamp = Timestamp::from_response(&response.variable_bindings);
   Ok(
-    response.data.0.variable_bindings.iter()
+    response.variable_bindings.iter()
       .map(|binding| VariableBinding {
         object_id: ObjectIdentifier(binding.name.clone()),
-        value: convert(&binding.value),
+        value: convert(&binding.name, &binding.value),
+        timestamp,
       })
-      .filter(|binding| binding.object_id.starts_with(oid))
       .collect()
   )
 }
 
-fn convert(value: &model::v2::VarBindValue) -> ObjectValue {
+// Like `get`, but when the request as a whole fails each OID is retried on
+// its own, so one OID the agent cannot answer does not cost the others.
+async fn get_each(
+  target: &Target,
+  oids: &[ObjectIdentifier],
+) -> Vec<(ObjectIdentifier, Result<VariableBinding>)> {
+  if let Some(bindings) = get(target, oids).await.ok().filter(|bindings| bindings.len() == oids.len()) {
+    return bindings.into_iter()
+      .map(|binding| (binding.object_id.clone(), Ok(binding)))
+      .collect();
+  }
+  let mut unique = oids.to_vec();
+  unique.sort_unstable();
+  unique.dedup();
+  let mut values = Vec::with_capacity(unique.len());
+  for oid in &unique {
+    // Once the deadline has passed the remaining OIDs fail without a request.
+    let value = get(target, std::slice::from_ref(oid))
+      .await
+      .and_then(|bindings| bindings.into_iter().next().ok_or(Error::Malformed(None)));
+    values.push(value);
+  }
+  // Duplicates share the outcome of the one request made for them.
+  oids.iter()
+    .filter_map(|oid| Some((oid.clone(), values[unique.binary_search(oid).ok()?].clone())))
+    .collect()
+}
+
+// Turns the Opaque payload of a vendor object into a meaningful value, or
+// returns `None` to leave it as raw bytes.
+pub type OpaqueDecoder = Box<dyn Fn(&[u8]) -> Option<ObjectValue> + Send + Sync>;
+
+static OPAQUE_DECODERS: RwLock<Vec<(ObjectIdentifier, OpaqueDecoder)>> = RwLock::new(Vec::new());
+
+// Registers `decoder` for Opaque values of objects under `prefix`. When
+// several prefixes match, the longer ones are tried first.
+pub fn register_opaque_decoder(prefix: ObjectIdentifier, decoder: OpaqueDecoder) {
+  let mut decoders = OPAQUE_DECODERS.write().unwrap();
+  decoders.push((prefix, decoder));
+  decoders.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.arcs().len()));
+}
+
+// Registered decoders come first; floats and doubles are recognized
+// whatever the object.
+fn decode_opaque(name: &rasn::types::ObjectIdentifier, value: &[u8]) -> ObjectValue {
+  OPAQUE_DECODERS.read().unwrap()
+    .iter()
+    .filter(|(prefix, _)| name.starts_with(prefix.arcs()))
+    .find_map(|(_, decoder)| decoder(value))
+    .or_else(|| decode_float(value))
+    .unwrap_or_else(|| ObjectValue::Opaque(value.to_vec()))
+}
+
+// The tags of the Opaque-wrapped Float and Double of net-snmp, which vendors
+// such as APC and NetApp follow: the extension 0x9f, then 0x78 or 0x79.
+const OPAQUE_FLOAT: [u8; 2] = [0x9f, 0x78];
+const OPAQUE_DOUBLE: [u8; 2] = [0x9f, 0x79];
+
+// A Float or Double, its IEEE 754 bits in network order after the tag and
+// length.
+fn decode_float(value: &[u8]) -> Option<ObjectValue> {
+  match value {
+    [first, second, 4, bits @ ..] if [*first, *second] == OPAQUE_FLOAT =>
+      Some(ObjectValue::Float(f32::from_be_bytes(bits.try_into().ok()?))),
+    [first, second, 8, bits @ ..] if [*first, *second] == OPAQUE_DOUBLE =>
+      Some(ObjectValue::Double(f64::from_be_bytes(bits.try_into().ok()?))),
+    _ => None,
+  }
+}
+
+// The encoding of a value to be set; exceptions and NULL are not values.
+fn unconvert(value: &ObjectValue) -> Option<model::v2::VarBindValue> {
+  let simple = |value| Some(model::v2::VarBindValue::Value(rasn_smi::v2::ObjectSyntax::Simple(value)));
+  let application = |value| Some(model::v2::VarBindValue::Value(rasn_smi::v2::ObjectSyntax::ApplicationWide(value)));
+  match value {
+    ObjectValue::Integer(value) => simple(rasn_smi::v2::SimpleSyntax::Integer(value.clone())),
+    ObjectValue::Integer32(value) => simple(rasn_smi::v2::SimpleSyntax::Integer((*value).into())),
+    ObjectValue::OctetString(value) => simple(rasn_smi::v2::SimpleSyntax::String(value.clone())),
+    ObjectValue::ObjectIdentifier(value) => simple(rasn_smi::v2::SimpleSyntax::ObjectId(value.0.clone())),
+    // InetAddressIPv6, or InetAddressIPv6z with the zone index appended.
+    ObjectValue::Ipv6Address(address, zone) => {
+      let mut octets = address.octets().to_vec();
+      octets.extend(zone.iter().flat_map(|zone| zone.to_be_bytes()));
+      simple(rasn_smi::v2::SimpleSyntax::String(octets.into()))
+    },
+    ObjectValue::IpAddress(value) =>
+      application(rasn_smi::v2::ApplicationSyntax::Address(rasn_smi::v1::IpAddress(value.octets().into()))),
+    ObjectValue::Counter32(value) =>
+      application(rasn_smi::v2::ApplicationSyntax::Counter(rasn_smi::v1::Counter(*value))),
+    ObjectValue::Unsigned32(value) =>
+      application(rasn_smi::v2::ApplicationSyntax::Unsigned(rasn_smi::v1::Gauge(*value))),
+    ObjectValue::TimeTicks(value) =>
+      application(rasn_smi::v2::ApplicationSyntax::Ticks(rasn_smi::v1::TimeTicks(*value))),
+    // The contents of an Opaque are the BER encoding of the wrapped value.
+    ObjectValue::Opaque(value) => rasn::types::Any::new(value.clone()).to_opaque().ok()
+      .and_then(|opaque| application(rasn_smi::v2::ApplicationSyntax::Arbitrary(opaque))),
+    ObjectValue::Counter64(value) =>
+      application(rasn_smi::v2::ApplicationSyntax::BigCounter(rasn_smi::v2::Counter64(*value))),
+    ObjectValue::Float(value) => unconvert(&ObjectValue::Opaque([&OPAQUE_FLOAT[..], &[4], &value.to_be_bytes()].concat())),
+    ObjectValue::Double(value) => unconvert(&ObjectValue::Opaque([&OPAQUE_DOUBLE[..], &[8], &value.to_be_bytes()].concat())),
+    ObjectValue::Null | ObjectValue::NoSuchObject | ObjectValue::NoSuchInstance | ObjectValue::EndOfMibView => None,
+  }
+}
+
+fn convert(name: &rasn::types::ObjectIdentifier, value: &model::v2::VarBindValue) -> ObjectValue {
   match value {
     model::v3::VarBindValue::Value(rasn_smi::v2::ObjectSyntax::Simple(value)) =>
       match value {
@@ -221,15 +1197,82 @@ fn convert(value: &model::v2::VarBindValue) -> ObjectValue {
         rasn_smi::v2::ApplicationSyntax::Ticks(value) =>
           ObjectValue::TimeTicks(value.0),
         rasn_smi::v2::ApplicationSyntax::Arbitrary(value) =>
-          ObjectValue::Opaque(value.as_ref().to_vec()),
+          decode_opaque(name, value.as_ref()),
         rasn_smi::v2::ApplicationSyntax::BigCounter(value) =>
           ObjectValue::Counter64(value.0),
         rasn_smi::v2::ApplicationSyntax::Unsigned(value) =>
           ObjectValue::Unsigned32(value.0),
       },
-    model::v3::VarBindValue::Unspecified => todo!(),
-    model::v3::VarBindValue::NoSuchObject => todo!(),
-    model::v3::VarBindValue::NoSuchInstance => todo!(),
-    model::v3::VarBindValue::EndOfMibView => todo!(),
+    model::v3::VarBindValue::Unspecified => ObjectValue::Null,
+    model::v3::VarBindValue::NoSuchObject => ObjectValue::NoSuchObject,
+    model::v3::VarBindValue::NoSuchInstance => ObjectValue::NoSuchInstance,
+    model::v3::VarBindValue::EndOfMibView => ObjectValue::EndOfMibView,
 }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> std::result::Result<Vec<u32>, String> {
+    text.parse::<ObjectIdentifier>().map(|oid| oid.arcs().to_vec())
+  }
+
+  #[test]
+  fn parses_numeric_oids() {
+    assert_eq!(parse("1.3.6.1.2.1.1.5.0"), Ok(vec![1, 3, 6, 1, 2, 1, 1, 5, 0]));
+    assert_eq!(parse(".1.3.6.1"), Ok(vec![1, 3, 6, 1]));
+    assert_eq!(parse("2.999.1"), Ok(vec![2, 999, 1]));
+  }
+
+  #[test]
+  fn rejects_bad_arcs() {
+    assert!(parse("1.3.6.x").is_err());
+    assert!(parse("1.3..6").is_err());
+    assert!(parse("1.3.6.").is_err());
+    assert!(parse("").is_err());
+    assert!(parse("1.3.4294967296").is_err());
+  }
+
+  #[test]
+  fn rejects_impossible_oids() {
+    assert!(parse("1").is_err());
+    assert!(parse("3.1").is_err());
+    assert!(parse("1.40").is_err());
+    assert!(parse("0.39").is_ok());
+  }
+
+  #[test]
+  fn resolves_mib_names() {
+    crate::mib::install(crate::mib::load_dir(std::path::Path::new("/nonexistent")));
+    assert_eq!(parse("mib-2.1.5.0"), Ok(vec![1, 3, 6, 1, 2, 1, 1, 5, 0]));
+    assert_eq!(parse("SNMPv2-SMI::enterprises.318"), Ok(vec![1, 3, 6, 1, 4, 1, 318]));
+    assert!(parse("noSuchObjectName.1").is_err());
+    assert!(parse("enterprises.x").is_err());
+  }
+
+  #[test]
+  fn decodes_opaque_floats() {
+    let float = [&OPAQUE_FLOAT[..], &[4], &1.5f32.to_be_bytes()].concat();
+    assert!(matches!(decode_float(&float), Some(ObjectValue::Float(value)) if value == 1.5));
+    let double = [&OPAQUE_DOUBLE[..], &[8], &(-0.25f64).to_be_bytes()].concat();
+    assert!(matches!(decode_float(&double), Some(ObjectValue::Double(value)) if value == -0.25));
+  }
+
+  #[test]
+  fn leaves_other_opaques_alone() {
+    // A double's length with a float's tag, a short float and a plain Opaque.
+    assert!(decode_float(&[&OPAQUE_FLOAT[..], &[8], &1.5f64.to_be_bytes()].concat()).is_none());
+    assert!(decode_float(&[0x9f, 0x78, 4, 0x3f, 0xc0]).is_none());
+    assert!(decode_float(&[0x04, 0x02, 0x41, 0x42]).is_none());
+  }
+
+  #[test]
+  fn encodes_floats_as_opaques() {
+    let oid = "1.3.6.1.4.1.2021.10.1.6.1".parse::<ObjectIdentifier>().unwrap();
+    let value = unconvert(&ObjectValue::Float(0.75)).unwrap();
+    assert!(matches!(convert(&oid.0, &value), ObjectValue::Float(value) if value == 0.75));
+    let value = unconvert(&ObjectValue::Double(1e100)).unwrap();
+    assert!(matches!(convert(&oid.0, &value), ObjectValue::Double(value) if value == 1e100));
+  }
+}
@@ -0,0 +1,269 @@
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use futures_util::{Stream, StreamExt};
+use rasn_snmp as model;
+
+use super::{AuthProtocol, Certificates, ObjectIdentifier, ObjectValue, OctetString, PrivacyProtocol, Result, Target, Timing, VariableBinding};
+
+// The community of clients not given one, and of agents the configuration
+// gives none. It is the collector's own rather than "public", which agents
+// are commonly set up to refuse.
+pub const DEFAULT_COMMUNITY: &str = "vitalumos";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Version {
+  V1,
+  #[default]
+  V2c,
+  V3,
+}
+
+// A session with one agent. Its address, credentials and timing are fixed
+// when it is built, so that requests only say what they ask for; requests
+// of all clients share one socket per address family.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SnmpClient {
+  target: Target,
+}
+
+#[derive(Clone, Debug)]
+pub struct Builder {
+  version: Version,
+  community: OctetString,
+  user: OctetString,
+  auth: Option<(AuthProtocol, OctetString)>,
+  privacy: Option<(PrivacyProtocol, OctetString)>,
+  context: OctetString,
+  context_engine: Option<OctetString>,
+  tls: Option<Certificates>,
+  timing: Timing,
+}
+
+impl Default for Builder {
+
+  fn default() -> Self {
+    Builder {
+      version: Version::default(),
+      community: DEFAULT_COMMUNITY.into(),
+      user: OctetString::new(),
+      auth: None,
+      privacy: None,
+      context: OctetString::new(),
+      context_engine: None,
+      tls: None,
+      timing: Timing::default(),
+    }
+  }
+}
+
+impl Builder {
+
+  pub fn version(mut self, version: Version) -> Self {
+    self.version = version;
+    self
+  }
+
+  // The community of SNMPv1 and v2c.
+  pub fn community(mut self, community: impl Into<OctetString>) -> Self {
+    self.community = community.into();
+    self
+  }
+
+  // The USM user of SNMPv3, with its passwords, and the context asked.
+  pub fn user(mut self, user: impl Into<OctetString>) -> Self {
+    self.user = user.into();
+    self
+  }
+
+  pub fn auth(mut self, protocol: AuthProtocol, password: impl Into<OctetString>) -> Self {
+    self.auth = Some((protocol, password.into()));
+    self
+  }
+
+  pub fn privacy(mut self, protocol: PrivacyProtocol, password: impl Into<OctetString>) -> Self {
+    self.privacy = Some((protocol, password.into()));
+    self
+  }
+
+  pub fn context(mut self, context: impl Into<OctetString>) -> Self {
+    self.context = context.into();
+    self
+  }
+
+  // The engine the context belongs to, when not the agent's own.
+  pub fn context_engine(mut self, engine_id: impl Into<OctetString>) -> Self {
+    self.context_engine = Some(engine_id.into());
+    self
+  }
+
+  // SNMPv3 over TLS, whatever the version and USM credentials say.
+  pub fn tls(mut self, certificates: Certificates) -> Self {
+    self.tls = Some(certificates);
+    self
+  }
+
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.timing.timeout = timeout;
+    self
+  }
+
+  pub fn retries(mut self, retries: u32) -> Self {
+    self.timing.retries = retries;
+    self
+  }
+
+  // At most that many requests awaiting the agent's answers at once.
+  pub fn outstanding(mut self, limit: usize) -> Self {
+    self.timing.outstanding = Some(limit);
+    self
+  }
+
+  pub fn build(self, address: SocketAddr) -> SnmpClient {
+    let Builder { version, community, user, auth, privacy, context, context_engine, tls, timing } = self;
+    let target = match (version, tls) {
+      (_, Some(certificates)) => Target::Tls { address, certificates, context, context_engine, timing },
+      (Version::V1, None) => Target::CommunityV1 { address, community, timing },
+      (Version::V2c, None) => Target::Community { address, community, timing },
+      (Version::V3, None) => Target::Usm { address, user, auth, privacy, context, context_engine, timing },
+    };
+    SnmpClient { target }
+  }
+}
+
+impl From<Target> for SnmpClient {
+
+  fn from(target: Target) -> Self {
+    SnmpClient { target }
+  }
+}
+
+impl SnmpClient {
+
+  pub fn builder() -> Builder {
+    Builder::default()
+  }
+
+  pub fn target(&self) -> &Target {
+    &self.target
+  }
+
+  pub fn address(&self) -> &SocketAddr {
+    self.target.get_address()
+  }
+
+  pub async fn request(&self, data: model::v2::Pdus) -> Result<model::v2::Pdu> {
+    super::request(&self.target, data).await
+      .map_err(|error| error.context(self.address(), "Request", &[]))
+  }
+
+  pub async fn get(&self, oids: &[ObjectIdentifier]) -> Result<Vec<VariableBinding>> {
+    super::get(&self.target, oids).await
+      .map_err(|error| error.context(self.address(), "Get", oids))
+  }
+
+  pub async fn get_each(&self, oids: &[ObjectIdentifier]) -> Vec<(ObjectIdentifier, Result<VariableBinding>)> {
+    super::get_each(&self.target, oids).await
+      .into_iter()
+      .map(|(oid, value)| {
+        let value = value.map_err(|error| error.context(self.address(), "Get", std::slice::from_ref(&oid)));
+        (oid, value)
+      })
+      .collect()
+  }
+
+  pub async fn get_next(&self, oids: &[ObjectIdentifier]) -> Result<Vec<VariableBinding>> {
+    super::get_next(&self.target, oids).await
+      .map_err(|error| error.context(self.address(), "GetNext", oids))
+  }
+
+  // The subtree under `oid`, or its first `max_bindings`, in as many
+  // GetBulk requests as that takes.
+  pub async fn get_bulk(&self, oid: &ObjectIdentifier, max_repetitions: u32, max_bindings: usize) -> Result<Vec<VariableBinding>> {
+    self.get_bulk_after(oid, oid, max_repetitions, max_bindings).await
+  }
+
+  // Like `get_bulk`, going on past `after` rather than from the start.
+  pub async fn get_bulk_after(
+    &self,
+    root: &ObjectIdentifier,
+    after: &ObjectIdentifier,
+    max_repetitions: u32,
+    max_bindings: usize,
+  ) -> Result<Vec<VariableBinding>> {
+    super::get_bulk(&self.target, root, after, max_repetitions, max_bindings).await
+      .map_err(|error| error.context(self.address(), "GetBulk", std::slice::from_ref(after)))
+  }
+
+  pub async fn get_bulk_mixed(
+    &self,
+    scalars: &[ObjectIdentifier],
+    columns: &[ObjectIdentifier],
+    max_repetitions: u32,
+  ) -> Result<Vec<VariableBinding>> {
+    self.get_bulk_mixed_after(scalars, columns, columns, max_repetitions).await
+  }
+
+  // Like `get_bulk_mixed`, the rows of each column going on past the OID at
+  // the same position in `after` rather than from the start.
+  pub async fn get_bulk_mixed_after(
+    &self,
+    scalars: &[ObjectIdentifier],
+    columns: &[ObjectIdentifier],
+    after: &[ObjectIdentifier],
+    max_repetitions: u32,
+  ) -> Result<Vec<VariableBinding>> {
+    super::get_bulk_mixed(&self.target, scalars, columns, after, max_repetitions).await
+      .map_err(|error| error.context(self.address(), "GetBulk", &[scalars, after].concat()))
+  }
+
+  pub async fn walk(&self, root: &ObjectIdentifier) -> Result<Vec<VariableBinding>> {
+    super::walk(&self.target, root, root, usize::MAX, super::MAX_REPETITIONS, &|_walked| {}).await
+      .map_err(|error| error.context(self.address(), "Walk", std::slice::from_ref(root)))
+  }
+
+  // A walk telling `progress` the bindings walked so far after each answer.
+  pub async fn walk_reporting(
+    &self,
+    root: &ObjectIdentifier,
+    progress: &(dyn Fn(&[VariableBinding]) + Sync),
+  ) -> Result<Vec<VariableBinding>> {
+    super::walk(&self.target, root, root, usize::MAX, super::MAX_REPETITIONS, progress).await
+      .map_err(|error| error.context(self.address(), "Walk", std::slice::from_ref(root)))
+  }
+
+  // Up to `limit` bindings of a walk of `root`, those past `after`.
+  pub async fn walk_page(&self, root: &ObjectIdentifier, after: &ObjectIdentifier, limit: usize) -> Result<Vec<VariableBinding>> {
+    super::walk(&self.target, root, after, limit, super::MAX_REPETITIONS, &|_walked| {}).await
+      .map_err(|error| error.context(self.address(), "Walk", std::slice::from_ref(after)))
+  }
+
+  // A walk yielding its bindings as the agent answers; see `walk_stream`.
+  pub fn walk_stream(&self, root: &ObjectIdentifier) -> impl Stream<Item = Result<VariableBinding>> + Send + 'static {
+    self.walk_stream_after(root, root)
+  }
+
+  pub fn walk_stream_after(
+    &self,
+    root: &ObjectIdentifier,
+    after: &ObjectIdentifier,
+  ) -> impl Stream<Item = Result<VariableBinding>> + Send + 'static {
+    let (address, root) = (*self.address(), root.clone());
+    super::walk_stream_after(&self.target, &root, after)
+      .map(move |binding| binding.map_err(|error| error.context(&address, "Walk", std::slice::from_ref(&root))))
+  }
+
+  pub async fn get_table(
+    &self,
+    table: &ObjectIdentifier,
+    columns: &[u32],
+  ) -> Result<HashMap<Vec<u32>, HashMap<u32, ObjectValue>>> {
+    super::get_table(&self.target, table, columns).await
+      .map_err(|error| error.context(self.address(), "Table walk", std::slice::from_ref(table)))
+  }
+
+  pub async fn set(&self, bindings: Vec<VariableBinding>) -> Result<Vec<VariableBinding>> {
+    let oids = bindings.iter().map(|binding| binding.object_id.clone()).collect::<Vec<_>>();
+    super::set(&self.target, bindings).await
+      .map_err(|error| error.context(self.address(), "Set", &oids))
+  }
+}
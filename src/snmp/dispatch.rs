@@ -0,0 +1,109 @@
+use std::{collections::HashMap, net::{IpAddr, SocketAddr}, sync::{Arc, Mutex}};
+
+use rasn_snmp as model;
+use tokio::{net::UdpSocket, sync::mpsc};
+
+use super::{rate, Error, Result};
+use crate::stats;
+
+// One socket per address family for all requests to agents, IPv4 first.
+// A task reads each and hands answers to the request waiting for them, by
+// the agent's address and the request's ID.
+static DISPATCHERS: Mutex<[Option<Arc<Dispatcher>>; 2]> = Mutex::new([None, None]);
+
+type Key = (IpAddr, u16, i32);
+
+struct Dispatcher {
+  socket: UdpSocket,
+  pending: Mutex<HashMap<Key, mpsc::UnboundedSender<Vec<u8>>>>,
+}
+
+// A request awaiting its answers; there may be several, as requests are
+// sent again when they go unanswered.
+pub(super) struct Exchange {
+  dispatcher: Arc<Dispatcher>,
+  key: Key,
+  answers: mpsc::UnboundedReceiver<Vec<u8>>,
+  _awaiting: stats::Held,
+}
+
+impl Exchange {
+
+  // Expects answers from `address` to the request with ID `id`, which is a
+  // msgID for SNMPv3 and a request-id for earlier versions.
+  pub(super) fn new(address: &SocketAddr, id: i32) -> Result<Exchange> {
+    let dispatcher = dispatcher(address)?;
+    let key = (address.ip(), address.port(), id);
+    let (sender, answers) = mpsc::unbounded_channel();
+    dispatcher.pending.lock().unwrap().insert(key, sender);
+    Ok(Exchange { dispatcher, key, answers, _awaiting: stats::hold(&stats::STATS.snmp_awaiting) })
+  }
+
+  pub(super) async fn send(&self, message: &[u8], address: &SocketAddr) -> Result<()> {
+    rate::take().await;
+    stats::count(&stats::STATS.snmp_requests);
+    self.dispatcher.socket.send_to(message, address) // TODO: check sent bytes count
+      .await
+      .map(|_sent| ())
+      .map_err(Error::io)
+  }
+
+  pub(super) async fn receive(&mut self) -> Option<Vec<u8>> {
+    self.answers.recv().await
+  }
+}
+
+impl Drop for Exchange {
+
+  fn drop(&mut self) {
+    self.dispatcher.pending.lock().unwrap().remove(&self.key);
+  }
+}
+
+// The socket of the agent's address family, so that IPv4 agents are reached
+// on hosts without dual-stack sockets and IPv6 ones keep their scope.
+fn dispatcher(address: &SocketAddr) -> Result<Arc<Dispatcher>> {
+  let (family, local) = match address {
+    SocketAddr::V4(_) => (0, "0.0.0.0:0"),
+    SocketAddr::V6(_) => (1, "[::]:0"),
+  };
+  let mut dispatchers = DISPATCHERS.lock().unwrap();
+  if let Some(dispatcher) = &dispatchers[family] {
+    return Ok(dispatcher.clone());
+  }
+  let socket = std::net::UdpSocket::bind(local)
+    .and_then(|socket| {
+      socket.set_nonblocking(true)?;
+      UdpSocket::from_std(socket)
+    })
+    .map_err(Error::io)?;
+  let dispatcher = Arc::new(Dispatcher { socket, pending: Mutex::default() });
+  tokio::spawn(dispatch(dispatcher.clone()));
+  dispatchers[family] = Some(dispatcher.clone());
+  Ok(dispatcher)
+}
+
+async fn dispatch(dispatcher: Arc<Dispatcher>) {
+  let mut buffer = vec![0; 65535];
+  loop {
+    let Ok((length, origin)) = dispatcher.socket.recv_from(&mut buffer).await else {
+      continue;
+    };
+    let datagram = &buffer[..length];
+    let Some(id) = answer_id(datagram) else {
+      stats::count(&stats::STATS.snmp_decode_errors);
+      continue;
+    };
+    if let Some(waiting) = dispatcher.pending.lock().unwrap().get(&(origin.ip(), origin.port(), id)) {
+      let _ = waiting.send(datagram.to_vec());
+    }
+  }
+}
+
+fn answer_id(datagram: &[u8]) -> Option<i32> {
+  if let Ok(message) = rasn::ber::decode::<model::v2c::Message<model::v2::Response>>(datagram) {
+    return Some(message.data.0.request_id);
+  }
+  let message = rasn::ber::decode::<model::v3::Message>(datagram).ok()?;
+  i32::try_from(&message.global_data.message_id).ok()
+}
@@ -0,0 +1,135 @@
+use std::{net::Ipv4Addr, time::SystemTime};
+
+use num_traits::ToPrimitive;
+use rasn_snmp as model;
+
+use super::{convert, decode_opaque, ObjectIdentifier, ObjectValue, OctetString, Timestamp, VariableBinding, Version, SYS_UP_TIME};
+
+const SNMP_TRAP_OID: [u32; 11] = [1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
+const SNMP_TRAP_ENTERPRISE: [u32; 11] = [1, 3, 6, 1, 6, 3, 1, 1, 4, 3, 0];
+const SNMP_TRAP_ADDRESS: [u32; 10] = [1, 3, 6, 1, 6, 3, 18, 1, 3, 0];
+// The generic traps of SNMPv1, coldStart to egpNeighborLoss, are numbered
+// one up under snmpTraps.
+const SNMP_TRAPS: [u32; 9] = [1, 3, 6, 1, 6, 3, 1, 1, 5];
+const ENTERPRISE_SPECIFIC: u32 = 6;
+
+// A trap or inform as an agent sent it. The bindings of an SNMPv1 trap are
+// those it would have had as an SNMPv2-Trap (RFC 3584): sysUpTime.0 and
+// snmpTrapOID.0 first, then the trap's own, then snmpTrapAddress.0 and
+// snmpTrapEnterprise.0.
+#[derive(Clone)]
+pub struct Notification {
+  pub version: Version,
+  pub community: OctetString,
+  pub bindings: Vec<VariableBinding>,
+  pub inform: bool,
+  // The Response an inform is to be answered with.
+  acknowledgement: Option<Vec<u8>>,
+}
+
+impl Notification {
+
+  // What the notification is, by the value of its snmpTrapOID.0.
+  pub fn trap_oid(&self) -> Option<&ObjectIdentifier> {
+    self.bindings.iter()
+      .find(|binding| binding.object_id.arcs() == SNMP_TRAP_OID)
+      .and_then(|binding| match &binding.value {
+        ObjectValue::ObjectIdentifier(oid) => Some(oid),
+        _ => None,
+      })
+  }
+
+  // The message to send back to the sender of an inform, which keeps
+  // sending it until answered; none for traps.
+  pub fn acknowledgement(&self) -> Option<&[u8]> {
+    self.acknowledgement.as_deref()
+  }
+}
+
+// Reads an SNMPv1 Trap-PDU, or an SNMPv2-Trap or InformRequest. Anything
+// else, SNMPv3 messages included, is not a notification here.
+pub fn decode_notification(datagram: &[u8]) -> Option<Notification> {
+  if let Ok(message) = rasn::ber::decode::<model::v1::Message<model::v1::Trap>>(datagram) {
+    return Some(translate(message));
+  }
+  let message = rasn::ber::decode::<model::v2c::Message<model::v2::Pdus>>(datagram).ok()?;
+  if message.version != 1.into() {
+    return None;
+  }
+  let (pdu, inform) = match message.data {
+    model::v2::Pdus::Trap(trap) => (trap.0, false),
+    model::v2::Pdus::InformRequest(inform) => (inform.0, true),
+    _ => return None,
+  };
+  // The response repeats the inform's request-id and bindings.
+  let acknowledgement = match inform {
+    true => Some(rasn::ber::encode(&model::v2c::Message {
+      version: 1.into(),
+      community: message.community.clone(),
+      data: model::v2::Response(model::v2::Pdu { error_status: 0, error_index: 0, ..pdu.clone() }),
+    }).ok()?),
+    false => None,
+  };
+  let bindings = &pdu.variable_bindings;
+  let timestamp = Timestamp::from_response(bindings);
+  Some(Notification {
+    version: Version::V2c,
+    community: message.community,
+    // NULL values are no values; agents should not send them in traps.
+    bindings: bindings.iter()
+      .filter(|binding| binding.value != model::v2::VarBindValue::Unspecified)
+      .map(|binding| VariableBinding {
+        object_id: ObjectIdentifier(binding.name.clone()),
+        value: convert(&binding.name, &binding.value),
+        timestamp,
+      })
+      .collect(),
+    inform,
+    acknowledgement,
+  })
+}
+
+fn translate(message: model::v1::Message<model::v1::Trap>) -> Notification {
+  let trap = message.data;
+  let timestamp = Timestamp { collected_at: SystemTime::now(), sys_up_time: Some(trap.time_stamp.0) };
+  let binding = |arcs: &[u32], value| VariableBinding { object_id: arcs.to_vec().into(), value, timestamp };
+  let enterprise = ObjectIdentifier(trap.enterprise);
+  let trap_oid = match trap.generic_trap.to_u32() {
+    Some(generic) if generic < ENTERPRISE_SPECIFIC => ObjectIdentifier::from(SNMP_TRAPS.to_vec()).child(&[generic + 1]),
+    _ => enterprise.child(&[0, trap.specific_trap.to_u32().unwrap_or_default()]),
+  };
+  let rasn_smi::v1::NetworkAddress::Internet(address) = trap.agent_addr;
+  let [o0, o1, o2, o3] = address.0.map(|octet| octet);
+  let mut bindings = vec![
+    binding(&SYS_UP_TIME, ObjectValue::TimeTicks(trap.time_stamp.0)),
+    binding(&SNMP_TRAP_OID, ObjectValue::ObjectIdentifier(trap_oid)),
+  ];
+  bindings.extend(trap.variable_bindings.iter().filter_map(|variable| {
+    let value = convert_v1(&variable.name, &variable.value)?;
+    Some(VariableBinding { object_id: ObjectIdentifier(variable.name.clone()), value, timestamp })
+  }));
+  bindings.push(binding(&SNMP_TRAP_ADDRESS, ObjectValue::IpAddress(Ipv4Addr::new(o0, o1, o2, o3))));
+  bindings.push(binding(&SNMP_TRAP_ENTERPRISE, ObjectValue::ObjectIdentifier(enterprise)));
+  Notification { version: Version::V1, community: message.community, bindings, inform: false, acknowledgement: None }
+}
+
+fn convert_v1(name: &rasn::types::ObjectIdentifier, value: &rasn_smi::v1::ObjectSyntax) -> Option<ObjectValue> {
+  Some(match value {
+    rasn_smi::v1::ObjectSyntax::Simple(value) => match value {
+      rasn_smi::v1::SimpleSyntax::Number(value) => ObjectValue::Integer(value.clone()),
+      rasn_smi::v1::SimpleSyntax::String(value) => ObjectValue::OctetString(value.clone()),
+      rasn_smi::v1::SimpleSyntax::Object(value) => ObjectValue::ObjectIdentifier(ObjectIdentifier(value.clone())),
+      rasn_smi::v1::SimpleSyntax::Empty => return None,
+    },
+    rasn_smi::v1::ObjectSyntax::ApplicationWide(value) => match value {
+      rasn_smi::v1::ApplicationSyntax::Address(rasn_smi::v1::NetworkAddress::Internet(value)) => {
+        let [o0, o1, o2, o3] = value.0.map(|octet| octet);
+        ObjectValue::IpAddress(Ipv4Addr::new(o0, o1, o2, o3))
+      },
+      rasn_smi::v1::ApplicationSyntax::Counter(value) => ObjectValue::Counter32(value.0),
+      rasn_smi::v1::ApplicationSyntax::Gauge(value) => ObjectValue::Unsigned32(value.0),
+      rasn_smi::v1::ApplicationSyntax::Ticks(value) => ObjectValue::TimeTicks(value.0),
+      rasn_smi::v1::ApplicationSyntax::Arbitrary(value) => decode_opaque(name, value.as_ref()),
+    },
+  })
+}
@@ -0,0 +1,51 @@
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::stats;
+
+// PDUs sent to agents per second across the process, when limited: a bucket
+// holding a second's worth of tokens, refilled as time passes, one taken for
+// every PDU sent. PDUs finding it empty wait their turn.
+static BUCKET: Mutex<Option<Bucket>> = Mutex::new(None);
+
+struct Bucket {
+  per_second: f64,
+  tokens: f64,
+  at: Instant,
+}
+
+// Limits PDUs to `per_second`, or lifts the limit. The bucket starts full.
+pub fn limit(per_second: Option<f64>) {
+  let mut bucket = BUCKET.lock().unwrap();
+  if bucket.as_ref().map(|bucket| bucket.per_second) == per_second {
+    return;
+  }
+  *bucket = per_second.map(|per_second| Bucket { per_second, tokens: burst(per_second), at: Instant::now() });
+}
+
+// At least one PDU may go at once however low the rate.
+fn burst(per_second: f64) -> f64 {
+  per_second.max(1.0)
+}
+
+// Waits until a PDU may be sent. Its token is taken at once, so that PDUs
+// waiting go in the order they came.
+pub(super) async fn take() {
+  let wait = {
+    let mut bucket = BUCKET.lock().unwrap();
+    let Some(bucket) = bucket.as_mut() else {
+      return;
+    };
+    let now = Instant::now();
+    let refilled = (now - bucket.at).as_secs_f64() * bucket.per_second;
+    bucket.tokens = (bucket.tokens + refilled).min(burst(bucket.per_second)) - 1.0;
+    bucket.at = now;
+    if bucket.tokens >= 0.0 {
+      return;
+    }
+    Duration::from_secs_f64(-bucket.tokens / bucket.per_second)
+  };
+  let _queued = stats::hold(&stats::STATS.snmp_queued);
+  tokio::time::sleep(wait).await;
+}
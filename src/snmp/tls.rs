@@ -0,0 +1,532 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use rasn_snmp as model;
+use serde::Deserialize;
+
+use super::{OctetString, Result, Timing};
+
+// The PEM files of the client's certificate chain and key, and of the
+// authorities the agent's certificate is checked against. That certificate
+// must name `server_name`, or else the agent's address; the agent maps the
+// client's certificate to a security name of its own.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Certificates {
+  pub authorities: PathBuf,
+  pub certificate: PathBuf,
+  pub key: PathBuf,
+  pub server_name: Option<String>,
+  pub transport: TlsTransport,
+}
+
+// The two transports of RFC 6353: TLS over TCP, and DTLS over UDP.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsTransport {
+  #[default]
+  Tcp,
+  Udp,
+}
+
+// Sends `data` to the agent as SNMPv3 over TLS or DTLS (RFC 6353) and
+// returns the PDU of its response. The connection, or DTLS association, is
+// kept for the target's next requests. The context is that of whichever
+// engine answers unless `context_engine` names one.
+pub(super) async fn request(
+  address: &SocketAddr,
+  certificates: &Certificates,
+  context: &OctetString,
+  context_engine: Option<&OctetString>,
+  timing: &Timing,
+  data: model::v2::Pdus,
+) -> Result<model::v2::Pdu> {
+  transport::request(address, certificates, context, context_engine, timing, data).await
+}
+
+#[cfg(feature = "tls")]
+mod transport {
+  use std::{collections::{hash_map::Entry, HashMap}, fs::File, io::{self, BufReader}, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}, path::Path, pin::Pin, sync::{Arc, Mutex, OnceLock}, task::{Context, Poll}};
+
+  use openssl::ssl::{SslConnector, SslFiletype, SslMethod, SslOptions};
+  use rasn_snmp as model;
+  use tokio::{io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf}, net::{TcpStream, UdpSocket}, time::Instant};
+  use tokio_openssl::SslStream;
+  use tokio_rustls::{client::TlsStream, rustls, TlsConnector};
+
+  use super::{Certificates, TlsTransport};
+  use crate::{snmp::{rate, usm::next_id, Error, OctetString, Result, Timing, DEADLINE}, stats};
+
+  const TSM: u32 = 4;
+  // authPriv and reportable: TLS both authenticates and encrypts.
+  const FLAGS: u8 = 0x07;
+  pub(super) const MAX_SIZE: u32 = 65507;
+  // The contextEngineID standing for the engine that answers (RFC 5343), so
+  // that no discovery is needed.
+  const LOCAL_ENGINE: [u8; 5] = [0x80, 0x00, 0x00, 0x00, 0x06];
+  // Connections kept open per target; concurrent requests beyond these get
+  // connections of their own, closed once answered.
+  const IDLE: usize = 4;
+  // DTLS records are kept within an Ethernet frame, as path MTU discovery
+  // does not reach through a connected UDP socket.
+  pub(super) const MTU: u32 = 1400;
+
+  type Key = (SocketAddr, Certificates);
+
+  // The TLS settings of a target, which keep its sessions for resumption,
+  // and its open connections, or DTLS associations, not in use.
+  struct Session<C, S> {
+    config: C,
+    idle: Vec<S>,
+  }
+
+  type Sessions<C, S> = Mutex<HashMap<Key, Session<C, S>>>;
+
+  fn streams() -> &'static Sessions<Arc<rustls::ClientConfig>, TlsStream<TcpStream>> {
+    static STREAMS: OnceLock<Sessions<Arc<rustls::ClientConfig>, TlsStream<TcpStream>>> = OnceLock::new();
+    STREAMS.get_or_init(Default::default)
+  }
+
+  fn associations() -> &'static Sessions<SslConnector, SslStream<Datagrams>> {
+    static ASSOCIATIONS: OnceLock<Sessions<SslConnector, SslStream<Datagrams>>> = OnceLock::new();
+    ASSOCIATIONS.get_or_init(Default::default)
+  }
+
+  // The settings of the target behind `key` and one of its idle connections.
+  fn check_out<C: Clone, S>(sessions: &Sessions<C, S>, key: &Key, config: impl FnOnce() -> Result<C>) -> Result<(C, Option<S>)> {
+    let mut sessions = sessions.lock().unwrap();
+    let session = match sessions.entry(key.clone()) {
+      Entry::Occupied(entry) => entry.into_mut(),
+      Entry::Vacant(entry) => entry.insert(Session { config: config()?, idle: Vec::new() }),
+    };
+    Ok((session.config.clone(), session.idle.pop()))
+  }
+
+  fn check_in<C, S>(sessions: &Sessions<C, S>, key: &Key, stream: S) {
+    if let Some(session) = sessions.lock().unwrap().get_mut(key) {
+      if session.idle.len() < IDLE {
+        session.idle.push(stream);
+      }
+    }
+  }
+
+  pub(super) async fn request(
+    address: &SocketAddr,
+    certificates: &Certificates,
+    context: &OctetString,
+    context_engine: Option<&OctetString>,
+    timing: &Timing,
+    data: model::v2::Pdus,
+  ) -> Result<model::v2::Pdu> {
+    let key = (*address, certificates.clone());
+    let message_id = next_id();
+    let message = encode(message_id, context, context_engine, data)?;
+    // The request waits as long as all of its tries would over plain UDP,
+    // whether sent again over DTLS or not at all over TCP.
+    let wait = (0..=timing.retries).map(|retry| timing.timeout * 2u32.saturating_pow(retry)).sum();
+    let until = Instant::now() + wait;
+    let until = DEADLINE.try_with(|deadline| *deadline.min(&until)).unwrap_or(until);
+    let _awaiting = stats::hold(&stats::STATS.snmp_awaiting);
+    let response = match certificates.transport {
+      TlsTransport::Tcp => tokio::time::timeout_at(until, over_tcp(&key, &message, message_id)).await,
+      TlsTransport::Udp => tokio::time::timeout_at(until, over_udp(&key, &message, message_id, timing)).await,
+    };
+    response.map_err(|_elapsed| {
+      stats::count(&stats::STATS.snmp_timeouts);
+      Error::Timeout()
+    })?
+  }
+
+  async fn over_tcp(key: &Key, message: &[u8], message_id: i32) -> Result<model::v2::Pdu> {
+    let (address, certificates) = key;
+    let (config, mut reused) = check_out(streams(), key, || client_config(certificates))?;
+    loop {
+      // A kept connection may have been closed by the agent meanwhile; a
+      // new one is tried then.
+      let fresh = reused.is_none();
+      let mut stream = match reused.take() {
+        Some(stream) => stream,
+        None => connect(address, certificates, config.clone()).await?,
+      };
+      rate::take().await;
+      stats::count(&stats::STATS.snmp_requests);
+      match exchange(&mut stream, message, message_id).await {
+        Ok(response) => {
+          check_in(streams(), key, stream);
+          return Ok(response);
+        },
+        Err(error) if fresh => return Err(error),
+        Err(_) => continue,
+      }
+    }
+  }
+
+  async fn over_udp(key: &Key, message: &[u8], message_id: i32, timing: &Timing) -> Result<model::v2::Pdu> {
+    let (address, certificates) = key;
+    let (connector, mut reused) = check_out(associations(), key, || connector(certificates))?;
+    loop {
+      // A kept association the agent has since forgotten goes unanswered
+      // rather than closed; it gets one try before a new one is made, which
+      // gets all of the target's.
+      let fresh = reused.is_none();
+      let (mut stream, tries) = match reused.take() {
+        Some(stream) => (stream, 1),
+        None => (associate(address, certificates, &connector).await?, timing.retries + 1),
+      };
+      match exchange_datagrams(&mut stream, message, message_id, timing, tries).await {
+        Ok(response) => {
+          check_in(associations(), key, stream);
+          return Ok(response);
+        },
+        Err(error) if fresh => return Err(error),
+        Err(_) => continue,
+      }
+    }
+  }
+
+  fn client_config(certificates: &Certificates) -> Result<Arc<rustls::ClientConfig>> {
+    let pem = |path: &Path| File::open(path).map(BufReader::new).map_err(|_io_error| Error::Security());
+    let mut authorities = rustls::RootCertStore::empty();
+    for certificate in rustls_pemfile::certs(&mut pem(&certificates.authorities)?).map_err(|_io_error| Error::Security())? {
+      authorities.add(&rustls::Certificate(certificate)).map_err(|_tls_error| Error::Security())?;
+    }
+    let chain = rustls_pemfile::certs(&mut pem(&certificates.certificate)?)
+      .map_err(|_io_error| Error::Security())?
+      .into_iter()
+      .map(rustls::Certificate)
+      .collect();
+    let mut keys = pem(&certificates.key)?;
+    let key = std::iter::from_fn(|| rustls_pemfile::read_one(&mut keys).transpose())
+      .find_map(|item| match item {
+        Ok(rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key)) => Some(key),
+        _ => None,
+      })
+      .ok_or(Error::Security())?;
+    let config = rustls::ClientConfig::builder()
+      .with_safe_defaults()
+      .with_root_certificates(authorities)
+      .with_client_auth_cert(chain, rustls::PrivateKey(key))
+      .map_err(|_tls_error| Error::Security())?;
+    Ok(Arc::new(config))
+  }
+
+  async fn connect(
+    address: &SocketAddr,
+    certificates: &Certificates,
+    config: Arc<rustls::ClientConfig>,
+  ) -> Result<TlsStream<TcpStream>> {
+    let name = match &certificates.server_name {
+      Some(name) => rustls::ServerName::try_from(name.as_str()).map_err(|_name_error| Error::Security())?,
+      None => rustls::ServerName::IpAddress(address.ip()),
+    };
+    let stream = TcpStream::connect(address).await.map_err(Error::io)?;
+    TlsConnector::from(config).connect(name, stream).await.map_err(|_io_error| Error::Security())
+  }
+
+  // rustls has no DTLS, so associations come from OpenSSL, set up from the
+  // same files.
+  fn connector(certificates: &Certificates) -> Result<SslConnector> {
+    let mut builder = SslConnector::builder(SslMethod::dtls()).map_err(|_ssl_error| Error::Security())?;
+    builder.set_ca_file(&certificates.authorities).map_err(|_ssl_error| Error::Security())?;
+    builder.set_certificate_chain_file(&certificates.certificate).map_err(|_ssl_error| Error::Security())?;
+    builder.set_private_key_file(&certificates.key, SslFiletype::PEM).map_err(|_ssl_error| Error::Security())?;
+    builder.set_options(SslOptions::NO_QUERY_MTU);
+    Ok(builder.build())
+  }
+
+  async fn associate(address: &SocketAddr, certificates: &Certificates, connector: &SslConnector) -> Result<SslStream<Datagrams>> {
+    let name = certificates.server_name.clone().unwrap_or_else(|| address.ip().to_string());
+    let mut ssl = connector
+      .configure()
+      .and_then(|configuration| configuration.into_ssl(&name))
+      .map_err(|_ssl_error| Error::Security())?;
+    ssl.set_mtu(MTU).map_err(|_ssl_error| Error::Security())?;
+    let local = match address.ip() {
+      IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+      IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((local, 0)).await.map_err(Error::io)?;
+    socket.connect(address).await.map_err(Error::io)?;
+    let mut stream = SslStream::new(ssl, Datagrams(socket)).map_err(|_ssl_error| Error::Security())?;
+    Pin::new(&mut stream).connect().await.map_err(|_ssl_error| Error::Security())?;
+    Ok(stream)
+  }
+
+  // A connected UDP socket as the stream OpenSSL reads and writes: a read
+  // takes one datagram and a write sends one, each a whole DTLS record.
+  pub(super) struct Datagrams(pub(super) UdpSocket);
+
+  impl AsyncRead for Datagrams {
+    fn poll_read(self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+      self.0.poll_recv(context, buffer)
+    }
+  }
+
+  impl AsyncWrite for Datagrams {
+    fn poll_write(self: Pin<&mut Self>, context: &mut Context<'_>, buffer: &[u8]) -> Poll<io::Result<usize>> {
+      self.0.poll_send(context, buffer)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _context: &mut Context<'_>) -> Poll<io::Result<()>> {
+      Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _context: &mut Context<'_>) -> Poll<io::Result<()>> {
+      Poll::Ready(Ok(()))
+    }
+  }
+
+  // Security comes from the transport, so the message carries no security
+  // parameters and its scoped PDU goes in the clear.
+  fn encode(message_id: i32, context: &OctetString, context_engine: Option<&OctetString>, data: model::v2::Pdus) -> Result<Vec<u8>> {
+    let message = model::v3::Message {
+      version: 3.into(),
+      global_data: model::v3::HeaderData {
+        message_id: message_id.into(),
+        max_size: MAX_SIZE.into(),
+        flags: vec![FLAGS].into(),
+        security_model: TSM.into(),
+      },
+      security_parameters: OctetString::new(),
+      scoped_data: model::v3::ScopedPduData::CleartextPdu(model::v3::ScopedPdu {
+        engine_id: context_engine.cloned().unwrap_or_else(|| LOCAL_ENGINE.to_vec().into()),
+        name: context.clone(),
+        data,
+      }),
+    };
+    rasn::ber::encode(&message).map_err(Error::codec)
+  }
+
+  async fn exchange(stream: &mut TlsStream<TcpStream>, message: &[u8], message_id: i32) -> Result<model::v2::Pdu> {
+    stream.write_all(message).await.map_err(Error::io)?;
+    loop {
+      if let Some(response) = response(&read_message(stream).await?, message_id)? {
+        return Ok(response);
+      }
+    }
+  }
+
+  // Over DTLS a request may be lost as over plain UDP, and is sent again
+  // after the target's timeout, doubled each time.
+  async fn exchange_datagrams(
+    stream: &mut SslStream<Datagrams>,
+    message: &[u8],
+    message_id: i32,
+    timing: &Timing,
+    tries: u32,
+  ) -> Result<model::v2::Pdu> {
+    let mut timeout = timing.timeout;
+    for _ in 0..tries {
+      rate::take().await;
+      stats::count(&stats::STATS.snmp_requests);
+      stream.write_all(message).await.map_err(Error::io)?;
+      let answer = async {
+        let mut datagram = vec![0; MAX_SIZE as usize];
+        loop {
+          let length = stream.read(&mut datagram).await.map_err(Error::io)?;
+          if length == 0 {
+            return Err(Error::Connection(None));
+          }
+          if let Some(response) = response(&datagram[..length], message_id)? {
+            return Ok(response);
+          }
+        }
+      };
+      match tokio::time::timeout(timeout, answer).await {
+        Ok(answer) => return answer,
+        Err(_elapsed) => timeout *= 2,
+      }
+    }
+    Err(Error::Timeout())
+  }
+
+  // The PDU of `message` if it answers `message_id`, or None when it
+  // answers an earlier request.
+  fn response(message: &[u8], message_id: i32) -> Result<Option<model::v2::Pdu>> {
+    let message = rasn::ber::decode::<model::v3::Message>(message).map_err(|error| {
+      stats::count(&stats::STATS.snmp_decode_errors);
+      Error::malformed(error)
+    })?;
+    if message.global_data.message_id != message_id.into() {
+      return Ok(None);
+    }
+    match message.scoped_data {
+      model::v3::ScopedPduData::CleartextPdu(model::v3::ScopedPdu { data: model::v2::Pdus::Response(response), .. }) => Ok(Some(response.0)),
+      // A report here means the agent refused the certificate's security
+      // name or the context.
+      _ => Err(Error::Security()),
+    }
+  }
+
+  // Messages follow each other on the stream unframed (RFC 3430), so each
+  // is read by the length of its outer BER sequence.
+  pub(super) async fn read_message(stream: &mut (impl AsyncRead + Unpin)) -> Result<Vec<u8>> {
+    let mut message = vec![0; 2];
+    stream.read_exact(&mut message).await.map_err(Error::io)?;
+    let length = match message[1] {
+      short if short < 0x80 => short as usize,
+      long => {
+        let mut octets = vec![0; (long & 0x7f) as usize];
+        if octets.is_empty() || octets.len() > 4 {
+          return Err(Error::Malformed(None));
+        }
+        stream.read_exact(&mut octets).await.map_err(Error::io)?;
+        message.extend(&octets);
+        octets.iter().fold(0, |length, octet| length << 8 | *octet as usize)
+      },
+    };
+    if length > MAX_SIZE as usize {
+      return Err(Error::Malformed(None));
+    }
+    let header = message.len();
+    message.resize(header + length, 0);
+    stream.read_exact(&mut message[header..]).await.map_err(Error::io)?;
+    Ok(message)
+  }
+}
+
+#[cfg(not(feature = "tls"))]
+mod transport {
+  use std::net::SocketAddr;
+
+  use rasn_snmp as model;
+
+  use super::Certificates;
+  use crate::snmp::{Error, OctetString, Result, Timing};
+
+  pub(super) async fn request(
+    _address: &SocketAddr,
+    _certificates: &Certificates,
+    _context: &OctetString,
+    _context_engine: Option<&OctetString>,
+    _timing: &Timing,
+    _data: model::v2::Pdus,
+  ) -> Result<model::v2::Pdu> {
+    Err(Error::Unsupported())
+  }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+  use std::{path::Path, pin::Pin, time::Duration};
+
+  use openssl::{asn1::Asn1Time, bn::BigNum, ec::{EcGroup, EcKey}, hash::MessageDigest, nid::Nid, pkey::PKey, ssl::{Ssl, SslContext, SslFiletype, SslMethod, SslVerifyMode}, x509::{extension::SubjectAlternativeName, X509NameBuilder, X509}};
+  use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::UdpSocket};
+  use tokio_openssl::SslStream;
+
+  use super::*;
+  use super::transport::{read_message, Datagrams, MAX_SIZE, MTU};
+  use crate::snmp::Error;
+
+  // A self-signed certificate for 127.0.0.1 and its key, as PEM files.
+  fn certificate(directory: &Path, name: &str) -> (PathBuf, PathBuf) {
+    let key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()).unwrap();
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_text("CN", name).unwrap();
+    let subject = subject.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    let names = SubjectAlternativeName::new().ip("127.0.0.1").build(&builder.x509v3_context(None, None)).unwrap();
+    builder.append_extension(names).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    let (certificate_file, key_file) = (directory.join(format!("{}.crt", name)), directory.join(format!("{}.key", name)));
+    std::fs::write(&certificate_file, builder.build().to_pem().unwrap()).unwrap();
+    std::fs::write(&key_file, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    (certificate_file, key_file)
+  }
+
+  // An agent answering one request over DTLS with sysName "dtls", once it
+  // has checked the client's certificate against `authority`.
+  async fn agent(socket: UdpSocket, certificate: (PathBuf, PathBuf), authority: PathBuf) {
+    let mut datagram = [0; 1];
+    let (_, client) = socket.peek_from(&mut datagram).await.unwrap();
+    socket.connect(client).await.unwrap();
+    let mut context = SslContext::builder(SslMethod::dtls()).unwrap();
+    context.set_certificate_chain_file(&certificate.0).unwrap();
+    context.set_private_key_file(&certificate.1, SslFiletype::PEM).unwrap();
+    context.set_ca_file(&authority).unwrap();
+    context.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    let mut ssl = Ssl::new(&context.build()).unwrap();
+    ssl.set_mtu(MTU).unwrap();
+    let mut stream = SslStream::new(ssl, Datagrams(socket)).unwrap();
+    Pin::new(&mut stream).accept().await.unwrap();
+    let mut request = vec![0; MAX_SIZE as usize];
+    let length = stream.read(&mut request).await.unwrap();
+    let request = rasn::ber::decode::<model::v3::Message>(&request[..length]).unwrap();
+    let model::v3::ScopedPduData::CleartextPdu(scoped) = request.scoped_data else { panic!("an encrypted scoped PDU") };
+    let model::v2::Pdus::GetRequest(model::v2::GetRequest(pdu)) = scoped.data else { panic!("not a GetRequest") };
+    let value = rasn_smi::v2::ObjectSyntax::Simple(rasn_smi::v2::SimpleSyntax::String(b"dtls".to_vec().into()));
+    let response = model::v3::Message {
+      scoped_data: model::v3::ScopedPduData::CleartextPdu(model::v3::ScopedPdu {
+        data: model::v2::Pdus::Response(model::v2::Response(model::v2::Pdu {
+          variable_bindings: vec![model::v2::VarBind { name: pdu.variable_bindings[0].name.clone(), value: model::v2::VarBindValue::Value(value) }],
+          ..pdu
+        })),
+        ..scoped
+      }),
+      ..request
+    };
+    stream.write_all(&rasn::ber::encode(&response).unwrap()).await.unwrap();
+  }
+
+  #[tokio::test]
+  async fn dtls_round_trip() {
+    let directory = std::env::temp_dir().join(format!("snmp-dtls-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let server = certificate(&directory, "agent");
+    let (client_certificate, client_key) = certificate(&directory, "manager");
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let agent = tokio::spawn(agent(socket, server.clone(), client_certificate.clone()));
+    let certificates = Certificates {
+      authorities: server.0,
+      certificate: client_certificate,
+      key: client_key,
+      server_name: None,
+      transport: TlsTransport::Udp,
+    };
+    let sys_name = rasn::types::ObjectIdentifier::new(vec![1, 3, 6, 1, 2, 1, 1, 5, 0]).unwrap();
+    let data = model::v2::Pdus::GetRequest(model::v2::GetRequest(model::v2::Pdu {
+      request_id: 7,
+      error_status: model::v2::Pdu::ERROR_STATUS_NO_ERROR,
+      error_index: 0,
+      variable_bindings: vec![model::v2::VarBind { name: sys_name.clone(), value: model::v2::VarBindValue::Unspecified }],
+    }));
+    let timing = Timing { timeout: Duration::from_secs(2), retries: 0, outstanding: None };
+    let response = request(&address, &certificates, &OctetString::new(), None, &timing, data).await.unwrap();
+    agent.await.unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(response.request_id, 7);
+    assert_eq!(response.variable_bindings[0].name, sys_name);
+    let value = rasn_smi::v2::ObjectSyntax::Simple(rasn_smi::v2::SimpleSyntax::String(b"dtls".to_vec().into()));
+    assert_eq!(response.variable_bindings[0].value, model::v2::VarBindValue::Value(value));
+  }
+
+  #[tokio::test]
+  async fn reads_messages_by_their_length() {
+    let long = [vec![0x30, 0x81, 0x80], vec![0x04; 0x80]].concat();
+    let stream = [vec![0x30, 0x03, 0x02, 0x01, 0x03], long.clone(), vec![0x30, 0x00]].concat();
+    let mut stream = stream.as_slice();
+    assert_eq!(read_message(&mut stream).await.unwrap(), [0x30, 0x03, 0x02, 0x01, 0x03]);
+    assert_eq!(read_message(&mut stream).await.unwrap(), long);
+    assert_eq!(read_message(&mut stream).await.unwrap(), [0x30, 0x00]);
+    assert!(matches!(read_message(&mut stream).await, Err(Error::Connection(_))));
+  }
+
+  #[tokio::test]
+  async fn refuses_unframed_messages() {
+    // Indefinite, over four length octets, and over the maximum size.
+    for header in [&[0x30, 0x80][..], &[0x30, 0x85, 0, 0, 0, 0, 1], &[0x30, 0x83, 0x01, 0x00, 0x00]] {
+      assert!(matches!(read_message(&mut &header[..]).await, Err(Error::Malformed(None))));
+    }
+  }
+
+  #[tokio::test]
+  async fn stops_at_truncated_messages() {
+    assert!(matches!(read_message(&mut &[0x30, 0x05, 0x02, 0x01][..]).await, Err(Error::Connection(_))));
+  }
+}
@@ -0,0 +1,470 @@
+use std::{collections::HashMap, net::SocketAddr, sync::{atomic::{AtomicU64, Ordering}, Mutex, OnceLock}, time::{Instant, SystemTime, UNIX_EPOCH}};
+
+use aes::cipher::{block_padding::NoPadding, AsyncStreamCipher, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use rasn_snmp as model;
+use serde::Deserialize;
+use sha2::Digest;
+
+use super::{send_receive, CodecError, Error, OctetString, Result, Timing};
+
+const USM: u32 = 3;
+const AUTH_FLAG: u8 = 0x01;
+const PRIV_FLAG: u8 = 0x02;
+const REPORTABLE_FLAG: u8 = 0x04;
+const MAX_SIZE: u32 = 65507;
+// The reports of an agent that no longer knows the engine it is addressed
+// as, or finds the request's engine time too far from its own.
+const NOT_IN_TIME_WINDOWS: [u32; 11] = [1, 3, 6, 1, 6, 3, 15, 1, 1, 2, 0];
+const UNKNOWN_ENGINE_IDS: [u32; 11] = [1, 3, 6, 1, 6, 3, 15, 1, 1, 4, 0];
+
+// Authentication protocols of RFC 3414 and RFC 7860, by their usual names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthProtocol {
+  Md5,
+  Sha,
+  Sha224,
+  Sha256,
+  Sha384,
+  Sha512,
+}
+
+// DES-CBC (RFC 3414) and AES-128-CFB (RFC 3826).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyProtocol {
+  Des,
+  Aes,
+}
+
+// The credentials of a USM user. Passwords are turned into keys localized
+// to the agent's engine; privacy is only used along with authentication.
+// The context is that of the agent's engine unless `context_engine` names
+// another, as proxies serving several engines need.
+pub struct User<'a> {
+  pub name: &'a OctetString,
+  pub auth: Option<&'a (AuthProtocol, OctetString)>,
+  pub privacy: Option<&'a (PrivacyProtocol, OctetString)>,
+  pub context: &'a OctetString,
+  pub context_engine: Option<&'a OctetString>,
+}
+
+// The agent's engine as learned from its report to an empty request.
+#[derive(Clone)]
+struct Engine {
+  id: OctetString,
+  boots: u32,
+  time: u32,
+}
+
+// The engines of agents, kept between requests along with when they were
+// learned, since engine time runs on from there.
+fn engines() -> &'static Mutex<HashMap<SocketAddr, (Engine, Instant)>> {
+  static ENGINES: OnceLock<Mutex<HashMap<SocketAddr, (Engine, Instant)>>> = OnceLock::new();
+  ENGINES.get_or_init(Default::default)
+}
+
+// Passwords localized to engines, by engine ID, protocol and password, as
+// the megabyte hashed for each is too much to redo for every PDU. Dropped
+// whole once it holds `MAX_KEYS`, for credentials given with requests.
+type KeyCache = HashMap<(OctetString, AuthProtocol, OctetString), Vec<u8>>;
+const MAX_KEYS: usize = 1024;
+
+fn keys() -> &'static Mutex<KeyCache> {
+  static KEYS: OnceLock<Mutex<KeyCache>> = OnceLock::new();
+  KEYS.get_or_init(Default::default)
+}
+
+struct Keys {
+  auth: (AuthProtocol, Vec<u8>),
+  privacy: Option<(PrivacyProtocol, Vec<u8>)>,
+}
+
+// Request and message IDs and privacy salts, starting from the clock so
+// that restarts do not reuse the salts of the previous run.
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+impl AuthProtocol {
+
+  fn digest(self, data: &[u8]) -> Vec<u8> {
+    match self {
+      AuthProtocol::Md5 => md5::Md5::digest(data).to_vec(),
+      AuthProtocol::Sha => sha1::Sha1::digest(data).to_vec(),
+      AuthProtocol::Sha224 => sha2::Sha224::digest(data).to_vec(),
+      AuthProtocol::Sha256 => sha2::Sha256::digest(data).to_vec(),
+      AuthProtocol::Sha384 => sha2::Sha384::digest(data).to_vec(),
+      AuthProtocol::Sha512 => sha2::Sha512::digest(data).to_vec(),
+    }
+  }
+
+  fn hmac(self, key: &[u8], data: &[u8]) -> Vec<u8> {
+    fn mac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+      <M as Mac>::new_from_slice(key)
+        .expect("HMAC takes keys of any length")
+        .chain_update(data)
+        .finalize()
+        .into_bytes()
+        .to_vec()
+    }
+    let mut code = match self {
+      AuthProtocol::Md5 => mac::<Hmac<md5::Md5>>(key, data),
+      AuthProtocol::Sha => mac::<Hmac<sha1::Sha1>>(key, data),
+      AuthProtocol::Sha224 => mac::<Hmac<sha2::Sha224>>(key, data),
+      AuthProtocol::Sha256 => mac::<Hmac<sha2::Sha256>>(key, data),
+      AuthProtocol::Sha384 => mac::<Hmac<sha2::Sha384>>(key, data),
+      AuthProtocol::Sha512 => mac::<Hmac<sha2::Sha512>>(key, data),
+    };
+    code.truncate(self.mac_length());
+    code
+  }
+
+  fn mac_length(self) -> usize {
+    match self {
+      AuthProtocol::Md5 | AuthProtocol::Sha => 12,
+      AuthProtocol::Sha224 => 16,
+      AuthProtocol::Sha256 => 24,
+      AuthProtocol::Sha384 => 32,
+      AuthProtocol::Sha512 => 48,
+    }
+  }
+
+  // The password-to-key algorithm of RFC 3414 A.2: a megabyte of repeated
+  // password hashed, then hashed again around the engine ID.
+  fn localize(self, password: &[u8], engine_id: &[u8]) -> Vec<u8> {
+    let expanded = password.iter().cycle().take(1 << 20).copied().collect::<Vec<_>>();
+    let key = self.digest(&expanded);
+    self.digest(&[&key[..], engine_id, &key[..]].concat())
+  }
+}
+
+impl PrivacyProtocol {
+
+  // Returns the ciphertext and the salt sent as msgPrivacyParameters.
+  fn encrypt(self, key: &[u8], engine: &Engine, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let counter = next();
+    match self {
+      PrivacyProtocol::Des => {
+        let salt = [engine.boots.to_be_bytes(), (counter as u32).to_be_bytes()].concat();
+        let iv = des_iv(key, &salt);
+        let mut buffer = plaintext.to_vec();
+        buffer.resize(plaintext.len().div_ceil(8) * 8, 0);
+        let length = buffer.len();
+        cbc::Encryptor::<des::Des>::new(key[..8].into(), iv[..].into())
+          .encrypt_padded_mut::<NoPadding>(&mut buffer, length)
+          .map_err(|_padding_error| Error::Security())?;
+        Ok((buffer, salt))
+      },
+      PrivacyProtocol::Aes => {
+        let salt = counter.to_be_bytes().to_vec();
+        let mut buffer = plaintext.to_vec();
+        cfb_mode::Encryptor::<aes::Aes128>::new(key[..16].into(), aes_iv(engine, &salt)[..].into())
+          .encrypt(&mut buffer);
+        Ok((buffer, salt))
+      },
+    }
+  }
+
+  fn decrypt(self, key: &[u8], engine: &Engine, salt: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    if salt.len() != 8 {
+      return Err(Error::Security());
+    }
+    let mut buffer = ciphertext.to_vec();
+    match self {
+      PrivacyProtocol::Des => {
+        if !buffer.len().is_multiple_of(8) {
+          return Err(Error::Security());
+        }
+        cbc::Decryptor::<des::Des>::new(key[..8].into(), des_iv(key, salt)[..].into())
+          .decrypt_padded_mut::<NoPadding>(&mut buffer)
+          .map_err(|_padding_error| Error::Security())?;
+      },
+      PrivacyProtocol::Aes => {
+        cfb_mode::Decryptor::<aes::Aes128>::new(key[..16].into(), aes_iv(engine, salt)[..].into())
+          .decrypt(&mut buffer);
+      },
+    }
+    Ok(buffer)
+  }
+}
+
+// The last eight octets of the DES key are the pre-IV, XORed with the salt.
+fn des_iv(key: &[u8], salt: &[u8]) -> Vec<u8> {
+  key[8..16].iter().zip(salt).map(|(pre_iv, salt)| pre_iv ^ salt).collect()
+}
+
+fn aes_iv(engine: &Engine, salt: &[u8]) -> Vec<u8> {
+  [&engine.boots.to_be_bytes()[..], &engine.time.to_be_bytes(), salt].concat()
+}
+
+impl Keys {
+
+  fn new(user: &User<'_>, engine: &Engine) -> Option<Keys> {
+    let (protocol, password) = user.auth?;
+    let privacy = user.privacy.map(|(privacy, password)| (*privacy, localized(*protocol, password, &engine.id)));
+    Some(Keys { auth: (*protocol, localized(*protocol, password, &engine.id)), privacy })
+  }
+}
+
+fn localized(protocol: AuthProtocol, password: &OctetString, engine_id: &OctetString) -> Vec<u8> {
+  let key = (engine_id.clone(), protocol, password.clone());
+  if let Some(localized) = keys().lock().unwrap().get(&key) {
+    return localized.clone();
+  }
+  let localized = protocol.localize(password, engine_id);
+  let mut keys = keys().lock().unwrap();
+  if keys.len() >= MAX_KEYS {
+    keys.clear();
+  }
+  keys.insert(key, localized.clone());
+  localized
+}
+
+// Whether two MACs are the same, looking at every octet whatever differs,
+// so that timing tells nothing of how much of a forged one was right.
+fn same(code: &[u8], expected: &[u8]) -> bool {
+  code.len() == expected.len() && code.iter().zip(expected).fold(0, |differ, (a, b)| differ | (a ^ b)) == 0
+}
+
+// Sends `data` to the agent as the user and returns the PDU of its response.
+// The agent's engine is discovered first, with an unauthenticated request
+// answered by a report, unless known from earlier requests. Once the agent
+// has restarted or its clock has drifted, it reports the known engine out of
+// date. An authenticated notInTimeWindows report carries the agent's boots
+// and time, which are taken from it and the request sent once more, even
+// right after a discovery (RFC 3414 4); other reports of an out-of-date
+// engine have it discovered again.
+pub(super) async fn request(
+  address: &SocketAddr,
+  user: &User<'_>,
+  timing: &Timing,
+  data: model::v2::Pdus,
+) -> Result<model::v2::Pdu> {
+  let known = engines().lock().unwrap().get(address)
+    .map(|(engine, learned)| Engine { time: engine.time.saturating_add(learned.elapsed().as_secs() as u32), ..engine.clone() });
+  let (mut engine, mut discovered) = match known {
+    Some(engine) => (engine, false),
+    None => (discover(address, user, timing).await?, true),
+  };
+  let mut resynchronized = false;
+  loop {
+    let keys = Keys::new(user, &engine);
+    match exchange(address, user, &engine, keys.as_ref(), timing, data.clone()).await? {
+      (model::v2::Pdus::Response(response), parameters, _) => {
+        if let Ok(engine) = agent_engine(&parameters) {
+          engines().lock().unwrap().insert(*address, (engine, Instant::now()));
+        }
+        return Ok(response.0);
+      },
+      (model::v2::Pdus::Report(report), parameters, true) if !resynchronized && not_in_time_window(&report.0) => {
+        engine = agent_engine(&parameters)?;
+        engines().lock().unwrap().insert(*address, (engine.clone(), Instant::now()));
+        resynchronized = true;
+      },
+      (model::v2::Pdus::Report(report), _, _) if !discovered && out_of_date(&report.0) => {
+        engine = discover(address, user, timing).await?;
+        discovered = true;
+      },
+      // A report here means the agent refused the user, keys or timing.
+      _ => return Err(Error::Security()),
+    }
+  }
+}
+
+async fn discover(address: &SocketAddr, user: &User<'_>, timing: &Timing) -> Result<Engine> {
+  let discovery = model::v2::Pdus::GetRequest(model::v2::GetRequest(model::v2::Pdu {
+    request_id: next_id(),
+    error_status: model::v2::Pdu::ERROR_STATUS_NO_ERROR,
+    error_index: 0,
+    variable_bindings: Vec::new(),
+  }));
+  let empty = User { name: &OctetString::new(), auth: None, privacy: None, context: user.context, context_engine: None };
+  let unknown = Engine { id: OctetString::new(), boots: 0, time: 0 };
+  let (_, parameters, _) = exchange(address, &empty, &unknown, None, timing, discovery).await?;
+  let engine = agent_engine(&parameters)?;
+  engines().lock().unwrap().insert(*address, (engine.clone(), Instant::now()));
+  Ok(engine)
+}
+
+fn agent_engine(parameters: &model::v3::USMSecurityParameters) -> Result<Engine> {
+  Ok(Engine {
+    id: parameters.authoritative_engine_id.clone(),
+    boots: integer(&parameters.authoritative_engine_boots)?,
+    time: integer(&parameters.authoritative_engine_time)?,
+  })
+}
+
+fn not_in_time_window(report: &model::v2::Pdu) -> bool {
+  report.variable_bindings.first().is_some_and(|binding| binding.name.to_vec() == NOT_IN_TIME_WINDOWS)
+}
+
+fn out_of_date(report: &model::v2::Pdu) -> bool {
+  report.variable_bindings.first()
+    .is_some_and(|binding| binding.name.to_vec() == NOT_IN_TIME_WINDOWS || binding.name.to_vec() == UNKNOWN_ENGINE_IDS)
+}
+
+// The agent's answer, its security parameters, and whether it came
+// authenticated.
+async fn exchange(
+  address: &SocketAddr,
+  user: &User<'_>,
+  engine: &Engine,
+  keys: Option<&Keys>,
+  timing: &Timing,
+  data: model::v2::Pdus,
+) -> Result<(model::v2::Pdus, model::v3::USMSecurityParameters, bool)> {
+  let scoped = model::v3::ScopedPdu {
+    engine_id: user.context_engine.unwrap_or(&engine.id).clone(),
+    name: user.context.clone(),
+    data,
+  };
+  let mut flags = REPORTABLE_FLAG;
+  let (scoped_data, salt) = match keys.and_then(|keys| keys.privacy.as_ref()) {
+    Some((protocol, key)) => {
+      flags |= PRIV_FLAG;
+      let plaintext = rasn::ber::encode(&scoped).map_err(Error::codec)?;
+      let (ciphertext, salt) = protocol.encrypt(key, engine, &plaintext)?;
+      (model::v3::ScopedPduData::EncryptedPdu(ciphertext.into()), salt)
+    },
+    None => (model::v3::ScopedPduData::CleartextPdu(scoped), Vec::new()),
+  };
+  if keys.is_some() {
+    flags |= AUTH_FLAG;
+  }
+  let parameters = model::v3::USMSecurityParameters {
+    authoritative_engine_id: engine.id.clone(),
+    authoritative_engine_boots: engine.boots.into(),
+    authoritative_engine_time: engine.time.into(),
+    user_name: user.name.clone(),
+    authentication_parameters: vec![0; keys.map_or(0, |keys| keys.auth.0.mac_length())].into(),
+    privacy_parameters: salt.into(),
+  };
+  let security_parameters = rasn::ber::encode(&parameters).map_err(Error::codec)?;
+  let message_id = next_id();
+  let message = model::v3::Message {
+    version: 3.into(),
+    global_data: model::v3::HeaderData {
+      message_id: message_id.into(),
+      max_size: MAX_SIZE.into(),
+      flags: vec![flags].into(),
+      security_model: USM.into(),
+    },
+    security_parameters: security_parameters.into(),
+    scoped_data,
+  };
+  let mut request = rasn::ber::encode(&message).map_err(Error::codec)?;
+  if let Some(Keys { auth: (protocol, key), .. }) = keys {
+    let placeholder = mac_range(&request, &message.security_parameters, &parameters).ok_or(Error::Serialization(None))?;
+    let code = protocol.hmac(key, &request);
+    request[placeholder].copy_from_slice(&code);
+  }
+  let answer = |datagram: &[u8]| Some(datagram.to_vec());
+  let mut response = send_receive(address, message_id, &request, timing, answer).await?;
+  let message = rasn::ber::decode::<model::v3::Message>(&response).map_err(Error::malformed)?;
+  let parameters = message.decode_security_parameters::<model::v3::USMSecurityParameters>(rasn::codec::Codec::Ber)
+    .map_err(|error| Error::malformed(CodecError(format!("cannot decode security parameters: {}", error))))?;
+  let response_flags = message.global_data.flags.first().copied().unwrap_or_default();
+  if let Some(Keys { auth: (protocol, key), .. }) = keys {
+    // Reports of failed authentication come back unauthenticated.
+    if response_flags & AUTH_FLAG == 0 {
+      return match decode_scoped(&message.scoped_data) {
+        Some(model::v2::Pdus::Report(report)) => Ok((model::v2::Pdus::Report(report), parameters, false)),
+        _ => Err(Error::Security()),
+      };
+    }
+    let received = mac_range(&response, &message.security_parameters, &parameters).ok_or(Error::Security())?;
+    let code = response[received.clone()].to_vec();
+    response[received].fill(0);
+    if !same(&protocol.hmac(key, &response), &code) {
+      return Err(Error::Security());
+    }
+  }
+  let data = match (&message.scoped_data, keys.and_then(|keys| keys.privacy.as_ref())) {
+    (model::v3::ScopedPduData::EncryptedPdu(ciphertext), Some((protocol, key))) => {
+      let agent = agent_engine(&parameters)?;
+      let plaintext = protocol.decrypt(key, &agent, &parameters.privacy_parameters, ciphertext)?;
+      rasn::ber::decode::<model::v3::ScopedPdu>(&plaintext)
+        .map(|scoped| scoped.data)
+        .map_err(|_decode_error| Error::Security())?
+    },
+    (scoped_data, _) => decode_scoped(scoped_data).ok_or(Error::Security())?,
+  };
+  Ok((data, parameters, keys.is_some()))
+}
+
+fn decode_scoped(scoped_data: &model::v3::ScopedPduData) -> Option<model::v2::Pdus> {
+  match scoped_data {
+    model::v3::ScopedPduData::CleartextPdu(scoped) => Some(scoped.data.clone()),
+    model::v3::ScopedPduData::EncryptedPdu(_) => None,
+  }
+}
+
+// Where msgAuthenticationParameters sit in an encoded message: inside the
+// encoded security parameters, just before the privacy parameters, which
+// certainly are shorter than 128 octets.
+fn mac_range(
+  message: &[u8],
+  security_parameters: &[u8],
+  parameters: &model::v3::USMSecurityParameters,
+) -> Option<std::ops::Range<usize>> {
+  let start = message.windows(security_parameters.len()).position(|window| window == security_parameters)?;
+  let privacy = 2 + parameters.privacy_parameters.len();
+  let end = start + security_parameters.len().checked_sub(privacy)?;
+  let range = end.checked_sub(parameters.authentication_parameters.len())?..end;
+  (message.get(range.clone())? == &parameters.authentication_parameters[..]).then_some(range)
+}
+
+fn integer(value: &rasn::types::Integer) -> Result<u32> {
+  u32::try_from(value).map_err(|error| Error::malformed(CodecError(error.to_string())))
+}
+
+fn next() -> u64 {
+  if COUNTER.load(Ordering::Relaxed) == 0 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let _ = COUNTER.compare_exchange(0, now.as_nanos() as u64, Ordering::Relaxed, Ordering::Relaxed);
+  }
+  COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+pub(super) fn next_id() -> i32 {
+  (next() & 0x7fff_ffff) as i32
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn hex(octets: &[u8]) -> String {
+    octets.iter().map(|octet| format!("{:02x}", octet)).collect()
+  }
+
+  // RFC 3414 A.3.1 and A.3.2.
+  const ENGINE_ID: [u8; 12] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+  #[test]
+  fn localizes_md5_keys() {
+    assert_eq!(hex(&AuthProtocol::Md5.localize(b"maplesyrup", &ENGINE_ID)), "526f5eed9fcce26f8964c2930787d82b");
+  }
+
+  #[test]
+  fn localizes_sha_keys() {
+    assert_eq!(hex(&AuthProtocol::Sha.localize(b"maplesyrup", &ENGINE_ID)), "6695febc9288e36282235fc7151f128497b38f3f");
+  }
+
+  #[test]
+  fn caches_keys_by_engine() {
+    let password = OctetString::from_static(b"maplesyrup");
+    let engine = OctetString::from_static(&ENGINE_ID);
+    let other = OctetString::from_static(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+    assert_eq!(hex(&localized(AuthProtocol::Md5, &password, &engine)), "526f5eed9fcce26f8964c2930787d82b");
+    assert_eq!(hex(&localized(AuthProtocol::Md5, &password, &engine)), "526f5eed9fcce26f8964c2930787d82b");
+    assert_ne!(localized(AuthProtocol::Md5, &password, &other), localized(AuthProtocol::Md5, &password, &engine));
+  }
+
+  #[test]
+  fn compares_macs() {
+    assert!(same(&[1, 2, 3], &[1, 2, 3]));
+    assert!(!same(&[1, 2, 3], &[1, 2, 4]));
+    assert!(!same(&[1, 2], &[1, 2, 3]));
+  }
+}
@@ -0,0 +1,78 @@
+use crate::snmp;
+
+// Renders bindings the way net-snmp's `snmpwalk -On` does, one
+// `.OID = TYPE: value` line per binding, so that scripts written against
+// its output keep working.
+pub fn format(bindings: &[snmp::VariableBinding]) -> String {
+  bindings.iter()
+    .map(|binding| format!("{}\n", format_binding(binding)))
+    .collect()
+}
+
+pub fn format_binding(binding: &snmp::VariableBinding) -> String {
+  format!(".{} = {}", binding.object_id, format_value(&binding.value))
+}
+
+pub fn format_value(value: &snmp::ObjectValue) -> String {
+  match value {
+    snmp::ObjectValue::Integer(value) => format!("INTEGER: {}", value),
+    snmp::ObjectValue::OctetString(value) => format_octet_string(value),
+    snmp::ObjectValue::ObjectIdentifier(value) => format!("OID: .{}", value),
+    snmp::ObjectValue::Integer32(value) => format!("INTEGER: {}", value),
+    snmp::ObjectValue::IpAddress(value) => format!("IpAddress: {}", value),
+    snmp::ObjectValue::Ipv6Address(..) => format!("STRING: {}", value),
+    snmp::ObjectValue::Counter32(value) => format!("Counter32: {}", value),
+    snmp::ObjectValue::Unsigned32(value) => format!("Gauge32: {}", value),
+    snmp::ObjectValue::TimeTicks(value) => format!("Timeticks: ({}) {}", value, format_ticks(*value)),
+    snmp::ObjectValue::Opaque(value) => format!("Opaque: {}", hex(value)),
+    snmp::ObjectValue::Counter64(value) => format!("Counter64: {}", value),
+    snmp::ObjectValue::Float(value) => format!("Opaque: Float: {}", value),
+    snmp::ObjectValue::Double(value) => format!("Opaque: Double: {}", value),
+    snmp::ObjectValue::Null => "NULL".to_string(),
+    snmp::ObjectValue::NoSuchObject => "No Such Object available on this agent at this OID".to_string(),
+    snmp::ObjectValue::NoSuchInstance => "No Such Instance currently exists at this OID".to_string(),
+    snmp::ObjectValue::EndOfMibView => "No more variables left in this MIB View (It is past the end of the MIB tree)".to_string(),
+  }
+}
+
+fn format_octet_string(value: &[u8]) -> String {
+  if value.is_empty() {
+    return "\"\"".to_string();
+  }
+  let printable = value.iter()
+    .all(|octet| octet.is_ascii_graphic() || octet.is_ascii_whitespace());
+  if !printable {
+    return format!("Hex-STRING: {}", hex(value));
+  }
+  let mut text = String::from("STRING: \"");
+  for octet in value {
+    if *octet == b'"' || *octet == b'\\' {
+      text.push('\\');
+    }
+    text.push(*octet as char);
+  }
+  text.push('"');
+  text
+}
+
+// net-snmp prints upper-case octets, each followed by a space.
+fn hex(value: &[u8]) -> String {
+  value.iter().map(|octet| format!("{:02X} ", octet)).collect()
+}
+
+fn format_ticks(ticks: u32) -> String {
+  let centiseconds = ticks % 100;
+  let seconds = ticks / 100;
+  let (days, hours, minutes, seconds) = (
+    seconds / 86400,
+    seconds / 3600 % 24,
+    seconds / 60 % 60,
+    seconds % 60,
+  );
+  let clock = format!("{}:{:02}:{:02}.{:02}", hours, minutes, seconds, centiseconds);
+  match days {
+    0 => clock,
+    1 => format!("1 day, {}", clock),
+    days => format!("{} days, {}", days, clock),
+  }
+}
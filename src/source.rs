@@ -0,0 +1,269 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Display, future::Future, net::SocketAddr, pin::Pin, time::{Duration, Instant, SystemTime}};
+
+use serde::Deserialize;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+use crate::{config, pipeline, profile, snmp};
+
+pub type BoxFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<profile::Sample>, Error>> + Send + 'a>>;
+
+// Anything that produces samples for a target on schedule: SNMP profiles,
+// and companion checks run from the same daemon.
+pub trait Source: Send + Sync {
+
+  fn name(&self) -> &str;
+
+  fn collect<'a>(&'a self, target: &'a config::TargetConfig) -> BoxFuture<'a>;
+
+  // Seconds between collections, if not the collection interval.
+  fn interval(&self) -> Option<u64> {
+    None
+  }
+
+  // Stages the samples go through ahead of the source's pipeline.
+  fn stages(&self) -> &[pipeline::Stage] {
+    &[]
+  }
+}
+
+// Builds a source from the options of a target's `[[targets.checks]]` entry.
+pub type Factory = Box<dyn Fn(&toml::Table) -> Result<Box<dyn Source>, Error> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum Error {
+  UnknownKind(String),
+  Config(String),
+  Snmp(snmp::Error),
+  Io(std::io::Error),
+}
+
+impl Display for Error {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::UnknownKind(kind) => write!(f, "unknown check kind {}", kind),
+      Error::Config(error) => write!(f, "invalid check configuration: {}", error),
+      Error::Snmp(error) => write!(f, "{}", error),
+      Error::Io(error) => write!(f, "{}", error),
+    }
+  }
+}
+
+// Source kinds by the name used in the configuration. Crates embedding the
+// collector register their own kinds before building the targets' sources.
+pub struct Registry {
+  factories: HashMap<String, Factory>,
+}
+
+impl Default for Registry {
+
+  fn default() -> Self {
+    let mut registry = Registry { factories: HashMap::new() };
+    registry.register("tcp", Box::new(|options| Ok(Box::new(TcpCheck::new(options)?))));
+    registry.register("http", Box::new(|options| Ok(Box::new(HttpCheck::new(options)?))));
+    registry
+  }
+}
+
+impl Registry {
+
+  pub fn register(&mut self, kind: &str, factory: Factory) {
+    self.factories.insert(kind.to_string(), factory);
+  }
+
+  // The target's profiles followed by its checks.
+  pub fn build(
+    &self,
+    target: &config::TargetConfig,
+    profiles: &[profile::Profile],
+  ) -> Result<Vec<Box<dyn Source>>, Error> {
+    let mut sources = Vec::<Box<dyn Source>>::new();
+    for name in &target.profiles {
+      let profile = profile::find(profiles, name)
+        .ok_or_else(|| Error::Config(format!("unknown profile {}", name)))?;
+      sources.push(Box::new(ProfileSource(profile.clone(), profile::Cache::default())));
+    }
+    for check in &target.checks {
+      let factory = self.factories.get(&check.kind)
+        .ok_or_else(|| Error::UnknownKind(check.kind.clone()))?;
+      sources.push(factory(&check.options)?);
+    }
+    Ok(sources)
+  }
+}
+
+// A profile with the static values it fetched from the target.
+struct ProfileSource(profile::Profile, profile::Cache);
+
+impl Source for ProfileSource {
+
+  fn name(&self) -> &str {
+    &self.0.name
+  }
+
+  fn collect<'a>(&'a self, target: &'a config::TargetConfig) -> BoxFuture<'a> {
+    Box::pin(async move {
+      profile::collect(&target.agent(), &self.0, &target.variables, &self.1)
+        .await
+        .map_err(Error::Snmp)
+    })
+  }
+
+  fn interval(&self) -> Option<u64> {
+    self.0.interval
+  }
+
+  fn stages(&self) -> &[pipeline::Stage] {
+    &self.0.stages
+  }
+}
+
+fn options<T: serde::de::DeserializeOwned>(options: &toml::Table) -> Result<T, Error> {
+  options.clone().try_into().map_err(|error| Error::Config(error.to_string()))
+}
+
+fn sample(name: &str, labels: &BTreeMap<String, String>, value: f64) -> profile::Sample {
+  profile::Sample {
+    name: name.to_string(),
+    kind: profile::MetricKind::Gauge,
+    labels: labels.clone(),
+    value,
+    timestamp: snmp::Timestamp { collected_at: SystemTime::now(), sys_up_time: None },
+  }
+}
+
+fn default_timeout() -> u64 {
+  5
+}
+
+// Whether a TCP port on the target accepts connections, and how quickly.
+struct TcpCheck {
+  name: String,
+  port: u16,
+  timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct TcpOptions {
+  port: u16,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+impl TcpCheck {
+
+  fn new(options: &toml::Table) -> Result<TcpCheck, Error> {
+    let options = self::options::<TcpOptions>(options)?;
+    Ok(TcpCheck {
+      name: format!("tcp:{}", options.port),
+      port: options.port,
+      timeout: Duration::from_secs(options.timeout),
+    })
+  }
+}
+
+impl Source for TcpCheck {
+
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn collect<'a>(&'a self, target: &'a config::TargetConfig) -> BoxFuture<'a> {
+    Box::pin(async move {
+      let labels = BTreeMap::from([("port".to_string(), self.port.to_string())]);
+      let started = Instant::now();
+      let connected = tokio::time::timeout(self.timeout, TcpStream::connect(target.address.socket(self.port)))
+        .await
+        .is_ok_and(|stream| stream.is_ok());
+      let mut samples = vec![sample("tcpUp", &labels, if connected { 1.0 } else { 0.0 })];
+      if connected {
+        samples.push(sample("tcpConnectSeconds", &labels, started.elapsed().as_secs_f64()));
+      }
+      Ok(samples)
+    })
+  }
+}
+
+// A plain HTTP GET against the target, reporting the status code and the
+// time until the status line arrived.
+struct HttpCheck {
+  name: String,
+  port: u16,
+  path: String,
+  host: Option<String>,
+  timeout: Duration,
+}
+
+#[derive(Deserialize)]
+struct HttpOptions {
+  #[serde(default = "default_http_port")]
+  port: u16,
+  #[serde(default = "default_http_path")]
+  path: String,
+  host: Option<String>,
+  #[serde(default = "default_timeout")]
+  timeout: u64,
+}
+
+fn default_http_port() -> u16 {
+  80
+}
+
+fn default_http_path() -> String {
+  "/".to_string()
+}
+
+impl HttpCheck {
+
+  fn new(options: &toml::Table) -> Result<HttpCheck, Error> {
+    let options = self::options::<HttpOptions>(options)?;
+    Ok(HttpCheck {
+      name: format!("http:{}{}", options.port, options.path),
+      port: options.port,
+      path: options.path,
+      host: options.host,
+      timeout: Duration::from_secs(options.timeout),
+    })
+  }
+
+  async fn status(&self, address: SocketAddr) -> Result<u16, Error> {
+    let mut stream = TcpStream::connect(address).await.map_err(Error::Io)?;
+    let host = self.host.clone().unwrap_or_else(|| address.ip().to_string());
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", self.path, host);
+    stream.write_all(request.as_bytes()).await.map_err(Error::Io)?;
+    let mut head = [0; 12];
+    stream.read_exact(&mut head).await.map_err(Error::Io)?;
+    std::str::from_utf8(&head[9..12])
+      .ok()
+      .and_then(|status| status.parse().ok())
+      .filter(|_| head.starts_with(b"HTTP/"))
+      .ok_or_else(|| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, "not an HTTP response")))
+  }
+}
+
+impl Source for HttpCheck {
+
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn collect<'a>(&'a self, target: &'a config::TargetConfig) -> BoxFuture<'a> {
+    Box::pin(async move {
+      let labels = BTreeMap::from([
+        ("port".to_string(), self.port.to_string()),
+        ("path".to_string(), self.path.clone()),
+      ]);
+      let started = Instant::now();
+      let status = tokio::time::timeout(self.timeout, self.status(target.address.socket(self.port)))
+        .await
+        .ok()
+        .and_then(Result::ok);
+      let mut samples = vec![sample("httpUp", &labels, if status.is_some() { 1.0 } else { 0.0 })];
+      if let Some(status) = status {
+        samples.push(sample("httpStatus", &labels, f64::from(status)));
+        samples.push(sample("httpResponseSeconds", &labels, started.elapsed().as_secs_f64()));
+      }
+      Ok(samples)
+    })
+  }
+}
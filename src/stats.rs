@@ -0,0 +1,111 @@
+use std::{sync::{atomic::{AtomicU64, Ordering}, LazyLock}, time::{Duration, Instant}};
+
+// Operational counters of this process, readable over AgentX, and with the
+// rest at `/internal/metrics`. `snmp_awaiting` and `snmp_queued` are gauges:
+// requests awaiting an agent's answer, and requests waiting for their turn
+// under the limits on outstanding requests and on the PDU rate.
+pub struct Stats {
+  pub collections: AtomicU64,
+  pub collection_failures: AtomicU64,
+  pub sink_writes: AtomicU64,
+  pub sink_failures: AtomicU64,
+  pub snmp_requests: AtomicU64,
+  pub snmp_timeouts: AtomicU64,
+  pub snmp_decode_errors: AtomicU64,
+  pub snmp_awaiting: AtomicU64,
+  pub snmp_queued: AtomicU64,
+  pub snmp_latency: Histogram,
+  pub http_requests: AtomicU64,
+  pub http_latency: Histogram,
+}
+
+pub static STATS: Stats = Stats {
+  collections: AtomicU64::new(0),
+  collection_failures: AtomicU64::new(0),
+  sink_writes: AtomicU64::new(0),
+  sink_failures: AtomicU64::new(0),
+  snmp_requests: AtomicU64::new(0),
+  snmp_timeouts: AtomicU64::new(0),
+  snmp_decode_errors: AtomicU64::new(0),
+  snmp_awaiting: AtomicU64::new(0),
+  snmp_queued: AtomicU64::new(0),
+  snmp_latency: Histogram::new(),
+  http_requests: AtomicU64::new(0),
+  http_latency: Histogram::new(),
+};
+
+// The upper bounds, in seconds, of the buckets of latency histograms.
+pub const BUCKETS: [f64; 12] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+// Durations counted by the bucket they fall in, along with their count and
+// sum, as Prometheus histograms have them.
+pub struct Histogram {
+  buckets: [AtomicU64; BUCKETS.len()],
+  count: AtomicU64,
+  micros: AtomicU64,
+}
+
+impl Histogram {
+
+  const fn new() -> Histogram {
+    Histogram {
+      buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+      count: AtomicU64::new(0),
+      micros: AtomicU64::new(0),
+    }
+  }
+
+  pub fn observe(&self, duration: Duration) {
+    if let Some(bucket) = BUCKETS.iter().position(|bound| duration.as_secs_f64() <= *bound) {
+      self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+    self.count.fetch_add(1, Ordering::Relaxed);
+    self.micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+  }
+
+  // The cumulative count of each bucket, then the count and the sum in
+  // seconds.
+  pub fn read(&self) -> (Vec<u64>, u64, f64) {
+    let buckets = self.buckets.iter()
+      .scan(0, |total, bucket| {
+        *total += bucket.load(Ordering::Relaxed);
+        Some(*total)
+      })
+      .collect();
+    (buckets, self.count.load(Ordering::Relaxed), self.micros.load(Ordering::Relaxed) as f64 / 1e6)
+  }
+}
+
+// Counts one more in a gauge until the guard returned is dropped.
+pub fn hold(gauge: &'static AtomicU64) -> Held {
+  gauge.fetch_add(1, Ordering::Relaxed);
+  Held(gauge)
+}
+
+pub struct Held(&'static AtomicU64);
+
+impl Drop for Held {
+
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::Relaxed);
+  }
+}
+
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+pub fn count(counter: &AtomicU64) {
+  counter.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn read(counter: &AtomicU64) -> u64 {
+  counter.load(Ordering::Relaxed)
+}
+
+// Hundredths of a second since `start`, as TimeTicks wrap.
+pub fn uptime() -> u32 {
+  (STARTED.elapsed().as_millis() / 10) as u32
+}
+
+pub fn start() {
+  LazyLock::force(&STARTED);
+}
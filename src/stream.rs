@@ -0,0 +1,103 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::broadcast::error::RecvError;
+use warp::ws::{Message, WebSocket};
+
+use crate::{collector, mib, profile, snmp};
+
+// What a client of `/stream` subscribes to, sent as a text message such as
+// `{"targets": ["sw1"], "oids": ["1.3.6.1.2.1.2.2.1.10"]}` and replacing
+// any before it. OIDs match the samples named after their MIB object, and
+// `samples` names them directly; lists left empty match everything.
+#[derive(Deserialize)]
+struct Subscription {
+  #[serde(default)]
+  targets: BTreeSet<String>,
+  #[serde(default)]
+  oids: Vec<snmp::ObjectIdentifier>,
+  #[serde(default)]
+  samples: BTreeSet<String>,
+}
+
+struct Filter {
+  targets: BTreeSet<String>,
+  names: BTreeSet<String>,
+}
+
+// The samples of one collection that a client subscribed to.
+#[derive(Serialize)]
+struct Update<'a> {
+  target: &'a str,
+  source: &'a str,
+  samples: Vec<&'a profile::Sample>,
+}
+
+impl From<Subscription> for Filter {
+
+  fn from(subscription: Subscription) -> Self {
+    let objects = subscription.oids.iter().map(|oid| match mib::object_name(oid.arcs()) {
+      Some((name, _index)) => name.to_string(),
+      None => oid.to_string(),
+    });
+    let names = subscription.samples.into_iter().chain(objects).collect();
+    Filter { targets: subscription.targets, names }
+  }
+}
+
+impl Filter {
+
+  // The message for a collection, None when nothing of it was subscribed to.
+  fn update(&self, collection: &collector::Collection) -> Option<String> {
+    if !self.targets.is_empty() && !self.targets.contains(&collection.target) {
+      return None;
+    }
+    let samples = collection.samples.iter()
+      .filter(|sample| self.names.is_empty() || self.names.contains(&sample.name))
+      .collect::<Vec<_>>();
+    if samples.is_empty() {
+      return None;
+    }
+    serde_json::to_string(&Update { target: &collection.target, source: &collection.source, samples }).ok()
+  }
+}
+
+// Streams collections to a client as they are recorded, nothing before its
+// first subscription. A client too slow to keep up is told how many
+// collections it missed.
+pub async fn session(socket: WebSocket, latest: Arc<collector::Latest>) {
+  let (mut outgoing, mut incoming) = socket.split();
+  let mut collections = latest.subscribe();
+  let mut filter: Option<Filter> = None;
+  loop {
+    let reply = tokio::select! {
+      message = incoming.next() => match message {
+        Some(Ok(message)) if message.is_text() => {
+          match serde_json::from_str::<Subscription>(message.to_str().unwrap_or_default()) {
+            Ok(subscription) => {
+              filter = Some(subscription.into());
+              continue;
+            },
+            Err(error) => json!({ "error": format!("invalid subscription: {}", error) }).to_string(),
+          }
+        },
+        Some(Ok(message)) if message.is_close() => break,
+        Some(Ok(_other)) => continue,
+        Some(Err(_)) | None => break,
+      },
+      collection = collections.recv() => match collection {
+        Ok(collection) => match filter.as_ref().and_then(|filter| filter.update(&collection)) {
+          Some(update) => update,
+          None => continue,
+        },
+        Err(RecvError::Lagged(missed)) => json!({ "missed": missed }).to_string(),
+        Err(RecvError::Closed) => break,
+      },
+    };
+    if outgoing.send(Message::text(reply)).await.is_err() {
+      break;
+    }
+  }
+}
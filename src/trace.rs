@@ -0,0 +1,50 @@
+use std::{fmt::Arguments, future::Future, sync::atomic::{AtomicU64, Ordering}};
+
+use tokio::time::Instant;
+
+// The header carrying a request's ID, both ways.
+pub const HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+  static SPAN: Span;
+}
+
+// A request of the HTTP API, from its arrival to its response, with the
+// exchanges with agents made for it. When `log` is set, what it does is
+// written to stderr under its ID, with the milliseconds since it began.
+struct Span {
+  id: String,
+  started: Instant,
+  log: bool,
+}
+
+static LAST_ID: AtomicU64 = AtomicU64::new(0);
+
+// The ID a request is known by: the one the client gave, if it is short
+// and printable enough to go into logs and headers as it is, or else one
+// unique in this process.
+pub fn request_id(given: Option<&str>) -> String {
+  match given {
+    Some(id) if !id.is_empty() && id.len() <= 64 && id.bytes().all(|byte| byte.is_ascii_graphic()) => id.to_string(),
+    _ => format!("{:x}-{:06x}", std::process::id(), LAST_ID.fetch_add(1, Ordering::Relaxed) + 1),
+  }
+}
+
+// Runs `future` in the span of the request with ID `id`.
+pub async fn within<F: Future>(id: String, log: bool, future: F) -> F::Output {
+  SPAN.scope(Span { id, started: Instant::now(), log }, future).await
+}
+
+// Notes a step of the request being served, if any.
+pub fn event(what: Arguments) {
+  let _ = SPAN.try_with(|span| span.event(what));
+}
+
+impl Span {
+
+  fn event(&self, what: Arguments) {
+    if self.log {
+      eprintln!("[{}] +{}ms {}", self.id, self.started.elapsed().as_millis(), what);
+    }
+  }
+}
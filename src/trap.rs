@@ -0,0 +1,136 @@
+use std::{collections::{HashMap, VecDeque}, net::{IpAddr, SocketAddr}, sync::{Arc, Mutex}};
+
+use serde::{ser::SerializeStruct, Serialize};
+use tokio::{net::UdpSocket, sync::broadcast};
+
+use crate::{config, profile, snmp};
+
+mod webhook;
+
+// Subscribers further behind than this miss the traps in between.
+const BACKLOG: usize = 256;
+
+// A notification and the address it came from.
+#[derive(Clone)]
+pub struct Trap {
+  pub source: SocketAddr,
+  pub notification: snmp::Notification,
+}
+
+// The form traps take at `/traps` and for webhooks.
+impl Serialize for Trap {
+
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer
+  {
+    #[derive(Serialize)]
+    struct Binding<'a> {
+      oid: &'a snmp::ObjectIdentifier,
+      #[serde(flatten)]
+      value: &'a snmp::ObjectValue,
+      #[serde(flatten)]
+      timestamp: &'a snmp::Timestamp,
+    }
+
+    let notification = &self.notification;
+    let mut obj = serializer.serialize_struct("Trap", 6)?;
+    obj.serialize_field("source", &self.source.ip().to_string())?;
+    obj.serialize_field("version", match notification.version {
+      snmp::Version::V1 => "1",
+      snmp::Version::V2c => "2c",
+      snmp::Version::V3 => "3",
+    })?;
+    obj.serialize_field("community", &String::from_utf8_lossy(&notification.community))?;
+    obj.serialize_field("trapOid", &notification.trap_oid())?;
+    obj.serialize_field("inform", &notification.inform)?;
+    obj.serialize_field("bindings", &notification.bindings.iter()
+      .map(|binding| Binding { oid: &binding.object_id, value: &binding.value, timestamp: &binding.timestamp })
+      .collect::<Vec<_>>())?;
+    obj.end()
+  }
+}
+
+// The traps received lately, oldest first, and the subscribers to those
+// still to come.
+pub struct Store {
+  recent: Mutex<VecDeque<Trap>>,
+  subscribers: broadcast::Sender<Trap>,
+}
+
+impl Default for Store {
+
+  fn default() -> Self {
+    Store { recent: Mutex::default(), subscribers: broadcast::channel(BACKLOG).0 }
+  }
+}
+
+impl Store {
+
+  fn record(&self, trap: Trap, keep: usize) {
+    let mut recent = self.recent.lock().unwrap();
+    recent.push_back(trap.clone());
+    while recent.len() > keep {
+      recent.pop_front();
+    }
+    // Nobody may be listening, which is fine.
+    let _ = self.subscribers.send(trap);
+  }
+
+  pub fn recent(&self) -> Vec<Trap> {
+    self.recent.lock().unwrap().iter().cloned().collect()
+  }
+
+  pub fn subscribe(&self) -> broadcast::Receiver<Trap> {
+    self.subscribers.subscribe()
+  }
+}
+
+// Receives SNMPv1 and v2c traps and informs on `listen` into `store`,
+// acknowledging the informs, and forwards them to the webhooks.
+// Notifications are taken from anyone, whatever their community; those
+// from configured targets first go through the `trap_received` scripts of
+// the target's profiles.
+pub fn spawn(config: &config::Config, profiles: &[profile::Profile], store: Arc<Store>) -> std::io::Result<()> {
+  let Some(listen) = config.traps.listen else {
+    return Ok(());
+  };
+  for webhook in &config.traps.webhooks {
+    webhook::spawn(webhook.clone(), store.subscribe());
+  }
+  let keep = config.traps.keep;
+  let mut scripts: HashMap<IpAddr, Vec<String>> = HashMap::new();
+  for target in &config.targets {
+    let target_scripts = target.profiles.iter()
+      .filter_map(|name| profile::find(profiles, name)?.scripts.trap_received.clone());
+    scripts.entry(target.address.ip).or_default().extend(target_scripts);
+  }
+  let socket = std::net::UdpSocket::bind(listen).and_then(|socket| {
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket)
+  })?;
+  tokio::spawn(async move {
+    let mut buffer = vec![0; 65535];
+    loop {
+      let Ok((length, source)) = socket.recv_from(&mut buffer).await else {
+        continue;
+      };
+      let Some(notification) = snmp::decode_notification(&buffer[..length]) else {
+        continue;
+      };
+      if let Some(acknowledgement) = notification.acknowledgement() {
+        if let Err(error) = socket.send_to(acknowledgement, source).await {
+          eprintln!("Cannot acknowledge inform from {}: {}", source, error);
+        }
+      }
+      let trap = Trap { source, notification };
+      let trap = match scripts.get(&source.ip()) {
+        Some(scripts) => profile::script::trap(scripts, trap),
+        None => Some(trap),
+      };
+      if let Some(trap) = trap {
+        store.record(trap, keep);
+      }
+    }
+  });
+  Ok(())
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use tokio::{sync::broadcast::{self, error::RecvError}, time::Instant};
+
+use super::Trap;
+use crate::{config, http_client};
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+pub fn spawn(webhook: config::WebhookConfig, mut traps: broadcast::Receiver<Trap>) {
+  tokio::spawn(async move {
+    while let Some(batch) = next_batch(&webhook, &mut traps).await {
+      if let Err(error) = post(&webhook, &batch).await {
+        eprintln!("Cannot forward {} traps to {}: {}", batch.len(), webhook.url, error);
+      }
+    }
+  });
+}
+
+// Waits for a trap, then gathers those that follow it until the batch is
+// full or has lingered long enough. None once no more traps can come.
+async fn next_batch(webhook: &config::WebhookConfig, traps: &mut broadcast::Receiver<Trap>) -> Option<Vec<Trap>> {
+  let mut batch = Vec::new();
+  let mut until = None;
+  while batch.len() < webhook.batch_size.max(1) {
+    let received = match until {
+      None => traps.recv().await,
+      Some(until) => match tokio::time::timeout_at(until, traps.recv()).await {
+        Ok(received) => received,
+        Err(_elapsed) => break,
+      },
+    };
+    match received {
+      Ok(trap) => {
+        batch.push(trap);
+        until.get_or_insert_with(|| Instant::now() + Duration::from_millis(webhook.linger_ms));
+      },
+      Err(RecvError::Lagged(missed)) => eprintln!("Trap webhook {} missed {} traps", webhook.url, missed),
+      Err(RecvError::Closed) if batch.is_empty() => return None,
+      Err(RecvError::Closed) => break,
+    }
+  }
+  Some(batch)
+}
+
+async fn post(webhook: &config::WebhookConfig, batch: &[Trap]) -> Result<(), http_client::Error> {
+  let body = serde_json::to_vec(batch).expect("traps serialize");
+  let headers = std::iter::once(("content-type", "application/json"))
+    .chain(webhook.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())))
+    .collect::<Vec<_>>();
+  let timeout = Duration::from_secs(webhook.timeout);
+  let mut wait = FIRST_RETRY;
+  let mut retries = webhook.retries;
+  loop {
+    match http_client::post(&webhook.url, &headers, body.clone(), timeout).await {
+      Ok(_response) => return Ok(()),
+      Err(error) if retries == 0 => return Err(error),
+      Err(_error) => {
+        tokio::time::sleep(wait).await;
+        wait *= 2;
+        retries -= 1;
+      },
+    }
+  }
+}
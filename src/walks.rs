@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use tokio::{task::AbortHandle, time::Instant};
+
+use crate::{config, snmp};
+
+// Walks run in the background for clients that would rather not hold a
+// request open for as long as a big subtree takes. Each is looked up by its
+// ID while it runs, and for `retention` after it ends.
+#[derive(Default)]
+pub struct Walks {
+  walks: Mutex<Walked>,
+}
+
+#[derive(Default)]
+struct Walked {
+  next_id: u64,
+  walks: BTreeMap<u64, Walk>,
+}
+
+struct Walk {
+  job: Job,
+  ended: Option<Instant>,
+  abort: Option<AbortHandle>,
+}
+
+// Where a walk stands. `walked` counts the bindings so far and `last_oid`
+// is the furthest reached, until it ends with an outcome.
+#[derive(Clone)]
+pub struct Job {
+  pub id: u64,
+  pub address: config::Address,
+  pub oid: snmp::ObjectIdentifier,
+  pub started_at: u64,
+  pub ended_at: Option<u64>,
+  pub walked: usize,
+  pub last_oid: Option<snmp::ObjectIdentifier>,
+  pub outcome: Outcome,
+}
+
+#[derive(Clone)]
+pub enum Outcome {
+  Running,
+  Done(Arc<Vec<snmp::VariableBinding>>),
+  Failed(String),
+  Cancelled,
+}
+
+impl Walks {
+
+  // Starts walking `oid` on the agent, returning the walk's ID.
+  pub fn start(
+    self: &Arc<Self>,
+    address: config::Address,
+    agent: snmp::SnmpClient,
+    oid: snmp::ObjectIdentifier,
+    retention: Duration,
+  ) -> u64 {
+    let mut walks = self.walks.lock().unwrap();
+    walks.walks.retain(|_, walk| walk.ended.is_none_or(|ended| ended.elapsed() < retention));
+    walks.next_id += 1;
+    let id = walks.next_id;
+    let job = Job {
+      id,
+      address,
+      oid: oid.clone(),
+      started_at: now(),
+      ended_at: None,
+      walked: 0,
+      last_oid: None,
+      outcome: Outcome::Running,
+    };
+    let this = self.clone();
+    let task = tokio::spawn(async move {
+      let progress = |walked: &[snmp::VariableBinding]| this.update(id, |job| {
+        job.walked = walked.len();
+        job.last_oid = walked.last().map(|binding| binding.object_id.clone());
+      });
+      let outcome = match agent.walk_reporting(&oid, &progress).await {
+        Ok(bindings) => Outcome::Done(Arc::new(bindings)),
+        Err(error) => Outcome::Failed(error.to_string()),
+      };
+      this.end(id, outcome);
+    });
+    walks.walks.insert(id, Walk { job, ended: None, abort: Some(task.abort_handle()) });
+    id
+  }
+
+  pub fn get(&self, id: u64) -> Option<Job> {
+    self.walks.lock().unwrap().walks.get(&id).map(|walk| walk.job.clone())
+  }
+
+  // Stops a running walk, which is then kept as cancelled, or forgets one
+  // that ended. False if there is no such walk.
+  pub fn cancel(&self, id: u64) -> bool {
+    let mut walks = self.walks.lock().unwrap();
+    let Some(walk) = walks.walks.get_mut(&id) else {
+      return false;
+    };
+    if walk.ended.is_some() {
+      walks.walks.remove(&id);
+      return true;
+    }
+    if let Some(abort) = walk.abort.take() {
+      abort.abort();
+    }
+    drop(walks);
+    self.end(id, Outcome::Cancelled);
+    true
+  }
+
+  fn update(&self, id: u64, change: impl FnOnce(&mut Job)) {
+    if let Some(walk) = self.walks.lock().unwrap().walks.get_mut(&id).filter(|walk| walk.ended.is_none()) {
+      change(&mut walk.job);
+    }
+  }
+
+  fn end(&self, id: u64, outcome: Outcome) {
+    if let Some(walk) = self.walks.lock().unwrap().walks.get_mut(&id).filter(|walk| walk.ended.is_none()) {
+      if let Outcome::Done(bindings) = &outcome {
+        walk.job.walked = bindings.len();
+        walk.job.last_oid = bindings.last().map(|binding| binding.object_id.clone());
+      }
+      walk.job.outcome = outcome;
+      walk.job.ended_at = Some(now());
+      walk.ended = Some(Instant::now());
+      walk.abort = None;
+    }
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}